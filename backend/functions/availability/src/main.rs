@@ -1,18 +1,27 @@
+use std::collections::{HashMap, HashSet};
+
 use lambda_http::{run, service_fn, Body, Request, Response, Error, RequestPayloadExt};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
-use shared_lib::{init_tracing, success_response, ApiError, get_client, table_name, require_tenant};
-use aws_sdk_dynamodb::types::AttributeValue;
-use chrono::{DateTime, Utc, Duration as ChronoDuration};
+use shared_lib::{init_tracing, success_response, ApiError, require_tenant};
+use shared_lib::{DynamoStore, Repository};
+use shared_lib::models::{Professional, Site, Treatment};
+use shared_lib::slots::{granularity_minutes, open_shifts, units};
+use shared_lib::timezone::{local_day_bounds, today, Tz};
+use chrono::{Duration as ChronoDuration, NaiveDate};
 
 #[derive(Debug, Deserialize, Validate)]
 struct AvailabilityRequest {
     #[validate(length(min = 1, max = 50))]
     site_id: String,
-    
+
     #[validate(length(min = 1, max = 50))]
     professional_id: Option<String>,
-    
+
+    #[validate(length(min = 1, max = 50))]
+    treatment_id: String,
+
+    /// Día local de la clínica (`YYYY-MM-DD`)
     #[serde(default)]
     date: Option<String>,
 }
@@ -25,107 +34,394 @@ struct Slot {
     available: bool,
 }
 
-async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let result: Result<Response<Body>, ApiError> = (|| async {
+async fn handler(repo: &dyn Repository, req: Request) -> Result<Response<Body>, Error> {
+    let result: Result<Response<Body>, ApiError> = async {
         // Multitenancy: extraer tenant desde el token
-        let tenant_id = require_tenant(&req)?;
+        let tenant_id = require_tenant(&req).await?;
 
         let payload = req.payload::<AvailabilityRequest>()?
             .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
-        
+
         payload.validate()
             .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
-        
+
         tracing::info!(
             site_id = %payload.site_id,
             professional_id = ?payload.professional_id,
+            treatment_id = %payload.treatment_id,
             "Processing availability request"
         );
-        
-        // Consultar slots ocupados en DynamoDB
-        let date_str = payload.date.clone().unwrap_or_else(|| {
-            let tomorrow = Utc::now() + ChronoDuration::days(1);
-            tomorrow.format("%Y-%m-%d").to_string()
-        });
-        let date = date_str.as_str();
-        
-        let occupied_slots = query_occupied_slots(&tenant_id, &payload.site_id, date).await?;
-        
-        // Generar slots disponibles (horario 9am-5pm, cada 15 min)
-        let mut available_slots = vec![];
-        let start_hour = 9;
-        let end_hour = 17;
-        
-        for hour in start_hour..end_hour {
-            for minute in [0, 15, 30, 45] {
-                let start_time = format!("{}T{:02}:{:02}:00Z", date, hour, minute);
-                let end_time_dt = DateTime::parse_from_rfc3339(&start_time)
-                    .map_err(|_| ApiError::Internal(anyhow::anyhow!("Date parse error")))?
-                    + ChronoDuration::minutes(45);
-                
-                let prefix = if let Some(pid) = &payload.professional_id {
-                    format!("{:02}:{:02}#{}", hour, minute, pid)
-                } else {
-                    format!("{:02}:{:02}", hour, minute)
-                };
-
-                let is_occupied = occupied_slots.iter().any(|slot| slot.starts_with(&prefix));
-                
-                if !is_occupied {
-                    available_slots.push(Slot {
-                        start: start_time,
-                        end: end_time_dt.to_rfc3339(),
-                        professional_id: payload.professional_id.clone().unwrap_or_else(|| "default".into()),
-                        available: true,
-                    });
-                }
-            }
+
+        let site = repo.get_site(&tenant_id, &payload.site_id).await?
+            .filter(Site::is_active)
+            .ok_or_else(|| ApiError::NotFound("Sede no encontrada".into()))?;
+
+        // Horarios y fecha son hora local de la sede (o del tenant si no la sobreescribe)
+        let tenant_tz = repo.get_tenant(&tenant_id).await?
+            .ok_or_else(|| ApiError::NotFound("Tenant no encontrado".into()))?
+            .tz();
+        let tz = site.tz(tenant_tz);
+
+        let date = match &payload.date {
+            Some(raw) => NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .map_err(|_| ApiError::Validation("date inválida (usar YYYY-MM-DD)".into()))?,
+            None => today(tz) + ChronoDuration::days(1),
+        };
+
+        let treatment = repo.get_treatment(&tenant_id, &payload.treatment_id).await?
+            .ok_or_else(|| ApiError::NotFound("Tratamiento no encontrado".into()))?;
+
+        // Solo se ofrecen profesionales asignados a la sede
+        let professionals: Vec<Professional> = match &payload.professional_id {
+            Some(id) => vec![repo.get_professional(&tenant_id, id).await?
+                .ok_or_else(|| ApiError::NotFound("Profesional no encontrado".into()))?],
+            None => repo.list_professionals(&tenant_id).await?
+                .into_iter()
+                .filter(|p| p.status == "active")
+                .collect(),
         }
-        
+        .into_iter()
+        .filter(|p| p.works_at(&site.id))
+        .collect();
+
+        let taken = query_taken_units(repo, &tenant_id, &professionals, tz, date).await?;
+        let no_locks = HashSet::new();
+
+        let mut available_slots: Vec<Slot> = professionals.iter()
+            .flat_map(|p| {
+                let taken = taken.get(&p.id).unwrap_or(&no_locks);
+                free_slots(p, &treatment, &site, date, tz, taken, granularity_minutes())
+            })
+            .collect();
+        available_slots.sort_by(|a, b| (&a.start, &a.professional_id).cmp(&(&b.start, &b.professional_id)));
+
         let response = serde_json::json!({
             "slots": available_slots,
             "total": available_slots.len(),
-            "date": date
+            "date": date.format("%Y-%m-%d").to_string(),
+            "timezone": tz.name()
         });
-        
+
         success_response(response)
-    })().await;
-    
+    }.await;
+
     match result {
         Ok(resp) => Ok(resp),
         Err(api_err) => Ok(api_err.into_response())
     }
 }
 
-async fn query_occupied_slots(tenant_id: &str, site_id: &str, date: &str) -> Result<Vec<String>, ApiError> {
-    let client = get_client().await;
-    
-    // Query slots reservados para esta fecha alineado con clave de bookings
-    let pk = format!("TENANT#{}#SITE#{}#DATE#{}", tenant_id, site_id, date);
-    
-    let result = client.query()
-        .table_name(table_name())
-        .key_condition_expression("PK = :pk AND begins_with(SK, :sk)")
-        .expression_attribute_values(":pk", AttributeValue::S(pk))
-        .expression_attribute_values(":sk", AttributeValue::S("SLOT#".to_string()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB query error: {}", e)))?;
-    
-    let slots = result.items.unwrap_or_default()
-        .iter()
-        .filter_map(|item| {
-            let sk = item.get("SK").and_then(|v| v.as_s().ok())?;
-            // SLOT#HH:MM#PROFESSIONAL_ID
-            sk.strip_prefix("SLOT#").map(|s| s.to_string())
-        })
-        .collect();
-    
-    Ok(slots)
+/// Unidades ocupadas (`YYYY-MM-DDTHH:MM` UTC) por profesional durante el día
+/// local `date`, en cualquier sede: una cita en otra sede también lo ocupa. Los
+/// locks se guardan por fecha UTC, así que un día local puede abarcar dos
+/// particiones. Las retenciones vigentes ocupan; las vencidas no, aunque el TTL
+/// todavía no las haya borrado.
+async fn query_taken_units(repo: &dyn Repository, tenant_id: &str, professionals: &[Professional], tz: Tz, date: NaiveDate) -> Result<HashMap<String, HashSet<String>>, ApiError> {
+    let now = chrono::Utc::now().timestamp();
+    let (day_start, day_end) = local_day_bounds(tz, date);
+    let first = day_start.date_naive();
+    let last = (day_end - ChronoDuration::seconds(1)).date_naive();
+
+    let mut taken: HashMap<String, HashSet<String>> = HashMap::new();
+    for professional in professionals {
+        let mut utc_date = first;
+        while utc_date <= last {
+            let locks = repo.list_slot_locks(tenant_id, &professional.id, &utc_date.format("%Y-%m-%d").to_string()).await?;
+            for lock in locks.into_iter().filter(|l| l.is_active(now)) {
+                taken.entry(lock.professional_id).or_default().insert(format!("{}T{}", lock.date, lock.time));
+            }
+            utc_date += ChronoDuration::days(1);
+        }
+    }
+    Ok(taken)
+}
+
+/// Inicios cada `granularity` minutos dentro de cada franja de atención del
+/// profesional (recortada al horario de apertura de la sede, ver `open_shifts`)
+/// en los que la cita completa (duración + buffer) cabe antes del fin de la
+/// franja o del siguiente descanso y no toca ninguna unidad ocupada.
+fn free_slots(
+    professional: &Professional,
+    treatment: &Treatment,
+    site: &Site,
+    date: NaiveDate,
+    tz: Tz,
+    taken: &HashSet<String>,
+    granularity: i64,
+) -> Vec<Slot> {
+    let length = ChronoDuration::minutes(treatment.occupied_minutes());
+    let step = ChronoDuration::minutes(granularity);
+    let mut slots = vec![];
+
+    for (mut start, range_end) in open_shifts(professional, site, date, tz) {
+        while start + length <= range_end {
+            let end = start + length;
+            let free = units(start, end, granularity).iter()
+                .all(|unit| !taken.contains(&unit.format("%Y-%m-%dT%H:%M").to_string()));
+            if free {
+                slots.push(Slot {
+                    start: start.with_timezone(&tz).to_rfc3339(),
+                    end: end.with_timezone(&tz).to_rfc3339(),
+                    professional_id: professional.id.clone(),
+                    available: true,
+                });
+            }
+            start += step;
+        }
+    }
+    slots
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     init_tracing();
-    run(service_fn(handler)).await
+    let repo = DynamoStore::from_env();
+    run(service_fn(|req| handler(&repo, req))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_http::http::{Method, StatusCode};
+    use serde_json::json;
+    use shared_lib::models::{BookingStatus, SlotHold, SlotLock};
+    use shared_lib::slots::hold_locks;
+    use shared_lib::repository::{BookingRepository, ProfessionalRepository, SiteRepository, TenantRepository, TreatmentRepository};
+    use shared_lib::testing::{bearer, booking, tenant};
+    use shared_lib::MemoryStore;
+
+    fn treatment(duration: i32, buffer: i32) -> Treatment {
+        Treatment {
+            id: "treat-1".into(),
+            tenant_id: "tenant-a".into(),
+            name: "Limpieza".into(),
+            duration_minutes: duration,
+            buffer_minutes: buffer,
+            price: 0.0,
+            created_at: String::new(),
+        }
+    }
+
+    fn professional(id: &str) -> Professional {
+        Professional {
+            id: id.into(),
+            tenant_id: "tenant-a".into(),
+            name: "Dra. Pérez".into(),
+            email: "perez@example.com".into(),
+            specialties: vec![],
+            site_ids: vec!["site-1".into()],
+            // 2025-10-01 es miércoles
+            schedule: serde_json::from_value(json!({"days": [
+                {"weekday": "wed", "site_id": "site-1", "start": "09:00", "end": "11:00",
+                 "breaks": [{"start": "10:00", "end": "10:15"}]}
+            ]})).unwrap(),
+            status: "active".into(),
+            created_at: String::new(),
+        }
+    }
+
+    fn site(id: &str) -> Site {
+        Site {
+            id: id.into(),
+            tenant_id: "tenant-a".into(),
+            name: "Sede Norte".into(),
+            address: "Calle 100".into(),
+            timezone: None,
+            opening_hours: Default::default(),
+            chairs: 1,
+            contact_email: None,
+            status: "active".into(),
+            created_at: String::new(),
+        }
+    }
+
+    fn starts(slots: &[Slot]) -> Vec<&str> {
+        slots.iter().map(|s| &s.start[11..16]).collect()
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 10, 1).unwrap()
+    }
+
+    fn bogota() -> Tz {
+        "America/Bogota".parse().unwrap()
+    }
+
+    fn new_york() -> Tz {
+        "America/New_York".parse().unwrap()
+    }
+
+    fn sunday_shift(start: &str, end: &str) -> Professional {
+        Professional {
+            schedule: serde_json::from_value(json!({"days": [
+                {"weekday": "sun", "site_id": "site-1", "start": start, "end": end}
+            ]})).unwrap(),
+            ..professional("prof-1")
+        }
+    }
+
+    fn all_starts(slots: &[Slot]) -> Vec<&str> {
+        slots.iter().map(|s| s.start.as_str()).collect()
+    }
+
+    #[test]
+    fn test_slots_fit_before_breaks_and_end_of_day() {
+        let slots = free_slots(&professional("prof-1"), &treatment(30, 10), &site("site-1"), date(), bogota(), &HashSet::new(), 15);
+        // 09:00-10:00 y 10:15-11:00 (hora local) con citas de 40 minutos
+        assert_eq!(starts(&slots), ["09:00", "09:15", "10:15"]);
+        assert_eq!(slots[0].start, "2025-10-01T09:00:00-05:00");
+        assert_eq!(slots[0].end, "2025-10-01T09:40:00-05:00");
+    }
+
+    #[test]
+    fn test_slots_skip_taken_units_and_other_days_or_sites() {
+        // 09:30 en Bogotá es 14:30 UTC
+        let taken = HashSet::from(["2025-10-01T14:30".to_string()]);
+        let slots = free_slots(&professional("prof-1"), &treatment(15, 0), &site("site-1"), date(), bogota(), &taken, 15);
+        assert_eq!(starts(&slots), ["09:00", "09:15", "09:45", "10:15", "10:30", "10:45"]);
+
+        let other_site = free_slots(&professional("prof-1"), &treatment(15, 0), &site("site-2"), date(), bogota(), &HashSet::new(), 15);
+        assert!(other_site.is_empty());
+        let thursday = free_slots(&professional("prof-1"), &treatment(15, 0), &site("site-1"), date().succ_opt().unwrap(), bogota(), &HashSet::new(), 15);
+        assert!(thursday.is_empty());
+    }
+
+    #[test]
+    fn test_spring_forward_shortens_the_shift() {
+        // 2025-03-09: a las 02:00 EST el reloj salta a 03:00 EDT; 01:00-04:00 son 2 horas reales
+        let slots = free_slots(&sunday_shift("01:00", "04:00"), &treatment(60, 0), &site("site-1"),
+            NaiveDate::from_ymd_opt(2025, 3, 9).unwrap(), new_york(), &HashSet::new(), 60);
+        assert_eq!(all_starts(&slots), ["2025-03-09T01:00:00-05:00", "2025-03-09T03:00:00-04:00"]);
+    }
+
+    #[test]
+    fn test_fall_back_offers_the_repeated_hour_twice() {
+        // 2025-11-02: a las 02:00 EDT el reloj vuelve a 01:00 EST; 00:00-03:00 son 4 horas reales
+        let slots = free_slots(&sunday_shift("00:00", "03:00"), &treatment(60, 0), &site("site-1"),
+            NaiveDate::from_ymd_opt(2025, 11, 2).unwrap(), new_york(), &HashSet::new(), 60);
+        assert_eq!(all_starts(&slots), [
+            "2025-11-02T00:00:00-04:00",
+            "2025-11-02T01:00:00-04:00",
+            "2025-11-02T01:00:00-05:00",
+            "2025-11-02T02:00:00-05:00",
+        ]);
+    }
+
+    #[test]
+    fn test_slots_respect_site_opening_hours() {
+        let open = Site {
+            opening_hours: serde_json::from_value(json!({"days": [{"weekday": "wed", "start": "09:30", "end": "10:30"}]})).unwrap(),
+            ..site("site-1")
+        };
+        let slots = free_slots(&professional("prof-1"), &treatment(15, 0), &open, date(), bogota(), &HashSet::new(), 15);
+        assert_eq!(starts(&slots), ["09:30", "09:45", "10:15"]);
+    }
+
+    async fn seeded_store() -> MemoryStore {
+        let store = MemoryStore::new();
+        store.put_tenant(&tenant("tenant-a")).await.unwrap();
+        store.put_site(&site("site-1")).await.unwrap();
+        store.put_treatment(&treatment(30, 0)).await.unwrap();
+        store.put_professional(&professional("prof-1")).await.unwrap();
+        // Atiende en site-1 según el horario, pero está asignado a otra sede
+        store.put_professional(&Professional { site_ids: vec!["site-2".into()], ..professional("prof-2") }).await.unwrap();
+        store
+    }
+
+    /// Inicios locales (`HH:MM`) que devuelve el endpoint para el 2025-10-01.
+    async fn available_starts(store: &MemoryStore) -> Vec<String> {
+        let mut request = Request::new(Body::from(json!({
+            "site_id": "site-1",
+            "treatment_id": "treat-1",
+            "date": "2025-10-01"
+        }).to_string()));
+        *request.method_mut() = Method::POST;
+        *request.uri_mut() = "/booking/availability".parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Paciente"], "ana@example.com").parse().unwrap());
+
+        let response = handler(store, request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        body["slots"].as_array().unwrap().iter().map(|s| s["start"].as_str().unwrap()[11..16].to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_availability_excludes_booked_units() {
+        let store = seeded_store().await;
+        let lock = SlotLock {
+            tenant_id: "tenant-a".into(),
+            site_id: "site-1".into(),
+            date: "2025-10-01".into(),
+            time: "14:15".into(),
+            professional_id: "prof-1".into(),
+            booking_id: "b1".into(),
+            status: "reserved".into(),
+            created_at: String::new(),
+            hold_token: None,
+            expires_at: None,
+        };
+        let booking = shared_lib::models::Booking {
+            start_time: "2025-10-01T14:15:00Z".into(),
+            end_time: "2025-10-01T14:30:00Z".into(),
+            ..booking(BookingStatus::Confirmed)
+        };
+        let event = shared_lib::models::BookingEvent::created(&booking, "2025-09-30T12:00:00Z");
+        store.create_booking(&booking, None, &[lock], &event).await.unwrap();
+
+        assert_eq!(available_starts(&store).await, ["09:30", "10:15", "10:30"]);
+    }
+
+    #[tokio::test]
+    async fn test_expired_hold_frees_the_slot_before_ttl_deletion() {
+        let store = seeded_store().await;
+        let free = available_starts(&store).await;
+        let now = chrono::Utc::now().timestamp();
+        let hold = |id: &str, expires_at: i64| SlotHold {
+            id: id.into(),
+            tenant_id: "tenant-a".into(),
+            site_id: "site-1".into(),
+            professional_id: "prof-1".into(),
+            treatment_id: "treat-1".into(),
+            start_time: "2025-10-01T14:15:00Z".into(),
+            end_time: "2025-10-01T14:30:00Z".into(),
+            token: format!("token-{}", id),
+            expires_at,
+            created_by: "ana@example.com".into(),
+            created_at: String::new(),
+        };
+
+        let expired = hold("h1", now - 1);
+        store.create_hold(&expired, &hold_locks(&expired).unwrap()).await.unwrap();
+        assert_eq!(available_starts(&store).await, free);
+
+        // Otra retención puede tomar la unidad vencida; mientras esté vigente, ocupa
+        let active = hold("h2", now + 600);
+        store.create_hold(&active, &hold_locks(&active).unwrap()).await.unwrap();
+        assert_eq!(available_starts(&store).await, ["09:30", "10:15", "10:30"]);
+    }
+
+    #[tokio::test]
+    async fn test_booking_at_another_site_takes_the_professional() {
+        let store = seeded_store().await;
+        let free = available_starts(&store).await;
+        // 09:00 en Bogotá (14:00 UTC), retenida en otra sede donde también atiende
+        let elsewhere = SlotHold {
+            id: "h1".into(),
+            tenant_id: "tenant-a".into(),
+            site_id: "site-2".into(),
+            professional_id: "prof-1".into(),
+            treatment_id: "treat-1".into(),
+            start_time: "2025-10-01T14:00:00Z".into(),
+            end_time: "2025-10-01T14:15:00Z".into(),
+            token: "token-h1".into(),
+            expires_at: chrono::Utc::now().timestamp() + 600,
+            created_by: "ana@example.com".into(),
+            created_at: String::new(),
+        };
+        store.create_hold(&elsewhere, &hold_locks(&elsewhere).unwrap()).await.unwrap();
+
+        let expected: Vec<String> = free.into_iter().filter(|start| start != "09:00").collect();
+        assert_eq!(available_starts(&store).await, expected);
+    }
 }
@@ -0,0 +1,70 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use shared_lib::reminders::{ReminderRequest, ReminderScheduler};
+
+/// Efectos de un evento de reserva. Todas las operaciones deben tolerar repetirse:
+/// la entrega es at-least-once y un reintento puede volver a ejecutarlas.
+#[async_trait]
+pub trait Dispatcher: Send + Sync {
+    /// Encola el payload para `send-notification`.
+    async fn send_notification(&self, payload: &Value) -> anyhow::Result<()>;
+
+    /// Programa los recordatorios; los que ya existen se actualizan.
+    async fn schedule_reminders(&self, request: &ReminderRequest) -> anyhow::Result<()>;
+
+    /// Reemplaza los recordatorios de la reserva por los de su nueva hora.
+    async fn reschedule_reminders(&self, request: &ReminderRequest) -> anyhow::Result<()>;
+
+    async fn cancel_reminders(&self, booking_id: &str) -> anyhow::Result<()>;
+}
+
+/// Notificaciones por la cola SQS de `send-notification` y recordatorios con
+/// EventBridge Scheduler.
+pub struct AwsDispatcher {
+    sqs: aws_sdk_sqs::Client,
+    queue_url: String,
+    reminders: ReminderScheduler,
+}
+
+impl AwsDispatcher {
+    /// Requiere `NOTIFICATIONS_QUEUE_URL` además de las variables de `ReminderScheduler`.
+    pub async fn from_env() -> anyhow::Result<Self> {
+        let queue_url = std::env::var("NOTIFICATIONS_QUEUE_URL")
+            .map_err(|_| anyhow::anyhow!("NOTIFICATIONS_QUEUE_URL no configurado"))?;
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Ok(Self {
+            sqs: aws_sdk_sqs::Client::new(&config),
+            queue_url,
+            reminders: ReminderScheduler::from_env().await?,
+        })
+    }
+}
+
+#[async_trait]
+impl Dispatcher for AwsDispatcher {
+    async fn send_notification(&self, payload: &Value) -> anyhow::Result<()> {
+        self.sqs
+            .send_message()
+            .queue_url(&self.queue_url)
+            .message_body(payload.to_string())
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("SQS error: {}", e))?;
+        Ok(())
+    }
+
+    async fn schedule_reminders(&self, request: &ReminderRequest) -> anyhow::Result<()> {
+        self.reminders.schedule(request).await?;
+        Ok(())
+    }
+
+    async fn reschedule_reminders(&self, request: &ReminderRequest) -> anyhow::Result<()> {
+        self.reminders.reschedule(request).await?;
+        Ok(())
+    }
+
+    async fn cancel_reminders(&self, booking_id: &str) -> anyhow::Result<()> {
+        self.reminders.cancel(booking_id).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,484 @@
+//! Consumidor del stream de la tabla principal. Cada cambio de reserva escribe
+//! un evento `BOOKING#id` / `EVENT#...` en su misma transacción (outbox); aquí
+//! se convierte en notificaciones y recordatorios.
+//!
+//! La entrega del stream es at-least-once: cada evento se toma por su id antes
+//! de procesarlo y se marca como hecho al terminar, así que una entrega repetida
+//! se descarta. Ante un fallo se reporta ese registro en `batchItemFailures` y
+//! Lambda reintenta desde él, sin perder el orden del shard.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use lambda_runtime::{run, service_fn, Error, LambdaEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use shared_lib::models::{preferred_channels, Booking, BookingEvent, BookingEventType, BookingStatus, Channel, Tenant};
+use shared_lib::reminders::{ReminderRequest, ReminderRule};
+use shared_lib::timezone::{Tz, DEFAULT_TIMEZONE};
+use shared_lib::{init_tracing, DynamoStore, Repository, StoreError};
+
+mod dispatch;
+
+use dispatch::{AwsDispatcher, Dispatcher};
+
+/// Cuánto puede tardar una ejecución con un evento tomado antes de que otra lo retome.
+const LEASE_SECONDS: i64 = 120;
+
+#[derive(Debug, Deserialize)]
+struct StreamEvent {
+    #[serde(rename = "Records", default)]
+    records: Vec<StreamRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamRecord {
+    #[serde(rename = "eventName", default)]
+    event_name: String,
+    dynamodb: StreamPayload,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamPayload {
+    #[serde(rename = "NewImage", default)]
+    new_image: HashMap<String, Value>,
+    #[serde(rename = "SequenceNumber", default)]
+    sequence_number: String,
+}
+
+impl StreamRecord {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.dynamodb.new_image.get(name)?.get("S")?.as_str()
+    }
+
+    /// Solo las inserciones de eventos del outbox; el resto del stream se ignora.
+    fn is_outbox_insert(&self) -> bool {
+        self.event_name == "INSERT" && self.attr("SK").is_some_and(|sk| sk.starts_with("EVENT#"))
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct BatchResponse {
+    #[serde(rename = "batchItemFailures")]
+    batch_item_failures: Vec<BatchItemFailure>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchItemFailure {
+    #[serde(rename = "itemIdentifier")]
+    item_identifier: String,
+}
+
+async fn handler(repo: &dyn Repository, dispatcher: &dyn Dispatcher, event: LambdaEvent<Value>) -> Result<BatchResponse, Error> {
+    let stream: StreamEvent = serde_json::from_value(event.payload)?;
+    let mut response = BatchResponse::default();
+
+    for record in stream.records.iter().filter(|r| r.is_outbox_insert()) {
+        // Un payload ilegible no se arregla reintentando: se registra y se sigue
+        let Some(booking_event) = record.attr("payload").and_then(|raw| serde_json::from_str::<BookingEvent>(raw).ok()) else {
+            tracing::error!(sequence_number = %record.dynamodb.sequence_number, "Evento de outbox ilegible");
+            continue;
+        };
+        if let Err(e) = process_event(repo, dispatcher, &booking_event).await {
+            tracing::error!(error = %e, event_id = %booking_event.id, "Falló el procesamiento del evento");
+            // Lambda reintenta desde aquí; lo ya procesado se descarta por id
+            response.batch_item_failures.push(BatchItemFailure { item_identifier: record.dynamodb.sequence_number.clone() });
+            break;
+        }
+    }
+    Ok(response)
+}
+
+async fn process_event(repo: &dyn Repository, dispatcher: &dyn Dispatcher, event: &BookingEvent) -> anyhow::Result<()> {
+    let now = Utc::now().timestamp();
+    match repo.claim_event(&event.id, now, now + LEASE_SECONDS).await {
+        Ok(()) => {}
+        Err(StoreError::ConditionFailed) if repo.is_event_processed(&event.id).await? => {
+            tracing::info!(event_id = %event.id, "Evento ya procesado; se descarta");
+            return Ok(());
+        }
+        Err(StoreError::ConditionFailed) => anyhow::bail!("El evento {} está en proceso en otra ejecución", event.id),
+        Err(e) => return Err(e.into()),
+    }
+
+    match dispatch(repo, dispatcher, event).await {
+        Ok(()) => {
+            repo.complete_event(&event.id).await?;
+            tracing::info!(event_id = %event.id, event_type = event.event_type.as_str(), booking_id = %event.booking.id, "Evento procesado");
+            Ok(())
+        }
+        Err(e) => {
+            if let Err(release) = repo.release_event(&event.id).await {
+                tracing::warn!(error = %release, event_id = %event.id, "No se pudo liberar el evento; se retomará al vencer el lease");
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Qué hacer con los recordatorios de la reserva.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reminders {
+    Schedule,
+    Reschedule,
+    Cancel,
+}
+
+/// Qué hacer con cada evento.
+#[derive(Debug, PartialEq)]
+struct Plan {
+    notification: Option<&'static str>,
+    reminders: Option<Reminders>,
+}
+
+fn plan(event: &BookingEvent) -> Plan {
+    let status = event.booking.status;
+    let (notification, reminders) = match event.event_type {
+        // Las reservas pendientes esperan a que la clínica las confirme
+        BookingEventType::Created if status == BookingStatus::Confirmed => (Some("confirmation"), Some(Reminders::Schedule)),
+        BookingEventType::StatusChanged if status == BookingStatus::Confirmed => (Some("confirmation"), Some(Reminders::Schedule)),
+        BookingEventType::StatusChanged if status.is_cancelled() => (Some("cancellation"), Some(Reminders::Cancel)),
+        // Una pendiente todavía no tiene recordatorios que mover
+        BookingEventType::Rescheduled if status == BookingStatus::Confirmed => (Some("rescheduled"), Some(Reminders::Reschedule)),
+        BookingEventType::Rescheduled => (Some("rescheduled"), None),
+        _ => (None, None),
+    };
+    Plan { notification, reminders }
+}
+
+async fn dispatch(repo: &dyn Repository, dispatcher: &dyn Dispatcher, event: &BookingEvent) -> anyhow::Result<()> {
+    let plan = plan(event);
+    if plan.notification.is_none() && plan.reminders.is_none() {
+        return Ok(());
+    }
+    let booking = &event.booking;
+    let BookingDetails { tz, reminder_rules, channels, details } = booking_details(repo, booking).await?;
+    let appointment = DateTime::parse_from_rfc3339(&booking.start_time)?.with_timezone(&Utc);
+
+    // Los recordatorios primero: son idempotentes por nombre, así que si la
+    // notificación falla el reintento no los duplica
+    let reminder_request = || ReminderRequest {
+        booking_id: booking.id.clone(),
+        patient_email: booking.patient_email.clone(),
+        patient_name: booking.patient_name.clone(),
+        patient_phone: booking.patient_phone.clone(),
+        preferred_channels: preferred_channels(&booking.notification_channels, &channels, booking.patient_phone.as_deref()),
+        appointment,
+        tz,
+        rules: reminder_rules.clone(),
+        details: details.clone(),
+    };
+    match plan.reminders {
+        Some(Reminders::Schedule) => dispatcher.schedule_reminders(&reminder_request()).await?,
+        Some(Reminders::Reschedule) => dispatcher.reschedule_reminders(&reminder_request()).await?,
+        Some(Reminders::Cancel) => dispatcher.cancel_reminders(&booking.id).await?,
+        None => {}
+    }
+    if let Some(notification_type) = plan.notification {
+        let mut payload = details;
+        payload.extend([
+            ("type".to_string(), notification_type.into()),
+            ("event_id".to_string(), event.id.clone().into()),
+            ("booking_id".to_string(), booking.id.clone().into()),
+            ("patient_email".to_string(), booking.patient_email.clone().into()),
+            ("patient_name".to_string(), booking.patient_name.clone().into()),
+            ("timezone".to_string(), tz.name().into()),
+            // Para el evento de calendario adjunto
+            ("start_time".to_string(), booking.start_time.clone().into()),
+            ("end_time".to_string(), booking.end_time.clone().into()),
+            ("sequence".to_string(), calendar_sequence(repo, event).await?.into()),
+        ]);
+        payload.extend(local_date_time("appointment", appointment, tz));
+        if let Some(previous) = event.previous_start_time.as_deref().and_then(|raw| DateTime::parse_from_rfc3339(raw).ok()) {
+            payload.extend(local_date_time("previous_appointment", previous.with_timezone(&Utc), tz));
+        }
+        if let Some(phone) = &booking.patient_phone {
+            payload.insert("patient_phone".to_string(), phone.clone().into());
+        }
+        // Un mensaje por canal: cada uno se reintenta por separado. Si el evento
+        // se reintenta, send-notification descarta los canales ya enviados por
+        // su id (`event_id` y canal)
+        for channel in booking.notification_channels(&channels) {
+            payload.insert("channel".to_string(), channel.as_str().into());
+            dispatcher.send_notification(&Value::Object(payload.clone())).await?;
+        }
+    }
+    Ok(())
+}
+
+struct BookingDetails {
+    tz: Tz,
+    /// Política de recordatorios del tenant
+    reminder_rules: Vec<ReminderRule>,
+    /// Canales que habilita el tenant
+    channels: Vec<Channel>,
+    /// Datos de la clínica para las plantillas
+    details: Map<String, Value>,
+}
+
+/// Zona de la cita, recordatorios y canales del tenant y datos de la clínica
+/// para las plantillas. Lo que falte (un tratamiento borrado, p. ej.)
+/// simplemente no se incluye; sin tenant se usan la zona y la configuración
+/// por defecto.
+async fn booking_details(repo: &dyn Repository, booking: &Booking) -> Result<BookingDetails, StoreError> {
+    let tenant = repo.get_tenant(&booking.tenant_id).await?;
+    let site = repo.get_site(&booking.tenant_id, &booking.site_id).await?;
+    let professional = repo.get_professional(&booking.tenant_id, &booking.professional_id).await?;
+    let treatment = repo.get_treatment(&booking.tenant_id, &booking.treatment_id).await?;
+
+    let tenant_tz = tenant.as_ref().map_or(DEFAULT_TIMEZONE, Tenant::tz);
+    let tz = site.as_ref().map_or(tenant_tz, |site| site.tz(tenant_tz));
+    let clinic_email = site.as_ref().and_then(|site| site.contact_email.clone())
+        .or_else(|| tenant.as_ref().map(|tenant| tenant.contact_email.clone()));
+    let settings = tenant.as_ref().map(|tenant| tenant.settings.clone()).unwrap_or_default();
+
+    let details = [
+        // Para la lista de supresión y las preferencias del paciente
+        ("tenant_id", Some(booking.tenant_id.clone())),
+        ("professional_name", professional.map(|p| p.name)),
+        ("treatment_name", treatment.map(|t| t.name)),
+        ("clinic_address", site.map(|s| s.address)),
+        ("clinic_email", clinic_email),
+        ("clinic_name", tenant.as_ref().map(|tenant| tenant.name.clone())),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name.to_string(), Value::String(value?))))
+    .collect();
+    Ok(BookingDetails { tz, reminder_rules: settings.reminders, channels: settings.channels, details })
+}
+
+/// `SEQUENCE` del evento de calendario: cuántos cambios tuvo la reserva antes
+/// de este. Crece con cada cambio y no depende de reintentos.
+async fn calendar_sequence(repo: &dyn Repository, event: &BookingEvent) -> Result<usize, StoreError> {
+    let events = repo.list_booking_events(&event.booking.id).await?;
+    Ok(events.iter().position(|e| e.id == event.id).unwrap_or(events.len()))
+}
+
+fn local_date_time(prefix: &str, at: DateTime<Utc>, tz: Tz) -> [(String, Value); 2] {
+    let local = at.with_timezone(&tz);
+    [
+        (format!("{}_date", prefix), local.format("%Y-%m-%d").to_string().into()),
+        (format!("{}_time", prefix), local.format("%H:%M").to_string().into()),
+    ]
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    init_tracing();
+    let repo = DynamoStore::from_env();
+    let dispatcher = AwsDispatcher::from_env().await?;
+    run(service_fn(|event| handler(&repo, &dispatcher, event))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use shared_lib::models::{Professional, Site, TenantSettings, Treatment};
+    use shared_lib::repository::{ProfessionalRepository, SiteRepository, TenantRepository, TreatmentRepository};
+    use shared_lib::testing::{booking, tenant};
+    use shared_lib::MemoryStore;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct FakeDispatcher {
+        notifications: Mutex<Vec<Value>>,
+        reminders: Mutex<Vec<String>>,
+        /// Offsets de la política con la que se programó cada vez
+        reminder_offsets: Mutex<Vec<Vec<String>>>,
+        fail_notifications: AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl Dispatcher for FakeDispatcher {
+        async fn send_notification(&self, payload: &Value) -> anyhow::Result<()> {
+            if self.fail_notifications.load(Ordering::SeqCst) {
+                anyhow::bail!("SQS caído");
+            }
+            self.notifications.lock().unwrap().push(payload.clone());
+            Ok(())
+        }
+
+        async fn schedule_reminders(&self, request: &ReminderRequest) -> anyhow::Result<()> {
+            self.reminders.lock().unwrap().push(format!("schedule:{}", request.booking_id));
+            self.reminder_offsets.lock().unwrap().push(request.rules.iter().map(|r| r.offset.label()).collect());
+            Ok(())
+        }
+
+        async fn reschedule_reminders(&self, request: &ReminderRequest) -> anyhow::Result<()> {
+            self.reminders.lock().unwrap().push(format!("reschedule:{}", request.booking_id));
+            Ok(())
+        }
+
+        async fn cancel_reminders(&self, booking_id: &str) -> anyhow::Result<()> {
+            self.reminders.lock().unwrap().push(format!("cancel:{}", booking_id));
+            Ok(())
+        }
+    }
+
+    impl FakeDispatcher {
+        fn sent_types(&self) -> Vec<String> {
+            self.notifications.lock().unwrap().iter().map(|n| n["type"].as_str().unwrap().to_string()).collect()
+        }
+    }
+
+    async fn seeded_store() -> MemoryStore {
+        let store = MemoryStore::new();
+        store.put_tenant(&Tenant {
+            settings: TenantSettings {
+                reminders: vec![ReminderRule::hours_before(48), ReminderRule::hours_before(3)],
+                channels: vec![Channel::Email, Channel::Whatsapp],
+                ..Default::default()
+            },
+            ..tenant("tenant-a")
+        }).await.unwrap();
+        store.put_site(&Site {
+            id: "site-1".into(),
+            tenant_id: "tenant-a".into(),
+            name: "Sede".into(),
+            address: "Calle 100".into(),
+            timezone: None,
+            opening_hours: Default::default(),
+            chairs: 1,
+            contact_email: None,
+            status: "active".into(),
+            created_at: String::new(),
+        }).await.unwrap();
+        store.put_treatment(&Treatment {
+            id: "treat-1".into(),
+            tenant_id: "tenant-a".into(),
+            name: "Limpieza".into(),
+            duration_minutes: 30,
+            buffer_minutes: 10,
+            price: 0.0,
+            created_at: String::new(),
+        }).await.unwrap();
+        store.put_professional(&Professional {
+            id: "prof-1".into(),
+            tenant_id: "tenant-a".into(),
+            name: "Dra. Pérez".into(),
+            email: "perez@example.com".into(),
+            specialties: vec![],
+            site_ids: vec!["site-1".into()],
+            schedule: Default::default(),
+            status: "active".into(),
+            created_at: String::new(),
+        }).await.unwrap();
+        store
+    }
+
+    /// Registro del stream tal como lo entrega Lambda para un item del outbox.
+    fn record(sequence_number: &str, event: &BookingEvent) -> Value {
+        json!({
+            "eventName": "INSERT",
+            "dynamodb": {
+                "SequenceNumber": sequence_number,
+                "NewImage": {
+                    "PK": {"S": format!("BOOKING#{}", event.booking.id)},
+                    "SK": {"S": format!("EVENT#{}#{}", event.occurred_at, event.id)},
+                    "payload": {"S": serde_json::to_string(event).unwrap()}
+                }
+            }
+        })
+    }
+
+    async fn deliver(store: &MemoryStore, dispatcher: &FakeDispatcher, records: Vec<Value>) -> Vec<String> {
+        let event = LambdaEvent::new(json!({ "Records": records }), Default::default());
+        let response = handler(store, dispatcher, event).await.unwrap();
+        response.batch_item_failures.into_iter().map(|f| f.item_identifier).collect()
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_booking_notifies_once_and_schedules_reminders() {
+        let store = seeded_store().await;
+        let dispatcher = FakeDispatcher::default();
+        let created = BookingEvent::created(&booking(BookingStatus::Confirmed), "2025-09-30T12:00:00Z");
+
+        assert!(deliver(&store, &dispatcher, vec![record("1", &created)]).await.is_empty());
+        // Reentrega del mismo evento (at-least-once): se descarta por id
+        assert!(deliver(&store, &dispatcher, vec![record("1", &created)]).await.is_empty());
+
+        assert_eq!(*dispatcher.reminders.lock().unwrap(), ["schedule:b1"]);
+        assert_eq!(*dispatcher.reminder_offsets.lock().unwrap(), [["48h", "3h"]]);
+        let notifications = dispatcher.notifications.lock().unwrap();
+        assert_eq!(notifications.len(), 1);
+        let sent = &notifications[0];
+        assert_eq!(sent["type"], "confirmation");
+        assert_eq!(sent["event_id"], created.id.as_str());
+        // Hora local de la clínica (Bogotá, UTC-5) y datos para la plantilla
+        assert_eq!((sent["appointment_date"].as_str(), sent["appointment_time"].as_str()), (Some("2025-10-01"), Some("10:00")));
+        assert_eq!(sent["professional_name"], "Dra. Pérez");
+        assert_eq!(sent["treatment_name"], "Limpieza");
+        assert_eq!(sent["clinic_address"], "Calle 100");
+        assert_eq!(sent["clinic_email"], "a@example.com");
+        assert_eq!(sent["clinic_name"], "Clínica A");
+        // Datos del adjunto de calendario: horas UTC y primera revisión
+        assert_eq!(sent["start_time"], "2025-10-01T15:00:00+00:00");
+        assert_eq!(sent["sequence"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_failure_reports_the_record_and_retry_delivers_it() {
+        let store = seeded_store().await;
+        let dispatcher = FakeDispatcher::default();
+        let cancelled = BookingEvent::status_changed(&booking(BookingStatus::CancelledByPatient), BookingStatus::Confirmed, "2025-09-30T13:00:00Z");
+        let moved = BookingEvent::rescheduled(&booking(BookingStatus::Confirmed), "2025-10-01T14:00:00+00:00", "2025-09-30T14:00:00Z");
+        let batch = || vec![record("10", &cancelled), record("11", &moved)];
+
+        dispatcher.fail_notifications.store(true, Ordering::SeqCst);
+        // Se corta en el primero que falla para no procesar fuera de orden
+        assert_eq!(deliver(&store, &dispatcher, batch()).await, ["10"]);
+
+        dispatcher.fail_notifications.store(false, Ordering::SeqCst);
+        assert!(deliver(&store, &dispatcher, batch()).await.is_empty());
+        assert_eq!(dispatcher.sent_types(), ["cancellation", "rescheduled"]);
+        let notifications = dispatcher.notifications.lock().unwrap();
+        assert_eq!(notifications[1]["previous_appointment_time"], "09:00");
+        assert_eq!(notifications[1]["appointment_time"], "10:00");
+        // La cancelación se reintentó completa: borrar recordatorios es idempotente
+        assert_eq!(*dispatcher.reminders.lock().unwrap(), ["cancel:b1", "cancel:b1", "reschedule:b1"]);
+    }
+
+    #[tokio::test]
+    async fn test_pending_bookings_and_other_items_are_ignored() {
+        let store = seeded_store().await;
+        let dispatcher = FakeDispatcher::default();
+        let pending = BookingEvent::created(&booking(BookingStatus::Pending), "2025-09-30T12:00:00Z");
+        let mut booking_item = record("2", &pending);
+        booking_item["dynamodb"]["NewImage"]["SK"] = json!({"S": "METADATA"});
+        let mut modify = record("3", &pending);
+        modify["eventName"] = json!("MODIFY");
+
+        assert!(deliver(&store, &dispatcher, vec![record("1", &pending), booking_item, modify]).await.is_empty());
+        assert!(dispatcher.sent_types().is_empty());
+        assert!(dispatcher.reminders.lock().unwrap().is_empty());
+
+        // Al confirmarla la clínica sí se notifica
+        let confirmed = BookingEvent::status_changed(&booking(BookingStatus::Confirmed), BookingStatus::Pending, "2025-09-30T12:30:00Z");
+        assert!(deliver(&store, &dispatcher, vec![record("4", &confirmed)]).await.is_empty());
+        assert_eq!(dispatcher.sent_types(), ["confirmation"]);
+    }
+
+    #[tokio::test]
+    async fn test_one_notification_per_channel_of_the_patient() {
+        let store = seeded_store().await;
+        let dispatcher = FakeDispatcher::default();
+        let channels = || dispatcher.notifications.lock().unwrap().iter().map(|n| n["channel"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+
+        // Con teléfono y sin preferencias: todos los canales de la clínica
+        let mut with_phone = booking(BookingStatus::Confirmed);
+        with_phone.patient_phone = Some("+573001234567".into());
+        let created = BookingEvent::created(&with_phone, "2025-09-30T12:00:00Z");
+        assert!(deliver(&store, &dispatcher, vec![record("1", &created)]).await.is_empty());
+        assert_eq!(channels(), ["email", "whatsapp"]);
+        assert_eq!(dispatcher.notifications.lock().unwrap()[1]["patient_phone"], "+573001234567");
+
+        // Solo WhatsApp; SMS no lo habilita la clínica
+        with_phone.notification_channels = vec![Channel::Sms, Channel::Whatsapp];
+        with_phone.status = BookingStatus::CancelledByPatient;
+        let cancelled = BookingEvent::status_changed(&with_phone, BookingStatus::Confirmed, "2025-09-30T13:00:00Z");
+        assert!(deliver(&store, &dispatcher, vec![record("2", &cancelled)]).await.is_empty());
+        assert_eq!(channels(), ["email", "whatsapp", "whatsapp"]);
+    }
+}
@@ -0,0 +1,38 @@
+//! Pasa los slot locks de las reservas futuras del esquema por sede
+//! (`TENANT#t#SITE#s#DATE#d` / `SLOT#HH:MM#prof`) al esquema por profesional
+//! (`TENANT#t#PROF#p#DATE#d` / `SLOT#HH:MM`). Se ejecuta una vez por tenant,
+//! antes de desplegar la versión que lee los locks por profesional, desde una
+//! máquina con acceso a la tabla (TABLE_NAME y credenciales de AWS en el entorno):
+//!
+//! ```text
+//! cargo run --bin migrate-slot-locks -- <tenant_id> [--dry-run]
+//! ```
+//!
+//! Cada reserva queda con un lock por unidad, también las que solo tenían el
+//! de su hora de inicio.
+//!
+//! Repetirlo no duplica nada: las reservas sin locks viejos se saltan. Las
+//! reservas en `conflicts` (un profesional con dos citas a la misma hora en
+//! sedes distintas) quedan como estaban y hay que reprogramar una de ellas.
+
+use lambda_http::Error;
+use shared_lib::slots::migrate_slot_locks;
+use shared_lib::{init_tracing, DynamoStore};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    init_tracing();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let Some(tenant_id) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        return Err("uso: migrate-slot-locks <tenant_id> [--dry-run]".into());
+    };
+
+    let repo = DynamoStore::from_env();
+    let now = chrono::Utc::now().to_rfc3339();
+    let report = migrate_slot_locks(&repo, tenant_id, dry_run, &now).await?;
+
+    tracing::info!(tenant_id = %tenant_id, dry_run, ?report, "Slot lock migration finished");
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! Máquina de estados de una reserva.
+//!
+//! ```text
+//! pending ──confirm──▶ confirmed ──check-in──▶ checked_in ──start──▶ in_progress ──complete──▶ completed
+//!    │                     │                        │
+//!    └──────cancel─────────┴──────cancel────────────┘──▶ cancelled_by_patient | cancelled_by_clinic
+//!                          └──no-show──▶ no_show
+//! ```
+//!
+//! `completed`, `no_show` y las cancelaciones son finales.
+
+use shared_lib::models::BookingStatus::{self, *};
+use shared_lib::{Action, ApiError, Scope};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Confirm,
+    CheckIn,
+    Start,
+    Complete,
+    NoShow,
+    Cancel,
+}
+
+impl Transition {
+    /// Segmento de ruta: `POST /bookings/{id}/<segmento>`.
+    pub fn from_path(segment: &str) -> Option<Transition> {
+        match segment {
+            "confirm" => Some(Transition::Confirm),
+            "check-in" => Some(Transition::CheckIn),
+            "start" => Some(Transition::Start),
+            "complete" => Some(Transition::Complete),
+            "no-show" => Some(Transition::NoShow),
+            "cancel" => Some(Transition::Cancel),
+            _ => None,
+        }
+    }
+
+    /// Cancelar equivale a borrar (lo que ya permitía `DELETE /bookings/{id}`);
+    /// el resto son actualizaciones.
+    pub fn action(&self) -> Action {
+        match self {
+            Transition::Cancel => Action::Delete,
+            _ => Action::Update,
+        }
+    }
+
+    fn allowed_from(&self) -> &'static [BookingStatus] {
+        match self {
+            Transition::Confirm => &[Pending],
+            Transition::CheckIn => &[Confirmed],
+            Transition::Start => &[CheckedIn],
+            Transition::Complete => &[InProgress],
+            Transition::NoShow => &[Confirmed],
+            Transition::Cancel => &[Pending, Confirmed, CheckedIn],
+        }
+    }
+
+    /// Estado destino. La cancelación de un paciente (alcance `Own`) queda como
+    /// `cancelled_by_patient`; la del personal, como `cancelled_by_clinic`.
+    fn target(&self, scope: Scope) -> BookingStatus {
+        match self {
+            Transition::Confirm => Confirmed,
+            Transition::CheckIn => CheckedIn,
+            Transition::Start => InProgress,
+            Transition::Complete => Completed,
+            Transition::NoShow => NoShow,
+            Transition::Cancel if scope == Scope::Own => CancelledByPatient,
+            Transition::Cancel => CancelledByClinic,
+        }
+    }
+}
+
+/// Estado al que lleva `transition` desde `current`, o `Conflict` si no es válida.
+/// Los pacientes solo pueden cancelar; el resto es operación de la clínica.
+pub fn next_status(current: BookingStatus, transition: Transition, scope: Scope) -> Result<BookingStatus, ApiError> {
+    if scope == Scope::Own && transition != Transition::Cancel {
+        return Err(ApiError::Forbidden("Solo la clínica puede cambiar este estado".into()));
+    }
+    if !transition.allowed_from().contains(&current) {
+        return Err(ApiError::Conflict(format!(
+            "Transición inválida: {:?} no se permite desde {}",
+            transition,
+            current.as_str()
+        )));
+    }
+    Ok(transition.target(scope))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_happy_path_reaches_completed() {
+        let mut status = Pending;
+        for t in [Transition::Confirm, Transition::CheckIn, Transition::Start, Transition::Complete] {
+            status = next_status(status, t, Scope::Tenant).unwrap();
+        }
+        assert_eq!(status, Completed);
+    }
+
+    #[test]
+    fn test_final_states_reject_every_transition() {
+        let all = [Transition::Confirm, Transition::CheckIn, Transition::Start, Transition::Complete, Transition::NoShow, Transition::Cancel];
+        for status in [Completed, NoShow, CancelledByPatient, CancelledByClinic] {
+            for t in all {
+                assert!(matches!(next_status(status, t, Scope::Tenant), Err(ApiError::Conflict(_))), "{:?} {:?}", status, t);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cancel_records_who_cancelled() {
+        assert_eq!(next_status(Confirmed, Transition::Cancel, Scope::Own).unwrap(), CancelledByPatient);
+        assert_eq!(next_status(Pending, Transition::Cancel, Scope::Tenant).unwrap(), CancelledByClinic);
+        assert!(matches!(next_status(InProgress, Transition::Cancel, Scope::Tenant), Err(ApiError::Conflict(_))));
+        assert!(matches!(next_status(Confirmed, Transition::CheckIn, Scope::Own), Err(ApiError::Forbidden(_))));
+    }
+}
@@ -1,10 +1,20 @@
-use lambda_http::{run, service_fn, Body, Request, Response, Error, RequestPayloadExt};
-use serde::{Deserialize, Serialize};
+use lambda_http::{run, service_fn, Body, Request, RequestExt, Response, Error, RequestPayloadExt};
+use serde::Deserialize;
 use validator::Validate;
-use shared_lib::{init_tracing, success_response, created_response, ApiError, get_client, table_name, require_tenant};
-use aws_sdk_dynamodb::types::AttributeValue;
+use shared_lib::{init_tracing, success_response, created_response, idempotent, ApiError, parse_jwt_claims, authorize, Action, Resource, Scope, JwtClaims};
+use shared_lib::models::{validate_phone, Booking, BookingEvent, BookingStatus, Channel, Patient, Professional, Site, SlotHold, StatusChange, Treatment};
+use shared_lib::patients::{find_match, new_patient, PatientContact};
+use shared_lib::slots::{booking_locks, hold_locks, hold_minutes, within_shifts};
+use shared_lib::repository::{BookingFilter, PageRequest};
+use shared_lib::timezone::{local_day_bounds, parse_instant, Tz};
+use shared_lib::{DynamoStore, Repository};
+use chrono::{DateTime, NaiveDate, Utc};
 use uuid::Uuid;
 
+mod lifecycle;
+
+use lifecycle::{next_status, Transition};
+
 #[derive(Debug, Deserialize, Validate)]
 struct CreateBookingRequest {
     #[validate(length(min = 1, max = 50))]
@@ -19,40 +29,58 @@ struct CreateBookingRequest {
     #[validate(length(min = 1, max = 50))]
     treatment_id: String,
     
-    start_time: String, // ISO8601
+    start_time: String, // ISO8601; sin offset = hora local de la clínica
     
-    patient_name: String,
-    patient_email: String,
+    #[serde(flatten)]
+    patient: PatientDetails,
+    /// Canales por los que el paciente quiere recibir las notificaciones
+    #[serde(default)]
+    notification_channels: Vec<Channel>,
 }
 
-#[derive(Debug, Serialize)]
-struct Booking {
-    id: String,
-    tenant_id: String,
-    site_id: String,
-    professional_id: String,
-    treatment_id: String,
-    start_time: String,
-    end_time: String,
+/// El paciente de una reserva: uno del directorio (`patient_id`) o sus datos,
+/// con los que se busca o se da de alta en el directorio.
+#[derive(Debug, Default, Deserialize)]
+struct PatientDetails {
+    #[serde(default)]
+    patient_id: Option<String>,
+    /// Vacíos con `patient_id`: se toman del paciente
+    #[serde(default)]
     patient_name: String,
+    #[serde(default)]
     patient_email: String,
-    status: String,
-    created_at: String,
+    #[serde(default)]
+    patient_phone: Option<String>,
+    /// Documento de identidad; identifica al paciente aunque cambie su contacto
+    #[serde(default)]
+    patient_document: Option<String>,
 }
 
-async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let result: Result<Response<Body>, ApiError> = (|| async {
-        let method = req.method().as_str();
-        let path = req.uri().path();
+async fn handler(repo: &dyn Repository, req: Request) -> Result<Response<Body>, Error> {
+    let result: Result<Response<Body>, ApiError> = async {
+        let method = req.method().as_str().to_string();
+        let path = req.uri().path().to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
         
-        match (method, path) {
-            ("POST", "/bookings") => create_booking(req).await,
-            ("GET", "/bookings") => list_bookings(req).await,
-            ("DELETE", path) if path.starts_with("/bookings/") => cancel_booking(req).await,
-            ("PUT", path) if path.starts_with("/bookings/") => update_booking(req).await,
+        match (method.as_str(), segments.as_slice()) {
+            // Las mutaciones aceptan Idempotency-Key: un reintento repite la respuesta
+            ("POST", ["bookings"]) => idempotent(repo, &req, || create_booking(repo, &req)).await,
+            ("POST", ["bookings", "holds"]) => idempotent(repo, &req, || create_hold(repo, &req)).await,
+            ("POST", ["bookings", "holds", id, "confirm"]) => idempotent(repo, &req, || confirm_hold(repo, &req, id)).await,
+            ("GET", ["bookings"]) => list_bookings(repo, req).await,
+            ("GET", ["bookings", id]) => get_booking(repo, &req, id).await,
+            ("GET", ["bookings", id, "history"]) => booking_history(repo, &req, id).await,
+            ("GET", ["bookings", id, "notifications"]) => booking_notifications(repo, &req, id).await,
+            // DELETE se mantiene como alias de POST /bookings/{id}/cancel
+            ("DELETE", ["bookings", id]) => idempotent(repo, &req, || change_status(repo, &req, id, Transition::Cancel)).await,
+            ("PUT", ["bookings", id]) => idempotent(repo, &req, || update_booking(repo, &req, id)).await,
+            ("POST", ["bookings", id, action]) => match Transition::from_path(action) {
+                Some(transition) => idempotent(repo, &req, || change_status(repo, &req, id, transition)).await,
+                None => Err(ApiError::NotFound("Ruta no encontrada".into())),
+            },
             _ => Err(ApiError::NotFound("Método no soportado".into()))
         }
-    })().await;
+    }.await;
     
     match result {
         Ok(resp) => Ok(resp),
@@ -60,253 +88,422 @@ async fn handler(req: Request) -> Result<Response<Body>, Error> {
     }
 }
 
-async fn create_booking(req: Request) -> Result<Response<Body>, ApiError> {
+async fn create_booking(repo: &dyn Repository, req: &Request) -> Result<Response<Body>, ApiError> {
     let payload = req.payload::<CreateBookingRequest>()?
         .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
     
     payload.validate()
         .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
     
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Create, Resource::Booking)?;
+
     // Enforce multitenancy: tenant del token debe coincidir con el payload
-    let tenant_from_token = require_tenant(&req)?;
+    let tenant_from_token = claims.require_tenant()?;
     if tenant_from_token != payload.tenant_id {
         return Err(ApiError::Forbidden("tenant_id del payload no coincide con el token".into()));
     }
 
     let booking_id = Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
+    let (start, end) = resolve_slot(repo, &tenant_from_token, &payload.site_id, &payload.professional_id, &payload.treatment_id, &payload.start_time).await?;
+    let mut patient = payload.patient;
+    let (patient_id, new_patient) = resolve_patient(repo, &tenant_from_token, scope, &claims, &mut patient, &payload.notification_channels, &now).await?;
     
-    // Parse start_time
-    let start = chrono::DateTime::parse_from_rfc3339(&payload.start_time)
-        .map_err(|_| ApiError::Validation("start_time inválido (usar ISO8601)".into()))?;
-    // Obtener duración y buffer desde el tratamiento
-    let treatment_minutes = fetch_treatment_duration_minutes(&tenant_from_token, &payload.treatment_id).await?;
-    let buffer_minutes = fetch_treatment_buffer_minutes(&tenant_from_token, &payload.treatment_id).await?;
-    let end = start + chrono::Duration::minutes((treatment_minutes + buffer_minutes) as i64);
-    
-    let client = get_client().await;
-    
-    // Reserva atómica con ConditionExpression
-    // PK=TENANT#tid#SITE#sid#DATE#2025-09-30, SK=SLOT#10:00#prof-123
-    let slot_pk = format!("TENANT#{}#SITE#{}#DATE#{}", 
-        tenant_from_token, payload.site_id, start.format("%Y-%m-%d"));
-    let slot_sk = format!("SLOT#{}#{}", start.format("%H:%M"), payload.professional_id);
-    
-    let transact_result = client.transact_write_items()
-        .transact_items(
-            aws_sdk_dynamodb::types::TransactWriteItem::builder()
-                .put(
-                    aws_sdk_dynamodb::types::Put::builder()
-                        .table_name(table_name())
-                        .item("PK", AttributeValue::S(slot_pk.clone()))
-                        .item("SK", AttributeValue::S(slot_sk.clone()))
-                        .item("bookingId", AttributeValue::S(booking_id.clone()))
-                        .item("status", AttributeValue::S("reserved".to_string()))
-                        .item("createdAt", AttributeValue::S(now.clone()))
-                        .condition_expression("attribute_not_exists(PK)")
-                        .build()
-                        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-                )
-                .build()
-        )
-        .transact_items(
-            aws_sdk_dynamodb::types::TransactWriteItem::builder()
-                .put(
-                    aws_sdk_dynamodb::types::Put::builder()
-                        .table_name(table_name())
-                        .item("PK", AttributeValue::S(format!("BOOKING#{}", booking_id)))
-                        .item("SK", AttributeValue::S("METADATA".to_string()))
-                        .item("GSI1PK", AttributeValue::S(format!("TENANT#{}", tenant_from_token)))
-                        .item("GSI1SK", AttributeValue::S(format!("BOOKING#{}", booking_id)))
-                        .item("GSI3PK", AttributeValue::S(format!("PROFESSIONAL#{}", payload.professional_id)))
-                        .item("GSI3SK", AttributeValue::S(start.to_rfc3339()))
-                        .item("id", AttributeValue::S(booking_id.clone()))
-                        .item("tenantId", AttributeValue::S(tenant_from_token.clone()))
-                        .item("siteId", AttributeValue::S(payload.site_id.clone()))
-                        .item("professionalId", AttributeValue::S(payload.professional_id.clone()))
-                        .item("treatmentId", AttributeValue::S(payload.treatment_id.clone()))
-                        .item("startTime", AttributeValue::S(start.to_rfc3339()))
-                        .item("endTime", AttributeValue::S(end.to_rfc3339()))
-                        .item("patientName", AttributeValue::S(payload.patient_name.clone()))
-                        .item("patientEmail", AttributeValue::S(payload.patient_email.clone()))
-                        .item("status", AttributeValue::S("confirmed".to_string()))
-                        .item("createdAt", AttributeValue::S(now.clone()))
-                        .build()
-                        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-                )
-                .build()
-        )
-        .send()
-        .await;
-    
-    match transact_result {
-        Ok(_) => {
-            tracing::info!(booking_id = %booking_id, "Booking created atomically");
-            
-            let booking = Booking {
-                id: booking_id,
-                tenant_id: tenant_from_token,
-                site_id: payload.site_id,
-                professional_id: payload.professional_id,
-                treatment_id: payload.treatment_id,
-                start_time: start.to_rfc3339(),
-                end_time: end.to_rfc3339(),
-                patient_name: payload.patient_name,
-                patient_email: payload.patient_email,
-                status: "confirmed".into(),
-                created_at: now,
-            };
-            
-            created_response(booking)
-        }
-        Err(e) => {
-            if e.to_string().contains("ConditionalCheckFailed") {
-                Err(ApiError::Conflict("Slot no disponible (reservado por otro usuario)".into()))
-            } else {
-                Err(ApiError::Internal(anyhow::anyhow!("DynamoDB error: {}", e)))
-            }
-        }
+    let booking = Booking {
+        id: booking_id,
+        tenant_id: tenant_from_token,
+        site_id: payload.site_id,
+        professional_id: payload.professional_id,
+        treatment_id: payload.treatment_id,
+        start_time: start.to_rfc3339(),
+        end_time: end.to_rfc3339(),
+        patient_name: patient.patient_name,
+        patient_email: patient.patient_email,
+        patient_phone: patient.patient_phone,
+        notification_channels: payload.notification_channels,
+        patient_id,
+        // Las reservas del propio paciente esperan confirmación de la clínica
+        status: if scope == Scope::Own { BookingStatus::Pending } else { BookingStatus::Confirmed },
+        created_at: now.clone(),
+    };
+
+    // Reserva atómica: se reclama cada unidad de agenda que cubre [start, end);
+    // si cualquiera ya existe la transacción completa falla.
+    // PK=TENANT#tid#PROF#prof-123#DATE#2025-09-30, SK=SLOT#10:00 (en cualquier sede)
+    let locks = booking_locks(&booking, &now)?;
+    repo.create_booking(&booking, new_patient.as_ref(), &locks, &BookingEvent::created(&booking, &now)).await
+        .map_err(|e| e.conflict("Slot no disponible (reservado por otro usuario)"))?;
+    log_new_patient(new_patient.as_ref());
+
+    tracing::info!(booking_id = %booking.id, "Booking created atomically");
+    created_response(booking)
+}
+
+/// Valida sede, profesional y tratamiento y devuelve `[inicio, fin)` en UTC.
+async fn resolve_slot(
+    repo: &dyn Repository,
+    tenant_id: &str,
+    site_id: &str,
+    professional_id: &str,
+    treatment_id: &str,
+    start_time: &str,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), ApiError> {
+    // La sede debe existir y estar activa, y el profesional atender en ella
+    let site = repo.get_site(tenant_id, site_id).await?
+        .ok_or_else(|| ApiError::NotFound("Sede no encontrada".into()))?;
+    if !site.is_active() {
+        return Err(ApiError::Validation("La sede no está activa".into()));
+    }
+    let professional = repo.get_professional(tenant_id, professional_id).await?
+        .ok_or_else(|| ApiError::NotFound("Profesional no encontrado".into()))?;
+    if !professional.works_at(&site.id) {
+        return Err(ApiError::Validation("El profesional no atiende en esta sede".into()));
     }
+    
+    // start_time se interpreta en la zona de la sede y se guarda en UTC
+    let tz = clinic_tz(repo, tenant_id, Some(&site)).await?;
+    let start = parse_instant(start_time, tz)?;
+    // Duración y buffer salen de una sola lectura del tratamiento
+    let treatment = fetch_treatment(repo, tenant_id, treatment_id).await?;
+    let end = start + chrono::Duration::minutes(treatment.occupied_minutes());
+    ensure_within_schedule(&professional, &site, tz, start, end)?;
+    Ok((start, end))
 }
 
-async fn list_bookings(req: Request) -> Result<Response<Body>, ApiError> {
-    // Enforce multitenancy desde el token, ignorando tenant_id por query si difiere
-    let tenant_id = require_tenant(&req)?;
-    
-    let client = get_client().await;
-    
-    let result = client.query()
-        .table_name(table_name())
-        .index_name("GSI1")
-        .key_condition_expression("GSI1PK = :pk")
-        .expression_attribute_values(":pk", AttributeValue::S(format!("TENANT#{}", tenant_id)))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB query error: {}", e)))?;
-    
-    let bookings: Vec<Booking> = result.items.unwrap_or_default()
-        .iter()
-        .filter_map(|item| {
-            Some(Booking {
-                id: item.get("id")?.as_s().ok()?.to_string(),
-                tenant_id: item.get("tenantId")?.as_s().ok()?.to_string(),
-                site_id: item.get("siteId")?.as_s().ok()?.to_string(),
-                professional_id: item.get("professionalId")?.as_s().ok()?.to_string(),
-                treatment_id: item.get("treatmentId")?.as_s().ok()?.to_string(),
-                start_time: item.get("startTime")?.as_s().ok()?.to_string(),
-                end_time: item.get("endTime")?.as_s().ok()?.to_string(),
-                patient_name: item.get("patientName")?.as_s().ok()?.to_string(),
-                patient_email: item.get("patientEmail")?.as_s().ok()?.to_string(),
-                status: item.get("status")?.as_s().ok()?.to_string(),
-                created_at: item.get("createdAt")?.as_s().ok()?.to_string(),
-            })
-        })
-        .collect();
-    
-    success_response(serde_json::json!({"bookings": bookings, "count": bookings.len()}))
+/// Solo se reserva lo que ofrece la disponibilidad: la cita completa dentro de
+/// una franja del profesional en la sede y del horario de apertura.
+fn ensure_within_schedule(professional: &Professional, site: &Site, tz: Tz, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<(), ApiError> {
+    if !within_shifts(professional, site, tz, start, end) {
+        return Err(ApiError::Validation("El horario está fuera de la agenda del profesional en esta sede".into()));
+    }
+    Ok(())
 }
 
-async fn cancel_booking(req: Request) -> Result<Response<Body>, ApiError> {
-    let path = req.uri().path();
-    let booking_id = path.strip_prefix("/bookings/")
-        .ok_or_else(|| ApiError::Validation("ID de booking inválido".into()))?;
+#[derive(Debug, Deserialize, Validate)]
+struct CreateHoldRequest {
+    #[validate(length(min = 1, max = 50))]
+    tenant_id: String,
     
-    let client = get_client().await;
+    #[validate(length(min = 1, max = 50))]
+    site_id: String,
     
-    // Primero obtener el booking para conocer el slot
-    let get_result = client.get_item()
-        .table_name(table_name())
-        .key("PK", AttributeValue::S(format!("BOOKING#{}", booking_id)))
-        .key("SK", AttributeValue::S("METADATA".to_string()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB get error: {}", e)))?;
+    #[validate(length(min = 1, max = 50))]
+    professional_id: String,
     
-    let item = get_result.item()
-        .ok_or_else(|| ApiError::NotFound("Booking no encontrado".into()))?;
+    #[validate(length(min = 1, max = 50))]
+    treatment_id: String,
     
-    let tenant_id = item.get("tenantId")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("tenantId no encontrado")))?
-        .to_string();
+    start_time: String,
+}
 
-    // Verificar que el tenant del token coincide
-    let tenant_from_token = require_tenant(&req)?;
-    if tenant_from_token != tenant_id {
-        return Err(ApiError::Forbidden("No puedes cancelar reservas de otro tenant".into()));
+/// Retiene el horario `SLOT_HOLD_MINUTES` minutos mientras el paciente completa
+/// sus datos. Los locks quedan `held` con vencimiento; otro intento sobre las
+/// mismas unidades recibe 409 hasta que se confirme o venza.
+async fn create_hold(repo: &dyn Repository, req: &Request) -> Result<Response<Body>, ApiError> {
+    let payload = req.payload::<CreateHoldRequest>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+    
+    payload.validate()
+        .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
+    
+    let claims = parse_jwt_claims(req).await?;
+    authorize(&claims, Action::Create, Resource::Booking)?;
+    let tenant_from_token = claims.require_tenant()?;
+    if tenant_from_token != payload.tenant_id {
+        return Err(ApiError::Forbidden("tenant_id del payload no coincide con el token".into()));
     }
     
-    let site_id = item.get("siteId")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("siteId no encontrado")))?;
+    let (start, end) = resolve_slot(repo, &tenant_from_token, &payload.site_id, &payload.professional_id, &payload.treatment_id, &payload.start_time).await?;
+    let now = Utc::now();
+    let hold = SlotHold {
+        id: Uuid::new_v4().to_string(),
+        tenant_id: tenant_from_token,
+        site_id: payload.site_id,
+        professional_id: payload.professional_id,
+        treatment_id: payload.treatment_id,
+        start_time: start.to_rfc3339(),
+        end_time: end.to_rfc3339(),
+        token: Uuid::new_v4().to_string(),
+        expires_at: now.timestamp() + hold_minutes() * 60,
+        created_by: actor(&claims),
+        created_at: now.to_rfc3339(),
+    };
     
-    let professional_id = item.get("professionalId")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("professionalId no encontrado")))?;
+    let locks = hold_locks(&hold)?;
+    repo.create_hold(&hold, &locks).await
+        .map_err(|e| e.conflict("Slot no disponible (reservado o retenido por otro usuario)"))?;
     
-    let start_time = item.get("startTime")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("startTime no encontrado")))?;
+    tracing::info!(hold_id = %hold.id, expires_at = hold.expires_at, "Slot held");
+    created_response(serde_json::json!({
+        "hold_id": hold.id,
+        "hold_token": hold.token,
+        "expires_at": DateTime::<Utc>::from_timestamp(hold.expires_at, 0).unwrap_or(now).to_rfc3339(),
+        "site_id": hold.site_id,
+        "professional_id": hold.professional_id,
+        "treatment_id": hold.treatment_id,
+        "start_time": hold.start_time,
+        "end_time": hold.end_time
+    }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ConfirmHoldRequest {
+    #[validate(length(min = 1, max = 100))]
+    hold_token: String,
     
-    let start = chrono::DateTime::parse_from_rfc3339(start_time)
-        .map_err(|_| ApiError::Internal(anyhow::anyhow!("startTime inválido")))?;
+    #[serde(flatten)]
+    patient: PatientDetails,
+    #[serde(default)]
+    notification_channels: Vec<Channel>,
+}
+
+/// Convierte la retención en una reserva con el mismo id, atómicamente.
+async fn confirm_hold(repo: &dyn Repository, req: &Request, hold_id: &str) -> Result<Response<Body>, ApiError> {
+    let payload = req.payload::<ConfirmHoldRequest>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
     
-    let slot_pk = format!("TENANT#{}#SITE#{}#DATE#{}", tenant_id, site_id, start.format("%Y-%m-%d"));
-    let slot_sk = format!("SLOT#{}#{}", start.format("%H:%M"), professional_id);
+    payload.validate()
+        .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
     
-    // Transacción atómica para cancelar booking y liberar slot
-    let now = chrono::Utc::now().to_rfc3339();
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Create, Resource::Booking)?;
     
-    let transact_result = client.transact_write_items()
-        .transact_items(
-            aws_sdk_dynamodb::types::TransactWriteItem::builder()
-                .update(
-                    aws_sdk_dynamodb::types::Update::builder()
-                        .table_name(table_name())
-                        .key("PK", AttributeValue::S(format!("BOOKING#{}", booking_id)))
-                        .key("SK", AttributeValue::S("METADATA".to_string()))
-                        .update_expression("SET #status = :cancelled, cancelledAt = :now")
-                        .expression_attribute_names("#status", "status")
-                        .expression_attribute_values(":cancelled", AttributeValue::S("cancelled".to_string()))
-                        .expression_attribute_values(":now", AttributeValue::S(now.clone()))
-                        .condition_expression("#status <> :cancelled")
-                        .build()
-                        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-                )
-                .build()
-        )
-        .transact_items(
-            aws_sdk_dynamodb::types::TransactWriteItem::builder()
-                .delete(
-                    aws_sdk_dynamodb::types::Delete::builder()
-                        .table_name(table_name())
-                        .key("PK", AttributeValue::S(slot_pk))
-                        .key("SK", AttributeValue::S(slot_sk))
-                        .build()
-                        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-                )
-                .build()
-        )
-        .send()
-        .await;
-    
-    match transact_result {
-        Ok(_) => {
-            tracing::info!(booking_id = %booking_id, "Booking cancelled atomically");
-            success_response(serde_json::json!({
-                "message": "Booking cancelado exitosamente",
-                "booking_id": booking_id,
-                "cancelled_at": now
-            }))
-        }
-        Err(e) => {
-            if e.to_string().contains("ConditionalCheckFailed") {
-                Err(ApiError::Conflict("Booking ya está cancelado".into()))
-            } else {
-                Err(ApiError::Internal(anyhow::anyhow!("DynamoDB error: {}", e)))
+    let hold = repo.get_hold(hold_id).await?
+        .ok_or_else(|| ApiError::NotFound("Retención no encontrada".into()))?;
+    if claims.require_tenant()? != hold.tenant_id {
+        return Err(ApiError::Forbidden("No puedes confirmar retenciones de otro tenant".into()));
+    }
+    if hold.token != payload.hold_token {
+        return Err(ApiError::Forbidden("hold_token inválido".into()));
+    }
+    let now = Utc::now();
+    if !hold.is_active(now.timestamp()) {
+        return Err(ApiError::Conflict("La retención venció; vuelve a elegir el horario".into()));
+    }
+    let mut patient = payload.patient;
+    let (patient_id, new_patient) = resolve_patient(repo, &hold.tenant_id, scope, &claims, &mut patient, &payload.notification_channels, &now.to_rfc3339()).await?;
+    
+    let booking = Booking {
+        id: hold.id.clone(),
+        tenant_id: hold.tenant_id.clone(),
+        site_id: hold.site_id.clone(),
+        professional_id: hold.professional_id.clone(),
+        treatment_id: hold.treatment_id.clone(),
+        start_time: hold.start_time.clone(),
+        end_time: hold.end_time.clone(),
+        patient_name: patient.patient_name,
+        patient_email: patient.patient_email,
+        patient_phone: patient.patient_phone,
+        notification_channels: payload.notification_channels,
+        patient_id,
+        status: if scope == Scope::Own { BookingStatus::Pending } else { BookingStatus::Confirmed },
+        created_at: now.to_rfc3339(),
+    };
+    let locks = hold_locks(&hold)?;
+    let event = BookingEvent::created(&booking, &booking.created_at);
+    repo.confirm_hold(&hold, &booking, new_patient.as_ref(), &locks, now.timestamp(), &event).await
+        .map_err(|e| e.conflict("La retención venció o el slot ya no está disponible"))?;
+    log_new_patient(new_patient.as_ref());
+    
+    tracing::info!(booking_id = %booking.id, "Hold confirmed");
+    created_response(booking)
+}
+
+async fn list_bookings(repo: &dyn Repository, req: Request) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(&req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Booking)?;
+
+    // Enforce multitenancy desde el token, ignorando tenant_id por query si difiere
+    let tenant_id = claims.require_tenant()?;
+
+    let params = req.query_string_parameters_ref();
+    let param = |name: &str| params
+        .and_then(|p| p.first(name))
+        .filter(|v| !v.is_empty())
+        .map(str::to_string);
+
+    // Pacientes: solo sus propias reservas, ignorando patient_email por query
+    let patient_email = if scope == Scope::Own {
+        Some(claims.email.clone()
+            .ok_or_else(|| ApiError::Forbidden("Email no presente en token".into()))?)
+    } else {
+        param("patient_email")
+    };
+    let status = match param("status") {
+        Some(raw) => Some(BookingStatus::parse(&raw)
+            .ok_or_else(|| ApiError::Validation(format!("status inválido: {}", raw)))?),
+        None => None,
+    };
+    let site_id = param("site_id");
+
+    // from_date/to_date son días locales de la sede filtrada (o del tenant),
+    // ambos inclusive; se traducen a instantes UTC para comparar con startTime
+    let start_between = match (param("from_date"), param("to_date")) {
+        (None, None) => None,
+        (from, to) => {
+            let site = match &site_id {
+                Some(id) => repo.get_site(&tenant_id, id).await?,
+                None => None,
+            };
+            let tz = clinic_tz(repo, &tenant_id, site.as_ref()).await?;
+            let from = match from {
+                Some(raw) => local_day_bounds(tz, parse_date("from_date", &raw)?).0.to_rfc3339(),
+                None => String::new(),
+            };
+            let to = match to {
+                Some(raw) => (local_day_bounds(tz, parse_date("to_date", &raw)?).1 - chrono::Duration::seconds(1)).to_rfc3339(),
+                // Cualquier RFC3339 ordena antes que "~"
+                None => "~".into(),
+            };
+            if from > to {
+                return Err(ApiError::Validation("from_date posterior a to_date".into()));
             }
+            Some((from, to))
         }
+    };
+
+    let filter = BookingFilter {
+        patient_email,
+        patient_id: param("patient_id"),
+        professional_id: param("professional_id"),
+        site_id,
+        status,
+        start_between,
+    };
+    let limit = match param("limit") {
+        Some(raw) => Some(raw.parse::<usize>()
+            .map_err(|_| ApiError::Validation("limit inválido".into()))?),
+        None => None,
+    };
+    let page = PageRequest::new(limit, param("cursor"));
+
+    let result = repo.list_bookings(&tenant_id, &filter, &page).await?;
+    
+    success_response(serde_json::json!({
+        "bookings": result.bookings,
+        "count": result.bookings.len(),
+        "next_cursor": result.next_cursor
+    }))
+}
+
+fn parse_date(name: &str, raw: &str) -> Result<NaiveDate, ApiError> {
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map_err(|_| ApiError::Validation(format!("{} inválida (usar YYYY-MM-DD)", name)))
+}
+
+async fn get_booking(repo: &dyn Repository, req: &Request, booking_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Booking)?;
+    
+    let booking = repo.get_booking(booking_id).await?
+        .ok_or_else(|| ApiError::NotFound("Booking no encontrado".into()))?;
+    if claims.require_tenant()? != booking.tenant_id {
+        return Err(ApiError::Forbidden("No puedes consultar reservas de otro tenant".into()));
     }
+    ensure_own_booking(scope, &claims, &booking.patient_email)?;
+    
+    success_response(booking)
+}
+
+#[derive(Debug, Default, Deserialize, Validate)]
+struct StatusChangeRequest {
+    #[serde(default)]
+    #[validate(length(min = 1, max = 500))]
+    reason: Option<String>,
+}
+
+/// Aplica una transición de estado. La escritura es condicional al estado leído:
+/// si otra request lo cambió entretanto, responde `409` sin registrar nada.
+async fn change_status(repo: &dyn Repository, req: &Request, booking_id: &str, transition: Transition) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, transition.action(), Resource::Booking)?;
+    
+    // El body es opcional: solo lleva el motivo
+    let payload = if req.body().is_empty() {
+        StatusChangeRequest::default()
+    } else {
+        req.payload::<StatusChangeRequest>()?.unwrap_or_default()
+    };
+    payload.validate()
+        .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
+    
+    let booking = repo.get_booking(booking_id).await?
+        .ok_or_else(|| ApiError::NotFound("Booking no encontrado".into()))?;
+
+    // Verificar que el tenant del token coincide
+    let tenant_from_token = claims.require_tenant()?;
+    if tenant_from_token != booking.tenant_id {
+        return Err(ApiError::Forbidden("No puedes modificar reservas de otro tenant".into()));
+    }
+    ensure_own_booking(scope, &claims, &booking.patient_email)?;
+    
+    let to = next_status(booking.status, transition, scope)?;
+    let now = chrono::Utc::now().to_rfc3339();
+    let change = StatusChange {
+        booking_id: booking.id.clone(),
+        from: booking.status,
+        to,
+        actor: actor(&claims),
+        reason: payload.reason,
+        at: now.clone(),
+    };
+    
+    // Al cancelar se liberan todas sus unidades en la misma transacción
+    let release = if to.is_cancelled() { booking_locks(&booking, &now)? } else { vec![] };
+    let event = BookingEvent::status_changed(&Booking { status: to, ..booking.clone() }, change.from, &now);
+    repo.transition_booking(&change, &release, &event).await
+        .map_err(|e| e.conflict("La reserva cambió de estado; vuelve a consultarla"))?;
+
+    tracing::info!(booking_id = %booking_id, from = change.from.as_str(), to = to.as_str(), actor = %change.actor, "Booking status changed");
+    success_response(serde_json::json!({
+        "message": "Estado de la reserva actualizado",
+        "booking_id": booking_id,
+        "previous_status": change.from,
+        "status": change.to,
+        "changed_at": change.at,
+        "changed_by": change.actor,
+        "reason": change.reason
+    }))
+}
+
+async fn booking_history(repo: &dyn Repository, req: &Request, booking_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Booking)?;
+    
+    let booking = repo.get_booking(booking_id).await?
+        .ok_or_else(|| ApiError::NotFound("Booking no encontrado".into()))?;
+    if claims.require_tenant()? != booking.tenant_id {
+        return Err(ApiError::Forbidden("No puedes consultar reservas de otro tenant".into()));
+    }
+    ensure_own_booking(scope, &claims, &booking.patient_email)?;
+    
+    let history = repo.list_status_changes(booking_id).await?;
+    success_response(serde_json::json!({
+        "booking_id": booking_id,
+        "status": booking.status,
+        "history": history
+    }))
+}
+
+/// Intentos de envío de las notificaciones de la reserva y su estado de
+/// entrega: si el paciente realmente recibió la confirmación o el recordatorio.
+async fn booking_notifications(repo: &dyn Repository, req: &Request, booking_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Booking)?;
+    
+    let booking = repo.get_booking(booking_id).await?
+        .ok_or_else(|| ApiError::NotFound("Booking no encontrado".into()))?;
+    if claims.require_tenant()? != booking.tenant_id {
+        return Err(ApiError::Forbidden("No puedes consultar reservas de otro tenant".into()));
+    }
+    ensure_own_booking(scope, &claims, &booking.patient_email)?;
+    
+    let notifications = repo.list_notifications(booking_id).await?;
+    success_response(serde_json::json!({
+        "booking_id": booking_id,
+        "notifications": notifications
+    }))
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -314,184 +511,922 @@ struct UpdateBookingRequest {
     start_time: String,
 }
 
-async fn update_booking(req: Request) -> Result<Response<Body>, ApiError> {
-    let path = req.uri().path();
-    let booking_id = path.strip_prefix("/bookings/")
-        .ok_or_else(|| ApiError::Validation("ID de booking inválido".into()))?;
-    
+async fn update_booking(repo: &dyn Repository, req: &Request, booking_id: &str) -> Result<Response<Body>, ApiError> {
     let payload = req.payload::<UpdateBookingRequest>()?
         .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
     
     payload.validate()
         .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
-    
-    let client = get_client().await;
+
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Update, Resource::Booking)?;
     
     // Primero obtener el booking actual
-    let get_result = client.get_item()
-        .table_name(table_name())
-        .key("PK", AttributeValue::S(format!("BOOKING#{}", booking_id)))
-        .key("SK", AttributeValue::S("METADATA".to_string()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB get error: {}", e)))?;
-    
-    let item = get_result.item()
+    let booking = repo.get_booking(booking_id).await?
         .ok_or_else(|| ApiError::NotFound("Booking no encontrado".into()))?;
-    
-    let tenant_id = item.get("tenantId")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("tenantId no encontrado")))?.to_string();
 
     // Enforce multitenancy del token
-    let tenant_from_token = require_tenant(&req)?;
-    if tenant_from_token != tenant_id {
+    let tenant_from_token = claims.require_tenant()?;
+    if tenant_from_token != booking.tenant_id {
         return Err(ApiError::Forbidden("No puedes reprogramar reservas de otro tenant".into()));
     }
+    ensure_own_booking(scope, &claims, &booking.patient_email)?;
+    if !matches!(booking.status, BookingStatus::Pending | BookingStatus::Confirmed) {
+        return Err(ApiError::Conflict(format!("No se puede reprogramar una reserva en estado {}", booking.status.as_str())));
+    }
     
-    let site_id = item.get("siteId")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("siteId no encontrado")))?.to_string();
-    
-    let professional_id = item.get("professionalId")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("professionalId no encontrado")))?.to_string();
-    
-    let old_start_time = item.get("startTime")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("startTime no encontrado")))?.to_string();
-    
-    let old_start = chrono::DateTime::parse_from_rfc3339(&old_start_time)
-        .map_err(|_| ApiError::Internal(anyhow::anyhow!("startTime inválido")))?;
-    
-    let new_start = chrono::DateTime::parse_from_rfc3339(&payload.start_time)
-        .map_err(|_| ApiError::Validation("start_time inválido (usar ISO8601)".into()))?;
+    let site = repo.get_site(&booking.tenant_id, &booking.site_id).await?;
+    let tz = clinic_tz(repo, &booking.tenant_id, site.as_ref()).await?;
+    let new_start = parse_instant(&payload.start_time, tz)?;
     
     // Calcular nueva duración según el tratamiento
-    let treatment_id = item.get("treatmentId")
-        .and_then(|v| v.as_s().ok())
-        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("treatmentId no encontrado")))?
-        .to_string();
-    let treatment_minutes = fetch_treatment_duration_minutes(&tenant_id, &treatment_id).await?;
-    let buffer_minutes = fetch_treatment_buffer_minutes(&tenant_id, &treatment_id).await?;
-    let new_end = new_start + chrono::Duration::minutes((treatment_minutes + buffer_minutes) as i64);
-    
-    let old_slot_pk = format!("TENANT#{}#SITE#{}#DATE#{}", tenant_id, site_id, old_start.format("%Y-%m-%d"));
-    let old_slot_sk = format!("SLOT#{}#{}", old_start.format("%H:%M"), professional_id);
-    
-    let new_slot_pk = format!("TENANT#{}#SITE#{}#DATE#{}", tenant_id, site_id, new_start.format("%Y-%m-%d"));
-    let new_slot_sk = format!("SLOT#{}#{}", new_start.format("%H:%M"), professional_id);
+    let treatment = fetch_treatment(repo, &booking.tenant_id, &booking.treatment_id).await?;
+    let new_end = new_start + chrono::Duration::minutes(treatment.occupied_minutes());
+    // Reservas anteriores a las sedes no tienen sede que validar
+    if let Some(site) = &site {
+        let professional = repo.get_professional(&booking.tenant_id, &booking.professional_id).await?
+            .ok_or_else(|| ApiError::NotFound("Profesional no encontrado".into()))?;
+        ensure_within_schedule(&professional, site, tz, new_start, new_end)?;
+    }
     
     let now = chrono::Utc::now().to_rfc3339();
+    let old_locks = booking_locks(&booking, &now)?;
+    let previous_start = booking.start_time.clone();
+    let rescheduled = Booking {
+        start_time: new_start.to_rfc3339(),
+        end_time: new_end.to_rfc3339(),
+        ..booking
+    };
+    let new_locks = booking_locks(&rescheduled, &now)?;
+    let event = BookingEvent::rescheduled(&rescheduled, &previous_start, &now);
     
-    // Transacción: liberar slot antiguo, reservar nuevo slot, actualizar booking
-    let transact_result = client.transact_write_items()
-        .transact_items(
-            aws_sdk_dynamodb::types::TransactWriteItem::builder()
-                .delete(
-                    aws_sdk_dynamodb::types::Delete::builder()
-                        .table_name(table_name())
-                        .key("PK", AttributeValue::S(old_slot_pk))
-                        .key("SK", AttributeValue::S(old_slot_sk))
-                        .build()
-                        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-                )
-                .build()
-        )
-        .transact_items(
-            aws_sdk_dynamodb::types::TransactWriteItem::builder()
-                .put(
-                    aws_sdk_dynamodb::types::Put::builder()
-                        .table_name(table_name())
-                        .item("PK", AttributeValue::S(new_slot_pk))
-                        .item("SK", AttributeValue::S(new_slot_sk))
-                        .item("bookingId", AttributeValue::S(booking_id.to_string()))
-                        .item("status", AttributeValue::S("reserved".to_string()))
-                        .item("createdAt", AttributeValue::S(now.clone()))
-                        .condition_expression("attribute_not_exists(PK)")
-                        .build()
-                        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-                )
-                .build()
-        )
-        .transact_items(
-            aws_sdk_dynamodb::types::TransactWriteItem::builder()
-                .update(
-                    aws_sdk_dynamodb::types::Update::builder()
-                        .table_name(table_name())
-                        .key("PK", AttributeValue::S(format!("BOOKING#{}", booking_id)))
-                        .key("SK", AttributeValue::S("METADATA".to_string()))
-                        .update_expression("SET startTime = :start, endTime = :end, GSI3SK = :gsi3sk, updatedAt = :now")
-                        .expression_attribute_values(":start", AttributeValue::S(new_start.to_rfc3339()))
-                        .expression_attribute_values(":end", AttributeValue::S(new_end.to_rfc3339()))
-                        .expression_attribute_values(":gsi3sk", AttributeValue::S(new_start.to_rfc3339()))
-                        .expression_attribute_values(":now", AttributeValue::S(now.clone()))
-                        .build()
-                        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-                )
-                .build()
-        )
-        .send()
-        .await;
-    
-    match transact_result {
-        Ok(_) => {
-            tracing::info!(booking_id = %booking_id, "Booking rescheduled atomically");
-            success_response(serde_json::json!({
-                "message": "Booking reprogramado exitosamente",
-                "booking_id": booking_id,
-                "new_start_time": new_start.to_rfc3339(),
-                "updated_at": now
-            }))
+    // Transacción: liberar unidades que ya no cubre, reclamar las nuevas, actualizar booking
+    // Falla también si el estado cambió desde la lectura
+    repo.reschedule_booking(&rescheduled, &old_locks, &new_locks, &now, &event).await
+        .map_err(|e| e.conflict("Nuevo slot no disponible"))?;
+
+    tracing::info!(booking_id = %booking_id, "Booking rescheduled atomically");
+    success_response(serde_json::json!({
+        "message": "Booking reprogramado exitosamente",
+        "booking_id": booking_id,
+        "new_start_time": new_start.to_rfc3339(),
+        "updated_at": now
+    }))
+}
+
+/// Quién hizo el cambio, para el historial de estados.
+fn actor(claims: &JwtClaims) -> String {
+    claims.email.clone()
+        .or_else(|| claims.sub.clone())
+        .unwrap_or_else(|| "desconocido".into())
+}
+
+/// Teléfono en E.164 y, si el paciente elige SMS o WhatsApp, teléfono presente.
+/// Los canales que la clínica no habilita se ignoran al notificar.
+fn validate_contact(phone: Option<&str>, channels: &[Channel]) -> Result<(), ApiError> {
+    if let Some(phone) = phone {
+        validate_phone(phone).map_err(|e| ApiError::Validation(format!("patient_phone inválido: {}", e)))?;
+    }
+    if phone.is_none() {
+        if let Some(channel) = channels.iter().find(|c| c.needs_phone()) {
+            return Err(ApiError::Validation(format!("notification_channels: {} requiere patient_phone", channel.as_str())));
         }
-        Err(e) => {
-            if e.to_string().contains("ConditionalCheckFailed") {
-                Err(ApiError::Conflict("Nuevo slot no disponible".into()))
-            } else {
-                Err(ApiError::Internal(anyhow::anyhow!("DynamoDB error: {}", e)))
+    }
+    Ok(())
+}
+
+/// Id del paciente del directorio al que pertenece la reserva y, si hay que
+/// darlo de alta, el paciente nuevo, que se guarda en la transacción de la
+/// reserva. Con `patient_id` completa los datos vacíos con los del paciente;
+/// sin él busca al paciente por documento o contacto. Un paciente (alcance
+/// `Own`) solo lee el directorio: si no está, su reserva queda sin vincular.
+async fn resolve_patient(
+    repo: &dyn Repository,
+    tenant_id: &str,
+    scope: Scope,
+    claims: &JwtClaims,
+    details: &mut PatientDetails,
+    channels: &[Channel],
+    now: &str,
+) -> Result<(Option<String>, Option<Patient>), ApiError> {
+    let existing = match &details.patient_id {
+        Some(id) => {
+            let patient = repo.get_patient(tenant_id, id).await?
+                .ok_or_else(|| ApiError::NotFound("Paciente no encontrado".into()))?;
+            // Un paciente solo reserva para sí (o para quien tiene a su cargo)
+            ensure_own_booking(scope, claims, patient.contact_email().unwrap_or_default())?;
+            if details.patient_name.trim().is_empty() {
+                details.patient_name = patient.name.clone();
+            }
+            if details.patient_email.trim().is_empty() {
+                details.patient_email = patient.contact_email().unwrap_or_default().to_string();
             }
+            if details.patient_phone.is_none() {
+                details.patient_phone = patient.contact_phone().map(str::to_string);
+            }
+            Some(patient)
         }
+        None => None,
+    };
+    if details.patient_name.trim().is_empty() {
+        return Err(ApiError::Validation("patient_name requerido".into()));
+    }
+    if details.patient_email.trim().is_empty() {
+        return Err(ApiError::Validation("patient_email requerido".into()));
+    }
+    validate_contact(details.patient_phone.as_deref(), channels)?;
+    ensure_own_booking(scope, claims, &details.patient_email)?;
+    if let Some(patient) = existing {
+        return Ok((Some(patient.id), None));
+    }
+    let contact = PatientContact {
+        name: &details.patient_name,
+        email: Some(&details.patient_email),
+        phone: details.patient_phone.as_deref(),
+        document_id: details.patient_document.as_deref(),
+    };
+    if let Some(patient) = find_match(repo, tenant_id, &contact).await? {
+        return Ok((Some(patient.id), None));
     }
+    if scope == Scope::Own {
+        return Ok((None, None));
+    }
+    let patient = new_patient(tenant_id, &contact, now);
+    Ok((Some(patient.id.clone()), Some(patient)))
 }
 
-async fn fetch_treatment_duration_minutes(tenant_id: &str, treatment_id: &str) -> Result<i64, ApiError> {
-    let client = get_client().await;
-    let result = client.get_item()
-        .table_name(table_name())
-        .key("PK", AttributeValue::S(format!("TENANT#{}", tenant_id)))
-        .key("SK", AttributeValue::S(format!("TREATMENT#{}", treatment_id)))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB get error: {}", e)))?;
-
-    let item = result.item().ok_or_else(|| ApiError::NotFound("Tratamiento no encontrado".into()))?;
-    let duration = item.get("durationMinutes")
-        .and_then(|v| v.as_n().ok())
-        .and_then(|n| n.parse::<i64>().ok())
-        .unwrap_or(45);
-    Ok(duration)
+fn log_new_patient(patient: Option<&Patient>) {
+    if let Some(patient) = patient {
+        tracing::info!(patient_id = %patient.id, "Patient added to directory from booking");
+    }
 }
 
-async fn fetch_treatment_buffer_minutes(tenant_id: &str, treatment_id: &str) -> Result<i64, ApiError> {
-    let client = get_client().await;
-    let result = client.get_item()
-        .table_name(table_name())
-        .key("PK", AttributeValue::S(format!("TENANT#{}", tenant_id)))
-        .key("SK", AttributeValue::S(format!("TREATMENT#{}", treatment_id)))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB get error: {}", e)))?;
-
-    let item = result.item().ok_or_else(|| ApiError::NotFound("Tratamiento no encontrado".into()))?;
-    let buffer = item.get("bufferMinutes")
-        .and_then(|v| v.as_n().ok())
-        .and_then(|n| n.parse::<i64>().ok())
-        .unwrap_or(0);
-    Ok(buffer)
+/// Con alcance `Own` (pacientes) la reserva debe pertenecer al email del token.
+fn ensure_own_booking(scope: Scope, claims: &JwtClaims, patient_email: &str) -> Result<(), ApiError> {
+    if scope != Scope::Own {
+        return Ok(());
+    }
+    match claims.email.as_deref() {
+        Some(email) if email.eq_ignore_ascii_case(patient_email) => Ok(()),
+        _ => Err(ApiError::Forbidden("Solo puedes gestionar tus propias reservas".into())),
+    }
+}
+
+/// Zona de la sede si la sobreescribe; si no (o si la reserva es anterior a las
+/// sedes y no la encuentra), la del tenant.
+async fn clinic_tz(repo: &dyn Repository, tenant_id: &str, site: Option<&Site>) -> Result<Tz, ApiError> {
+    let tenant = repo.get_tenant(tenant_id).await?
+        .ok_or_else(|| ApiError::NotFound("Tenant no encontrado".into()))?;
+    Ok(site.map_or(tenant.tz(), |site| site.tz(tenant.tz())))
+}
+
+async fn fetch_treatment(repo: &dyn Repository, tenant_id: &str, treatment_id: &str) -> Result<Treatment, ApiError> {
+    repo.get_treatment(tenant_id, treatment_id).await?
+        .ok_or_else(|| ApiError::NotFound("Tratamiento no encontrado".into()))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     init_tracing();
-    run(service_fn(handler)).await
+    let repo = DynamoStore::from_env();
+    run(service_fn(|req| handler(&repo, req))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_http::http::{Method, StatusCode};
+    use lambda_http::aws_lambda_events::query_map::QueryMap;
+    use serde_json::json;
+    use shared_lib::models::{DeliveryStatus, NotificationRecord};
+    use shared_lib::schedule::WeeklySchedule;
+    use shared_lib::repository::{BookingRepository, NotificationRepository, OutboxRepository, PatientRepository, ProfessionalRepository, SiteRepository, SlotLockRepository, TenantRepository, TreatmentRepository};
+    use shared_lib::StoreError;
+    use shared_lib::testing::{bearer, tenant};
+    use shared_lib::MemoryStore;
+    use std::collections::HashMap;
+
+    /// Atiende en la sede todos los días de 00:00 a 23:00, hora local.
+    fn every_day(site_id: &str) -> WeeklySchedule {
+        let days: Vec<serde_json::Value> = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"].iter()
+            .map(|weekday| json!({"weekday": weekday, "site_id": site_id, "start": "00:00", "end": "23:00"}))
+            .collect();
+        serde_json::from_value(json!({"days": days})).unwrap()
+    }
+
+    async fn store_with_treatment() -> MemoryStore {
+        let store = MemoryStore::new();
+        store.put_tenant(&tenant("tenant-a")).await.unwrap();
+        store.put_treatment(&Treatment {
+            id: "treat-1".into(),
+            tenant_id: "tenant-a".into(),
+            name: "Limpieza".into(),
+            duration_minutes: 30,
+            buffer_minutes: 10,
+            price: 0.0,
+            created_at: "2025-01-01T00:00:00Z".into(),
+        }).await.unwrap();
+        for (id, status) in [("site-1", "active"), ("site-2", "active"), ("site-old", "inactive")] {
+            store.put_site(&Site {
+                id: id.into(),
+                tenant_id: "tenant-a".into(),
+                name: "Sede".into(),
+                address: "Calle 100".into(),
+                timezone: None,
+                opening_hours: Default::default(),
+                chairs: 1,
+                contact_email: None,
+                status: status.into(),
+                created_at: String::new(),
+            }).await.unwrap();
+        }
+        store.put_professional(&Professional {
+            id: "prof-1".into(),
+            tenant_id: "tenant-a".into(),
+            name: "Dra. Pérez".into(),
+            email: "perez@example.com".into(),
+            specialties: vec![],
+            site_ids: vec!["site-1".into(), "site-old".into()],
+            schedule: every_day("site-1"),
+            status: "active".into(),
+            created_at: String::new(),
+        }).await.unwrap();
+        store
+    }
+
+    fn request(method: Method, uri: &str, body: Option<serde_json::Value>, auth: String) -> Request {
+        let mut request = Request::new(body.map(|b| Body::from(b.to_string())).unwrap_or(Body::Empty));
+        *request.method_mut() = method;
+        *request.uri_mut() = uri.parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", auth.parse().unwrap());
+        request
+    }
+
+    fn create_request(auth: String) -> Request {
+        create_request_at("2025-10-01T10:00:00Z", auth)
+    }
+
+    fn create_request_at(start_time: &str, auth: String) -> Request {
+        create_request_at_site("site-1", start_time, auth)
+    }
+
+    fn create_request_at_site(site_id: &str, start_time: &str, auth: String) -> Request {
+        request(Method::POST, "/bookings", Some(json!({
+            "tenant_id": "tenant-a",
+            "site_id": site_id,
+            "professional_id": "prof-1",
+            "treatment_id": "treat-1",
+            "start_time": start_time,
+            "patient_name": "Ana",
+            "patient_email": "ana@example.com"
+        })), auth)
+    }
+
+    fn reception() -> String {
+        bearer("tenant-a", &["Recepcion"], "front@example.com")
+    }
+
+    #[tokio::test]
+    async fn test_create_booking_applies_duration_and_buffer() {
+        let store = store_with_treatment().await;
+
+        let response = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["end_time"], "2025-10-01T10:40:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn test_create_booking_stores_contact_preferences() {
+        let store = store_with_treatment().await;
+        let with_contact = |phone: Option<&str>, channels: serde_json::Value| request(Method::POST, "/bookings", Some(json!({
+            "tenant_id": "tenant-a",
+            "site_id": "site-1",
+            "professional_id": "prof-1",
+            "treatment_id": "treat-1",
+            "start_time": "2025-10-01T10:00:00Z",
+            "patient_name": "Ana",
+            "patient_email": "ana@example.com",
+            "patient_phone": phone,
+            "notification_channels": channels
+        })), reception());
+
+        let no_phone = handler(&store, with_contact(None, json!(["whatsapp"]))).await.unwrap();
+        assert_eq!(no_phone.status(), StatusCode::BAD_REQUEST);
+        let bad_phone = handler(&store, with_contact(Some("3001234567"), json!([]))).await.unwrap();
+        assert_eq!(bad_phone.status(), StatusCode::BAD_REQUEST);
+
+        let response = handler(&store, with_contact(Some("+573001234567"), json!(["whatsapp", "email"]))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let stored = store.get_booking(body["id"].as_str().unwrap()).await.unwrap().unwrap();
+        assert_eq!(stored.patient_phone.as_deref(), Some("+573001234567"));
+        assert_eq!(stored.notification_channels, [Channel::Whatsapp, Channel::Email]);
+    }
+
+    #[tokio::test]
+    async fn test_bookings_are_linked_to_directory_patients() {
+        let store = store_with_treatment().await;
+        let created = |start_time: &str, patient: serde_json::Value, auth: String| {
+            let mut body = json!({
+                "tenant_id": "tenant-a",
+                "site_id": "site-1",
+                "professional_id": "prof-1",
+                "treatment_id": "treat-1",
+                "start_time": start_time
+            });
+            body.as_object_mut().unwrap().extend(patient.as_object().unwrap().clone());
+            request(Method::POST, "/bookings", Some(body), auth)
+        };
+        let booked = |response: Response<Body>| -> Booking {
+            assert_eq!(response.status(), StatusCode::CREATED);
+            serde_json::from_slice(response.body()).unwrap()
+        };
+
+        // Sin patient_id se da de alta en el directorio y se reutiliza después
+        let first = booked(handler(&store, create_request(reception())).await.unwrap());
+        let patient_id = first.patient_id.clone().unwrap();
+        let second = booked(handler(&store, created("2025-10-02T10:00:00Z", json!({"patient_name": "ANA", "patient_email": "ana@example.com"}), reception())).await.unwrap());
+        assert_eq!(second.patient_id.as_deref(), Some(patient_id.as_str()));
+        assert_eq!(store.list_patients("tenant-a", &PageRequest::default()).await.unwrap().patients.len(), 1);
+
+        // Con patient_id el contacto se toma del directorio
+        let third = booked(handler(&store, created("2025-10-03T10:00:00Z", json!({"patient_id": patient_id}), reception())).await.unwrap());
+        assert_eq!((third.patient_name.as_str(), third.patient_email.as_str()), ("Ana", "ana@example.com"));
+
+        let unknown = handler(&store, created("2025-10-04T10:00:00Z", json!({"patient_id": "nope"}), reception())).await.unwrap();
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+        let nameless = handler(&store, created("2025-10-04T10:00:00Z", json!({"patient_email": "x@example.com"}), reception())).await.unwrap();
+        assert_eq!(nameless.status(), StatusCode::BAD_REQUEST);
+        // Un paciente no reserva a nombre de otro del directorio
+        let other = handler(&store, created("2025-10-04T10:00:00Z", json!({"patient_id": patient_id}), bearer("tenant-a", &["Paciente"], "luis@example.com"))).await.unwrap();
+        assert_eq!(other.status(), StatusCode::FORBIDDEN);
+
+        let by_patient = list_all(&store, &[("patient_id", &patient_id)]).await;
+        assert_eq!(by_patient.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_bookings_leave_no_patient_behind() {
+        let store = store_with_treatment().await;
+        let luis = |site_id: &str, start_time: &str, auth: String| request(Method::POST, "/bookings", Some(json!({
+            "tenant_id": "tenant-a",
+            "site_id": site_id,
+            "professional_id": "prof-1",
+            "treatment_id": "treat-1",
+            "start_time": start_time,
+            "patient_name": "Luis",
+            "patient_email": "luis@example.com",
+            "patient_document": "CC 77"
+        })), auth);
+        let held = handler(&store, hold_request(reception())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(held.body()).unwrap();
+        let (hold_id, token) = (body["hold_id"].as_str().unwrap(), body["hold_token"].as_str().unwrap());
+
+        // El paciente nuevo va en la transacción de la reserva: si falla, no queda
+        let taken = handler(&store, luis("site-1", "2025-10-01T10:00:00Z", reception())).await.unwrap();
+        assert_eq!(taken.status(), StatusCode::CONFLICT);
+        let no_site = handler(&store, luis("site-9", "2025-10-02T10:00:00Z", reception())).await.unwrap();
+        assert_eq!(no_site.status(), StatusCode::NOT_FOUND);
+        assert!(store.list_patients("tenant-a", &PageRequest::default()).await.unwrap().patients.is_empty());
+
+        let confirmed = handler(&store, confirm_request(hold_id, token, reception())).await.unwrap();
+        assert_eq!(confirmed.status(), StatusCode::CREATED);
+        let patients = store.list_patients("tenant-a", &PageRequest::default()).await.unwrap().patients;
+        assert_eq!(patients.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["Ana"]);
+
+        // Un paciente solo lee el directorio: si no está, su reserva no se vincula
+        let own = handler(&store, luis("site-1", "2025-10-02T10:00:00Z", bearer("tenant-a", &["Paciente"], "luis@example.com"))).await.unwrap();
+        assert_eq!(own.status(), StatusCode::CREATED);
+        let booking: Booking = serde_json::from_slice(own.body()).unwrap();
+        assert_eq!(booking.patient_id, None);
+        assert_eq!(store.list_patients("tenant-a", &PageRequest::default()).await.unwrap().patients.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_local_start_time_uses_tenant_zone_and_stores_utc() {
+        let store = store_with_treatment().await;
+
+        // Sin offset: 10:00 en Bogotá (UTC-5)
+        let response = handler(&store, create_request_at("2025-10-01T10:00:00", reception())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["start_time"], "2025-10-01T15:00:00+00:00");
+
+        // El mismo instante enviado con offset choca con el lock ya tomado
+        let same_instant = handler(&store, create_request_at("2025-10-01T10:15:00-05:00", reception())).await.unwrap();
+        assert_eq!(same_instant.status(), StatusCode::CONFLICT);
+
+        let locks = store.list_slot_locks("tenant-a", "prof-1", "2025-10-01").await.unwrap();
+        assert_eq!(locks.first().map(|l| l.time.as_str()), Some("15:00"));
+    }
+
+    #[tokio::test]
+    async fn test_create_booking_validates_site_and_professional_assignment() {
+        let store = store_with_treatment().await;
+
+        let unknown = handler(&store, create_request_at_site("site-9", "2025-10-01T10:00:00Z", reception())).await.unwrap();
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+
+        let inactive = handler(&store, create_request_at_site("site-old", "2025-10-01T10:00:00Z", reception())).await.unwrap();
+        assert_eq!(inactive.status(), StatusCode::BAD_REQUEST);
+
+        // prof-1 no está asignado a site-2
+        let unassigned = handler(&store, create_request_at_site("site-2", "2025-10-01T10:00:00Z", reception())).await.unwrap();
+        assert_eq!(unassigned.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_site_timezone_overrides_tenant_zone() {
+        let store = store_with_treatment().await;
+        let mut site = store.get_site("tenant-a", "site-1").await.unwrap().unwrap();
+        site.timezone = Some("America/Sao_Paulo".into());
+        store.put_site(&site).await.unwrap();
+
+        // 10:00 en São Paulo (UTC-3)
+        let response = handler(&store, create_request_at("2025-10-01T10:00:00", reception())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["start_time"], "2025-10-01T13:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn test_double_booking_same_slot_conflicts() {
+        let store = store_with_treatment().await;
+
+        let first = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+
+        let second = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(second.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_professional_cannot_be_booked_at_two_sites_at_once() {
+        let store = store_with_treatment().await;
+        let first = handler(&store, create_request_at_site("site-1", "2025-10-01T10:00:00Z", reception())).await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+
+        // Después pasa a atender en site-2 en el mismo horario
+        let professional = store.get_professional("tenant-a", "prof-1").await.unwrap().unwrap();
+        store.put_professional(&Professional {
+            site_ids: vec!["site-1".into(), "site-2".into()],
+            schedule: every_day("site-2"),
+            ..professional
+        }).await.unwrap();
+        // Se solapa con la de site-1 aunque empiece más tarde y en otra sede
+        let second = handler(&store, create_request_at_site("site-2", "2025-10-01T10:15:00Z", reception())).await.unwrap();
+        assert_eq!(second.status(), StatusCode::CONFLICT);
+        let hold = handler(&store, request(Method::POST, "/bookings/holds", Some(json!({
+            "tenant_id": "tenant-a",
+            "site_id": "site-2",
+            "professional_id": "prof-1",
+            "treatment_id": "treat-1",
+            "start_time": "2025-10-01T10:30:00Z"
+        })), reception())).await.unwrap();
+        assert_eq!(hold.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_every_booking_change_writes_an_outbox_event() {
+        let store = store_with_treatment().await;
+        let uri = created_uri(&store, reception()).await;
+        let id = uri.trim_start_matches("/bookings/");
+
+        // Un intento fallido no deja evento
+        let conflict = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(conflict.status(), StatusCode::CONFLICT);
+
+        let moved = handler(&store, request(Method::PUT, &uri, Some(json!({"start_time": "2025-10-02T10:00:00Z"})), reception())).await.unwrap();
+        assert_eq!(moved.status(), StatusCode::OK);
+        let cancelled = handler(&store, request(Method::POST, &format!("{}/cancel", uri), None, reception())).await.unwrap();
+        assert_eq!(cancelled.status(), StatusCode::OK);
+
+        let events = store.list_booking_events(id).await.unwrap();
+        let types: Vec<_> = events.iter().map(|e| e.event_type.as_str()).collect();
+        assert_eq!(types, ["created", "rescheduled", "status_changed"]);
+        assert_eq!(events[1].previous_start_time.as_deref(), Some("2025-10-01T10:00:00+00:00"));
+        assert_eq!(events[1].booking.start_time, "2025-10-02T10:00:00+00:00");
+        assert_eq!(events[2].previous_status, Some(BookingStatus::Confirmed));
+        assert_eq!(events[2].booking.status, BookingStatus::CancelledByClinic);
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_booking_conflicts() {
+        let store = store_with_treatment().await;
+
+        // 10:00-10:40 (30 min + 10 de buffer) ocupa 10:00, 10:15 y 10:30
+        let first = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+
+        let overlapping = handler(&store, create_request_at("2025-10-01T10:15:00Z", reception())).await.unwrap();
+        assert_eq!(overlapping.status(), StatusCode::CONFLICT);
+
+        let adjacent = handler(&store, create_request_at("2025-10-01T10:45:00Z", reception())).await.unwrap();
+        assert_eq!(adjacent.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_reschedule_into_own_units_and_release_old_ones() {
+        let store = store_with_treatment().await;
+
+        let created = handler(&store, create_request(reception())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(created.body()).unwrap();
+        let uri = format!("/bookings/{}", body["id"].as_str().unwrap());
+
+        // Se solapa consigo misma: conserva 10:15/10:30, libera 10:00 y reclama 10:45
+        let moved = handler(&store, request(Method::PUT, &uri, Some(json!({"start_time": "2025-10-01T10:15:00Z"})), reception())).await.unwrap();
+        assert_eq!(moved.status(), StatusCode::OK);
+
+        let locks = store.list_slot_locks("tenant-a", "prof-1", "2025-10-01").await.unwrap();
+        let times: Vec<_> = locks.iter().map(|l| l.time.as_str()).collect();
+        assert_eq!(times, ["10:15", "10:30", "10:45"]);
+    }
+
+    #[tokio::test]
+    async fn test_only_times_within_schedule_and_opening_hours_are_bookable() {
+        let store = store_with_treatment().await;
+        let site = store.get_site("tenant-a", "site-1").await.unwrap().unwrap();
+        store.put_site(&Site {
+            opening_hours: serde_json::from_value(json!({"days": [{"weekday": "wed", "start": "08:00", "end": "12:00"}]})).unwrap(),
+            ..site
+        }).await.unwrap();
+
+        // 2025-10-01 es miércoles; horas locales de Bogotá
+        for outside in ["2025-10-01T07:30", "2025-10-01T11:45", "2025-10-02T09:00", "2025-10-01T23:30"] {
+            let response = handler(&store, create_request_at(outside, reception())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", outside);
+        }
+        let hold = handler(&store, request(Method::POST, "/bookings/holds", Some(json!({
+            "tenant_id": "tenant-a",
+            "site_id": "site-1",
+            "professional_id": "prof-1",
+            "treatment_id": "treat-1",
+            "start_time": "2025-10-01T07:30"
+        })), reception())).await.unwrap();
+        assert_eq!(hold.status(), StatusCode::BAD_REQUEST);
+
+        let created = handler(&store, create_request_at("2025-10-01T11:20", reception())).await.unwrap();
+        assert_eq!(created.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_slice(created.body()).unwrap();
+        let uri = format!("/bookings/{}", body["id"].as_str().unwrap());
+        let moved = handler(&store, request(Method::PUT, &uri, Some(json!({"start_time": "2025-10-01T12:00"})), reception())).await.unwrap();
+        assert_eq!(moved.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_releases_slot() {
+        let store = store_with_treatment().await;
+
+        let created = handler(&store, create_request(reception())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(created.body()).unwrap();
+        let uri = format!("/bookings/{}", body["id"].as_str().unwrap());
+
+        let cancelled = handler(&store, request(Method::DELETE, &uri, None, reception())).await.unwrap();
+        assert_eq!(cancelled.status(), StatusCode::OK);
+
+        let again = handler(&store, request(Method::DELETE, &uri, None, reception())).await.unwrap();
+        assert_eq!(again.status(), StatusCode::CONFLICT);
+
+        assert!(store.list_slot_locks("tenant-a", "prof-1", "2025-10-01").await.unwrap().is_empty());
+
+        let rebooked = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(rebooked.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_patient_cannot_cancel_someone_elses_booking() {
+        let store = store_with_treatment().await;
+
+        let created = handler(&store, create_request(reception())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(created.body()).unwrap();
+        let uri = format!("/bookings/{}", body["id"].as_str().unwrap());
+
+        let other_patient = bearer("tenant-a", &["Paciente"], "luis@example.com");
+        let response = handler(&store, request(Method::DELETE, &uri, None, other_patient)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    async fn created_uri(store: &MemoryStore, auth: String) -> String {
+        let created = handler(store, create_request(auth)).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(created.body()).unwrap();
+        format!("/bookings/{}", body["id"].as_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_records_each_transition() {
+        let store = store_with_treatment().await;
+        let uri = created_uri(&store, reception()).await;
+
+        for (action, status) in [("check-in", "checked_in"), ("start", "in_progress"), ("complete", "completed")] {
+            let response = handler(&store, request(Method::POST, &format!("{}/{}", uri, action), None, reception())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", action);
+            let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(body["status"], status);
+        }
+
+        // completed es final
+        let cancel = handler(&store, request(Method::POST, &format!("{}/cancel", uri), None, reception())).await.unwrap();
+        assert_eq!(cancel.status(), StatusCode::CONFLICT);
+
+        let history = handler(&store, request(Method::GET, &format!("{}/history", uri), None, reception())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(history.body()).unwrap();
+        let steps: Vec<_> = body["history"].as_array().unwrap().iter().map(|c| c["to"].as_str().unwrap()).collect();
+        assert_eq!(steps, ["checked_in", "in_progress", "completed"]);
+        assert_eq!(body["history"][0]["actor"], "front@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_patient_booking_is_pending_and_patient_cancellation_is_recorded() {
+        let store = store_with_treatment().await;
+        let patient = || bearer("tenant-a", &["Paciente"], "ana@example.com");
+
+        let created = handler(&store, create_request(patient())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(created.body()).unwrap();
+        assert_eq!(body["status"], "pending");
+        let uri = format!("/bookings/{}", body["id"].as_str().unwrap());
+
+        // El paciente no puede confirmarse a sí mismo
+        let confirm = handler(&store, request(Method::POST, &format!("{}/confirm", uri), None, patient())).await.unwrap();
+        assert_eq!(confirm.status(), StatusCode::FORBIDDEN);
+
+        let cancel = handler(&store, request(Method::POST, &format!("{}/cancel", uri), Some(json!({"reason": "Viaje"})), patient())).await.unwrap();
+        assert_eq!(cancel.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(cancel.body()).unwrap();
+        assert_eq!(body["status"], "cancelled_by_patient");
+        assert_eq!(body["reason"], "Viaje");
+        assert!(store.list_slot_locks("tenant-a", "prof-1", "2025-10-01").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_transition_fails_conditionally() {
+        let store = store_with_treatment().await;
+        let uri = created_uri(&store, reception()).await;
+        let id = uri.trim_start_matches("/bookings/");
+
+        let no_show = handler(&store, request(Method::POST, &format!("{}/no-show", uri), None, reception())).await.unwrap();
+        assert_eq!(no_show.status(), StatusCode::OK);
+
+        // Otra request que leyó `confirmed` antes del no-show
+        let stale = StatusChange {
+            booking_id: id.into(),
+            from: BookingStatus::Confirmed,
+            to: BookingStatus::CheckedIn,
+            actor: "front@example.com".into(),
+            reason: None,
+            at: chrono::Utc::now().to_rfc3339(),
+        };
+        let booking = store.get_booking(id).await.unwrap().unwrap();
+        let event = BookingEvent::status_changed(&booking, stale.from, &stale.at);
+        assert!(matches!(store.transition_booking(&stale, &[], &event).await, Err(StoreError::ConditionFailed)));
+        assert_eq!(store.list_status_changes(id).await.unwrap().len(), 1);
+
+        let reschedule = handler(&store, request(Method::PUT, &uri, Some(json!({"start_time": "2025-10-02T10:00:00Z"})), reception())).await.unwrap();
+        assert_eq!(reschedule.status(), StatusCode::CONFLICT);
+    }
+
+    fn list_request(params: &[(&str, &str)], auth: String) -> Request {
+        let query: HashMap<String, String> = params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        request(Method::GET, "/bookings", None, auth).with_query_string_parameters(QueryMap::from(query))
+    }
+
+    /// Recorre todas las páginas y devuelve los ids en orden de llegada.
+    async fn list_all(store: &MemoryStore, params: &[(&str, &str)]) -> Vec<String> {
+        let mut ids = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut page_params = params.to_vec();
+            if let Some(c) = &cursor {
+                page_params.push(("cursor", c));
+            }
+            let response = handler(store, list_request(&page_params, reception())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            ids.extend(body["bookings"].as_array().unwrap().iter().map(|b| b["id"].as_str().unwrap().to_string()));
+            match body["next_cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => return ids,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_bookings_filters_and_paginates() {
+        let store = store_with_treatment().await;
+        let mut ids = vec![];
+        for start in ["2025-10-01T10:00:00Z", "2025-10-01T14:00:00Z", "2025-10-02T10:00:00Z", "2025-10-03T10:00:00Z"] {
+            let created = handler(&store, create_request_at(start, reception())).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(created.body()).unwrap();
+            ids.push(body["id"].as_str().unwrap().to_string());
+        }
+        let cancel = handler(&store, request(Method::DELETE, &format!("/bookings/{}", ids[2]), None, reception())).await.unwrap();
+        assert_eq!(cancel.status(), StatusCode::OK);
+
+        // Días locales de Bogotá: del 1 al 2 de octubre, ambos inclusive
+        let filters = [("from_date", "2025-10-01"), ("to_date", "2025-10-02"), ("status", "confirmed"), ("limit", "1")];
+        let mut by_tenant = list_all(&store, &filters).await;
+        by_tenant.sort();
+        let mut expected = ids[..2].to_vec();
+        expected.sort();
+        assert_eq!(by_tenant, expected);
+
+        // Por profesional (GSI3) sale ordenado por inicio
+        let by_professional = list_all(&store, &[filters.as_slice(), &[("professional_id", "prof-1")]].concat()).await;
+        assert_eq!(by_professional, ids[..2]);
+
+        let other_site = list_all(&store, &[("site_id", "site-2")]).await;
+        assert!(other_site.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_bookings_rejects_bad_cursor_and_status() {
+        let store = store_with_treatment().await;
+        for params in [[("cursor", "no-es-un-cursor")], [("status", "cancelled_by_nobody")], [("from_date", "01/10/2025")]] {
+            let response = handler(&store, list_request(&params, reception())).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{:?}", params);
+        }
+
+        // Un cursor de la partición del tenant no sirve para la del profesional
+        handler(&store, create_request_at("2025-10-01T10:00:00Z", reception())).await.unwrap();
+        handler(&store, create_request_at("2025-10-01T14:00:00Z", reception())).await.unwrap();
+        let first = handler(&store, list_request(&[("limit", "1")], reception())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(first.body()).unwrap();
+        let cursor = body["next_cursor"].as_str().unwrap();
+        let mixed = handler(&store, list_request(&[("professional_id", "prof-1"), ("cursor", cursor)], reception())).await.unwrap();
+        assert_eq!(mixed.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_booking_by_id() {
+        let store = store_with_treatment().await;
+        let uri = created_uri(&store, reception()).await;
+
+        let response = handler(&store, request(Method::GET, &uri, None, reception())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(format!("/bookings/{}", body["id"].as_str().unwrap()), uri);
+
+        let stranger = handler(&store, request(Method::GET, &uri, None, bearer("tenant-a", &["Paciente"], "otro@example.com"))).await.unwrap();
+        assert_eq!(stranger.status(), StatusCode::FORBIDDEN);
+        let missing = handler(&store, request(Method::GET, "/bookings/nope", None, reception())).await.unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    fn with_key(mut request: Request, key: &str) -> Request {
+        request.headers_mut().insert("idempotency-key", key.parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_create_retry_with_same_key_replays_response() {
+        let store = store_with_treatment().await;
+
+        let first = handler(&store, with_key(create_request(reception()), "tap-1")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+        let retry = handler(&store, with_key(create_request(reception()), "tap-1")).await.unwrap();
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers()["idempotent-replayed"], "true");
+        assert_eq!(retry.body(), first.body());
+
+        // Misma key con otro body: se rechaza sin reservar nada
+        let other = handler(&store, with_key(create_request_at("2025-10-02T10:00:00Z", reception()), "tap-1")).await.unwrap();
+        assert_eq!(other.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(store.list_slot_locks("tenant-a", "prof-1", "2025-10-02").await.unwrap().is_empty());
+
+        // Sin key, el segundo intento es una reserva nueva y choca
+        let no_key = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(no_key.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_retry_with_same_key_is_not_a_conflict() {
+        let store = store_with_treatment().await;
+        let uri = created_uri(&store, reception()).await;
+
+        for _ in 0..2 {
+            let cancel = handler(&store, with_key(request(Method::DELETE, &uri, None, reception()), "cancel-1")).await.unwrap();
+            assert_eq!(cancel.status(), StatusCode::OK);
+        }
+        let id = uri.trim_start_matches("/bookings/");
+        assert_eq!(store.list_status_changes(id).await.unwrap().len(), 1);
+
+        let again = handler(&store, request(Method::DELETE, &uri, None, reception())).await.unwrap();
+        assert_eq!(again.status(), StatusCode::CONFLICT);
+    }
+
+    fn hold_request(auth: String) -> Request {
+        request(Method::POST, "/bookings/holds", Some(json!({
+            "tenant_id": "tenant-a",
+            "site_id": "site-1",
+            "professional_id": "prof-1",
+            "treatment_id": "treat-1",
+            "start_time": "2025-10-01T10:00:00Z"
+        })), auth)
+    }
+
+    fn confirm_request(hold_id: &str, token: &str, auth: String) -> Request {
+        request(Method::POST, &format!("/bookings/holds/{}/confirm", hold_id), Some(json!({
+            "hold_token": token,
+            "patient_name": "Ana",
+            "patient_email": "ana@example.com"
+        })), auth)
+    }
+
+    #[tokio::test]
+    async fn test_hold_blocks_slot_until_confirmed() {
+        let store = store_with_treatment().await;
+        let patient = || bearer("tenant-a", &["Paciente"], "ana@example.com");
+
+        let held = handler(&store, hold_request(patient())).await.unwrap();
+        assert_eq!(held.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_slice(held.body()).unwrap();
+        let (hold_id, token) = (body["hold_id"].as_str().unwrap(), body["hold_token"].as_str().unwrap());
+        assert_eq!(body["end_time"], "2025-10-01T10:40:00+00:00");
+
+        // Mientras dura la retención nadie más toma esas unidades
+        let other = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(other.status(), StatusCode::CONFLICT);
+
+        let wrong = handler(&store, confirm_request(hold_id, "otro-token", patient())).await.unwrap();
+        assert_eq!(wrong.status(), StatusCode::FORBIDDEN);
+
+        let confirmed = handler(&store, confirm_request(hold_id, token, patient())).await.unwrap();
+        assert_eq!(confirmed.status(), StatusCode::CREATED);
+        let booking: serde_json::Value = serde_json::from_slice(confirmed.body()).unwrap();
+        assert_eq!((booking["id"].as_str(), booking["status"].as_str()), (Some(hold_id), Some("pending")));
+
+        let locks = store.list_slot_locks("tenant-a", "prof-1", "2025-10-01").await.unwrap();
+        assert_eq!(locks.len(), 3);
+        assert!(locks.iter().all(|l| l.status == "reserved" && l.expires_at.is_none() && l.booking_id == hold_id));
+
+        let twice = handler(&store, confirm_request(hold_id, token, patient())).await.unwrap();
+        assert_eq!(twice.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_expired_hold_cannot_be_confirmed_and_frees_slot() {
+        let store = store_with_treatment().await;
+        let hold = SlotHold {
+            id: "hold-1".into(),
+            tenant_id: "tenant-a".into(),
+            site_id: "site-1".into(),
+            professional_id: "prof-1".into(),
+            treatment_id: "treat-1".into(),
+            start_time: "2025-10-01T10:00:00+00:00".into(),
+            end_time: "2025-10-01T10:40:00+00:00".into(),
+            token: "token-1".into(),
+            expires_at: chrono::Utc::now().timestamp() - 1,
+            created_by: "ana@example.com".into(),
+            created_at: String::new(),
+        };
+        store.create_hold(&hold, &hold_locks(&hold).unwrap()).await.unwrap();
+
+        let late = handler(&store, confirm_request("hold-1", "token-1", reception())).await.unwrap();
+        assert_eq!(late.status(), StatusCode::CONFLICT);
+
+        // El TTL aún no borró los locks, pero ya no bloquean
+        let booked = handler(&store, create_request(reception())).await.unwrap();
+        assert_eq!(booked.status(), StatusCode::CREATED);
+        let booking = Booking { id: "hold-1".into(), ..serde_json::from_slice(booked.body()).unwrap() };
+        let event = BookingEvent::created(&booking, &booking.created_at);
+        let contact = PatientContact { name: "Luis", email: Some("luis@example.com"), ..Default::default() };
+        let luis = new_patient("tenant-a", &contact, "now");
+        assert!(matches!(
+            store.confirm_hold(&hold, &booking, Some(&luis), &hold_locks(&hold).unwrap(), 0, &event).await,
+            Err(StoreError::ConditionFailed)
+        ));
+        assert!(store.get_patient("tenant-a", &luis.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_booking_notifications_show_delivery_status() {
+        let store = store_with_treatment().await;
+        let uri = created_uri(&store, reception()).await;
+        let booking_id = uri.trim_start_matches("/bookings/");
+        store.put_notification(&NotificationRecord {
+            id: "0000000000001-a".into(),
+            booking_id: booking_id.into(),
+            channel: Channel::Email,
+            notification_type: "confirmation".into(),
+            template: "booking-confirmation".into(),
+            recipient: "ana@example.com".into(),
+            provider_message_id: Some("ses-1".into()),
+            status: DeliveryStatus::Bounced,
+            error: Some("Permanent/NoEmail".into()),
+            attempted_at: "2025-09-30T10:00:00Z".into(),
+            updated_at: "2025-09-30T10:00:05Z".into(),
+        }).await.unwrap();
+
+        let response = handler(&store, request(Method::GET, &format!("{}/notifications", uri), None, reception())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["notifications"][0]["status"], "bounced");
+        assert_eq!(body["notifications"][0]["channel"], "email");
+        assert_eq!(body["notifications"][0]["error"], "Permanent/NoEmail");
+
+        let other_tenant = bearer("tenant-b", &["Recepcion"], "front@example.com");
+        let response = handler(&store, request(Method::GET, &format!("{}/notifications", uri), None, other_tenant)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
 }
@@ -0,0 +1,31 @@
+//! Vincula al directorio de pacientes las reservas creadas antes de que
+//! existiera. Se ejecuta una vez por tenant, desde una máquina con acceso a la
+//! tabla (TABLE_NAME y credenciales de AWS en el entorno):
+//!
+//! ```text
+//! cargo run --bin backfill-patients -- <tenant_id> [--dry-run]
+//! ```
+//!
+//! Repetirlo no duplica nada: las reservas ya vinculadas se saltan.
+
+use lambda_http::Error;
+use shared_lib::patients::backfill_bookings;
+use shared_lib::{init_tracing, DynamoStore};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    init_tracing();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let Some(tenant_id) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        return Err("uso: backfill-patients <tenant_id> [--dry-run]".into());
+    };
+
+    let repo = DynamoStore::from_env();
+    let now = chrono::Utc::now().to_rfc3339();
+    let report = backfill_bookings(&repo, tenant_id, dry_run, &now).await?;
+
+    tracing::info!(tenant_id = %tenant_id, dry_run, ?report, "Backfill finished");
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
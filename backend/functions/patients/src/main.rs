@@ -0,0 +1,374 @@
+//! Directorio de pacientes de cada clínica: alta, consulta, búsqueda, edición y
+//! baja. Las reservas se vinculan a un paciente al crearse (ver `bookings`).
+
+use lambda_http::{run, service_fn, Body, Request, Response, Error, RequestExt, RequestPayloadExt};
+use serde::Deserialize;
+use shared_lib::{init_tracing, success_response, created_response, ApiError, parse_jwt_claims, authorize, resolve_tenant, Action, Resource, Scope, JwtClaims};
+use shared_lib::models::{Guardian, Patient, PatientLookup};
+use shared_lib::repository::PageRequest;
+use shared_lib::{DynamoStore, Repository};
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+struct PatientRequest {
+    name: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    phone: Option<String>,
+    /// Documento de identidad; único en la clínica
+    #[serde(default)]
+    document_id: Option<String>,
+    #[serde(default)]
+    birth_date: Option<NaiveDate>,
+    /// Responsable del paciente (menores, p. ej.)
+    #[serde(default)]
+    guardian: Option<Guardian>,
+}
+
+async fn handler(repo: &dyn Repository, req: Request) -> Result<Response<Body>, Error> {
+    let result: Result<Response<Body>, ApiError> = async {
+        let method = req.method().as_str().to_string();
+        let path = req.uri().path().to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (method.as_str(), segments.as_slice()) {
+            ("POST", ["tenants", tenant_id, "patients"]) => create_patient(repo, &req, tenant_id).await,
+            ("GET", ["tenants", tenant_id, "patients"]) => list_patients(repo, &req, tenant_id).await,
+            ("GET", ["tenants", tenant_id, "patients", id]) => get_patient(repo, &req, tenant_id, id).await,
+            ("PUT", ["tenants", tenant_id, "patients", id]) => update_patient(repo, &req, tenant_id, id).await,
+            ("DELETE", ["tenants", tenant_id, "patients", id]) => delete_patient(repo, &req, tenant_id, id).await,
+            _ => Err(ApiError::NotFound("Método no soportado".into()))
+        }
+    }.await;
+
+    match result {
+        Ok(resp) => Ok(resp),
+        Err(api_err) => Ok(api_err.into_response())
+    }
+}
+
+async fn create_patient(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Create, Resource::Patient)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+
+    let now = chrono::Utc::now();
+    let patient = Patient {
+        id: Uuid::new_v4().to_string(),
+        tenant_id,
+        created_at: now.to_rfc3339(),
+        updated_at: now.to_rfc3339(),
+        ..patient_from_payload(req, now.date_naive())?
+    };
+    repo.create_patient(&patient).await
+        .map_err(|e| e.conflict("El documento ya pertenece a otro paciente"))?;
+
+    tracing::info!(tenant_id = %patient.tenant_id, patient_id = %patient.id, "Patient created");
+    created_response(patient)
+}
+
+/// El directorio por páginas (`limit`, `cursor`) o, con `email`, `phone` o
+/// `document`, los que tienen ese dato, ordenados por nombre y sin paginar.
+/// Con `q` se quedan los que contienen `q` en el nombre (en el directorio,
+/// dentro de cada página). Un paciente solo ve los que tienen su email de
+/// contacto (él y quienes tiene a su cargo).
+async fn list_patients(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Patient)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+
+    let lookup = [("email", PatientLookup::Email), ("phone", PatientLookup::Phone), ("document", PatientLookup::Document)]
+        .into_iter()
+        .find_map(|(name, lookup)| Some((lookup, query_param(req, name)?)));
+    let mut next_cursor = None;
+    let mut patients = if scope == Scope::Own {
+        let email = claims.email.clone()
+            .ok_or_else(|| ApiError::Forbidden("Email no presente en token".into()))?;
+        let own = repo.find_patients(&tenant_id, PatientLookup::Email, &email).await?;
+        match &lookup {
+            Some((lookup, value)) => own.into_iter()
+                .filter(|patient| patient.lookup_keys().contains(&(*lookup, lookup.normalize(value))))
+                .collect(),
+            None => own,
+        }
+    } else {
+        match &lookup {
+            Some((lookup, value)) => repo.find_patients(&tenant_id, *lookup, value).await?,
+            None => {
+                let limit = match query_param(req, "limit") {
+                    Some(raw) => Some(raw.parse::<usize>()
+                        .map_err(|_| ApiError::Validation("limit inválido".into()))?),
+                    None => None,
+                };
+                let page = repo.list_patients(&tenant_id, &PageRequest::new(limit, query_param(req, "cursor"))).await?;
+                next_cursor = page.next_cursor;
+                page.patients
+            }
+        }
+    };
+    if let Some(q) = query_param(req, "q") {
+        let q = q.to_lowercase();
+        patients.retain(|patient| patient.name.to_lowercase().contains(&q));
+    }
+    // Las páginas del directorio siguen el orden de la tabla para que el cursor sirva
+    if lookup.is_some() || scope == Scope::Own {
+        patients.sort_by_key(|patient| patient.name.to_lowercase());
+    }
+
+    success_response(serde_json::json!({"patients": patients, "count": patients.len(), "next_cursor": next_cursor}))
+}
+
+async fn get_patient(repo: &dyn Repository, req: &Request, tenant_id: &str, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Patient)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+
+    let patient = fetch_patient(repo, &tenant_id, id).await?;
+    ensure_own_patient(scope, &claims, &patient)?;
+
+    success_response(patient)
+}
+
+/// Reemplaza los datos del paciente; `id` y `created_at` se conservan.
+async fn update_patient(repo: &dyn Repository, req: &Request, tenant_id: &str, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Update, Resource::Patient)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+
+    let now = chrono::Utc::now();
+    let current = fetch_patient(repo, &tenant_id, id).await?;
+    let patient = Patient {
+        id: current.id.clone(),
+        tenant_id: current.tenant_id.clone(),
+        created_at: current.created_at.clone(),
+        updated_at: now.to_rfc3339(),
+        ..patient_from_payload(req, now.date_naive())?
+    };
+    repo.update_patient(&current, &patient).await
+        .map_err(|e| e.conflict("El documento ya pertenece a otro paciente, o el paciente cambió o se borró durante la edición"))?;
+
+    success_response(patient)
+}
+
+/// Borra al paciente del directorio. Sus reservas conservan nombre y contacto.
+async fn delete_patient(repo: &dyn Repository, req: &Request, tenant_id: &str, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Delete, Resource::Patient)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+
+    let patient = fetch_patient(repo, &tenant_id, id).await?;
+    repo.delete_patient(&patient).await
+        .map_err(|e| e.conflict("El paciente ya fue borrado, o cambió mientras se borraba"))?;
+
+    tracing::info!(tenant_id = %patient.tenant_id, patient_id = %patient.id, "Patient deleted");
+
+    success_response(serde_json::json!({
+        "message": "Paciente eliminado",
+        "patient_id": patient.id
+    }))
+}
+
+/// Paciente con los datos del body, sin id ni fechas.
+fn patient_from_payload(req: &Request, today: NaiveDate) -> Result<Patient, ApiError> {
+    let payload = req.payload::<PatientRequest>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+
+    let patient = Patient {
+        id: String::new(),
+        tenant_id: String::new(),
+        name: payload.name.trim().to_string(),
+        email: payload.email,
+        phone: payload.phone,
+        document_id: payload.document_id,
+        birth_date: payload.birth_date,
+        guardian: payload.guardian,
+        created_at: String::new(),
+        updated_at: String::new(),
+    };
+    patient.validate(today).map_err(ApiError::Validation)?;
+    Ok(patient)
+}
+
+/// Con alcance `Own` el paciente debe tener el email del token como contacto.
+fn ensure_own_patient(scope: Scope, claims: &JwtClaims, patient: &Patient) -> Result<(), ApiError> {
+    if scope != Scope::Own {
+        return Ok(());
+    }
+    match (claims.email.as_deref(), patient.contact_email()) {
+        (Some(email), Some(contact)) if email.eq_ignore_ascii_case(contact) => Ok(()),
+        _ => Err(ApiError::Forbidden("Solo puedes consultar tus propios datos".into())),
+    }
+}
+
+async fn fetch_patient(repo: &dyn Repository, tenant_id: &str, id: &str) -> Result<Patient, ApiError> {
+    repo.get_patient(tenant_id, id).await?
+        .ok_or_else(|| ApiError::NotFound("Paciente no encontrado".into()))
+}
+
+fn query_param(req: &Request, name: &str) -> Option<String> {
+    req.query_string_parameters_ref()
+        .and_then(|params| params.first(name))
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    init_tracing();
+    let repo = DynamoStore::from_env();
+    run(service_fn(|req| handler(&repo, req))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_http::http::{Method, StatusCode};
+    use lambda_http::aws_lambda_events::query_map::QueryMap;
+    use serde_json::json;
+    use shared_lib::testing::bearer;
+    use shared_lib::MemoryStore;
+    use std::collections::HashMap;
+
+    fn request(method: Method, uri: &str, body: Option<serde_json::Value>, auth: String) -> Request {
+        let mut request = Request::new(body.map(|b| Body::from(b.to_string())).unwrap_or(Body::Empty));
+        *request.method_mut() = method;
+        *request.uri_mut() = uri.parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", auth.parse().unwrap());
+        request
+    }
+
+    fn search(params: &[(&str, &str)], auth: String) -> Request {
+        let query: HashMap<String, String> = params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        request(Method::GET, "/tenants/tenant-a/patients", None, auth)
+            .with_query_string_parameters(QueryMap::from(query))
+    }
+
+    fn reception() -> String {
+        bearer("tenant-a", &["Recepcion"], "front@example.com")
+    }
+
+    async fn create(store: &MemoryStore, body: serde_json::Value) -> Response<Body> {
+        handler(store, request(Method::POST, "/tenants/tenant-a/patients", Some(body), reception())).await.unwrap()
+    }
+
+    async fn listed(store: &MemoryStore, request: Request) -> serde_json::Value {
+        let response = handler(store, request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    async fn names(store: &MemoryStore, request: Request) -> Vec<String> {
+        let body = listed(store, request).await;
+        body["patients"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap().to_string()).collect()
+    }
+
+    /// El directorio sin filtros va en el orden de la tabla, no por nombre.
+    async fn sorted_names(store: &MemoryStore, request: Request) -> Vec<String> {
+        let mut names = names(store, request).await;
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_patient_directory_crud_and_search() {
+        let store = MemoryStore::new();
+        let response = create(&store, json!({
+            "name": "Ana Pérez",
+            "email": "ana@example.com",
+            "phone": "+573001234567",
+            "document_id": "CC 1020",
+            "birth_date": "1990-05-01"
+        })).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ana: Patient = serde_json::from_slice(response.body()).unwrap();
+        let response = create(&store, json!({
+            "name": "Sofía Pérez",
+            "birth_date": "2018-02-10",
+            "guardian": {"name": "Ana Pérez", "relationship": "madre", "email": "ana@example.com"}
+        })).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // El documento identifica a un solo paciente
+        let duplicated = create(&store, json!({"name": "Otra", "document_id": "cc-1020"})).await;
+        assert_eq!(duplicated.status(), StatusCode::CONFLICT);
+        let future = create(&store, json!({"name": "Luis", "birth_date": "2999-01-01"})).await;
+        assert_eq!(future.status(), StatusCode::BAD_REQUEST);
+
+        assert_eq!(sorted_names(&store, search(&[], reception())).await, ["Ana Pérez", "Sofía Pérez"]);
+        assert_eq!(names(&store, search(&[("q", "sofí")], reception())).await, ["Sofía Pérez"]);
+        assert_eq!(names(&store, search(&[("email", "ANA@example.com")], reception())).await, ["Ana Pérez", "Sofía Pérez"]);
+        assert_eq!(names(&store, search(&[("document", "cc1020")], reception())).await, ["Ana Pérez"]);
+        assert!(names(&store, search(&[("phone", "+570000000")], reception())).await.is_empty());
+
+        let uri = format!("/tenants/tenant-a/patients/{}", ana.id);
+        let updated = handler(&store, request(Method::PUT, &uri, Some(json!({"name": "Ana María Pérez", "document_id": "CC 1021"})), reception())).await.unwrap();
+        assert_eq!(updated.status(), StatusCode::OK);
+        let fetched: Patient = serde_json::from_slice(handler(&store, request(Method::GET, &uri, None, reception())).await.unwrap().body()).unwrap();
+        assert_eq!((fetched.name.as_str(), fetched.email.as_deref(), fetched.created_at.as_str()), ("Ana María Pérez", None, ana.created_at.as_str()));
+        assert!(names(&store, search(&[("document", "CC 1020")], reception())).await.is_empty());
+        // El documento que dejó libre se puede usar; el nuevo no
+        let reused = create(&store, json!({"name": "Otra", "document_id": "CC 1020"})).await;
+        assert_eq!(reused.status(), StatusCode::CREATED);
+        let taken = create(&store, json!({"name": "Luis", "document_id": "cc1021"})).await;
+        assert_eq!(taken.status(), StatusCode::CONFLICT);
+
+        // Recepción no borra; administración sí
+        let forbidden = handler(&store, request(Method::DELETE, &uri, None, reception())).await.unwrap();
+        assert_eq!(forbidden.status(), StatusCode::FORBIDDEN);
+        let admin = bearer("tenant-a", &["Admin"], "admin@example.com");
+        let deleted = handler(&store, request(Method::DELETE, &uri, None, admin.clone())).await.unwrap();
+        assert_eq!(deleted.status(), StatusCode::OK);
+        let missing = handler(&store, request(Method::GET, &uri, None, admin)).await.unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_patients_only_see_themselves_and_their_dependents() {
+        let store = MemoryStore::new();
+        let ana: Patient = serde_json::from_slice(create(&store, json!({"name": "Ana", "email": "ana@example.com"})).await.body()).unwrap();
+        create(&store, json!({"name": "Sofía", "guardian": {"name": "Ana", "email": "ana@example.com"}})).await;
+        let luis: Patient = serde_json::from_slice(create(&store, json!({"name": "Luis", "email": "luis@example.com"})).await.body()).unwrap();
+
+        let patient = || bearer("tenant-a", &["Paciente"], "ana@example.com");
+        assert_eq!(names(&store, search(&[], patient())).await, ["Ana", "Sofía"]);
+        assert!(names(&store, search(&[("email", "luis@example.com")], patient())).await.is_empty());
+
+        let own = handler(&store, request(Method::GET, &format!("/tenants/tenant-a/patients/{}", ana.id), None, patient())).await.unwrap();
+        assert_eq!(own.status(), StatusCode::OK);
+        let other = handler(&store, request(Method::GET, &format!("/tenants/tenant-a/patients/{}", luis.id), None, patient())).await.unwrap();
+        assert_eq!(other.status(), StatusCode::FORBIDDEN);
+
+        // Otro tenant no accede al directorio
+        let foreign = handler(&store, search(&[], bearer("tenant-b", &["Admin"], "admin@b.com"))).await.unwrap();
+        assert_eq!(foreign.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_directory_is_listed_by_pages() {
+        let store = MemoryStore::new();
+        for name in ["Ana", "Luis", "Sofía"] {
+            assert_eq!(create(&store, json!({"name": name})).await.status(), StatusCode::CREATED);
+        }
+
+        let first = listed(&store, search(&[("limit", "2")], reception())).await;
+        assert_eq!(first["count"], 2);
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let second = listed(&store, search(&[("limit", "2"), ("cursor", cursor)], reception())).await;
+        assert_eq!(second["count"], 1);
+        assert!(second["next_cursor"].is_null());
+        let mut all: Vec<&str> = [&first, &second].iter()
+            .flat_map(|page| page["patients"].as_array().unwrap())
+            .map(|patient| patient["name"].as_str().unwrap())
+            .collect();
+        all.sort();
+        assert_eq!(all, ["Ana", "Luis", "Sofía"]);
+
+        for params in [[("limit", "x")], [("cursor", "no-es-un-cursor")]] {
+            let bad = handler(&store, search(&params, reception())).await.unwrap();
+            assert_eq!(bad.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -1,14 +1,18 @@
 use lambda_http::{run, service_fn, Body, Request, Response, Error, RequestExt, RequestPayloadExt};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use validator::Validate;
-use shared_lib::{init_tracing, success_response, created_response, ApiError, get_client, table_name};
-use aws_sdk_dynamodb::types::AttributeValue;
+use shared_lib::{init_tracing, success_response, created_response, ApiError, parse_jwt_claims, authorize, resolve_tenant, Action, Resource};
+use shared_lib::models::Professional;
+use shared_lib::schedule::WeeklySchedule;
+use shared_lib::{DynamoStore, Repository};
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize, Validate)]
 struct CreateProfessionalRequest {
+    /// Opcional: por defecto el tenant del token (override solo para super-admin)
+    #[serde(default)]
     #[validate(length(min = 1, max = 50))]
-    tenant_id: String,
+    tenant_id: Option<String>,
     
     #[validate(length(min = 3, max = 100))]
     name: String,
@@ -19,32 +23,32 @@ struct CreateProfessionalRequest {
     #[serde(default)]
     specialties: Vec<String>,
     
+    /// Sedes asignadas; por defecto las que aparecen en `schedule`
+    #[serde(default)]
+    site_ids: Option<Vec<String>>,
+    
     #[serde(default)]
-    schedule: Option<String>, // JSON con horarios
+    schedule: Option<WeeklySchedule>,
 }
 
-#[derive(Debug, Serialize)]
-struct Professional {
-    id: String,
-    tenant_id: String,
-    name: String,
-    email: String,
-    specialties: Vec<String>,
-    schedule: String,
-    status: String,
-    created_at: String,
+#[derive(Debug, Deserialize)]
+struct AssignSitesRequest {
+    site_ids: Vec<String>,
 }
 
-async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let result: Result<Response<Body>, ApiError> = (|| async {
-        let method = req.method().as_str();
+async fn handler(repo: &dyn Repository, req: Request) -> Result<Response<Body>, Error> {
+    let result: Result<Response<Body>, ApiError> = async {
+        let method = req.method().as_str().to_string();
+        let path = req.uri().path().to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
         
-        match method {
-            "POST" => create_professional(req).await,
-            "GET" => list_professionals(req).await,
+        match (method.as_str(), segments.as_slice()) {
+            ("POST", ["professionals"]) => create_professional(repo, req).await,
+            ("GET", ["professionals"]) => list_professionals(repo, req).await,
+            ("PUT", ["professionals", id, "sites"]) => assign_sites(repo, &req, id).await,
             _ => Err(ApiError::NotFound("Método no soportado".into()))
         }
-    })().await;
+    }.await;
     
     match result {
         Ok(resp) => Ok(resp),
@@ -52,93 +56,216 @@ async fn handler(req: Request) -> Result<Response<Body>, Error> {
     }
 }
 
-async fn create_professional(req: Request) -> Result<Response<Body>, ApiError> {
+async fn create_professional(repo: &dyn Repository, req: Request) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(&req).await?;
+    let scope = authorize(&claims, Action::Create, Resource::Professional)?;
+
     let payload = req.payload::<CreateProfessionalRequest>()?
         .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
     
     payload.validate()
         .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
+
+    let schedule = payload.schedule.unwrap_or_default();
+    schedule.validate()
+        .map_err(|e| ApiError::Validation(format!("schedule inválido: {}", e)))?;
+
+    let tenant_id = resolve_tenant(&claims, payload.tenant_id.as_deref(), scope)?;
+    
+    let site_ids = payload.site_ids
+        .unwrap_or_else(|| schedule.site_ids().into_iter().map(str::to_string).collect());
+    validate_sites(repo, &tenant_id, &site_ids, &schedule).await?;
     
     let prof_id = Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
     
-    let client = get_client().await;
-    
-    client.put_item()
-        .table_name(table_name())
-        .item("PK", AttributeValue::S(format!("TENANT#{}", payload.tenant_id)))
-        .item("SK", AttributeValue::S(format!("PROFESSIONAL#{}", prof_id)))
-        .item("GSI1PK", AttributeValue::S(format!("TENANT#{}", payload.tenant_id)))
-        .item("GSI1SK", AttributeValue::S(format!("PROFESSIONAL#{}", prof_id)))
-        .item("GSI3PK", AttributeValue::S(format!("PROFESSIONAL#{}", prof_id)))
-        .item("GSI3SK", AttributeValue::S("METADATA".to_string()))
-        .item("id", AttributeValue::S(prof_id.clone()))
-        .item("tenantId", AttributeValue::S(payload.tenant_id.clone()))
-        .item("name", AttributeValue::S(payload.name.clone()))
-        .item("email", AttributeValue::S(payload.email.clone()))
-        .item("specialties", AttributeValue::L(
-            payload.specialties.iter().map(|s| AttributeValue::S(s.clone())).collect()
-        ))
-        .item("schedule", AttributeValue::S(payload.schedule.unwrap_or_else(|| "{}".into())))
-        .item("status", AttributeValue::S("active".to_string()))
-        .item("createdAt", AttributeValue::S(now.clone()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB error: {}", e)))?;
-    
     let professional = Professional {
         id: prof_id,
-        tenant_id: payload.tenant_id,
+        tenant_id,
         name: payload.name,
         email: payload.email,
         specialties: payload.specialties,
-        schedule: "{}".into(),
+        site_ids,
+        schedule,
         status: "active".into(),
         created_at: now,
     };
+
+    repo.put_professional(&professional).await?;
     
     created_response(professional)
 }
 
-async fn list_professionals(req: Request) -> Result<Response<Body>, ApiError> {
-    let tenant_id = req.query_string_parameters_ref()
-        .and_then(|params| params.first("tenant_id"))
-        .ok_or_else(|| ApiError::Validation("tenant_id requerido".into()))?;
-    
-    let client = get_client().await;
-    
-    let result = client.query()
-        .table_name(table_name())
-        .key_condition_expression("PK = :pk AND begins_with(SK, :sk)")
-        .expression_attribute_values(":pk", AttributeValue::S(format!("TENANT#{}", tenant_id)))
-        .expression_attribute_values(":sk", AttributeValue::S("PROFESSIONAL#".to_string()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB query error: {}", e)))?;
+async fn list_professionals(repo: &dyn Repository, req: Request) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(&req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Professional)?;
+
+    // tenant_id por query es opcional y solo puede diferir del token para super-admin
+    let requested = req.query_string_parameters_ref()
+        .and_then(|params| params.first("tenant_id").map(str::to_string));
+    let tenant_id = resolve_tenant(&claims, requested.as_deref(), scope)?;
     
-    let professionals: Vec<Professional> = result.items.unwrap_or_default()
-        .iter()
-        .map(|item| Professional {
-            id: item.get("id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            tenant_id: item.get("tenantId").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            name: item.get("name").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            email: item.get("email").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            specialties: item.get("specialties").and_then(|v| v.as_l().ok())
-                .map(|list| list.iter().filter_map(|v| v.as_s().ok().map(|s| s.to_string())).collect())
-                .unwrap_or_default(),
-            schedule: item.get("schedule").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_else(|| "{}".into()),
-            status: item.get("status").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            created_at: item.get("createdAt").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-        })
-        .collect();
+    let professionals = repo.list_professionals(&tenant_id).await?;
     
     success_response(serde_json::json!({"professionals": professionals, "count": professionals.len()}))
 }
 
+/// Reemplaza las sedes asignadas. El horario vigente debe seguir cabiendo en ellas.
+async fn assign_sites(repo: &dyn Repository, req: &Request, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Update, Resource::Professional)?;
+    let tenant_id = resolve_tenant(&claims, None, scope)?;
+
+    let payload = req.payload::<AssignSitesRequest>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+
+    let professional = repo.get_professional(&tenant_id, id).await?
+        .ok_or_else(|| ApiError::NotFound("Profesional no encontrado".into()))?;
+
+    validate_sites(repo, &tenant_id, &payload.site_ids, &professional.schedule).await?;
+
+    let professional = Professional { site_ids: payload.site_ids, ..professional };
+    repo.put_professional(&professional).await?;
+
+    success_response(professional)
+}
+
+/// Cada sede debe existir y estar activa en el tenant, y el horario solo puede
+/// usar sedes asignadas.
+async fn validate_sites(repo: &dyn Repository, tenant_id: &str, site_ids: &[String], schedule: &WeeklySchedule) -> Result<(), ApiError> {
+    for site_id in site_ids {
+        match repo.get_site(tenant_id, site_id).await? {
+            Some(site) if site.is_active() => {}
+            Some(_) => return Err(ApiError::Validation(format!("La sede {} está inactiva", site_id))),
+            None => return Err(ApiError::Validation(format!("La sede {} no existe", site_id))),
+        }
+    }
+    if let Some(site_id) = schedule.site_ids().into_iter().find(|s| !site_ids.iter().any(|id| id == s)) {
+        return Err(ApiError::Validation(format!("schedule usa la sede {} que no está asignada", site_id)));
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     init_tracing();
-    run(service_fn(handler)).await
+    let repo = DynamoStore::from_env();
+    run(service_fn(|req| handler(&repo, req))).await
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_http::http::StatusCode;
+    use serde_json::json;
+    use shared_lib::models::Site;
+    use shared_lib::repository::SiteRepository;
+    use shared_lib::testing::bearer;
+    use shared_lib::MemoryStore;
+
+    async fn store_with_site(status: &str) -> MemoryStore {
+        let store = MemoryStore::new();
+        store.put_site(&Site {
+            id: "site-1".into(),
+            tenant_id: "tenant-a".into(),
+            name: "Sede Norte".into(),
+            address: "Calle 100".into(),
+            timezone: None,
+            opening_hours: Default::default(),
+            chairs: 2,
+            contact_email: None,
+            status: status.into(),
+            created_at: String::new(),
+        }).await.unwrap();
+        store
+    }
+
+    fn post(body: serde_json::Value) -> Request {
+        let mut request = Request::new(Body::from(body.to_string()));
+        *request.method_mut() = lambda_http::http::Method::POST;
+        *request.uri_mut() = "/professionals".parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Admin"], "admin@example.com").parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_create_professional_for_other_tenant_is_forbidden() {
+        let body = json!({
+            "tenant_id": "tenant-b",
+            "name": "Dra. Pérez",
+            "email": "perez@example.com"
+        }).to_string();
+
+        let mut request = Request::new(Body::from(body));
+        *request.method_mut() = lambda_http::http::Method::POST;
+        *request.uri_mut() = "/professionals".parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Admin"], "admin@example.com").parse().unwrap());
+
+        let response = handler(&MemoryStore::new(), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_create_professional_rejects_overlapping_schedule() {
+        let body = json!({
+            "name": "Dra. Pérez",
+            "email": "perez@example.com",
+            "schedule": {"days": [
+                {"weekday": "mon", "site_id": "site-1", "start": "09:00", "end": "13:00"},
+                {"weekday": "mon", "site_id": "site-2", "start": "12:00", "end": "16:00"}
+            ]}
+        }).to_string();
+
+        let mut request = Request::new(Body::from(body));
+        *request.method_mut() = lambda_http::http::Method::POST;
+        *request.uri_mut() = "/professionals".parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Admin"], "admin@example.com").parse().unwrap());
+
+        let response = handler(&MemoryStore::new(), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_professional_is_assigned_to_schedule_sites() {
+        let store = store_with_site("active").await;
+        let schedule = json!({"days": [{"weekday": "mon", "site_id": "site-1", "start": "09:00", "end": "13:00"}]});
 
+        let response = handler(&store, post(json!({
+            "name": "Dra. Pérez",
+            "email": "perez@example.com",
+            "schedule": schedule
+        }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["site_ids"], json!(["site-1"]));
+
+        // El horario no puede usar una sede fuera de las asignadas
+        let unassigned = handler(&store, post(json!({
+            "name": "Dr. Gómez",
+            "email": "gomez@example.com",
+            "site_ids": [],
+            "schedule": schedule
+        }))).await.unwrap();
+        assert_eq!(unassigned.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_professional_rejects_unknown_or_inactive_sites() {
+        let inactive = handler(&store_with_site("inactive").await, post(json!({
+            "name": "Dra. Pérez",
+            "email": "perez@example.com",
+            "site_ids": ["site-1"]
+        }))).await.unwrap();
+        assert_eq!(inactive.status(), StatusCode::BAD_REQUEST);
+
+        let unknown = handler(&store_with_site("active").await, post(json!({
+            "name": "Dra. Pérez",
+            "email": "perez@example.com",
+            "site_ids": ["site-9"]
+        }))).await.unwrap();
+        assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+    }
+}
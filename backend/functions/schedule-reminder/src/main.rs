@@ -1,143 +1,240 @@
 use lambda_http::{run, service_fn, Body, Request, Response, Error, RequestPayloadExt};
 use serde::{Deserialize, Serialize};
 use shared_lib::{init_tracing, success_response, ApiError};
-use aws_sdk_scheduler::types::{FlexibleTimeWindow, FlexibleTimeWindowMode, Target};
-use aws_sdk_scheduler::Client as SchedulerClient;
+use shared_lib::{DynamoStore, Repository};
+use shared_lib::timezone::parse_instant;
+use shared_lib::models::{preferred_channels, validate_phone, Channel};
+use shared_lib::reminders::{Reminder, ReminderOutcome, ReminderRequest, ReminderScheduler};
 
 #[derive(Debug, Deserialize)]
 struct ScheduleReminderRequest {
+  tenant_id: String,
+  /// Sede de la cita; si sobreescribe la zona horaria, manda sobre la del tenant
+  #[serde(default)]
+  site_id: Option<String>,
+  /// En `PUT /reminders/{booking_id}` lo da la ruta
+  #[serde(default)]
   booking_id: String,
-  appointment_time: String, // ISO8601
+  appointment_time: String, // ISO8601; sin offset = hora local de la clínica
   patient_email: String,
   patient_name: String,
+  /// E.164; sin él, los recordatorios por SMS o WhatsApp salen por email
+  #[serde(default)]
+  patient_phone: Option<String>,
+  /// Canales que prefiere el paciente
+  #[serde(default)]
+  notification_channels: Vec<Channel>,
 }
 
 #[derive(Debug, Serialize)]
 struct ScheduleReminderResponse {
   message: String,
-  schedule_name: String,
+  /// Primer recordatorio programado; se mantiene por compatibilidad
+  schedule_name: Option<String>,
+  schedule_names: Vec<String>,
   reminder_times: Vec<String>,
+  /// Un elemento por recordatorio programado, en el orden de la política del tenant
+  reminders: Vec<ScheduledReminder>,
+  /// Recordatorios cuya hora ya pasó y no se programaron
+  skipped: Vec<SkippedReminder>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScheduledReminder {
+  schedule_name: String,
+  offset: String,
+  channel: Channel,
+  template: String,
+  at: String,
 }
 
-async fn handler(req: Request) -> Result<Response<Body>, Error> {
-  let result: Result<Response<Body>, ApiError> = (|| async {
-    schedule_reminder(req).await
-  })().await;
-  
+#[derive(Debug, Serialize)]
+struct SkippedReminder {
+  offset: String,
+  at: String,
+}
+
+async fn handler(repo: &dyn Repository, req: Request) -> Result<Response<Body>, Error> {
+  let result: Result<Response<Body>, ApiError> = async {
+    let method = req.method().as_str().to_string();
+    let path = req.uri().path().to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method.as_str(), segments.as_slice()) {
+      // Crear es idempotente: repetirlo actualiza los schedules existentes
+      ("PUT", ["reminders", booking_id]) => schedule_reminder(repo, req.payload()?, Some(booking_id), true).await,
+      ("DELETE", ["reminders", booking_id]) => cancel_reminders(booking_id).await,
+      // Invocación histórica: POST en cualquier ruta
+      ("POST", _) => schedule_reminder(repo, req.payload()?, None, false).await,
+      _ => Err(ApiError::NotFound("Método no soportado".into())),
+    }
+  }.await;
+
   match result {
     Ok(resp) => Ok(resp),
     Err(api_err) => Ok(api_err.into_response())
   }
 }
 
-async fn schedule_reminder(req: Request) -> Result<Response<Body>, ApiError> {
-  let payload = req.payload::<ScheduleReminderRequest>()?
-    .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
-  
-  let appointment_time = chrono::DateTime::parse_from_rfc3339(&payload.appointment_time)
+/// Programa (o, con `replace`, reprograma) los recordatorios de una cita.
+async fn schedule_reminder(
+  repo: &dyn Repository,
+  payload: Option<ScheduleReminderRequest>,
+  booking_id: Option<&str>,
+  replace: bool,
+) -> Result<Response<Body>, ApiError> {
+  let mut payload = payload.ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+  if let Some(booking_id) = booking_id {
+    payload.booking_id = booking_id.to_string();
+  }
+  if payload.booking_id.is_empty() {
+    return Err(ApiError::Validation("booking_id requerido".into()));
+  }
+
+  let tenant = repo.get_tenant(&payload.tenant_id).await?
+    .ok_or_else(|| ApiError::NotFound("Tenant no encontrado".into()))?;
+  let tenant_tz = tenant.tz();
+  let tz = match &payload.site_id {
+    Some(site_id) => repo.get_site(&payload.tenant_id, site_id).await?
+      .map_or(tenant_tz, |site| site.tz(tenant_tz)),
+    None => tenant_tz,
+  };
+
+  let appointment_utc = parse_instant(&payload.appointment_time, tz)
     .map_err(|_| ApiError::Validation("appointment_time inválido (usar ISO8601)".into()))?;
-  
-  // Calcular tiempos de recordatorio: T-24h y T-2h
-  let reminder_24h = appointment_time - chrono::Duration::hours(24);
-  let reminder_2h = appointment_time - chrono::Duration::hours(2);
-  
-  let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
-  let scheduler_client = SchedulerClient::new(&config);
-  
-  let role_arn = std::env::var("SCHEDULER_ROLE_ARN")
-    .map_err(|_| ApiError::Internal(anyhow::anyhow!("SCHEDULER_ROLE_ARN no configurado")))?;
-  
-  let lambda_arn = std::env::var("NOTIFICATION_LAMBDA_ARN")
-    .map_err(|_| ApiError::Internal(anyhow::anyhow!("NOTIFICATION_LAMBDA_ARN no configurado")))?;
-  
-  // Crear schedule para recordatorio 24h
-  let schedule_name_24h = format!("booking-{}-24h", payload.booking_id);
-  
-  let payload_24h = serde_json::json!({
-    "type": "reminder",
-    "booking_id": payload.booking_id,
-    "patient_email": payload.patient_email,
-    "patient_name": payload.patient_name,
-    "hours_before": 24,
-    "appointment_date": appointment_time.format("%Y-%m-%d").to_string(),
-    "appointment_time": appointment_time.format("%H:%M").to_string()
-  });
-  
-  scheduler_client
-    .create_schedule()
-    .name(&schedule_name_24h)
-    .schedule_expression(format!("at({})", reminder_24h.format("%Y-%m-%dT%H:%M:%S")))
-    .flexible_time_window(
-      FlexibleTimeWindow::builder()
-        .mode(FlexibleTimeWindowMode::Off)
-        .build()
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-    )
-    .target(
-      Target::builder()
-        .arn(&lambda_arn)
-        .role_arn(&role_arn)
-        .input(payload_24h.to_string())
-        .build()
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-    )
-    .send()
-    .await
-    .map_err(|e| ApiError::Internal(anyhow::anyhow!("Scheduler error: {}", e)))?;
-  
-  tracing::info!(schedule_name = %schedule_name_24h, "24h reminder scheduled");
-  
-  // Crear schedule para recordatorio 2h
-  let schedule_name_2h = format!("booking-{}-2h", payload.booking_id);
-  
-  let payload_2h = serde_json::json!({
-    "type": "reminder",
-    "booking_id": payload.booking_id,
-    "patient_email": payload.patient_email,
-    "patient_name": payload.patient_name,
-    "hours_before": 2,
-    "appointment_date": appointment_time.format("%Y-%m-%d").to_string(),
-    "appointment_time": appointment_time.format("%H:%M").to_string()
-  });
-  
-  scheduler_client
-    .create_schedule()
-    .name(&schedule_name_2h)
-    .schedule_expression(format!("at({})", reminder_2h.format("%Y-%m-%dT%H:%M:%S")))
-    .flexible_time_window(
-      FlexibleTimeWindow::builder()
-        .mode(FlexibleTimeWindowMode::Off)
-        .build()
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-    )
-    .target(
-      Target::builder()
-        .arn(&lambda_arn)
-        .role_arn(&role_arn)
-        .input(payload_2h.to_string())
-        .build()
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?
-    )
-    .send()
-    .await
-    .map_err(|e| ApiError::Internal(anyhow::anyhow!("Scheduler error: {}", e)))?;
-  
-  tracing::info!(schedule_name = %schedule_name_2h, "2h reminder scheduled");
-  
-  let response = ScheduleReminderResponse {
-    message: "Recordatorios programados exitosamente".into(),
-    schedule_name: schedule_name_24h,
-    reminder_times: vec![
-      reminder_24h.to_rfc3339(),
-      reminder_2h.to_rfc3339(),
-    ],
+  if let Some(phone) = &payload.patient_phone {
+    validate_phone(phone).map_err(|e| ApiError::Validation(format!("patient_phone inválido: {}", e)))?;
+  }
+  let preferred = preferred_channels(&payload.notification_channels, &tenant.settings.channels, payload.patient_phone.as_deref());
+  let request = ReminderRequest {
+    booking_id: payload.booking_id,
+    patient_email: payload.patient_email,
+    patient_name: payload.patient_name,
+    patient_phone: payload.patient_phone,
+    preferred_channels: preferred,
+    appointment: appointment_utc,
+    tz,
+    rules: tenant.settings.reminders,
+    details: Default::default(),
+  };
+
+  let scheduler = ReminderScheduler::from_env().await?;
+  let outcome = if replace {
+    scheduler.reschedule(&request).await?
+  } else {
+    scheduler.schedule(&request).await?
   };
-  
-  success_response(response)
+
+  let message = if replace { "Recordatorios reprogramados exitosamente" } else { "Recordatorios programados exitosamente" };
+  success_response(schedule_response(message, &request, &outcome))
+}
+
+fn schedule_response(message: &str, request: &ReminderRequest, outcome: &ReminderOutcome) -> ScheduleReminderResponse {
+  let local = |r: &Reminder| r.at_utc.with_timezone(&request.tz).to_rfc3339();
+  let schedule_names: Vec<String> = outcome.scheduled.iter().map(|r| r.schedule_name(&request.booking_id)).collect();
+  ScheduleReminderResponse {
+    message: message.into(),
+    schedule_name: schedule_names.first().cloned(),
+    schedule_names,
+    reminder_times: outcome.scheduled.iter().map(local).collect(),
+    reminders: outcome.scheduled.iter().map(|r| ScheduledReminder {
+      schedule_name: r.schedule_name(&request.booking_id),
+      offset: r.label(),
+      channel: request.channel_for(&r.rule),
+      template: r.rule.template.clone(),
+      at: local(r),
+    }).collect(),
+    skipped: outcome.skipped.iter().map(|r| SkippedReminder { offset: r.label(), at: local(r) }).collect(),
+  }
+}
+
+/// Borra los recordatorios pendientes; sin recordatorios responde igual `200`.
+async fn cancel_reminders(booking_id: &str) -> Result<Response<Body>, ApiError> {
+  let scheduler = ReminderScheduler::from_env().await?;
+  let deleted = scheduler.cancel(booking_id).await?;
+
+  success_response(serde_json::json!({
+    "message": "Recordatorios cancelados",
+    "booking_id": booking_id,
+    "deleted": deleted
+  }))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
   init_tracing();
-  run(service_fn(handler)).await
+  let repo = DynamoStore::from_env();
+  run(service_fn(|req| handler(&repo, req))).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{DateTime, Utc};
+  use lambda_http::http::{Method, StatusCode};
+  use shared_lib::reminders::{plan_reminders, ReminderRule};
+  use shared_lib::repository::TenantRepository;
+  use shared_lib::testing::tenant;
+  use shared_lib::MemoryStore;
+
+  fn request(method: Method, uri: &str, body: serde_json::Value) -> Request {
+    let mut request = Request::new(Body::from(body.to_string()));
+    *request.method_mut() = method;
+    *request.uri_mut() = uri.parse().unwrap();
+    request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+    request
+  }
+
+  #[tokio::test]
+  async fn test_invalid_requests_fail_before_calling_scheduler() {
+    let store = MemoryStore::new();
+    store.put_tenant(&tenant("tenant-a")).await.unwrap();
+    let body = |booking_id: &str, appointment: &str| serde_json::json!({
+      "tenant_id": "tenant-a",
+      "booking_id": booking_id,
+      "appointment_time": appointment,
+      "patient_email": "ana@example.com",
+      "patient_name": "Ana"
+    });
+
+    let missing_id = handler(&store, request(Method::POST, "/", body("", "2025-10-02T10:00:00"))).await.unwrap();
+    assert_eq!(missing_id.status(), StatusCode::BAD_REQUEST);
+
+    let bad_time = handler(&store, request(Method::PUT, "/reminders/b1", body("", "mañana"))).await.unwrap();
+    assert_eq!(bad_time.status(), StatusCode::BAD_REQUEST);
+
+    let unknown = handler(&store, request(Method::PATCH, "/reminders/b1", body("b1", "2025-10-02T10:00:00"))).await.unwrap();
+    assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+  }
+
+  #[test]
+  fn test_response_lists_scheduled_and_skipped_reminders() {
+    let utc = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+    let request = ReminderRequest {
+      booking_id: "b1".into(),
+      patient_email: "ana@example.com".into(),
+      patient_name: "Ana".into(),
+      patient_phone: Some("+573001234567".into()),
+      preferred_channels: vec![],
+      appointment: utc("2025-10-02T15:00:00Z"),
+      tz: "America/Bogota".parse().unwrap(),
+      rules: serde_json::from_value(serde_json::json!([
+        {"hours_before": 24},
+        {"days_before": 0, "at": "07:00", "channel": "whatsapp"},
+        {"hours_before": 2},
+      ])).unwrap(),
+      details: Default::default(),
+    };
+    let planned = plan_reminders(request.appointment, request.tz, &request.rules);
+    let outcome = ReminderOutcome::split(planned, utc("2025-10-02T05:00:00Z"));
+
+    let response = schedule_response("ok", &request, &outcome);
+    assert_eq!(response.schedule_name.as_deref(), Some("booking-b1-d0-0700"));
+    assert_eq!(response.schedule_names, ["booking-b1-d0-0700", "booking-b1-2h"]);
+    assert_eq!(response.reminder_times, ["2025-10-02T07:00:00-05:00", "2025-10-02T08:00:00-05:00"]);
+    assert_eq!(response.reminders[0].channel, Channel::Whatsapp);
+    assert_eq!(response.reminders[1].template, ReminderRule::hours_before(2).template);
+    assert_eq!(response.skipped[0].offset, "24h");
+    assert_eq!(response.skipped[0].at, "2025-10-01T10:00:00-05:00");
+  }
 }
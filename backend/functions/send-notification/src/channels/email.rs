@@ -0,0 +1,77 @@
+use async_trait::async_trait;
+use aws_sdk_ses::error::SdkError;
+use aws_sdk_ses::operation::send_raw_email::SendRawEmailError;
+use aws_sdk_ses::primitives::Blob;
+use aws_sdk_ses::types::{MessageTag, RawMessage};
+
+use super::{Message, NotificationChannel};
+use crate::delivery::SendError;
+use crate::mime::Email;
+
+/// Email con `SendRawEmail`: HTML, texto plano y el adjunto de calendario.
+pub struct SesEmail {
+    ses: aws_sdk_ses::Client,
+    from: String,
+    /// `SES_CONFIGURATION_SET`: publica entregas, rebotes y quejas en SNS
+    configuration_set: Option<String>,
+}
+
+impl SesEmail {
+    pub fn from_env(config: &aws_config::SdkConfig) -> Self {
+        Self {
+            ses: aws_sdk_ses::Client::new(config),
+            from: std::env::var("SES_FROM_EMAIL").unwrap_or_else(|_| "noreply@nexioq.com".into()),
+            configuration_set: std::env::var("SES_CONFIGURATION_SET").ok(),
+        }
+    }
+}
+
+/// Rechazos de SES que se repiten igual en cada reintento: el mensaje (o la
+/// configuración del remitente) tiene que cambiar antes de volver a enviarlo.
+fn classify(err: SdkError<SendRawEmailError>) -> SendError {
+    let permanent = err.as_service_error().is_some_and(|e| {
+        e.is_message_rejected() || e.is_mail_from_domain_not_verified_exception() || e.is_configuration_set_does_not_exist_exception()
+    });
+    let err = anyhow::anyhow!("SES error: {}", aws_sdk_ses::error::DisplayErrorContext(&err));
+    if permanent {
+        SendError::Permanent(err)
+    } else {
+        SendError::Transient(err)
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for SesEmail {
+    async fn send(&self, message: &Message) -> Result<Option<String>, SendError> {
+        let raw = Email {
+            from: &self.from,
+            from_name: message.from_name.as_deref(),
+            reply_to: message.reply_to.as_deref(),
+            to: &message.to,
+            subject: &message.subject,
+            html: &message.body,
+            calendar: message.calendar.as_ref().map(|(event, method)| (event, *method)),
+            unsubscribe: message.unsubscribe.as_deref(),
+        }
+        .to_raw()
+        .map_err(SendError::Permanent)?;
+        let raw = RawMessage::builder().data(Blob::new(raw)).build().map_err(SendError::permanent)?;
+        // El feedback de SES trae los tags: así encuentra el intento que actualizar
+        let tag = |name: &str, value: &str| MessageTag::builder().name(name).value(value).build().map_err(SendError::permanent);
+        let mut request = self.ses
+            .send_raw_email()
+            .raw_message(raw)
+            .set_configuration_set_name(self.configuration_set.clone())
+            .tags(tag("booking_id", &message.booking_id)?)
+            .tags(tag("notification_id", &message.id)?);
+        // Para agregar los rebotes permanentes y quejas a la lista del tenant
+        if let Some(tenant_id) = &message.tenant_id {
+            request = request.tags(tag("tenant_id", tenant_id)?);
+        }
+        let output = request
+            .send()
+            .await
+            .map_err(classify)?;
+        Ok(Some(output.message_id().to_string()))
+    }
+}
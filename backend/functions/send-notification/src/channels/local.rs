@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use super::{Message, NotificationChannel};
+use crate::delivery::SendError;
+
+/// Reemplazo de los proveedores para desarrollo local y tests: escribe cada
+/// mensaje en un archivo de `dir` o, sin directorio, en stdout.
+pub struct LocalChannel {
+    dir: Option<PathBuf>,
+}
+
+impl LocalChannel {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    /// `NOTIFICATIONS_LOCAL_DIR`, o stdout.
+    pub fn from_env() -> Self {
+        Self::new(std::env::var_os("NOTIFICATIONS_LOCAL_DIR").map(PathBuf::from))
+    }
+}
+
+fn format(message: &Message) -> String {
+    let mut text = format!(
+        "Channel: {}\nTo: {}\nTemplate: {}\n",
+        message.channel.as_str(),
+        message.to,
+        message.template
+    );
+    if !message.subject.is_empty() {
+        text.push_str(&format!("Subject: {}\n", message.subject));
+    }
+    if let Some((event, method)) = &message.calendar {
+        text.push_str(&format!("Calendar: {} {} #{}\n", method.as_str(), event.uid, event.sequence));
+    }
+    text.push('\n');
+    text.push_str(&message.body);
+    text.push('\n');
+    text
+}
+
+#[async_trait]
+impl NotificationChannel for LocalChannel {
+    async fn send(&self, message: &Message) -> Result<Option<String>, SendError> {
+        let text = format(message);
+        let Some(dir) = &self.dir else {
+            println!("{}", text);
+            return Ok(None);
+        };
+        // Un archivo por mensaje, ordenados por envío
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = dir.join(format!("{}-{}-{}.txt", stamp, message.booking_id, message.channel.as_str()));
+        tokio::fs::create_dir_all(dir).await.map_err(SendError::transient)?;
+        tokio::fs::write(&path, text).await.map_err(SendError::transient)?;
+        Ok(None)
+    }
+}
@@ -0,0 +1,113 @@
+//! Canales de envío. Cada notificación trae su canal (`email` si no dice
+//! nada); se renderiza con las plantillas de ese canal y la entrega su
+//! implementación de `NotificationChannel`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use shared_lib::ics::{CalendarEvent, Method};
+use shared_lib::models::{Channel, Locale};
+
+use crate::delivery::SendError;
+
+mod email;
+mod local;
+mod sms;
+mod whatsapp;
+
+pub use email::SesEmail;
+pub use local::LocalChannel;
+pub use sms::TwilioSms;
+pub use whatsapp::WhatsappCloud;
+
+/// Notificación renderizada para un canal.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Id del intento en el registro de entregas de la reserva
+    pub id: String,
+    pub channel: Channel,
+    pub tenant_id: Option<String>,
+    pub booking_id: String,
+    /// Email o teléfono E.164, según el canal
+    pub to: String,
+    /// Asunto del email; los otros canales no lo usan
+    pub subject: String,
+    /// Email: HTML; SMS: el texto; WhatsApp: un parámetro por línea
+    pub body: String,
+    /// Plantilla con la que se renderizó
+    pub template: String,
+    /// Adjunto de calendario, solo en email
+    pub calendar: Option<(CalendarEvent, Method)>,
+    /// URL de baja en un clic, solo en email
+    pub unsubscribe: Option<String>,
+    /// Idioma en que se renderizó
+    pub locale: Locale,
+    /// Nombre del remitente y dirección de respuesta de la clínica, solo en email
+    pub from_name: Option<String>,
+    pub reply_to: Option<String>,
+}
+
+#[async_trait]
+pub trait NotificationChannel: Send + Sync {
+    /// Envía el mensaje; devuelve el id que le dio el proveedor, si da uno.
+    async fn send(&self, message: &Message) -> Result<Option<String>, SendError>;
+}
+
+/// Implementación de cada canal. Un canal sin implementación (sin
+/// credenciales configuradas) no se puede enviar.
+#[derive(Default, Clone)]
+pub struct Channels(HashMap<Channel, Arc<dyn NotificationChannel>>);
+
+impl Channels {
+    pub fn with(mut self, channel: Channel, implementation: Arc<dyn NotificationChannel>) -> Self {
+        self.0.insert(channel, implementation);
+        self
+    }
+
+    pub fn get(&self, channel: Channel) -> Option<&dyn NotificationChannel> {
+        self.0.get(&channel).map(|c| c.as_ref())
+    }
+
+    /// Con `NOTIFICATIONS_SINK=local` todos los canales escriben en
+    /// `NOTIFICATIONS_LOCAL_DIR` (o stdout) en lugar de enviar. Si no, SES para
+    /// email y Twilio y WhatsApp Cloud si tienen sus credenciales.
+    pub fn from_env(config: &aws_config::SdkConfig) -> Self {
+        if std::env::var("NOTIFICATIONS_SINK").as_deref() == Ok("local") {
+            let local: Arc<dyn NotificationChannel> = Arc::new(LocalChannel::from_env());
+            return [Channel::Email, Channel::Sms, Channel::Whatsapp]
+                .into_iter()
+                .fold(Self::default(), |channels, channel| channels.with(channel, local.clone()));
+        }
+
+        let mut channels = Self::default().with(Channel::Email, Arc::new(SesEmail::from_env(config)));
+        match TwilioSms::from_env() {
+            Some(sms) => channels = channels.with(Channel::Sms, Arc::new(sms)),
+            None => tracing::info!("TWILIO_* no configurado; SMS deshabilitado"),
+        }
+        match WhatsappCloud::from_env() {
+            Some(whatsapp) => channels = channels.with(Channel::Whatsapp, Arc::new(whatsapp)),
+            None => tracing::info!("WHATSAPP_* no configurado; WhatsApp deshabilitado"),
+        }
+        channels
+    }
+}
+
+/// Respuesta de una API HTTP de mensajería: sin respuesta, 429 y 5xx se
+/// reintentan; cualquier otro 4xx no va a cambiar. Si es exitosa devuelve el
+/// body JSON.
+async fn check_response(provider: &str, response: reqwest::Result<reqwest::Response>) -> Result<serde_json::Value, SendError> {
+    let response = response.map_err(|e| SendError::transient(anyhow::anyhow!("{} error: {}", provider, e)))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if status.is_success() {
+        // El mensaje ya se aceptó: un body inesperado solo deja el envío sin id
+        return Ok(serde_json::from_str(&body).unwrap_or_default());
+    }
+    let err = anyhow::anyhow!("{} respondió {}: {}", provider, status, body);
+    if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        Err(SendError::Transient(err))
+    } else {
+        Err(SendError::Permanent(err))
+    }
+}
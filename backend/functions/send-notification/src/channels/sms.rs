@@ -0,0 +1,41 @@
+use async_trait::async_trait;
+
+use super::{check_response, Message, NotificationChannel};
+use crate::delivery::SendError;
+
+/// SMS con la API de mensajes de Twilio.
+pub struct TwilioSms {
+    http: reqwest::Client,
+    account_sid: String,
+    auth_token: String,
+    /// Número (o Messaging Service SID) remitente
+    from: String,
+}
+
+impl TwilioSms {
+    /// `TWILIO_ACCOUNT_SID`, `TWILIO_AUTH_TOKEN` y `TWILIO_FROM`; sin alguno
+    /// no hay SMS.
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            http: reqwest::Client::new(),
+            account_sid: std::env::var("TWILIO_ACCOUNT_SID").ok()?,
+            auth_token: std::env::var("TWILIO_AUTH_TOKEN").ok()?,
+            from: std::env::var("TWILIO_FROM").ok()?,
+        })
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for TwilioSms {
+    async fn send(&self, message: &Message) -> Result<Option<String>, SendError> {
+        let url = format!("https://api.twilio.com/2010-04-01/Accounts/{}/Messages.json", self.account_sid);
+        let response = self.http
+            .post(url)
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&[("To", message.to.as_str()), ("From", self.from.as_str()), ("Body", message.body.as_str())])
+            .send()
+            .await;
+        let body = check_response("Twilio", response).await?;
+        Ok(body["sid"].as_str().map(String::from))
+    }
+}
@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use shared_lib::models::Locale;
+
+use super::{check_response, Message, NotificationChannel};
+use crate::delivery::SendError;
+
+const GRAPH_API: &str = "https://graph.facebook.com/v20.0";
+
+/// WhatsApp Business con la Cloud API de Meta. Los mensajes que inicia la
+/// clínica tienen que ser plantillas aprobadas en Meta: se usa la del mismo
+/// nombre que la plantilla local (`booking-reminder` → `booking_reminder`) y
+/// la plantilla local da sus parámetros, uno por línea.
+pub struct WhatsappCloud {
+    http: reqwest::Client,
+    phone_number_id: String,
+    access_token: String,
+}
+
+impl WhatsappCloud {
+    /// `WHATSAPP_PHONE_NUMBER_ID` y `WHATSAPP_ACCESS_TOKEN`; sin alguno no hay
+    /// WhatsApp.
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            http: reqwest::Client::new(),
+            phone_number_id: std::env::var("WHATSAPP_PHONE_NUMBER_ID").ok()?,
+            access_token: std::env::var("WHATSAPP_ACCESS_TOKEN").ok()?,
+        })
+    }
+}
+
+/// Código de idioma de Meta de la plantilla aprobada.
+fn language(locale: Locale) -> &'static str {
+    match locale {
+        Locale::Es => "es",
+        Locale::En => "en",
+        Locale::Pt => "pt_BR",
+    }
+}
+
+/// Cuerpo de `POST /{phone_number_id}/messages` para una plantilla, en el
+/// idioma del mensaje.
+fn template_message(message: &Message) -> Value {
+    let parameters: Vec<Value> = message.body
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| json!({ "type": "text", "text": line }))
+        .collect();
+    json!({
+        "messaging_product": "whatsapp",
+        "to": message.to.trim_start_matches('+'),
+        "type": "template",
+        "template": {
+            "name": message.template.replace('-', "_"),
+            "language": { "code": language(message.locale) },
+            "components": [{ "type": "body", "parameters": parameters }]
+        }
+    })
+}
+
+#[async_trait]
+impl NotificationChannel for WhatsappCloud {
+    async fn send(&self, message: &Message) -> Result<Option<String>, SendError> {
+        let response = self.http
+            .post(format!("{}/{}/messages", GRAPH_API, self.phone_number_id))
+            .bearer_auth(&self.access_token)
+            .header("content-type", "application/json")
+            .body(template_message(message).to_string())
+            .send()
+            .await;
+        let body = check_response("WhatsApp", response).await?;
+        Ok(body["messages"][0]["id"].as_str().map(String::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_lib::models::Channel;
+
+    #[test]
+    fn test_template_message_has_one_parameter_per_line() {
+        let message = Message {
+            id: "n1".into(),
+            channel: Channel::Whatsapp,
+            tenant_id: None,
+            booking_id: "b1".into(),
+            to: "+573001234567".into(),
+            subject: String::new(),
+            body: "Ana\nClínica A\n\n2025-10-01\n".into(),
+            template: "booking-reminder".into(),
+            calendar: None,
+            unsubscribe: None,
+            locale: Locale::Pt,
+            from_name: None,
+            reply_to: None,
+        };
+        let body = template_message(&message);
+        assert_eq!(body["to"], "573001234567");
+        assert_eq!(body["template"]["name"], "booking_reminder");
+        assert_eq!(body["template"]["language"]["code"], "pt_BR");
+        let parameters = &body["template"]["components"][0]["parameters"];
+        assert_eq!(*parameters, json!([
+            { "type": "text", "text": "Ana" },
+            { "type": "text", "text": "Clínica A" },
+            { "type": "text", "text": "2025-10-01" }
+        ]));
+    }
+}
@@ -0,0 +1,26 @@
+//! Lo que se consulta antes de enviar: la lista de supresión del tenant
+//! (rebotes permanentes y quejas) y las preferencias del paciente, que
+//! incluyen las bajas hechas con el enlace firmado.
+
+use shared_lib::models::{Channel, ContactPreferences, MessageCategory};
+use shared_lib::{Repository, StoreError};
+
+/// Motivo por el que el mensaje no se debe enviar, o `None` si se puede.
+/// `preferences` son las del paciente (las de por defecto si no tiene), o
+/// `None` si no se sabe quién es.
+pub async fn blocked(
+    repo: &dyn Repository,
+    tenant_id: &str,
+    channel: Channel,
+    category: MessageCategory,
+    recipient: &str,
+    preferences: Option<&ContactPreferences>,
+) -> Result<Option<String>, StoreError> {
+    if let Some(suppression) = repo.get_suppression(tenant_id, channel, recipient).await? {
+        return Ok(Some(format!("{} en la lista de supresión ({})", suppression.address, suppression.reason.as_str())));
+    }
+    if preferences.is_some_and(|preferences| !preferences.allows(channel, category)) {
+        return Ok(Some(format!("El paciente no acepta {} por {}", category.as_str(), channel.as_str())));
+    }
+    Ok(None)
+}
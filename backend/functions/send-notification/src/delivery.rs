@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+use aws_sdk_sqs::types::MessageAttributeValue;
+
+/// Por qué no se pudo enviar una notificación, para decidir si se reintenta.
+#[derive(Debug)]
+pub enum SendError {
+    /// Reintentar no cambia nada (JSON inválido, sin destinatario, rechazada por
+    /// el proveedor): el mensaje va directo a la cola de mensajes muertos
+    Permanent(anyhow::Error),
+    /// Throttling o caída del proveedor: el mensaje vuelve a la cola
+    Transient(anyhow::Error),
+}
+
+impl SendError {
+    pub fn permanent(err: impl Into<anyhow::Error>) -> Self {
+        SendError::Permanent(err.into())
+    }
+
+    pub fn transient(err: impl Into<anyhow::Error>) -> Self {
+        SendError::Transient(err.into())
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Permanent(e) => write!(f, "permanent: {}", e),
+            SendError::Transient(e) => write!(f, "transient: {}", e),
+        }
+    }
+}
+
+#[async_trait]
+pub trait DeadLetters: Send + Sync {
+    /// Deja en la cola de mensajes muertos un mensaje que no se puede enviar.
+    async fn dead_letter(&self, body: &str, reason: &str) -> anyhow::Result<()>;
+}
+
+/// La DLQ de `notifications`.
+pub struct SqsDeadLetters {
+    sqs: aws_sdk_sqs::Client,
+    /// `NOTIFICATIONS_DLQ_URL`; sin ella los fallos permanentes se reportan
+    /// como fallidos y llegan a la DLQ por la redrive policy de la cola
+    dlq_url: Option<String>,
+}
+
+impl SqsDeadLetters {
+    pub fn from_env(config: &aws_config::SdkConfig) -> Self {
+        Self {
+            sqs: aws_sdk_sqs::Client::new(config),
+            dlq_url: std::env::var("NOTIFICATIONS_DLQ_URL").ok(),
+        }
+    }
+}
+
+#[async_trait]
+impl DeadLetters for SqsDeadLetters {
+    async fn dead_letter(&self, body: &str, reason: &str) -> anyhow::Result<()> {
+        let dlq_url = self.dlq_url.as_deref().ok_or_else(|| anyhow::anyhow!("NOTIFICATIONS_DLQ_URL no configurado"))?;
+        let reason = MessageAttributeValue::builder().data_type("String").string_value(reason).build()?;
+        self.sqs
+            .send_message()
+            .queue_url(dlq_url)
+            .message_body(body)
+            .message_attributes("failure_reason", reason)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("SQS error: {}", e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! Feedback de SES: el configuration set publica en SNS las entregas, rebotes
+//! y quejas de cada email, y SNS invoca esta función. Los tags `booking_id` y
+//! `notification_id` del email dicen qué intento actualizar. Los rebotes
+//! permanentes y las quejas agregan las direcciones a la lista de supresión
+//! del tenant (tag `tenant_id`, o el de la reserva).
+
+use chrono::Utc;
+use serde_json::Value;
+use shared_lib::models::{Channel, DeliveryStatus, Suppression, SuppressionReason};
+use shared_lib::{Repository, StoreError};
+
+/// Actualización de un intento de envío.
+#[derive(Debug, PartialEq)]
+pub struct Feedback {
+    pub booking_id: String,
+    pub notification_id: String,
+    pub status: DeliveryStatus,
+    pub detail: Option<String>,
+    pub at: String,
+    pub tenant_id: Option<String>,
+    /// Si las direcciones van a la lista de supresión, y por qué
+    pub suppress: Option<SuppressionReason>,
+    pub recipients: Vec<String>,
+}
+
+/// Invocación de SNS en lugar de SQS o directa.
+pub fn is_sns_event(payload: &Value) -> bool {
+    payload["Records"][0]["EventSource"] == "aws:sns"
+}
+
+fn tag(mail: &Value, name: &str) -> Option<String> {
+    mail["tags"][name][0].as_str().map(String::from)
+}
+
+/// Evento de SES (`eventType` del configuration set o `notificationType` de
+/// las notificaciones de identidad). Los que no cambian el estado, o de emails
+/// sin tags, se ignoran.
+pub fn parse(message: &Value) -> Option<Feedback> {
+    let kind = message["eventType"].as_str().or_else(|| message["notificationType"].as_str())?;
+    let (status, detail, suppress, recipients, event) = match kind {
+        "Delivery" => (DeliveryStatus::Delivered, None, None, &Value::Null, &message["delivery"]),
+        "Bounce" => {
+            let bounce = &message["bounce"];
+            let mut detail = format!(
+                "{}/{}",
+                bounce["bounceType"].as_str().unwrap_or("Undetermined"),
+                bounce["bounceSubType"].as_str().unwrap_or("Undetermined")
+            );
+            if let Some(diagnostic) = bounce["bouncedRecipients"][0]["diagnosticCode"].as_str() {
+                detail.push_str(": ");
+                detail.push_str(diagnostic);
+            }
+            // Un rebote transitorio (buzón lleno) puede funcionar la próxima vez
+            let suppress = (bounce["bounceType"] == "Permanent").then_some(SuppressionReason::Bounce);
+            (DeliveryStatus::Bounced, Some(detail), suppress, &bounce["bouncedRecipients"], bounce)
+        }
+        "Complaint" => {
+            let complaint = &message["complaint"];
+            let detail = complaint["complaintFeedbackType"].as_str().map(String::from);
+            let recipients = &complaint["complainedRecipients"];
+            (DeliveryStatus::Complained, detail, Some(SuppressionReason::Complaint), recipients, complaint)
+        }
+        _ => return None,
+    };
+    let mail = &message["mail"];
+    Some(Feedback {
+        booking_id: tag(mail, "booking_id")?,
+        notification_id: tag(mail, "notification_id")?,
+        status,
+        detail,
+        at: event["timestamp"].as_str().map(String::from).unwrap_or_else(|| Utc::now().to_rfc3339()),
+        tenant_id: tag(mail, "tenant_id"),
+        suppress,
+        recipients: recipients
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r["emailAddress"].as_str().map(String::from))
+            .collect(),
+    })
+}
+
+/// Aplica el feedback de un evento de SNS; devuelve cuántos intentos se
+/// actualizaron. Un error del store falla la invocación para que SNS la
+/// reintente.
+pub async fn apply(repo: &dyn Repository, payload: &Value) -> Result<usize, StoreError> {
+    let mut updated = 0;
+    for record in payload["Records"].as_array().into_iter().flatten() {
+        let message = record["Sns"]["Message"].as_str().and_then(|raw| serde_json::from_str::<Value>(raw).ok());
+        let Some(feedback) = message.as_ref().and_then(parse) else {
+            tracing::debug!("Ignoring SES event without notification tags");
+            continue;
+        };
+        match repo
+            .update_notification_status(&feedback.booking_id, &feedback.notification_id, feedback.status, feedback.detail.as_deref(), &feedback.at)
+            .await
+        {
+            Ok(()) => updated += 1,
+            // Email enviado antes del registro de entregas, o feedback repetido
+            // o atrasado (una entrega después de la queja): no retrocede el estado
+            Err(StoreError::ConditionFailed) => {
+                tracing::warn!(
+                    booking_id = %feedback.booking_id,
+                    notification_id = %feedback.notification_id,
+                    status = feedback.status.as_str(),
+                    "Feedback for unknown notification or older than its status; ignored"
+                );
+            }
+            Err(e) => return Err(e),
+        }
+        if let Some(reason) = feedback.suppress {
+            suppress(repo, &feedback, reason).await?;
+        }
+    }
+    Ok(updated)
+}
+
+async fn suppress(repo: &dyn Repository, feedback: &Feedback, reason: SuppressionReason) -> Result<(), StoreError> {
+    let tenant_id = match &feedback.tenant_id {
+        Some(tenant_id) => Some(tenant_id.clone()),
+        None => repo.get_booking(&feedback.booking_id).await?.map(|booking| booking.tenant_id),
+    };
+    let Some(tenant_id) = tenant_id else {
+        tracing::warn!(booking_id = %feedback.booking_id, "Unknown tenant; address not suppressed");
+        return Ok(());
+    };
+    for address in &feedback.recipients {
+        repo.put_suppression(&Suppression {
+            tenant_id: tenant_id.clone(),
+            channel: Channel::Email,
+            address: address.clone(),
+            reason,
+            detail: feedback.detail.clone(),
+            created_at: feedback.at.clone(),
+        })
+        .await?;
+        tracing::info!(tenant_id = %tenant_id, reason = reason.as_str(), "Address added to suppression list");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ses_event(event_type: &str, detail: Value) -> Value {
+        let mut event = json!({
+            "eventType": event_type,
+            "mail": {
+                "messageId": "ses-1",
+                "tags": { "booking_id": ["b1"], "notification_id": ["n1"], "ses:configuration-set": ["turnaki"] }
+            }
+        });
+        event[event_type.to_lowercase()] = detail;
+        event
+    }
+
+    #[test]
+    fn test_parse_ses_events() {
+        let delivery = parse(&ses_event("Delivery", json!({ "timestamp": "2025-10-01T10:00:05Z" }))).unwrap();
+        assert_eq!(delivery, Feedback {
+            booking_id: "b1".into(),
+            notification_id: "n1".into(),
+            status: DeliveryStatus::Delivered,
+            detail: None,
+            at: "2025-10-01T10:00:05Z".into(),
+            tenant_id: None,
+            suppress: None,
+            recipients: vec![],
+        });
+
+        let bounce = parse(&ses_event("Bounce", json!({
+            "bounceType": "Permanent",
+            "bounceSubType": "General",
+            "bouncedRecipients": [{ "emailAddress": "ana@example.com", "diagnosticCode": "smtp; 550 5.1.1 user unknown" }],
+            "timestamp": "2025-10-01T10:00:05Z"
+        })))
+        .unwrap();
+        assert_eq!(bounce.status, DeliveryStatus::Bounced);
+        assert_eq!(bounce.detail.as_deref(), Some("Permanent/General: smtp; 550 5.1.1 user unknown"));
+        assert_eq!(bounce.suppress, Some(SuppressionReason::Bounce));
+        assert_eq!(bounce.recipients, ["ana@example.com"]);
+
+        let soft = parse(&ses_event("Bounce", json!({ "bounceType": "Transient", "bounceSubType": "MailboxFull" }))).unwrap();
+        assert_eq!(soft.status, DeliveryStatus::Bounced);
+        assert_eq!(soft.suppress, None);
+
+        let complaint = parse(&ses_event("Complaint", json!({
+            "complaintFeedbackType": "abuse",
+            "complainedRecipients": [{ "emailAddress": "ana@example.com" }]
+        })))
+        .unwrap();
+        assert_eq!(complaint.status, DeliveryStatus::Complained);
+        assert_eq!(complaint.detail.as_deref(), Some("abuse"));
+        assert_eq!(complaint.suppress, Some(SuppressionReason::Complaint));
+
+        assert!(parse(&ses_event("Open", json!({}))).is_none());
+        assert!(parse(&json!({ "eventType": "Delivery", "mail": { "tags": {} } })).is_none());
+    }
+}
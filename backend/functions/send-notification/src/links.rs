@@ -0,0 +1,45 @@
+//! Enlaces que van en las notificaciones: la app y la baja firmada.
+
+use shared_lib::unsubscribe::{secret_from_env, UnsubscribeToken};
+
+pub struct Links {
+    pub app_url: String,
+    /// Base de la API, para la baja en un clic de `List-Unsubscribe`
+    api_url: Option<String>,
+    unsubscribe_secret: Option<Vec<u8>>,
+}
+
+/// Enlaces de baja de un mensaje.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsubscribe {
+    /// Página de la app que confirma la baja (va en el cuerpo)
+    pub page: String,
+    /// `POST` de la API para la baja en un clic (RFC 8058), si hay `API_URL`
+    pub one_click: Option<String>,
+}
+
+impl Links {
+    pub fn new(app_url: impl Into<String>, api_url: Option<String>, unsubscribe_secret: Option<Vec<u8>>) -> Self {
+        Self { app_url: app_url.into(), api_url, unsubscribe_secret }
+    }
+
+    /// `APP_URL`, `API_URL` y `UNSUBSCRIBE_SECRET`.
+    pub fn from_env() -> Self {
+        let app_url = std::env::var("APP_URL").unwrap_or_else(|_| "https://turnaki.nexioq.com".into());
+        let api_url = std::env::var("API_URL").ok();
+        let secret = secret_from_env();
+        if secret.is_none() {
+            tracing::warn!("UNSUBSCRIBE_SECRET no configurado; las notificaciones salen sin enlace de baja");
+        }
+        Self::new(app_url, api_url, secret)
+    }
+
+    /// Sin secreto no hay enlace de baja.
+    pub fn unsubscribe(&self, token: &UnsubscribeToken) -> Option<Unsubscribe> {
+        let token = token.sign(self.unsubscribe_secret.as_deref()?);
+        Some(Unsubscribe {
+            page: format!("{}/unsubscribe?token={}", self.app_url.trim_end_matches('/'), token),
+            one_click: self.api_url.as_ref().map(|api| format!("{}/unsubscribe?token={}", api.trim_end_matches('/'), token)),
+        })
+    }
+}
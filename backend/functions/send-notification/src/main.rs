@@ -1,11 +1,52 @@
+//! Envía las notificaciones de reservas por email, SMS o WhatsApp (un canal
+//! por mensaje). Llegan por la cola SQS `notifications` (desde
+//! `booking-events`) o invocadas directamente por los schedules de
+//! recordatorio.
+//!
+//! En SQS, cada mensaje que falla por un error transitorio se devuelve en
+//! `batchItemFailures` para que vuelva a entregarse; los que nunca van a
+//! poder enviarse se mueven a la DLQ en lugar de agotar los reintentos.
+//!
+//! Cada intento queda registrado en la partición de la reserva. El feedback
+//! de SES (entregas, rebotes y quejas) llega por SNS a esta misma función y
+//! actualiza esos registros; los rebotes permanentes y las quejas, además,
+//! agregan la dirección a la lista de supresión del tenant.
+//!
+//! Antes de enviar se consultan esa lista y las preferencias del paciente; si
+//! alguna lo impide, el intento queda `suppressed` y no se envía.
+//!
+//! El mensaje sale en el idioma del paciente (o el de la clínica), con la
+//! marca de la clínica y, si tiene, su propia plantilla para ese canal,
+//! plantilla e idioma.
+//!
+//! Por API Gateway recibe además la vista previa y el envío de prueba de las
+//! plantillas (ver `preview`).
+
 use lambda_runtime::{run, service_fn, LambdaEvent, Error};
 use serde::{Deserialize, Serialize};
-use aws_sdk_ses::Client;
+use chrono::{DateTime, Utc};
+use shared_lib::ics::{CalendarEvent, Method, Party};
 use shared_lib::init_tracing;
-use std::fs;
-use std::path::Path;
+use shared_lib::models::{Channel, ContactPreferences, DeliveryStatus, Locale, MessageCategory, NotificationRecord, TemplateOverride, Tenant};
+use shared_lib::repository::{event_notification_id, notification_id};
+use shared_lib::unsubscribe::UnsubscribeToken;
+use shared_lib::{DynamoStore, Repository, StoreError};
 use serde_json::Value;
 
+mod channels;
+mod consent;
+mod delivery;
+mod feedback;
+mod links;
+mod mime;
+mod preview;
+mod templates;
+
+use channels::{Channels, Message};
+use delivery::{DeadLetters, SendError, SqsDeadLetters};
+use links::{Links, Unsubscribe};
+use templates::{Rendered, TemplateContext, Templates, DEFAULT_PRIMARY_COLOR};
+
 #[derive(Debug, Deserialize)]
 struct SqsEvent {
     #[serde(rename = "Records")]
@@ -14,16 +55,24 @@ struct SqsEvent {
 
 #[derive(Debug, Deserialize)]
 struct SqsRecord {
+    #[serde(rename = "messageId")]
+    message_id: String,
     body: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct NotificationPayload {
     #[serde(rename = "type")]
-    notification_type: String, // "confirmation", "reminder", "cancellation"
+    notification_type: String, // "confirmation", "reminder", "cancellation", "rescheduled"
     to: Option<String>,
     patient_email: Option<String>,
+    /// E.164, para SMS y WhatsApp
+    #[serde(default)]
+    patient_phone: Option<String>,
     patient_name: String,
+    /// Sin él se toma el de la reserva
+    #[serde(default)]
+    tenant_id: Option<String>,
     booking_id: String,
     appointment_date: Option<String>,
     appointment_time: Option<String>,
@@ -32,181 +81,1048 @@ struct NotificationPayload {
     clinic_address: Option<String>,
     clinic_email: Option<String>,
     hours_before: Option<u32>,
+    #[serde(default)]
+    channel: Channel,
+    #[serde(default)]
+    category: MessageCategory,
+    /// Idioma pedido; sin él, el que eligió el paciente o el de la clínica
+    #[serde(default)]
+    locale: Option<Locale>,
+    /// Plantilla de la política de recordatorios del tenant
+    #[serde(default)]
+    template: Option<String>,
+    /// Horario anterior, solo en `rescheduled`
+    previous_appointment_date: Option<String>,
+    previous_appointment_time: Option<String>,
+    /// Inicio y fin en RFC 3339 y revisión del evento de calendario adjunto
+    #[serde(default)]
+    start_time: Option<String>,
+    #[serde(default)]
+    end_time: Option<String>,
+    #[serde(default)]
+    sequence: Option<u32>,
+    #[serde(default)]
+    clinic_name: Option<String>,
+    /// Evento de la reserva que la originó; no viene en los recordatorios
+    #[serde(default)]
+    event_id: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 struct Response {
     sent: usize,
+    /// Fallos permanentes: no se reintentan
     failed: usize,
+    /// No enviadas por la lista de supresión o las preferencias del paciente,
+    /// o porque el mismo mensaje ya se había enviado
+    #[serde(skip_serializing_if = "is_zero")]
+    skipped: usize,
+    /// Envíos actualizados con el feedback de SES
+    #[serde(skip_serializing_if = "is_zero")]
+    updated: usize,
+    /// Mensajes que SQS debe volver a entregar; el resto se borra de la cola
+    #[serde(rename = "batchItemFailures")]
+    batch_item_failures: Vec<BatchItemFailure>,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+#[derive(Debug, Serialize)]
+struct BatchItemFailure {
+    #[serde(rename = "itemIdentifier")]
+    item_identifier: String,
 }
 
-fn get_subject(notification_type: &str) -> String {
+/// Plantilla incluida de cada tipo de notificación; también da el asunto.
+fn default_template(notification_type: &str) -> &'static str {
     match notification_type {
-        "confirmation" => "✅ Cita Confirmada - Turnaki NexioQ".into(),
-        "reminder" => "⏰ Recordatorio de Cita - Turnaki NexioQ".into(),
-        "cancellation" => "❌ Cita Cancelada - Turnaki NexioQ".into(),
-        _ => "Notificación - Turnaki NexioQ".into(),
+        "confirmation" => "booking-confirmation",
+        "reminder" => "booking-reminder",
+        "cancellation" => "booking-cancelled",
+        "rescheduled" => "booking-rescheduled",
+        _ => "booking-confirmation",
+    }
+}
+
+/// Plantilla incluida de la notificación: la configurada por el tenant si
+/// existe para el canal; si no, la del tipo.
+fn template_name<'a>(templates: &Templates, notification: &'a NotificationPayload) -> &'a str {
+    let default = default_template(&notification.notification_type);
+    match notification.template.as_deref() {
+        Some(name) if templates.exists(notification.channel, name) => name,
+        Some(name) => {
+            tracing::warn!(template = %name, channel = notification.channel.as_str(), booking_id = %notification.booking_id, "Unknown template; using default");
+            default
+        }
+        None => default,
+    }
+}
+
+/// Clínica y paciente de la notificación, de donde salen el idioma, la marca
+/// y las plantillas propias.
+#[derive(Debug, Default)]
+struct Audience {
+    tenant_id: Option<String>,
+    tenant: Option<Tenant>,
+    /// Las del paciente (o las de por defecto); `None` sin tenant o sin email
+    preferences: Option<ContactPreferences>,
+}
+
+impl Audience {
+    async fn load(repo: &dyn Repository, notification: &NotificationPayload) -> Result<Self, StoreError> {
+        let Some(tenant_id) = tenant_of(repo, notification).await? else {
+            return Ok(Self::default());
+        };
+        let tenant = repo.get_tenant(&tenant_id).await?;
+        let preferences = match notification.patient_email.as_deref() {
+            Some(email) => Some(repo.get_preferences(&tenant_id, email).await?.unwrap_or_else(|| ContactPreferences::new(&tenant_id, email))),
+            None => None,
+        };
+        Ok(Self { tenant_id: Some(tenant_id), tenant, preferences })
+    }
+
+    /// El de la notificación, el que eligió el paciente o el de la clínica.
+    fn locale(&self, notification: &NotificationPayload) -> Locale {
+        notification.locale
+            .or_else(|| self.preferences.as_ref().and_then(|preferences| preferences.locale))
+            .or_else(|| self.tenant.as_ref().map(|tenant| tenant.settings.locale))
+            .unwrap_or_default()
+    }
+}
+
+/// Plantilla a renderizar y, si la clínica tiene una propia con ese nombre
+/// para el canal y el idioma, esa. Una plantilla de la política de
+/// recordatorios puede existir solo como propia.
+async fn resolve_template(
+    repo: &dyn Repository,
+    templates: &Templates,
+    audience: &Audience,
+    notification: &NotificationPayload,
+    locale: Locale,
+) -> Result<(String, Option<TemplateOverride>), StoreError> {
+    let Some(tenant_id) = audience.tenant_id.as_deref() else {
+        return Ok((template_name(templates, notification).to_string(), None));
+    };
+    if let Some(name) = notification.template.as_deref().filter(|name| !templates.exists(notification.channel, name)) {
+        if let Some(custom) = repo.get_template_override(tenant_id, notification.channel, name, locale).await? {
+            return Ok((name.to_string(), Some(custom)));
+        }
     }
+    let name = template_name(templates, notification);
+    let custom = repo.get_template_override(tenant_id, notification.channel, name, locale).await?;
+    Ok((name.to_string(), custom))
+}
+
+fn template_context(links: &Links, notification: &NotificationPayload, audience: &Audience, unsubscribe: Option<&Unsubscribe>) -> TemplateContext {
+    let app_url = links.app_url.clone();
+    let tenant = audience.tenant.as_ref();
+    let branding = tenant.map(|tenant| tenant.settings.branding.clone()).unwrap_or_default();
+    TemplateContext {
+        patient_name: notification.patient_name.clone(),
+        booking_id: notification.booking_id.clone(),
+        appointment_date: notification.appointment_date.clone().unwrap_or_default(),
+        appointment_time: notification.appointment_time.clone().unwrap_or_default(),
+        previous_appointment_date: notification.previous_appointment_date.clone(),
+        previous_appointment_time: notification.previous_appointment_time.clone(),
+        professional_name: notification.professional_name.clone(),
+        treatment_name: notification.treatment_name.clone(),
+        clinic_address: notification.clinic_address.clone(),
+        clinic_email: notification.clinic_email.clone()
+            .or_else(|| tenant.map(|tenant| tenant.contact_email.clone()))
+            .unwrap_or_else(|| "soporte@nexioq.com".into()),
+        clinic_name: notification.clinic_name.clone()
+            .or_else(|| tenant.map(|tenant| tenant.name.clone()))
+            .unwrap_or_else(|| "Turnaki NexioQ".into()),
+        hours_before: notification.hours_before,
+        manage_booking_url: format!("{}/my-appointments", app_url),
+        booking_url: format!("{}/booking", app_url),
+        unsubscribe_url: unsubscribe.map(|u| u.page.clone()),
+        app_url,
+        logo_url: branding.logo_url,
+        primary_color: branding.primary_color.unwrap_or_else(|| DEFAULT_PRIMARY_COLOR.into()),
+        reply_to: branding.reply_to,
+    }
+}
+
+fn render_template(
+    templates: &Templates,
+    notification: &NotificationPayload,
+    name: &str,
+    locale: Locale,
+    custom: Option<&TemplateOverride>,
+    context: &TemplateContext,
+) -> Result<Rendered, minijinja::Error> {
+    let subject = default_template(&notification.notification_type);
+    templates.render(notification.channel, name, locale, subject, custom, context)
 }
 
-fn render_template(notification: &NotificationPayload) -> String {
-    let app_url = std::env::var("APP_URL").unwrap_or_else(|_| "https://turnaki.nexioq.com".into());
-    let templates_dir = std::env::var("TEMPLATES_DIR")
-        .unwrap_or_else(|_| "/var/task/templates".into());
+/// Evento de calendario para adjuntar: crea o actualiza la cita al confirmarla
+/// o reprogramarla y la cancela al cancelarla. Sin horas válidas no se adjunta.
+fn calendar_event(notification: &NotificationPayload, to: &str) -> Option<(CalendarEvent, Method)> {
+    let method = match notification.notification_type.as_str() {
+        "confirmation" | "rescheduled" => Method::Request,
+        "cancellation" => Method::Cancel,
+        _ => return None,
+    };
+    let parse = |raw: &Option<String>| raw.as_deref().and_then(|raw| DateTime::parse_from_rfc3339(raw).ok()).map(|dt| dt.with_timezone(&Utc));
+    let (start, end) = (parse(&notification.start_time)?, parse(&notification.end_time)?);
 
-    let template_name = match notification.notification_type.as_str() {
-        "confirmation" => "booking-confirmation.html",
-        "reminder" => "booking-reminder.html",
-        "cancellation" => "booking-cancelled.html",
-        _ => "booking-confirmation.html",
+    let clinic = notification.clinic_name.as_deref().unwrap_or("Turnaki NexioQ");
+    let summary = match &notification.treatment_name {
+        Some(treatment) => format!("{} - {}", treatment, clinic),
+        None => format!("Cita - {}", clinic),
+    };
+    let description = notification.professional_name.as_ref()
+        .map(|professional| format!("Profesional: {}\nCódigo de reserva: {}", professional, notification.booking_id))
+        .unwrap_or_else(|| format!("Código de reserva: {}", notification.booking_id));
+    let event = CalendarEvent {
+        uid: CalendarEvent::booking_uid(&notification.booking_id),
+        sequence: notification.sequence.unwrap_or_default(),
+        stamp: Utc::now(),
+        start,
+        end,
+        summary,
+        description: Some(description),
+        location: notification.clinic_address.clone(),
+        organizer: Party {
+            name: Some(clinic.to_string()),
+            email: notification.clinic_email.clone().unwrap_or_else(|| "soporte@nexioq.com".into()),
+        },
+        attendee: Party { name: Some(notification.patient_name.clone()), email: to.to_string() },
     };
+    Some((event, method))
+}
+
+/// Email o teléfono del paciente, según el canal.
+fn recipient(notification: &NotificationPayload) -> Option<&String> {
+    match notification.channel {
+        Channel::Email => notification.patient_email.as_ref().or(notification.to.as_ref()),
+        Channel::Sms | Channel::Whatsapp => notification.patient_phone.as_ref(),
+    }
+}
 
-    let path = Path::new(&templates_dir).join(template_name);
-    let Ok(html) = fs::read_to_string(&path) else {
-        return format!("<p>Notificación para {}</p>", notification.patient_name);
+/// La notificación renderizada para su canal. Sin destinatario para el canal,
+/// o con una plantilla que no renderiza, no se puede enviar nunca.
+fn build_message(
+    templates: &Templates,
+    links: &Links,
+    notification: &NotificationPayload,
+    audience: &Audience,
+    (name, custom): (&str, Option<&TemplateOverride>),
+    id: &str,
+) -> anyhow::Result<Message> {
+    let to = recipient(notification)
+        .ok_or_else(|| anyhow::anyhow!("No recipient for {}", notification.channel.as_str()))?;
+    let calendar = match notification.channel {
+        Channel::Email => calendar_event(notification, to),
+        _ => None,
     };
+    let tenant_id = audience.tenant_id.as_deref();
+    // La baja es de la clínica y del paciente (por su email), para este canal y tipo de mensaje
+    let unsubscribe = tenant_id.zip(notification.patient_email.as_deref()).and_then(|(tenant_id, email)| {
+        links.unsubscribe(&UnsubscribeToken {
+            tenant_id: tenant_id.to_string(),
+            email: email.to_string(),
+            channel: notification.channel,
+            category: notification.category,
+        })
+    });
+    let locale = audience.locale(notification);
+    let context = template_context(links, notification, audience, unsubscribe.as_ref());
+    let rendered = render_template(templates, notification, name, locale, custom, &context)?;
+    let branding = audience.tenant.as_ref().map(|tenant| &tenant.settings.branding);
+    let email_only = |value: Option<&String>| value.filter(|_| notification.channel == Channel::Email).cloned();
+    Ok(Message {
+        id: id.to_string(),
+        channel: notification.channel,
+        tenant_id: tenant_id.map(String::from),
+        booking_id: notification.booking_id.clone(),
+        to: to.clone(),
+        subject: rendered.subject,
+        body: rendered.body,
+        template: name.to_string(),
+        calendar,
+        unsubscribe: email_only(unsubscribe.and_then(|u| u.one_click).as_ref()),
+        locale,
+        from_name: email_only(branding.and_then(|b| b.sender_name.as_ref())),
+        reply_to: email_only(branding.and_then(|b| b.reply_to.as_ref())),
+    })
+}
+
+/// Resultado de una notificación que no falló.
+#[derive(Debug)]
+enum Delivery {
+    /// Enviada con esa plantilla, con el id del proveedor
+    Sent { template: String, provider_message_id: Option<String> },
+    /// No enviada, y por qué
+    Suppressed(String),
+    /// Entrega repetida de un mensaje ya enviado (o enviándose)
+    Duplicate,
+}
 
-    let manage_booking_url = format!("{}/my-appointments", app_url);
-    let booking_url = format!("{}/booking", app_url);
+/// Tenant de la notificación: el del payload o, en mensajes anteriores a
+/// que lo incluyera, el de la reserva.
+async fn tenant_of(repo: &dyn Repository, notification: &NotificationPayload) -> Result<Option<String>, StoreError> {
+    if let Some(tenant_id) = &notification.tenant_id {
+        return Ok(Some(tenant_id.clone()));
+    }
+    Ok(repo.get_booking(&notification.booking_id).await?.map(|booking| booking.tenant_id))
+}
+
+/// Consulta la lista de supresión y las preferencias y, si se puede, envía la
+/// notificación. Un canal sin configurar es un fallo permanente.
+async fn send(
+    repo: &dyn Repository,
+    templates: &Templates,
+    channels: &Channels,
+    links: &Links,
+    notification: &NotificationPayload,
+    id: &str,
+) -> Result<Delivery, SendError> {
+    let audience = Audience::load(repo, notification).await.map_err(SendError::transient)?;
+    match (audience.tenant_id.as_deref(), recipient(notification)) {
+        (Some(tenant_id), Some(to)) => {
+            let blocked = consent::blocked(repo, tenant_id, notification.channel, notification.category, to, audience.preferences.as_ref())
+                .await
+                .map_err(SendError::transient)?;
+            if let Some(reason) = blocked {
+                return Ok(Delivery::Suppressed(reason));
+            }
+        }
+        (None, _) => tracing::warn!(booking_id = %notification.booking_id, "Unknown tenant; suppression list and preferences not checked"),
+        // Sin destinatario falla al armar el mensaje
+        (Some(_), None) => {}
+    }
+    let channel = channels.get(notification.channel)
+        .ok_or_else(|| SendError::permanent(anyhow::anyhow!("Canal {} no configurado", notification.channel.as_str())))?;
+    let (name, custom) = resolve_template(repo, templates, &audience, notification, audience.locale(notification))
+        .await
+        .map_err(SendError::transient)?;
+    let message = build_message(templates, links, notification, &audience, (&name, custom.as_ref()), id).map_err(SendError::Permanent)?;
+    let provider_message_id = channel.send(&message).await?;
+    Ok(Delivery::Sent { template: name, provider_message_id })
+}
 
-    let mut output = html
-        .replace("{{patient_name}}", notification.patient_name.as_str())
-        .replace("{{appointment_date}}", notification.appointment_date.as_deref().unwrap_or(""))
-        .replace("{{appointment_time}}", notification.appointment_time.as_deref().unwrap_or(""))
-        .replace("{{professional_name}}", notification.professional_name.as_deref().unwrap_or(""))
-        .replace("{{treatment_name}}", notification.treatment_name.as_deref().unwrap_or(""))
-        .replace("{{clinic_address}}", notification.clinic_address.as_deref().unwrap_or(""))
-        .replace("{{booking_id}}", notification.booking_id.as_str())
-        .replace("{{manage_booking_url}}", manage_booking_url.as_str())
-        .replace("{{booking_url}}", booking_url.as_str())
-        .replace("{{app_url}}", app_url.as_str());
+/// Procesa un mensaje y registra el intento en la reserva. Un body que no es
+/// una notificación válida es un fallo permanente y no tiene reserva donde
+/// registrarse.
+///
+/// El intento se registra antes de enviar. Las notificaciones de un evento
+/// tienen id `<event_id>-<canal>`, así que si SQS o `booking-events` repiten el
+/// mensaje el registro ya existe y no se vuelve a enviar (salvo que el intento
+/// anterior haya fallado).
+async fn process(repo: &dyn Repository, templates: &Templates, channels: &Channels, links: &Links, body: &str) -> Result<Delivery, SendError> {
+    let notification: NotificationPayload = serde_json::from_str(body).map_err(SendError::permanent)?;
+    let now = Utc::now();
+    let id = match &notification.event_id {
+        Some(event_id) => event_notification_id(event_id, notification.channel),
+        None => notification_id(now),
+    };
+    let pending = NotificationRecord {
+        id: id.clone(),
+        booking_id: notification.booking_id.clone(),
+        channel: notification.channel,
+        notification_type: notification.notification_type.clone(),
+        template: template_name(templates, &notification).to_string(),
+        recipient: recipient(&notification).cloned().unwrap_or_default(),
+        provider_message_id: None,
+        status: DeliveryStatus::Pending,
+        error: None,
+        attempted_at: now.to_rfc3339(),
+        updated_at: now.to_rfc3339(),
+    };
+    match repo.claim_notification(&pending).await {
+        Ok(()) => {}
+        Err(StoreError::ConditionFailed) => {
+            tracing::info!(notification_id = %id, booking_id = %notification.booking_id, "Notification already sent; skipped");
+            return Ok(Delivery::Duplicate);
+        }
+        Err(e) => return Err(SendError::transient(e)),
+    }
+    let result = send(repo, templates, channels, links, &notification, &id).await;
 
-    if let Some(hours) = notification.hours_before {
-        output = output.replace("{{hours_before}}", &hours.to_string());
+    let (status, provider_message_id, error) = match &result {
+        Ok(Delivery::Sent { provider_message_id, .. }) => (DeliveryStatus::Sent, provider_message_id.clone(), None),
+        Ok(Delivery::Suppressed(reason)) => (DeliveryStatus::Suppressed, None, Some(reason.clone())),
+        Ok(Delivery::Duplicate) => return result,
+        Err(e) => (DeliveryStatus::Failed, None, Some(e.to_string())),
+    };
+    let template = match &result {
+        Ok(Delivery::Sent { template, .. }) => template.clone(),
+        _ => pending.template.clone(),
+    };
+    let record = NotificationRecord { template, provider_message_id, status, error, updated_at: Utc::now().to_rfc3339(), ..pending };
+    // Sin registro el envío sigue siendo válido: reintentarlo duplicaría el mensaje
+    if let Err(e) = repo.complete_notification(&record).await {
+        tracing::warn!(error = %e, booking_id = %record.booking_id, "Failed to record notification attempt");
     }
-    let clinic_email = notification.clinic_email.as_deref().unwrap_or("soporte@nexioq.com");
-    output = output.replace("{{clinic_email}}", clinic_email);
 
-    output
+    match &result {
+        Ok(Delivery::Sent { .. }) => {
+            tracing::info!(channel = notification.channel.as_str(), notification_type = %notification.notification_type, booking_id = %notification.booking_id, "Notification sent")
+        }
+        Ok(Delivery::Suppressed(reason)) => {
+            tracing::info!(channel = notification.channel.as_str(), booking_id = %notification.booking_id, reason = %reason, "Notification suppressed")
+        }
+        Ok(Delivery::Duplicate) | Err(_) => {}
+    }
+    result
 }
 
-async fn handler(event: LambdaEvent<Value>) -> Result<Response, Error> {
-    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
-    let ses_client = Client::new(&config);
-    
-    let from_email = std::env::var("SES_FROM_EMAIL").unwrap_or_else(|_| "noreply@nexioq.com".into());
-    
-    let mut sent = 0;
-    let mut failed = 0;
+async fn handler(
+    repo: &dyn Repository,
+    templates: &Templates,
+    channels: &Channels,
+    links: &Links,
+    dead_letters: &dyn DeadLetters,
+    event: LambdaEvent<Value>,
+) -> Result<Response, Error> {
+    let mut response = Response::default();
+
+    // Feedback de SES publicado en SNS
+    if feedback::is_sns_event(&event.payload) {
+        response.updated = feedback::apply(repo, &event.payload).await?;
+        return Ok(response);
+    }
     
-    // Si el evento tiene Records => SQS. Si no, intentamos parsear payload directo
-    if event.payload.get("Records").is_some() {
-        let sqs: SqsEvent = serde_json::from_value(event.payload)
-            .map_err(|e| lambda_runtime::Error::from(e.to_string()))?;
-        for record in sqs.records {
-            match serde_json::from_str::<NotificationPayload>(&record.body) {
-                Ok(notification) => {
-                    let to = notification.patient_email.as_ref()
-                        .or(notification.to.as_ref())
-                        .ok_or("No recipient email")?;
-                    let subject = get_subject(&notification.notification_type);
-                    let html_body = render_template(&notification);
-                    let result = ses_client
-                        .send_email()
-                        .source(&from_email)
-                        .destination(
-                            aws_sdk_ses::types::Destination::builder()
-                                .to_addresses(to)
-                                .build()
-                        )
-                        .message(
-                            aws_sdk_ses::types::Message::builder()
-                                .subject(
-                                    aws_sdk_ses::types::Content::builder()
-                                        .data(subject)
-                                        .build()
-                                        .unwrap()
-                                )
-                                .body(
-                                    aws_sdk_ses::types::Body::builder()
-                                        .html(
-                                            aws_sdk_ses::types::Content::builder()
-                                                .data(html_body)
-                                                .build()
-                                                .unwrap()
-                                        )
-                                        .build()
-                                )
-                                .build()
-                        )
-                        .send()
-                        .await;
-                    match result {
-                        Ok(_) => { tracing::info!(to = %to, notification_type = %notification.notification_type, "Email sent"); sent += 1; }
-                        Err(e) => { tracing::error!(error = %e, "Failed to send email"); failed += 1; }
-                    }
-                }
-                Err(e) => {
-                    tracing::error!(error = %e, "Failed to parse notification");
-                    failed += 1;
-                }
+    // Invocación directa (schedule de recordatorio): un fallo transitorio se
+    // devuelve como error para que la invocación se reintente
+    if event.payload.get("Records").is_none() {
+        match process(repo, templates, channels, links, &event.payload.to_string()).await {
+            Ok(Delivery::Sent { .. }) => response.sent += 1,
+            Ok(Delivery::Suppressed(_) | Delivery::Duplicate) => response.skipped += 1,
+            Err(SendError::Permanent(e)) => {
+                tracing::error!(error = %e, "Notification cannot be sent; dropped");
+                response.failed += 1;
             }
+            Err(SendError::Transient(e)) => return Err(e.into()),
         }
-    } else {
-        match serde_json::from_value::<NotificationPayload>(event.payload) {
-            Ok(notification) => {
-                let to = notification.patient_email.as_ref()
-                    .or(notification.to.as_ref())
-                    .ok_or("No recipient email")?;
-                let subject = get_subject(&notification.notification_type);
-                let html_body = render_template(&notification);
-                let result = ses_client
-                    .send_email()
-                    .source(&from_email)
-                    .destination(
-                        aws_sdk_ses::types::Destination::builder()
-                            .to_addresses(to)
-                            .build()
-                    )
-                    .message(
-                        aws_sdk_ses::types::Message::builder()
-                            .subject(
-                                aws_sdk_ses::types::Content::builder()
-                                    .data(subject)
-                                    .build()
-                                    .unwrap()
-                            )
-                            .body(
-                                aws_sdk_ses::types::Body::builder()
-                                    .html(
-                                        aws_sdk_ses::types::Content::builder()
-                                            .data(html_body)
-                                            .build()
-                                            .unwrap()
-                                    )
-                                    .build()
-                            )
-                            .build()
-                    )
-                    .send()
-                    .await;
-                match result {
-                    Ok(_) => { tracing::info!(to = %to, notification_type = %notification.notification_type, "Email sent"); sent += 1; }
-                    Err(e) => { tracing::error!(error = %e, "Failed to send email"); failed += 1; }
+        return Ok(response);
+    }
+    
+    let sqs: SqsEvent = serde_json::from_value(event.payload)
+        .map_err(|e| lambda_runtime::Error::from(e.to_string()))?;
+    for record in sqs.records {
+        let retry = match process(repo, templates, channels, links, &record.body).await {
+            Ok(Delivery::Sent { .. }) => {
+                response.sent += 1;
+                false
+            }
+            Ok(Delivery::Suppressed(_) | Delivery::Duplicate) => {
+                response.skipped += 1;
+                false
+            }
+            Err(SendError::Permanent(e)) => {
+                tracing::error!(error = %e, message_id = %record.message_id, "Notification cannot be sent; moving to DLQ");
+                response.failed += 1;
+                // Si no se pudo mover, que la cola lo reintente y lo mueva su redrive policy
+                match dead_letters.dead_letter(&record.body, &e.to_string()).await {
+                    Ok(()) => false,
+                    Err(e) => {
+                        tracing::error!(error = %e, message_id = %record.message_id, "Failed to move message to DLQ");
+                        true
+                    }
                 }
             }
-            Err(e) => {
-                tracing::error!(error = %e, "Failed to parse direct notification payload");
-                failed += 1;
+            Err(SendError::Transient(e)) => {
+                tracing::warn!(error = %e, message_id = %record.message_id, "Notification failed; will be retried");
+                true
             }
+        };
+        if retry {
+            response.batch_item_failures.push(BatchItemFailure { item_identifier: record.message_id });
         }
     }
     
-    Ok(Response { sent, failed })
+    Ok(response)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     init_tracing();
-    run(service_fn(handler)).await
+    // Una plantilla rota hace fallar el arranque, no cada envío
+    let templates = Templates::load()?;
+    let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+    let repo = DynamoStore::from_env();
+    let channels = Channels::from_env(&config);
+    let links = Links::from_env();
+    let dead_letters = SqsDeadLetters::from_env(&config);
+    run(service_fn(|event: LambdaEvent<Value>| async {
+        if preview::is_http_event(&event.payload) {
+            return preview::handle_event(&repo, &templates, &channels, &links, event.payload).await;
+        }
+        Ok::<Value, Error>(serde_json::to_value(handler(&repo, &templates, &channels, &links, &dead_letters, event).await?)?)
+    })).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use channels::{LocalChannel, NotificationChannel};
+    use serde_json::json;
+    use shared_lib::models::{Booking, BookingEvent, BookingStatus, Branding, Suppression, SuppressionReason, TenantSettings};
+    use shared_lib::repository::{BookingRepository, ContactRepository, NotificationRepository, TemplateRepository, TenantRepository};
+    use shared_lib::testing::{bearer, booking, tenant};
+    use shared_lib::MemoryStore;
+    use std::sync::{Arc, Mutex};
+
+    /// Canal falso: `fail_with` decide cómo falla el envío.
+    #[derive(Default)]
+    struct FakeChannel {
+        sent: Mutex<Vec<Message>>,
+        fail_with: Mutex<Option<fn() -> SendError>>,
+    }
+
+    #[async_trait::async_trait]
+    impl NotificationChannel for FakeChannel {
+        async fn send(&self, message: &Message) -> Result<Option<String>, SendError> {
+            if let Some(fail) = *self.fail_with.lock().unwrap() {
+                return Err(fail());
+            }
+            let mut sent = self.sent.lock().unwrap();
+            sent.push(message.clone());
+            Ok(Some(format!("provider-{}", sent.len())))
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeDeadLetters {
+        dead_letters: Mutex<Vec<String>>,
+        down: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl DeadLetters for FakeDeadLetters {
+        async fn dead_letter(&self, body: &str, reason: &str) -> anyhow::Result<()> {
+            if self.down {
+                anyhow::bail!("SQS caído");
+            }
+            self.dead_letters.lock().unwrap().push(format!("{} | {}", reason, body));
+            Ok(())
+        }
+    }
+
+    /// Solo email configurado, como un entorno sin credenciales de SMS ni WhatsApp.
+    struct Fakes {
+        store: MemoryStore,
+        email: Arc<FakeChannel>,
+        channels: Channels,
+        links: Links,
+        dlq: FakeDeadLetters,
+    }
+
+    impl Fakes {
+        fn new() -> Self {
+            let email = Arc::new(FakeChannel::default());
+            Self {
+                store: MemoryStore::new(),
+                channels: Channels::default().with(Channel::Email, email.clone()),
+                email,
+                links: Links::new("https://app.example.com", Some("https://api.example.com".into()), Some(b"secret".to_vec())),
+                dlq: Default::default(),
+            }
+        }
+    }
+
+    fn record(id: &str, body: &str) -> Value {
+        json!({ "messageId": id, "body": body })
+    }
+
+    async fn deliver(fakes: &Fakes, records: Vec<Value>) -> Response {
+        let templates = Templates::load().unwrap();
+        let event = LambdaEvent::new(json!({ "Records": records }), Default::default());
+        handler(&fakes.store, &templates, &fakes.channels, &fakes.links, &fakes.dlq, event).await.unwrap()
+    }
+
+    fn failed_ids(response: &Response) -> Vec<&str> {
+        response.batch_item_failures.iter().map(|f| f.item_identifier.as_str()).collect()
+    }
+
+    fn notification(notification_type: &str) -> NotificationPayload {
+        serde_json::from_value(serde_json::json!({
+            "type": notification_type,
+            "patient_email": "ana@example.com",
+            "patient_name": "Ana",
+            "booking_id": "b1",
+            "treatment_name": "Limpieza",
+            "clinic_name": "Clínica A",
+            "clinic_email": "a@example.com",
+            "start_time": "2025-10-01T15:00:00+00:00",
+            "end_time": "2025-10-01T15:30:00+00:00",
+            "sequence": 2
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_calendar_event_per_notification_type() {
+        let (event, method) = calendar_event(&notification("confirmation"), "ana@example.com").unwrap();
+        assert_eq!(method, Method::Request);
+        assert_eq!(event.uid, "booking-b1@turnaki.nexioq.com");
+        assert_eq!(event.summary, "Limpieza - Clínica A");
+        assert_eq!(event.sequence, 2);
+        assert_eq!(event.organizer.email, "a@example.com");
+
+        assert_eq!(calendar_event(&notification("rescheduled"), "ana@example.com").unwrap().1, Method::Request);
+        assert_eq!(calendar_event(&notification("cancellation"), "ana@example.com").unwrap().1, Method::Cancel);
+        assert!(calendar_event(&notification("reminder"), "ana@example.com").is_none());
+
+        let mut without_times = notification("confirmation");
+        without_times.end_time = None;
+        assert!(calendar_event(&without_times, "ana@example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failures_go_to_dead_letter_and_are_not_retried() {
+        let fakes = Fakes::new();
+        let ok = serde_json::to_string(&json!({"type": "confirmation", "patient_email": "ana@example.com", "patient_name": "Ana", "booking_id": "b1"})).unwrap();
+        let no_recipient = serde_json::to_string(&json!({"type": "confirmation", "patient_name": "Ana", "booking_id": "b2"})).unwrap();
+        let records = vec![record("m1", &ok), record("m2", "{no es json"), record("m3", &no_recipient)];
+
+        let response = deliver(&fakes, records).await;
+        assert_eq!((response.sent, response.failed), (1, 2));
+        assert!(failed_ids(&response).is_empty());
+        assert_eq!(fakes.email.sent.lock().unwrap().len(), 1);
+        let dead_letters = fakes.dlq.dead_letters.lock().unwrap();
+        assert_eq!(dead_letters.len(), 2);
+        assert!(dead_letters[1].starts_with("No recipient for email"));
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_are_reported_for_retry() {
+        let fakes = Fakes::new();
+        *fakes.email.fail_with.lock().unwrap() = Some(|| SendError::Transient(anyhow::anyhow!("Throttling")));
+        let body = serde_json::to_string(&json!({"type": "reminder", "patient_email": "ana@example.com", "patient_name": "Ana", "booking_id": "b1"})).unwrap();
+
+        let response = deliver(&fakes, vec![record("m1", &body), record("m2", &body)]).await;
+        assert_eq!(failed_ids(&response), ["m1", "m2"]);
+        assert!(fakes.dlq.dead_letters.lock().unwrap().is_empty());
+
+        // Rechazo del proveedor: permanente, no se reintenta
+        *fakes.email.fail_with.lock().unwrap() = Some(|| SendError::permanent(anyhow::anyhow!("MessageRejected")));
+        let response = deliver(&fakes, vec![record("m3", &body)]).await;
+        assert!(failed_ids(&response).is_empty());
+        assert_eq!(fakes.dlq.dead_letters.lock().unwrap().len(), 1);
+
+        // Sin DLQ disponible, el mensaje vuelve a la cola
+        let fakes = Fakes { dlq: FakeDeadLetters { down: true, ..Default::default() }, ..Fakes::new() };
+        let response = deliver(&fakes, vec![record("m4", "{no es json")]).await;
+        assert_eq!(failed_ids(&response), ["m4"]);
+    }
+
+    #[tokio::test]
+    async fn test_direct_invocation_fails_only_on_transient_errors() {
+        let templates = Templates::load().unwrap();
+        let fakes = Fakes::new();
+        let invoke = |payload: Value| handler(&fakes.store, &templates, &fakes.channels, &fakes.links, &fakes.dlq, LambdaEvent::new(payload, Default::default()));
+        let payload = json!({"type": "reminder", "patient_email": "ana@example.com", "patient_name": "Ana", "booking_id": "b1"});
+
+        assert_eq!(invoke(payload.clone()).await.unwrap().sent, 1);
+
+        // SMS sin credenciales configuradas
+        let sms = json!({"type": "reminder", "patient_phone": "+573001234567", "patient_name": "Ana", "booking_id": "b1", "channel": "sms"});
+        assert_eq!(invoke(sms).await.unwrap().failed, 1);
+
+        *fakes.email.fail_with.lock().unwrap() = Some(|| SendError::Transient(anyhow::anyhow!("Throttling")));
+        assert!(invoke(payload).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_each_channel_renders_its_own_template() {
+        let templates = Templates::load().unwrap();
+        let whatsapp = Arc::new(FakeChannel::default());
+        let fakes = Fakes::new();
+        let channels = fakes.channels.clone().with(Channel::Whatsapp, whatsapp.clone());
+        let payload = |channel: &str, phone: Option<&str>| json!({
+            "type": "reminder",
+            "channel": channel,
+            "template": "same-day",
+            "patient_email": "ana@example.com",
+            "patient_phone": phone,
+            "patient_name": "Ana",
+            "booking_id": "b1",
+            "clinic_name": "Clínica A",
+            "appointment_date": "2025-10-01",
+            "appointment_time": "10:00",
+            "start_time": "2025-10-01T15:00:00+00:00",
+            "end_time": "2025-10-01T15:30:00+00:00"
+        });
+        let invoke = |payload: Value| handler(&fakes.store, &templates, &channels, &fakes.links, &fakes.dlq, LambdaEvent::new(payload, Default::default()));
+
+        assert_eq!(invoke(payload("whatsapp", Some("+573001234567"))).await.unwrap().sent, 1);
+        let sent = whatsapp.sent.lock().unwrap()[0].clone();
+        assert_eq!(sent.to, "+573001234567");
+        // Plantilla del tenant inexistente: la del tipo, del canal
+        assert_eq!(sent.template, "booking-reminder");
+        assert_eq!(sent.body.lines().collect::<Vec<_>>(), ["Ana", "Clínica A", "2025-10-01", "10:00", "Clínica A"]);
+        assert!(sent.calendar.is_none());
+
+        // Sin teléfono no hay a quién enviarlo
+        assert_eq!(invoke(payload("whatsapp", None)).await.unwrap().failed, 1);
+
+        assert_eq!(invoke(payload("email", None)).await.unwrap().sent, 1);
+        assert!(fakes.email.sent.lock().unwrap()[0].body.contains("<html"));
+    }
+
+    #[tokio::test]
+    async fn test_local_channel_writes_one_file_per_message() {
+        let dir = std::env::temp_dir().join(format!("send-notification-{}", std::process::id()));
+        let local: Arc<dyn NotificationChannel> = Arc::new(LocalChannel::new(Some(dir.clone())));
+        let channels = Channels::default().with(Channel::Email, local.clone()).with(Channel::Sms, local);
+        let templates = Templates::load().unwrap();
+        let body = |channel: &str| serde_json::to_string(&json!({
+            "type": "cancellation",
+            "channel": channel,
+            "patient_email": "ana@example.com",
+            "patient_phone": "+573001234567",
+            "patient_name": "Ana",
+            "booking_id": "b1",
+            "start_time": "2025-10-01T15:00:00+00:00",
+            "end_time": "2025-10-01T15:30:00+00:00"
+        })).unwrap();
+        let event = LambdaEvent::new(json!({ "Records": [record("m1", &body("email")), record("m2", &body("sms"))] }), Default::default());
+
+        let response = handler(&MemoryStore::new(), &templates, &channels, &Links::new("https://app.example.com", None, None), &FakeDeadLetters::default(), event).await.unwrap();
+        assert_eq!(response.sent, 2);
+        let mut files: Vec<String> = std::fs::read_dir(&dir).unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        files.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].starts_with("Channel: email\nTo: ana@example.com\n"));
+        assert!(files[0].contains("Calendar: CANCEL booking-b1@turnaki.nexioq.com #0"));
+        assert!(files[1].starts_with("Channel: sms\nTo: +573001234567\n"));
+        assert!(files[1].contains("fue cancelada"));
+    }
+
+    #[tokio::test]
+    async fn test_every_attempt_is_recorded_and_updated_by_ses_feedback() {
+        let fakes = Fakes::new();
+        let body = serde_json::to_string(&json!({"type": "confirmation", "patient_email": "ana@example.com", "patient_name": "Ana", "booking_id": "b1"})).unwrap();
+        *fakes.email.fail_with.lock().unwrap() = Some(|| SendError::Transient(anyhow::anyhow!("Throttling")));
+        deliver(&fakes, vec![record("m1", &body)]).await;
+        *fakes.email.fail_with.lock().unwrap() = None;
+        // Los ids ordenan por milisegundo
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        deliver(&fakes, vec![record("m1", &body)]).await;
+
+        let attempts = fakes.store.list_notifications("b1").await.unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].status, DeliveryStatus::Failed);
+        assert_eq!(attempts[0].error.as_deref(), Some("transient: Throttling"));
+        assert_eq!(attempts[1].status, DeliveryStatus::Sent);
+        assert_eq!(attempts[1].provider_message_id.as_deref(), Some("provider-1"));
+        assert_eq!((attempts[1].recipient.as_str(), attempts[1].template.as_str()), ("ana@example.com", "booking-confirmation"));
+        // El id del intento viaja con el mensaje para que el feedback lo encuentre
+        assert_eq!(fakes.email.sent.lock().unwrap()[0].id, attempts[1].id);
+
+        let bounce = json!({
+            "eventType": "Bounce",
+            "bounce": { "bounceType": "Permanent", "bounceSubType": "NoEmail", "timestamp": "2025-10-01T10:00:05Z" },
+            "mail": { "tags": { "booking_id": ["b1"], "notification_id": [attempts[1].id] } }
+        });
+        let unknown = json!({ "eventType": "Delivery", "mail": { "tags": { "booking_id": ["b1"], "notification_id": ["missing"] } } });
+        let sns = |message: &Value| json!({ "EventSource": "aws:sns", "Sns": { "Message": message.to_string() } });
+        let event = LambdaEvent::new(json!({ "Records": [sns(&bounce), sns(&unknown)] }), Default::default());
+        let templates = Templates::load().unwrap();
+        let response = handler(&fakes.store, &templates, &fakes.channels, &fakes.links, &fakes.dlq, event).await.unwrap();
+        assert_eq!(response.updated, 1);
+
+        let bounced = &fakes.store.list_notifications("b1").await.unwrap()[1];
+        assert_eq!(bounced.status, DeliveryStatus::Bounced);
+        assert_eq!(bounced.error.as_deref(), Some("Permanent/NoEmail"));
+        assert_eq!(bounced.updated_at, "2025-10-01T10:00:05Z");
+
+        // Una entrega que llega tarde no deshace el rebote
+        let late = json!({ "eventType": "Delivery", "mail": { "tags": { "booking_id": ["b1"], "notification_id": [attempts[1].id] } } });
+        let event = LambdaEvent::new(json!({ "Records": [sns(&late)] }), Default::default());
+        let response = handler(&fakes.store, &templates, &fakes.channels, &fakes.links, &fakes.dlq, event).await.unwrap();
+        assert_eq!(response.updated, 0);
+        assert_eq!(fakes.store.list_notifications("b1").await.unwrap()[1].status, DeliveryStatus::Bounced);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_event_messages_are_sent_once_per_channel() {
+        let fakes = Fakes::new();
+        let body = serde_json::to_string(&json!({
+            "type": "confirmation", "event_id": "e1", "patient_email": "ana@example.com", "patient_name": "Ana", "booking_id": "b1"
+        }))
+        .unwrap();
+        // El primer intento falla y se reintenta sobre el mismo registro
+        *fakes.email.fail_with.lock().unwrap() = Some(|| SendError::Transient(anyhow::anyhow!("Throttling")));
+        assert_eq!(failed_ids(&deliver(&fakes, vec![record("m1", &body)]).await), ["m1"]);
+        *fakes.email.fail_with.lock().unwrap() = None;
+        assert_eq!(deliver(&fakes, vec![record("m1", &body)]).await.sent, 1);
+
+        // booking-events reintentó el evento, o SQS entregó el mensaje dos veces
+        let response = deliver(&fakes, vec![record("m2", &body)]).await;
+        assert_eq!((response.sent, response.skipped), (0, 1));
+        assert!(failed_ids(&response).is_empty());
+
+        assert_eq!(fakes.email.sent.lock().unwrap().len(), 1);
+        let attempts = fakes.store.list_notifications("b1").await.unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!((attempts[0].id.as_str(), attempts[0].status), ("e1-email", DeliveryStatus::Sent));
+    }
+
+    #[tokio::test]
+    async fn test_suppression_list_and_preferences_are_checked_before_sending() {
+        let fakes = Fakes::new();
+        fakes.store.put_suppression(&Suppression {
+            tenant_id: "tenant-a".into(),
+            channel: Channel::Email,
+            address: "ana@example.com".into(),
+            reason: SuppressionReason::Bounce,
+            detail: None,
+            created_at: "2025-10-01T10:00:00Z".into(),
+        }).await.unwrap();
+        let mut preferences = ContactPreferences::new("tenant-a", "luis@example.com");
+        preferences.set(Channel::Email, MessageCategory::Transactional, false);
+        fakes.store.put_preferences(&preferences).await.unwrap();
+
+        let body = |email: &str, booking_id: &str| serde_json::to_string(&json!({
+            "type": "confirmation",
+            "tenant_id": "tenant-a",
+            "patient_email": email,
+            "patient_name": "Paciente",
+            "booking_id": booking_id
+        })).unwrap();
+        let records = vec![
+            record("m1", &body("Ana@example.com", "b1")),
+            record("m2", &body("luis@example.com", "b2")),
+            record("m3", &body("eva@example.com", "b3")),
+        ];
+        let response = deliver(&fakes, records).await;
+        assert_eq!((response.sent, response.skipped, response.failed), (1, 2, 0));
+        assert!(failed_ids(&response).is_empty());
+
+        let suppressed = &fakes.store.list_notifications("b1").await.unwrap()[0];
+        assert_eq!(suppressed.status, DeliveryStatus::Suppressed);
+        assert!(suppressed.error.as_deref().unwrap().contains("lista de supresión"));
+        assert_eq!(fakes.store.list_notifications("b2").await.unwrap()[0].status, DeliveryStatus::Suppressed);
+
+        // El enviado lleva la baja firmada: la página en el cuerpo y la de un clic en las cabeceras
+        let sent = fakes.email.sent.lock().unwrap()[0].clone();
+        assert_eq!(sent.to, "eva@example.com");
+        assert_eq!(sent.tenant_id.as_deref(), Some("tenant-a"));
+        let one_click = sent.unsubscribe.unwrap();
+        let token = one_click.strip_prefix("https://api.example.com/unsubscribe?token=").unwrap();
+        // El autoescape codifica las `/` de la URL como `&#x2f;`
+        assert!(sent.body.contains(&format!("unsubscribe?token={}", token)));
+        let token = UnsubscribeToken::verify(token, b"secret").unwrap();
+        assert_eq!((token.email.as_str(), token.channel, token.category), ("eva@example.com", Channel::Email, MessageCategory::Transactional));
+    }
+
+    #[tokio::test]
+    async fn test_complaints_add_the_address_to_the_tenant_suppression_list() {
+        let fakes = Fakes::new();
+        let complaint = json!({
+            "eventType": "Complaint",
+            "complaint": { "complaintFeedbackType": "abuse", "complainedRecipients": [{ "emailAddress": "ana@example.com" }] },
+            "mail": { "tags": { "tenant_id": ["tenant-a"], "booking_id": ["b1"], "notification_id": ["n1"] } }
+        });
+        let event = json!({ "Records": [{ "EventSource": "aws:sns", "Sns": { "Message": complaint.to_string() } }] });
+        let templates = Templates::load().unwrap();
+        handler(&fakes.store, &templates, &fakes.channels, &fakes.links, &fakes.dlq, LambdaEvent::new(event, Default::default())).await.unwrap();
+
+        let suppression = fakes.store.get_suppression("tenant-a", Channel::Email, "ana@example.com").await.unwrap().unwrap();
+        assert_eq!(suppression.reason, SuppressionReason::Complaint);
+        assert_eq!(suppression.detail.as_deref(), Some("abuse"));
+    }
+
+    #[tokio::test]
+    async fn test_locale_branding_and_tenant_templates() {
+        let fakes = Fakes::new();
+        fakes.store.put_tenant(&Tenant {
+            settings: TenantSettings {
+                locale: Locale::En,
+                branding: Branding {
+                    sender_name: Some("Clínica A".into()),
+                    reply_to: Some("recepcion@example.com".into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..tenant("tenant-a")
+        }).await.unwrap();
+        let custom = |template: &str, body: &str| TemplateOverride {
+            tenant_id: "tenant-a".into(),
+            channel: Channel::Email,
+            template: template.into(),
+            locale: Locale::En,
+            subject: None,
+            body: body.into(),
+            updated_at: String::new(),
+        };
+        fakes.store.put_template_override(&custom("booking-reminder", "<p>See you soon, {{ patient_name }}</p>")).await.unwrap();
+        fakes.store.put_template_override(&custom("same-day", "<p>Today at {{ appointment_time }}</p>")).await.unwrap();
+        let mut preferences = ContactPreferences::new("tenant-a", "luis@example.com");
+        preferences.locale = Some(Locale::Pt);
+        fakes.store.put_preferences(&preferences).await.unwrap();
+
+        let body = |email: &str, booking_id: &str, template: Option<&str>| serde_json::to_string(&json!({
+            "type": "reminder",
+            "tenant_id": "tenant-a",
+            "template": template,
+            "patient_email": email,
+            "patient_name": "Paciente",
+            "booking_id": booking_id,
+            "appointment_time": "10:00"
+        })).unwrap();
+        let records = vec![
+            record("m1", &body("ana@example.com", "b1", None)),
+            record("m2", &body("luis@example.com", "b2", None)),
+            record("m3", &body("ana@example.com", "b3", Some("same-day"))),
+        ];
+        assert_eq!(deliver(&fakes, records).await.sent, 3);
+
+        let sent = fakes.email.sent.lock().unwrap().clone();
+        // Idioma de la clínica y su plantilla; el asunto, el incluido en ese idioma
+        assert_eq!(sent[0].body, "<p>See you soon, Paciente</p>");
+        assert_eq!(sent[0].subject, "Appointment reminder - Clínica A");
+        assert_eq!((sent[0].from_name.as_deref(), sent[0].reply_to.as_deref()), (Some("Clínica A"), Some("recepcion@example.com")));
+        // El paciente eligió portugués: la clínica no tiene plantilla propia en ese idioma
+        assert_eq!(sent[1].locale, Locale::Pt);
+        assert_eq!(sent[1].subject, "Lembrete da sua consulta - Clínica A");
+        assert!(sent[1].body.contains("Lembramos que você tem uma consulta agendada"));
+        // Plantilla de la política de recordatorios que solo existe como propia
+        assert_eq!((sent[2].template.as_str(), sent[2].body.as_str()), ("same-day", "<p>Today at 10:00</p>"));
+        assert_eq!(fakes.store.list_notifications("b3").await.unwrap()[0].template, "same-day");
+    }
+
+    #[tokio::test]
+    async fn test_admins_preview_and_send_test_notifications() {
+        let fakes = Fakes::new();
+        let templates = Templates::load().unwrap();
+        fakes.store.put_tenant(&Tenant {
+            settings: TenantSettings {
+                branding: Branding { sender_name: Some("Clínica A".into()), ..Default::default() },
+                ..Default::default()
+            },
+            ..tenant("tenant-a")
+        }).await.unwrap();
+        let booking = Booking {
+            end_time: "2025-10-01T15:30:00+00:00".into(),
+            patient_name: "Luis".into(),
+            patient_email: "luis@example.com".into(),
+            ..booking(BookingStatus::Confirmed)
+        };
+        fakes.store.create_booking(&booking, None, &[], &BookingEvent::created(&booking, "2025-09-01T00:00:00Z")).await.unwrap();
+        fakes.store.put_template_override(&TemplateOverride {
+            tenant_id: "tenant-a".into(),
+            channel: Channel::Email,
+            template: "booking-reminder".into(),
+            locale: Locale::Es,
+            subject: None,
+            body: "<p>{{ nonexistent.field }}</p>".into(),
+            updated_at: String::new(),
+        }).await.unwrap();
+
+        let request = |path: &str, body: Value, auth: String| {
+            let mut request = lambda_http::Request::new(lambda_http::Body::from(body.to_string()));
+            *request.method_mut() = lambda_http::http::Method::POST;
+            *request.uri_mut() = path.parse().unwrap();
+            request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+            request.headers_mut().insert("authorization", auth.parse().unwrap());
+            request
+        };
+        let admin = || bearer("tenant-a", &["Admin"], "admin@example.com");
+        let call = |path: &str, body: Value, auth: String| {
+            let request = request(path, body, auth);
+            let templates = &templates;
+            let fakes = &fakes;
+            async move {
+                let response = preview::handler(&fakes.store, templates, &fakes.channels, &fakes.links, request).await;
+                let body: Value = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+                (response.status().as_u16(), body)
+            }
+        };
+        let path = "/tenants/tenant-a/notifications/preview";
+
+        // Datos de ejemplo con la plantilla incluida
+        let (status, body) = call(path, json!({"type": "confirmation"}), admin()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["subject"], "Cita confirmada - Clínica A");
+        assert!(body["html"].as_str().unwrap().contains("Ana García"));
+        assert!(body["text"].as_str().unwrap().contains("Ana García"));
+        assert!(!body["text"].as_str().unwrap().contains("<p>"));
+        assert_eq!(body["custom"], false);
+
+        // Una reserva real, en inglés y por SMS: sin asunto ni HTML
+        let (status, body) = call(path, json!({"type": "confirmation", "booking_id": "b1", "channel": "sms", "locale": "en"}), admin()).await;
+        assert_eq!(status, 200);
+        assert_eq!((body["subject"].clone(), body["html"].clone()), (Value::Null, Value::Null));
+        assert!(body["text"].as_str().unwrap().contains("Luis"));
+        assert!(body["text"].as_str().unwrap().contains("10:00"));
+
+        // La plantilla guardada de la clínica no renderiza: se avisa y se muestra la incluida
+        let (status, body) = call(path, json!({"type": "reminder"}), admin()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["custom"], false);
+        assert!(body["warning"].is_string());
+
+        // Un borrador se renderiza sin guardarlo; si está roto, es un error
+        let draft = json!({"type": "reminder", "draft": {"subject": "Hola {{ patient_name }}", "body": "<b>{{ appointment_time }}</b>"}});
+        let (status, body) = call(path, draft, admin()).await;
+        assert_eq!(status, 200);
+        assert_eq!((body["subject"].as_str(), body["html"].as_str(), body["custom"].as_bool()), (Some("Hola Ana García"), Some("<b>10:00</b>"), Some(true)));
+        let (status, _) = call(path, json!({"type": "reminder", "draft": {"body": "{{ nonexistent }}"}}), admin()).await;
+        assert_eq!(status, 400);
+
+        // Solo administradores y solo reservas de su clínica
+        assert_eq!(call(path, json!({"type": "confirmation"}), bearer("tenant-a", &["Recepción"], "front@example.com")).await.0, 403);
+        assert_eq!(call("/tenants/tenant-b/notifications/preview", json!({"type": "confirmation"}), admin()).await.0, 403);
+        assert_eq!(call("/tenants/tenant-b/notifications/preview", json!({"type": "confirmation", "booking_id": "b1"}), bearer("tenant-b", &["Admin"], "b@example.com")).await.0, 404);
+        assert_eq!(call(path, json!({"type": "unknown"}), admin()).await.0, 400);
+
+        // La prueba va al administrador, con la marca de la clínica, y no queda en la reserva
+        let (status, body) = call("/tenants/tenant-a/notifications/test", json!({"type": "confirmation", "booking_id": "b1"}), admin()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["to"], "admin@example.com");
+        let sent = fakes.email.sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert_eq!((sent[0].to.as_str(), sent[0].from_name.as_deref()), ("admin@example.com", Some("Clínica A")));
+        assert!(sent[0].body.contains("Luis"));
+        assert!(fakes.store.list_notifications("b1").await.unwrap().is_empty());
+        let (status, _) = call("/tenants/tenant-a/notifications/test", json!({"type": "confirmation", "channel": "sms"}), admin()).await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_api_gateway_events_are_routed_to_preview() {
+        let fakes = Fakes::new();
+        let templates = Templates::load().unwrap();
+        let event = json!({
+            "version": "2.0",
+            "routeKey": "POST /tenants/{tenant_id}/notifications/preview",
+            "rawPath": "/tenants/tenant-a/notifications/preview",
+            "rawQueryString": "",
+            "headers": { "content-type": "application/json", "authorization": bearer("tenant-a", &["Admin"], "admin@example.com") },
+            "requestContext": {
+                "accountId": "123456789012",
+                "apiId": "api",
+                "domainName": "api.example.com",
+                "domainPrefix": "api",
+                "http": { "method": "POST", "path": "/tenants/tenant-a/notifications/preview", "protocol": "HTTP/1.1", "sourceIp": "127.0.0.1", "userAgent": "test" },
+                "requestId": "r1",
+                "routeKey": "POST /tenants/{tenant_id}/notifications/preview",
+                "stage": "$default",
+                "time": "01/Oct/2025:10:00:00 +0000",
+                "timeEpoch": 1759312800000u64
+            },
+            "body": "{\"type\": \"confirmation\"}",
+            "isBase64Encoded": false
+        });
+        assert!(preview::is_http_event(&event));
+        assert!(!preview::is_http_event(&json!({ "Records": [] })));
+
+        let response = preview::handle_event(&fakes.store, &templates, &fakes.channels, &fakes.links, event).await.unwrap();
+        assert_eq!(response["statusCode"], 200);
+        assert_eq!(response["headers"]["content-type"], "application/json");
+        let body: Value = serde_json::from_str(response["body"].as_str().unwrap()).unwrap();
+        assert_eq!(body["template"], "booking-confirmation");
+    }
 }
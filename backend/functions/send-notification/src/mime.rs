@@ -0,0 +1,259 @@
+//! Mensaje MIME que se envía con `SendRawEmail`:
+//!
+//! ```text
+//! multipart/mixed
+//! ├── multipart/alternative
+//! │   ├── text/plain   (generado del HTML)
+//! │   └── text/html
+//! └── invite.ics       (text/calendar; method=REQUEST|CANCEL), si hay evento
+//! ```
+
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
+use lettre::message::{Attachment, Mailbox, MultiPart};
+use lettre::Message;
+use shared_lib::ics::{CalendarEvent, Method};
+
+pub struct Email<'a> {
+    pub from: &'a str,
+    /// Nombre que ve el paciente junto a `from`
+    pub from_name: Option<&'a str>,
+    pub reply_to: Option<&'a str>,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub html: &'a str,
+    pub calendar: Option<(&'a CalendarEvent, Method)>,
+    /// URL de baja en un clic: `List-Unsubscribe` y `List-Unsubscribe-Post`
+    pub unsubscribe: Option<&'a str>,
+}
+
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.trim_start_matches('<').trim_end_matches('>').to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), format!("<{}>", self.0))
+    }
+}
+
+/// RFC 8058: el cliente de correo hace el `POST` sin abrir el navegador.
+#[derive(Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".into())
+    }
+}
+
+impl Email<'_> {
+    /// Bytes del mensaje completo, con cabeceras.
+    pub fn to_raw(&self) -> anyhow::Result<Vec<u8>> {
+        let alternative = MultiPart::alternative_plain_html(html_to_text(self.html), self.html.to_string());
+        let body = match self.calendar {
+            Some((event, method)) => {
+                let content_type = ContentType::parse(&format!("text/calendar; charset=utf-8; method={}", method.as_str()))?;
+                MultiPart::mixed()
+                    .multipart(alternative)
+                    .singlepart(Attachment::new("invite.ics".into()).body(event.to_ics(method), content_type))
+            }
+            None => alternative,
+        };
+        let from = match self.from_name {
+            Some(name) => Mailbox::new(Some(name.to_string()), self.from.parse()?),
+            None => self.from.parse::<Mailbox>()?,
+        };
+        let mut builder = Message::builder()
+            .from(from)
+            .to(self.to.parse::<Mailbox>()?)
+            .subject(self.subject);
+        if let Some(reply_to) = self.reply_to {
+            builder = builder.reply_to(reply_to.parse::<Mailbox>()?);
+        }
+        if let Some(url) = self.unsubscribe {
+            builder = builder.header(ListUnsubscribe(url.to_string())).header(ListUnsubscribePost);
+        }
+        let message = builder.multipart(body)?;
+        Ok(message.formatted())
+    }
+}
+
+/// Versión en texto plano del HTML de una plantilla: sin `<head>` ni estilos,
+/// un salto de línea por bloque, los enlaces como `texto (url)` y sin líneas
+/// en blanco repetidas.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    let mut href: Option<String> = None;
+    let mut link_text = String::new();
+
+    while let Some(start) = rest.find('<') {
+        push_text(&mut text, &mut link_text, href.is_some(), &rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let name = tag.trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("").to_ascii_lowercase();
+        let closing = tag.starts_with('/');
+        match name.as_str() {
+            // Contenido que no se lee: se salta hasta el cierre
+            "head" | "style" | "script" | "title" if !closing => {
+                let close = format!("</{}", name);
+                rest = match rest.to_ascii_lowercase().find(&close) {
+                    Some(i) => &rest[i..],
+                    None => "",
+                };
+            }
+            "a" if !closing => {
+                href = attribute(tag, "href");
+                link_text.clear();
+            }
+            "a" => {
+                let label = link_text.trim().to_string();
+                match href.take() {
+                    Some(url) if !url.is_empty() && url.trim_start_matches("mailto:") != label => {
+                        text.push_str(&format!("{} ({})", label, url))
+                    }
+                    _ => text.push_str(&label),
+                }
+            }
+            "br" | "p" | "div" | "h1" | "h2" | "h3" | "h4" | "li" | "tr" | "center" => text.push('\n'),
+            _ => {}
+        }
+    }
+    push_text(&mut text, &mut link_text, href.is_some(), rest);
+
+    let mut lines: Vec<String> = vec![];
+    for line in decode_entities(&text).lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines.join("\n") + "\n"
+}
+
+fn push_text(text: &mut String, link_text: &mut String, in_link: bool, chunk: &str) {
+    if in_link {
+        link_text.push_str(chunk);
+    } else {
+        text.push_str(chunk);
+    }
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = tag[start..].find('"')?;
+    Some(decode_entities(&tag[start..start + len]))
+}
+
+/// Entidades que produce el autoescape de minijinja (y `&nbsp;`).
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#x2f;", "/")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use shared_lib::ics::Party;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_plain_text_keeps_content_and_links() {
+        let html = r#"<!DOCTYPE html><html><head><title>Cita</title><style>.a { color: red; }</style></head>
+<body>
+  <div class="header"><h1>Turnaki</h1></div>
+  <p>Hola <strong>Ana &lt;3</strong>,</p>
+  <p>Fecha: 2025-10-01<br>Hora: 10:00</p>
+  <a href="https:&#x2f;&#x2f;example.com&#x2f;my-appointments" class="button">Ver mi Cita</a>
+  <p>Contacto: <a href="mailto:a@example.com">a@example.com</a></p>
+</body></html>"#;
+        assert_eq!(
+            html_to_text(html),
+            "Turnaki\n\nHola Ana <3,\n\nFecha: 2025-10-01\nHora: 10:00\n\nVer mi Cita (https://example.com/my-appointments)\n\nContacto: a@example.com\n"
+        );
+    }
+
+    #[test]
+    fn test_raw_message_has_text_html_and_calendar_parts() {
+        let event = CalendarEvent {
+            uid: CalendarEvent::booking_uid("b1"),
+            sequence: 1,
+            stamp: utc("2025-09-30T12:00:00Z"),
+            start: utc("2025-10-01T15:00:00Z"),
+            end: utc("2025-10-01T15:30:00Z"),
+            summary: "Limpieza".into(),
+            description: None,
+            location: None,
+            organizer: Party { name: None, email: "a@example.com".into() },
+            attendee: Party { name: None, email: "ana@example.com".into() },
+        };
+        let email = Email {
+            from: "noreply@example.com",
+            from_name: None,
+            reply_to: None,
+            to: "ana@example.com",
+            subject: "Cita Cancelada",
+            html: "<p>Hola</p>",
+            calendar: Some((&event, Method::Cancel)),
+            unsubscribe: None,
+        };
+        let raw = String::from_utf8(email.to_raw().unwrap()).unwrap();
+        assert!(raw.contains("Content-Type: multipart/mixed"));
+        assert!(raw.contains("Content-Type: multipart/alternative"));
+        assert!(raw.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(raw.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(raw.contains("Content-Type: text/calendar; charset=utf-8; method=CANCEL"));
+        assert!(raw.contains("filename=\"invite.ics\""));
+
+        let without = Email { calendar: None, ..email };
+        let raw = String::from_utf8(without.to_raw().unwrap()).unwrap();
+        assert!(!raw.contains("multipart/mixed"));
+        assert!(!raw.contains("text/calendar"));
+        assert!(!raw.contains("List-Unsubscribe"));
+
+        let with_unsubscribe = Email { unsubscribe: Some("https://api.example.com/unsubscribe?token=t"), ..without };
+        let raw = String::from_utf8(with_unsubscribe.to_raw().unwrap()).unwrap();
+        assert!(raw.contains("List-Unsubscribe: <https://api.example.com/unsubscribe?token=t>\r\n"));
+        assert!(raw.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n"));
+        assert!(raw.contains("From: noreply@example.com\r\n"));
+        assert!(!raw.contains("Reply-To"));
+
+        let branded = Email { from_name: Some("Clinica A"), reply_to: Some("recepcion@example.com"), ..with_unsubscribe };
+        let raw = String::from_utf8(branded.to_raw().unwrap()).unwrap();
+        assert!(raw.contains("From: \"Clinica A\" <noreply@example.com>\r\n"), "{}", raw);
+        assert!(raw.contains("Reply-To: recepcion@example.com\r\n"));
+    }
+}
@@ -0,0 +1,302 @@
+//! Vista previa y envío de prueba para los administradores de la clínica,
+//! por API Gateway. Renderizan igual que un envío real (`render_template`),
+//! con una reserva de la clínica o con datos de ejemplo, y opcionalmente con
+//! un borrador de plantilla que todavía no se guardó.
+//!
+//! El envío de prueba va solo por email y a la dirección del token de quien lo
+//! pide; no pasa por la lista de supresión ni queda en el historial de la
+//! reserva.
+
+use chrono::{DateTime, Duration, Utc};
+use lambda_http::aws_lambda_events::apigw::ApiGatewayV2httpResponse;
+use lambda_http::request::LambdaRequest;
+use lambda_http::{Body, Error, Request, RequestPayloadExt, Response};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use shared_lib::models::{Channel, Locale, TemplateOverride};
+use shared_lib::repository::notification_id;
+use shared_lib::timezone::{Tz, DEFAULT_TIMEZONE};
+use shared_lib::{authorize, parse_jwt_claims, resolve_tenant, success_response, Action, ApiError, JwtClaims, Repository, Resource};
+
+use crate::channels::{Channels, Message};
+use crate::delivery::SendError;
+use crate::links::Links;
+use crate::mime::html_to_text;
+use crate::templates::{check_override, Templates};
+use crate::{default_template, render_template, resolve_template, template_context, Audience, NotificationPayload};
+
+#[derive(Debug, Deserialize)]
+struct PreviewRequest {
+    /// `confirmation`, `reminder`, `cancellation` o `rescheduled`
+    #[serde(rename = "type")]
+    notification_type: String,
+    #[serde(default)]
+    channel: Channel,
+    /// Sin él, el de la clínica (o el del paciente de la reserva)
+    #[serde(default)]
+    locale: Option<Locale>,
+    /// Reserva de la clínica; sin ella se usan datos de ejemplo
+    #[serde(default)]
+    booking_id: Option<String>,
+    /// Plantilla propia con otro nombre (p. ej. de la política de recordatorios)
+    #[serde(default)]
+    template: Option<String>,
+    /// Plantilla sin guardar que reemplaza a la de la clínica
+    #[serde(default)]
+    draft: Option<Draft>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Draft {
+    #[serde(default)]
+    subject: Option<String>,
+    body: String,
+}
+
+/// Evento de API Gateway (HTTP API, payload 2.0).
+pub fn is_http_event(payload: &Value) -> bool {
+    payload["requestContext"]["http"].is_object()
+}
+
+/// Convierte el evento en `Request` y la respuesta en la que espera API Gateway.
+pub async fn handle_event(repo: &dyn Repository, templates: &Templates, channels: &Channels, links: &Links, payload: Value) -> Result<Value, Error> {
+    let request: LambdaRequest = serde_json::from_value(payload)?;
+    let response = handler(repo, templates, channels, links, request.into()).await;
+    let (parts, body) = response.into_parts();
+    let response = ApiGatewayV2httpResponse {
+        status_code: parts.status.as_u16().into(),
+        headers: parts.headers,
+        body: match body {
+            Body::Empty => None,
+            body => Some(body),
+        },
+        ..Default::default()
+    };
+    Ok(serde_json::to_value(response)?)
+}
+
+pub async fn handler(repo: &dyn Repository, templates: &Templates, channels: &Channels, links: &Links, req: Request) -> Response<Body> {
+    let result: Result<Response<Body>, ApiError> = async {
+        let method = req.method().as_str().to_string();
+        let path = req.uri().path().to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match (method.as_str(), segments.as_slice()) {
+            ("POST", ["tenants", tenant_id, "notifications", "preview"]) => preview(repo, templates, links, &req, tenant_id).await,
+            ("POST", ["tenants", tenant_id, "notifications", "test"]) => send_test(repo, templates, channels, links, &req, tenant_id).await,
+            _ => Err(ApiError::NotFound("Ruta no encontrada".into()))
+        }
+    }.await;
+
+    result.unwrap_or_else(ApiError::into_response)
+}
+
+/// Como editar plantillas: solo los administradores de la clínica.
+async fn admin(req: &Request, tenant_id: &str) -> Result<(JwtClaims, String), ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Update, Resource::Tenant)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+    Ok((claims, tenant_id))
+}
+
+/// Notificación de ejemplo: una cita mañana a las 10:00 en la zona de la clínica.
+fn sample_notification(tenant_id: &str, tz: Tz) -> NotificationPayload {
+    let tomorrow = (Utc::now() + Duration::days(1)).with_timezone(&tz).date_naive();
+    let day = |date: chrono::NaiveDate| Some(date.format("%Y-%m-%d").to_string());
+    NotificationPayload {
+        patient_name: "Ana García".into(),
+        tenant_id: Some(tenant_id.to_string()),
+        booking_id: "EJEMPLO".into(),
+        appointment_date: day(tomorrow),
+        appointment_time: Some("10:00".into()),
+        previous_appointment_date: day(tomorrow - Duration::days(1)),
+        previous_appointment_time: Some("09:00".into()),
+        professional_name: Some("Dra. Laura Gómez".into()),
+        treatment_name: Some("Limpieza dental".into()),
+        clinic_address: Some("Calle 100 #15-20".into()),
+        hours_before: Some(24),
+        ..Default::default()
+    }
+}
+
+/// Notificación de una reserva de la clínica, con los mismos datos que arma
+/// `booking-events`. La de otra clínica no existe.
+async fn booking_notification(repo: &dyn Repository, tenant_id: &str, booking_id: &str, tz: Tz) -> Result<NotificationPayload, ApiError> {
+    let booking = repo.get_booking(booking_id).await?
+        .filter(|booking| booking.tenant_id == tenant_id)
+        .ok_or_else(|| ApiError::NotFound(format!("Reserva {} no encontrada", booking_id)))?;
+    let site = repo.get_site(tenant_id, &booking.site_id).await?;
+    let professional = repo.get_professional(tenant_id, &booking.professional_id).await?;
+    let treatment = repo.get_treatment(tenant_id, &booking.treatment_id).await?;
+
+    let tz = site.as_ref().map_or(tz, |site| site.tz(tz));
+    let local = DateTime::parse_from_rfc3339(&booking.start_time)
+        .map_err(|e| ApiError::Internal(e.into()))?
+        .with_timezone(&tz);
+    Ok(NotificationPayload {
+        patient_email: Some(booking.patient_email),
+        patient_phone: booking.patient_phone,
+        patient_name: booking.patient_name,
+        tenant_id: Some(tenant_id.to_string()),
+        booking_id: booking.id,
+        appointment_date: Some(local.format("%Y-%m-%d").to_string()),
+        appointment_time: Some(local.format("%H:%M").to_string()),
+        professional_name: professional.map(|p| p.name),
+        treatment_name: treatment.map(|t| t.name),
+        clinic_email: site.as_ref().and_then(|site| site.contact_email.clone()),
+        clinic_address: site.map(|s| s.address),
+        start_time: Some(booking.start_time),
+        end_time: Some(booking.end_time),
+        hours_before: Some(24),
+        ..Default::default()
+    })
+}
+
+/// Una vista previa o un envío de prueba ya renderizados.
+struct Preview {
+    notification: NotificationPayload,
+    template: String,
+    locale: Locale,
+    /// La plantilla propia (o el borrador) se usó
+    custom: bool,
+    /// Por qué la plantilla propia guardada no se usaría
+    warning: Option<String>,
+    subject: String,
+    body: String,
+}
+
+async fn render(repo: &dyn Repository, templates: &Templates, links: &Links, req: &Request, tenant_id: &str) -> Result<Preview, ApiError> {
+    let payload = req.payload::<PreviewRequest>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+    if !["confirmation", "reminder", "cancellation", "rescheduled"].contains(&payload.notification_type.as_str()) {
+        return Err(ApiError::Validation("type debe ser confirmation, reminder, cancellation o rescheduled".into()));
+    }
+    let tz = repo.get_tenant(tenant_id).await?.map_or(DEFAULT_TIMEZONE, |tenant| tenant.tz());
+    let mut notification = match payload.booking_id.as_deref() {
+        Some(booking_id) => booking_notification(repo, tenant_id, booking_id, tz).await?,
+        None => sample_notification(tenant_id, tz),
+    };
+    notification.notification_type = payload.notification_type;
+    notification.channel = payload.channel;
+    notification.locale = payload.locale;
+    notification.template = payload.template;
+
+    let audience = Audience::load(repo, &notification).await?;
+    let locale = audience.locale(&notification);
+    let draft_given = payload.draft.is_some();
+    let (name, custom) = match payload.draft {
+        Some(draft) => {
+            let name = notification.template.clone()
+                .unwrap_or_else(|| default_template(&notification.notification_type).to_string());
+            let draft = TemplateOverride {
+                tenant_id: tenant_id.to_string(),
+                channel: notification.channel,
+                template: name.clone(),
+                locale,
+                subject: draft.subject,
+                body: draft.body,
+                updated_at: Utc::now().to_rfc3339(),
+            };
+            draft.validate()
+                .map_err(|e| ApiError::Validation(format!("Plantilla inválida: {}", e)))?;
+            (name, Some(draft))
+        }
+        None => resolve_template(repo, templates, &audience, &notification, locale).await?,
+    };
+    if custom.is_none() && !templates.exists(notification.channel, &name) {
+        return Err(ApiError::NotFound(format!("La clínica no tiene la plantilla {}", name)));
+    }
+    let context = template_context(links, &notification, &audience, None);
+    // Un envío real con una plantilla que no renderiza usa la incluida; el
+    // borrador se rechaza para que se pueda corregir
+    let warning = match custom.as_ref().map(|custom| check_override(custom, &context)) {
+        Some(Err(e)) if draft_given => return Err(ApiError::Validation(e.to_string())),
+        Some(Err(e)) => Some(e.to_string()),
+        _ => None,
+    };
+    let rendered = render_template(templates, &notification, &name, locale, custom.as_ref(), &context)
+        .map_err(|e| ApiError::Internal(e.into()))?;
+    Ok(Preview {
+        notification,
+        template: name,
+        locale,
+        custom: custom.is_some() && warning.is_none(),
+        warning,
+        subject: rendered.subject,
+        body: rendered.body,
+    })
+}
+
+/// Asunto, HTML y texto del mensaje, sin enviarlo.
+async fn preview(repo: &dyn Repository, templates: &Templates, links: &Links, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let (_, tenant_id) = admin(req, tenant_id).await?;
+    let preview = render(repo, templates, links, req, &tenant_id).await?;
+
+    let (subject, html, text) = match preview.notification.channel {
+        Channel::Email => (Some(preview.subject), Some(preview.body.clone()), html_to_text(&preview.body)),
+        Channel::Sms | Channel::Whatsapp => (None, None, preview.body),
+    };
+    success_response(json!({
+        "channel": preview.notification.channel,
+        "template": preview.template,
+        "locale": preview.locale,
+        "custom": preview.custom,
+        "warning": preview.warning,
+        "subject": subject,
+        "html": html,
+        "text": text,
+    }))
+}
+
+/// Envía la vista previa por email al administrador que la pide.
+async fn send_test(
+    repo: &dyn Repository,
+    templates: &Templates,
+    channels: &Channels,
+    links: &Links,
+    req: &Request,
+    tenant_id: &str,
+) -> Result<Response<Body>, ApiError> {
+    let (claims, tenant_id) = admin(req, tenant_id).await?;
+    let to = claims.email.clone()
+        .ok_or_else(|| ApiError::Validation("El token no tiene email al que enviar la prueba".into()))?;
+    let preview = render(repo, templates, links, req, &tenant_id).await?;
+    if preview.notification.channel != Channel::Email {
+        return Err(ApiError::Validation("El envío de prueba solo es por email".into()));
+    }
+    let channel = channels.get(Channel::Email)
+        .ok_or_else(|| ApiError::Unprocessable("Email no configurado".into()))?;
+
+    let branding = repo.get_tenant(&tenant_id).await?.map(|tenant| tenant.settings.branding).unwrap_or_default();
+    let message = Message {
+        id: notification_id(Utc::now()),
+        channel: Channel::Email,
+        tenant_id: Some(tenant_id.clone()),
+        booking_id: preview.notification.booking_id,
+        to: to.clone(),
+        subject: preview.subject,
+        body: preview.body,
+        template: preview.template,
+        calendar: None,
+        unsubscribe: None,
+        locale: preview.locale,
+        from_name: branding.sender_name,
+        reply_to: branding.reply_to,
+    };
+    let provider_message_id = channel.send(&message).await.map_err(|e| match e {
+        SendError::Permanent(e) => ApiError::Unprocessable(format!("El proveedor rechazó la prueba: {}", e)),
+        SendError::Transient(e) => ApiError::Internal(e),
+    })?;
+
+    tracing::info!(tenant_id = %tenant_id, template = %message.template, locale = message.locale.as_str(), "Test notification sent");
+
+    success_response(json!({
+        "message": "Prueba enviada",
+        "to": to,
+        "template": message.template,
+        "locale": message.locale,
+        "custom": preview.custom,
+        "warning": preview.warning,
+        "provider_message_id": provider_message_id,
+    }))
+}
@@ -0,0 +1,307 @@
+//! Plantillas de las notificaciones con minijinja. Van compiladas dentro del
+//! binario y se validan al arrancar la Lambda: un error de sintaxis, un
+//! include roto o una variable que el contexto no tiene hace fallar el init,
+//! no el primer envío.
+//!
+//! Hay un juego por idioma (`es/`, `en/`, `pt/`) y, en cada uno, las de cada
+//! canal: `email/*.html`, `sms/*.txt` y `whatsapp/*.txt` (una línea por
+//! parámetro de la plantilla aprobada en Meta), más el asunto de cada email en
+//! `subject/*.txt`. Los parciales, el contexto y el entorno están en
+//! `shared_lib::templates`, que comparten con tenants.
+//!
+//! Las clínicas pueden reemplazar cualquiera con una propia (`TemplateOverride`,
+//! en DynamoDB). Esas se validan al guardarlas; si aun así una no renderiza, se
+//! envía la incluida.
+//!
+//! Las `.html` escapan HTML automáticamente, y una variable desconocida es un
+//! error de render (incluso dentro de un `if`). Los campos opcionales del
+//! contexto siempre existen (`null` si faltan), así que se comprueban con
+//! `{% if campo %}`.
+
+use minijinja::Environment;
+use shared_lib::models::{Channel, Locale, TemplateOverride};
+use shared_lib::templates::{environment, file_name, render_override, subject_name};
+
+pub use shared_lib::templates::{check_override, TemplateContext, DEFAULT_PRIMARY_COLOR};
+
+macro_rules! bundled {
+    ($($path:literal),* $(,)?) => {
+        &[$(($path, include_str!(concat!("../templates/", $path)))),*]
+    };
+}
+
+/// Plantillas que se pueden enviar. Se piden por idioma, canal y nombre sin
+/// extensión; la extensión `.html` es la que activa el autoescape.
+const PAGES: &[(&str, &str)] = bundled![
+    "es/email/booking-confirmation.html",
+    "es/email/booking-reminder.html",
+    "es/email/booking-cancelled.html",
+    "es/email/booking-rescheduled.html",
+    "es/sms/booking-confirmation.txt",
+    "es/sms/booking-reminder.txt",
+    "es/sms/booking-cancelled.txt",
+    "es/sms/booking-rescheduled.txt",
+    "es/whatsapp/booking-confirmation.txt",
+    "es/whatsapp/booking-reminder.txt",
+    "es/whatsapp/booking-cancelled.txt",
+    "es/whatsapp/booking-rescheduled.txt",
+    "es/subject/booking-confirmation.txt",
+    "es/subject/booking-reminder.txt",
+    "es/subject/booking-cancelled.txt",
+    "es/subject/booking-rescheduled.txt",
+    "en/email/booking-confirmation.html",
+    "en/email/booking-reminder.html",
+    "en/email/booking-cancelled.html",
+    "en/email/booking-rescheduled.html",
+    "en/sms/booking-confirmation.txt",
+    "en/sms/booking-reminder.txt",
+    "en/sms/booking-cancelled.txt",
+    "en/sms/booking-rescheduled.txt",
+    "en/whatsapp/booking-confirmation.txt",
+    "en/whatsapp/booking-reminder.txt",
+    "en/whatsapp/booking-cancelled.txt",
+    "en/whatsapp/booking-rescheduled.txt",
+    "en/subject/booking-confirmation.txt",
+    "en/subject/booking-reminder.txt",
+    "en/subject/booking-cancelled.txt",
+    "en/subject/booking-rescheduled.txt",
+    "pt/email/booking-confirmation.html",
+    "pt/email/booking-reminder.html",
+    "pt/email/booking-cancelled.html",
+    "pt/email/booking-rescheduled.html",
+    "pt/sms/booking-confirmation.txt",
+    "pt/sms/booking-reminder.txt",
+    "pt/sms/booking-cancelled.txt",
+    "pt/sms/booking-rescheduled.txt",
+    "pt/whatsapp/booking-confirmation.txt",
+    "pt/whatsapp/booking-reminder.txt",
+    "pt/whatsapp/booking-cancelled.txt",
+    "pt/whatsapp/booking-rescheduled.txt",
+    "pt/subject/booking-confirmation.txt",
+    "pt/subject/booking-reminder.txt",
+    "pt/subject/booking-cancelled.txt",
+    "pt/subject/booking-rescheduled.txt",
+];
+
+/// Notificación renderizada. `subject` solo en email.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendered {
+    pub subject: String,
+    pub body: String,
+}
+
+pub struct Templates {
+    env: Environment<'static>,
+    pages: Vec<&'static str>,
+}
+
+impl Templates {
+    pub fn load() -> Result<Self, minijinja::Error> {
+        Self::build(PAGES)
+    }
+
+    /// Compila las plantillas y renderiza cada página con un contexto completo
+    /// y con uno sin opcionales.
+    fn build(pages: &[(&'static str, &'static str)]) -> Result<Self, minijinja::Error> {
+        let mut env = environment()?;
+        for (name, source) in pages {
+            env.add_template(name, source)?;
+        }
+
+        let templates = Self { env, pages: pages.iter().map(|(name, _)| *name).collect() };
+        for name in &templates.pages {
+            let template = templates.env.get_template(name)?;
+            template.render(TemplateContext::sample())?;
+            template.render(TemplateContext::minimal())?;
+        }
+        Ok(templates)
+    }
+
+    /// Si hay una plantilla incluida con ese nombre para el canal (todos los
+    /// idiomas tienen las mismas).
+    pub fn exists(&self, channel: Channel, name: &str) -> bool {
+        self.pages.contains(&file_name(Locale::default(), channel, name).as_str())
+    }
+
+    /// Renderiza la plantilla `name` del canal en el idioma pedido: la de la
+    /// clínica si tiene una y renderiza, si no la incluida. El asunto del
+    /// email sale de la de la clínica o, si no trae, del incluido para
+    /// `subject`, el nombre de la plantilla del tipo de notificación.
+    pub fn render(
+        &self,
+        channel: Channel,
+        name: &str,
+        locale: Locale,
+        subject: &str,
+        custom: Option<&TemplateOverride>,
+        context: &TemplateContext,
+    ) -> Result<Rendered, minijinja::Error> {
+        let custom = match custom.map(|custom| render_override(custom, context)) {
+            Some(Ok(rendered)) => Some(rendered),
+            Some(Err(e)) => {
+                tracing::warn!(error = %e, template = %name, locale = locale.as_str(), "Tenant template does not render; using bundled one");
+                None
+            }
+            None => None,
+        };
+        let (custom_subject, body) = match custom {
+            Some((subject, body)) => (subject, body),
+            None => (None, self.env.get_template(&file_name(locale, channel, name))?.render(context)?),
+        };
+        let subject = match (channel, custom_subject) {
+            (Channel::Email, Some(subject)) => subject,
+            (Channel::Email, None) => self.env.get_template(&subject_name(locale, subject))?.render(context)?,
+            (Channel::Sms | Channel::Whatsapp, _) => String::new(),
+        };
+        Ok(Rendered { subject: subject.trim().to_string(), body })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(templates: &Templates, channel: Channel, name: &str, context: &TemplateContext) -> String {
+        templates.render(channel, name, Locale::Es, name, None, context).unwrap().body
+    }
+
+    fn custom(channel: Channel, subject: Option<&str>, body: &str) -> TemplateOverride {
+        TemplateOverride {
+            tenant_id: "tenant-a".into(),
+            channel,
+            template: "booking-confirmation".into(),
+            locale: Locale::En,
+            subject: subject.map(String::from),
+            body: body.into(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_all_templates_load() {
+        let templates = Templates::load().unwrap();
+        for channel in [Channel::Email, Channel::Sms, Channel::Whatsapp] {
+            assert!(templates.exists(channel, "booking-reminder"));
+        }
+        assert!(!templates.exists(Channel::Email, "partials/header"));
+    }
+
+    #[test]
+    fn test_values_are_html_escaped() {
+        let templates = Templates::load().unwrap();
+        let context = TemplateContext {
+            patient_name: "<script>alert(1)</script>".into(),
+            clinic_address: Some("<a href=\"https://phish.example\">Pagar</a>".into()),
+            ..TemplateContext::sample()
+        };
+        let html = render(&templates, Channel::Email, "booking-confirmation", &context);
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;&#x2f;script&gt;"));
+        assert!(!html.contains("<a href=\"https://phish.example\">"));
+    }
+
+    #[test]
+    fn test_optional_fields_are_left_out() {
+        let templates = Templates::load().unwrap();
+        let context = TemplateContext { hours_before: None, treatment_name: None, ..TemplateContext::sample() };
+        let html = render(&templates, Channel::Email, "booking-reminder", &context);
+        assert!(html.contains("Tu cita se acerca"));
+        assert!(!html.contains("{{"));
+        assert!(!html.contains("Tratamiento"));
+        assert!(!html.contains(">none<"));
+
+        let html = render(&templates, Channel::Email, "booking-reminder", &TemplateContext::sample());
+        assert!(html.contains("Tu cita es en 24 horas"));
+        assert!(html.contains("Dra. Pérez"));
+    }
+
+    #[test]
+    fn test_text_channels_are_not_escaped() {
+        let templates = Templates::load().unwrap();
+        let context = TemplateContext { clinic_name: "Pérez & Hijos".into(), hours_before: None, ..TemplateContext::sample() };
+        let sms = templates.render(Channel::Sms, "booking-reminder", Locale::Es, "booking-reminder", None, &context).unwrap();
+        assert_eq!(
+            sms,
+            Rendered {
+                subject: String::new(),
+                body: "Pérez & Hijos: recordatorio de tu cita, el 2025-10-01 a las 10:00 en Calle 100. https://example.com/my-appointments".into(),
+            }
+        );
+
+        let whatsapp = render(&templates, Channel::Whatsapp, "booking-confirmation", &TemplateContext { treatment_name: None, ..context });
+        assert_eq!(whatsapp, "Ana\nPérez & Hijos\n2025-10-01\n10:00\ntu cita");
+    }
+
+    #[test]
+    fn test_locales_and_branding() {
+        let templates = Templates::load().unwrap();
+        let context = TemplateContext::sample();
+        let english = templates.render(Channel::Email, "booking-reminder", Locale::En, "booking-reminder", None, &context).unwrap();
+        assert_eq!(english.subject, "Appointment reminder - Clínica A");
+        assert!(english.body.contains("Your appointment is in 24 hours"));
+        // El parcial es el del mismo idioma
+        assert!(english.body.contains("Need help? Contact us"));
+        assert!(english.body.contains("<html lang=\"en\">"));
+
+        let portuguese = templates.render(Channel::Sms, "booking-cancelled", Locale::Pt, "booking-cancelled", None, &context).unwrap();
+        assert!(portuguese.body.starts_with("Clínica A: sua consulta de 2025-10-01 às 10:00 foi cancelada."));
+
+        let spanish = templates.render(Channel::Email, "booking-confirmation", Locale::Es, "booking-confirmation", None, &context).unwrap();
+        assert_eq!(spanish.subject, "Cita confirmada - Clínica A");
+        assert!(spanish.body.contains("src=\"https:&#x2f;&#x2f;example.com&#x2f;logo.png\""));
+        assert!(spanish.body.contains("background-color: #123456;"));
+        assert!(!spanish.body.contains("Turnaki"));
+        // Con reply_to la clínica recibe respuestas
+        assert!(!spanish.body.contains("por favor no responder"));
+
+        let unbranded = TemplateContext { primary_color: DEFAULT_PRIMARY_COLOR.into(), ..TemplateContext::minimal() };
+        let html = render(&templates, Channel::Email, "booking-confirmation", &unbranded);
+        assert!(html.contains("<h1>🦷 Clínica A</h1>"));
+        assert!(html.contains("background-color: #0ea5e9;"));
+        assert!(html.contains("por favor no responder"));
+    }
+
+    #[test]
+    fn test_tenant_templates_replace_bundled_ones_and_fall_back_when_broken() {
+        let templates = Templates::load().unwrap();
+        let context = TemplateContext::sample();
+        let mine = custom(
+            Channel::Email,
+            Some("{{ clinic_name }}: see you on {{ appointment_date }}"),
+            "<p>Hi {{ patient_name }}</p>{% include \"email/partials/footer.html\" %}",
+        );
+        let rendered = templates.render(Channel::Email, "booking-confirmation", Locale::En, "booking-confirmation", Some(&mine), &context).unwrap();
+        assert_eq!(rendered.subject, "Clínica A: see you on 2025-10-01");
+        assert!(rendered.body.starts_with("<p>Hi Ana</p>"));
+        assert!(rendered.body.contains("Need help? Contact us"));
+
+        // Sin asunto propio, el incluido
+        let body_only = custom(Channel::Email, None, "<p>{{ patient_name | upper }}</p>");
+        let rendered = templates.render(Channel::Email, "booking-confirmation", Locale::En, "booking-confirmation", Some(&body_only), &context).unwrap();
+        assert_eq!(rendered, Rendered { subject: "Appointment confirmed - Clínica A".into(), body: "<p>ANA</p>".into() });
+
+        // Las propias también escapan HTML
+        let escaped = templates
+            .render(Channel::Email, "booking-confirmation", Locale::En, "booking-confirmation", Some(&body_only), &TemplateContext { patient_name: "<b>".into(), ..context.clone() })
+            .unwrap();
+        assert_eq!(escaped.body, "<p>&lt;B&gt;</p>");
+
+        let broken = custom(Channel::Email, None, "Hi {{ patient_nmae }}");
+        let rendered = templates.render(Channel::Email, "booking-confirmation", Locale::En, "booking-confirmation", Some(&broken), &context).unwrap();
+        assert!(rendered.body.contains("Appointment Confirmed!"));
+    }
+
+    #[test]
+    fn test_broken_templates_fail_at_load() {
+        let typo = Templates::build(&[("typo.html", "Hola {{ patient_nmae }}")]);
+        assert_eq!(typo.err().unwrap().kind(), minijinja::ErrorKind::UndefinedError);
+
+        let in_condition = Templates::build(&[("typo.html", "{% if hours_befor %}pronto{% endif %}")]);
+        assert_eq!(in_condition.err().unwrap().kind(), minijinja::ErrorKind::UndefinedError);
+
+        let missing_partial = Templates::build(&[("es/email/broken.html", "{% include \"email/partials/missing.html\" %}")]);
+        assert_eq!(missing_partial.err().unwrap().kind(), minijinja::ErrorKind::TemplateNotFound);
+
+        assert!(Templates::build(&[("syntax.html", "{% if %}")]).is_err());
+    }
+}
@@ -1,8 +1,13 @@
-use lambda_http::{run, service_fn, Body, Request, Response, Error, RequestPayloadExt};
-use serde::{Deserialize, Serialize};
+use lambda_http::{run, service_fn, Body, Request, RequestExt, Response, Error, RequestPayloadExt};
+use serde::Deserialize;
 use validator::Validate;
-use shared_lib::{init_tracing, success_response, created_response, ApiError, get_client, table_name};
-use aws_sdk_dynamodb::types::AttributeValue;
+use shared_lib::{init_tracing, success_response, created_response, ApiError, parse_jwt_claims, authorize, resolve_tenant, Action, Resource, Scope, JwtClaims, StoreError};
+use shared_lib::models::{Channel, ChannelPreference, ContactPreferences, Locale, Site, TemplateOverride, Tenant, TenantSettings};
+use shared_lib::unsubscribe::{secret_from_env, UnsubscribeToken};
+use shared_lib::schedule::OpeningHours;
+use shared_lib::templates::{check_override, TemplateContext};
+use shared_lib::timezone::{parse_timezone, DEFAULT_TIMEZONE};
+use shared_lib::{DynamoStore, Repository};
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize, Validate)]
@@ -15,32 +20,83 @@ struct CreateTenantRequest {
     
     #[serde(default)]
     timezone: Option<String>,
+    
+    /// Sin ella se usan los valores por defecto (recordatorios T-24h y T-2h)
+    #[serde(default)]
+    settings: TenantSettings,
 }
 
-#[derive(Debug, Serialize)]
-struct Tenant {
-    id: String,
+#[derive(Debug, Deserialize, Validate)]
+struct SiteRequest {
+    #[validate(length(min = 3, max = 100))]
     name: String,
-    contact_email: String,
-    timezone: String,
-    created_at: String,
-    status: String,
+    
+    #[validate(length(min = 3, max = 200))]
+    address: String,
+    
+    /// Zona IANA propia de la sede; sin ella se usa la del tenant
+    #[serde(default)]
+    timezone: Option<String>,
+    
+    #[serde(default)]
+    opening_hours: OpeningHours,
+    
+    #[serde(default = "default_chairs")]
+    #[validate(range(min = 1, max = 100))]
+    chairs: u32,
+    
+    #[serde(default)]
+    #[validate(email)]
+    contact_email: Option<String>,
 }
 
-async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let result: Result<Response<Body>, ApiError> = (|| async {
-        let method = req.method().as_str();
-        let path = req.uri().path();
+fn default_chairs() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+struct PreferencesRequest {
+    preferences: Vec<ChannelPreference>,
+    #[serde(default)]
+    locale: Option<Locale>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TemplateRequest {
+    #[serde(default)]
+    subject: Option<String>,
+    body: String,
+}
+
+/// `unsubscribe_secret` firma los enlaces de baja (`UNSUBSCRIBE_SECRET`).
+async fn handler(repo: &dyn Repository, unsubscribe_secret: Option<&[u8]>, req: Request) -> Result<Response<Body>, Error> {
+    let result: Result<Response<Body>, ApiError> = async {
+        let method = req.method().as_str().to_string();
+        let path = req.uri().path().to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
         
-        match (method, path) {
-            ("POST", "/tenants") => create_tenant(req).await,
-            ("GET", path) if path.starts_with("/tenants/") => {
-                let id = path.strip_prefix("/tenants/").unwrap();
-                get_tenant(id).await
-            }
+        match (method.as_str(), segments.as_slice()) {
+            ("POST", ["tenants"]) => create_tenant(repo, req).await,
+            ("GET", ["tenants", id]) => get_tenant(repo, &req, id).await,
+            ("PUT", ["tenants", id, "settings"]) => update_settings(repo, &req, id).await,
+            ("POST", ["tenants", tenant_id, "sites"]) => create_site(repo, &req, tenant_id).await,
+            ("GET", ["tenants", tenant_id, "sites"]) => list_sites(repo, &req, tenant_id).await,
+            ("GET", ["tenants", tenant_id, "sites", id]) => get_site(repo, &req, tenant_id, id).await,
+            ("PUT", ["tenants", tenant_id, "sites", id]) => update_site(repo, &req, tenant_id, id).await,
+            ("DELETE", ["tenants", tenant_id, "sites", id]) => delete_site(repo, &req, tenant_id, id).await,
+            ("GET", ["tenants", tenant_id, "preferences"]) => get_preferences(repo, &req, tenant_id).await,
+            ("PUT", ["tenants", tenant_id, "preferences"]) => update_preferences(repo, &req, tenant_id).await,
+            ("GET", ["tenants", tenant_id, "suppressions"]) => list_suppressions(repo, &req, tenant_id).await,
+            ("DELETE", ["tenants", tenant_id, "suppressions"]) => delete_suppression(repo, &req, tenant_id).await,
+            ("GET", ["tenants", tenant_id, "templates"]) => list_templates(repo, &req, tenant_id).await,
+            ("PUT", ["tenants", tenant_id, "templates", channel, name, locale]) => put_template(repo, &req, tenant_id, channel, name, locale).await,
+            ("DELETE", ["tenants", tenant_id, "templates", channel, name, locale]) => delete_template(repo, &req, tenant_id, channel, name, locale).await,
+            // Públicas: el token firmado es la autorización
+            ("GET", ["unsubscribe"]) => unsubscribe_status(repo, unsubscribe_secret, &req).await,
+            ("POST", ["unsubscribe"]) => unsubscribe(repo, unsubscribe_secret, &req).await,
             _ => Err(ApiError::NotFound("Ruta no encontrada".into()))
         }
-    })().await;
+    }.await;
     
     match result {
         Ok(resp) => Ok(resp),
@@ -48,78 +104,418 @@ async fn handler(req: Request) -> Result<Response<Body>, Error> {
     }
 }
 
-async fn create_tenant(req: Request) -> Result<Response<Body>, ApiError> {
+async fn create_tenant(repo: &dyn Repository, req: Request) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(&req).await?;
+    authorize(&claims, Action::Create, Resource::Tenant)?;
+
     let payload = req.payload::<CreateTenantRequest>()?
         .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
     
     payload.validate()
         .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
+    payload.settings.validate()
+        .map_err(|e| ApiError::Validation(format!("settings inválido: {}", e)))?;
+    
+    let timezone = match payload.timezone.as_deref() {
+        Some(name) => parse_timezone(name)?,
+        None => DEFAULT_TIMEZONE,
+    };
     
     let tenant_id = Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
     
-    let client = get_client().await;
-    
-    client.put_item()
-        .table_name(table_name())
-        .item("PK", AttributeValue::S(format!("TENANT#{}", tenant_id)))
-        .item("SK", AttributeValue::S("METADATA".to_string()))
-        .item("GSI1PK", AttributeValue::S("TENANT".to_string()))
-        .item("GSI1SK", AttributeValue::S(format!("TENANT#{}", tenant_id)))
-        .item("id", AttributeValue::S(tenant_id.clone()))
-        .item("name", AttributeValue::S(payload.name.clone()))
-        .item("contactEmail", AttributeValue::S(payload.contact_email.clone()))
-        .item("timezone", AttributeValue::S(payload.timezone.clone().unwrap_or_else(|| "America/Bogota".into())))
-        .item("createdAt", AttributeValue::S(now.clone()))
-        .item("status", AttributeValue::S("active".to_string()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB error: {}", e)))?;
-    
-    tracing::info!(tenant_id = %tenant_id, "Tenant created");
-    
     let tenant = Tenant {
         id: tenant_id,
         name: payload.name,
         contact_email: payload.contact_email,
-        timezone: payload.timezone.unwrap_or_else(|| "America/Bogota".into()),
+        timezone: timezone.name().to_string(),
         created_at: now,
         status: "active".into(),
+        settings: payload.settings,
     };
+
+    repo.put_tenant(&tenant).await?;
+    
+    tracing::info!(tenant_id = %tenant.id, "Tenant created");
     
     created_response(tenant)
 }
 
-async fn get_tenant(id: &str) -> Result<Response<Body>, ApiError> {
-    let client = get_client().await;
-    
-    let result = client.get_item()
-        .table_name(table_name())
-        .key("PK", AttributeValue::S(format!("TENANT#{}", id)))
-        .key("SK", AttributeValue::S("METADATA".to_string()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB error: {}", e)))?;
-    
-    if let Some(item) = result.item {
-        let tenant = Tenant {
-            id: item.get("id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            name: item.get("name").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            contact_email: item.get("contactEmail").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            timezone: item.get("timezone").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_else(|| "UTC".into()),
-            created_at: item.get("createdAt").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            status: item.get("status").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-        };
-        success_response(tenant)
-    } else {
-        Err(ApiError::NotFound(format!("Tenant {} no encontrado", id)))
+async fn get_tenant(repo: &dyn Repository, req: &Request, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Tenant)?;
+    if scope < Scope::Any && claims.tenant() != Some(id) {
+        return Err(ApiError::Forbidden("No puedes consultar otro tenant".into()));
+    }
+
+    match repo.get_tenant(id).await? {
+        Some(tenant) => success_response(tenant),
+        None => Err(ApiError::NotFound(format!("Tenant {} no encontrado", id))),
+    }
+}
+
+/// Reemplaza la configuración completa del tenant (p. ej. su política de
+/// recordatorios). Aplica a las reservas que se confirmen o reprogramen después.
+async fn update_settings(repo: &dyn Repository, req: &Request, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Update, Resource::Tenant)?;
+    if scope < Scope::Any && claims.tenant() != Some(id) {
+        return Err(ApiError::Forbidden("No puedes modificar otro tenant".into()));
+    }
+
+    let settings = req.payload::<TenantSettings>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+    settings.validate()
+        .map_err(|e| ApiError::Validation(format!("settings inválido: {}", e)))?;
+
+    let tenant = Tenant {
+        settings,
+        ..repo.get_tenant(id).await?
+            .ok_or_else(|| ApiError::NotFound(format!("Tenant {} no encontrado", id)))?
+    };
+    repo.put_tenant(&tenant).await?;
+
+    tracing::info!(tenant_id = %tenant.id, "Tenant settings updated");
+
+    success_response(tenant)
+}
+
+/// Valida el body de una sede y devuelve la zona normalizada (si la sobreescribe).
+fn validate_site(req: &Request) -> Result<(SiteRequest, Option<String>), ApiError> {
+    let payload = req.payload::<SiteRequest>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+    
+    payload.validate()
+        .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
+    payload.opening_hours.validate()
+        .map_err(|e| ApiError::Validation(format!("opening_hours inválido: {}", e)))?;
+    
+    let timezone = payload.timezone.as_deref()
+        .map(parse_timezone)
+        .transpose()?
+        .map(|tz| tz.name().to_string());
+    Ok((payload, timezone))
+}
+
+async fn fetch_site(repo: &dyn Repository, tenant_id: &str, id: &str) -> Result<Site, ApiError> {
+    repo.get_site(tenant_id, id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("Sede {} no encontrada", id)))
+}
+
+async fn create_site(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Create, Resource::Site)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+    
+    let (payload, timezone) = validate_site(req)?;
+    
+    if repo.get_tenant(&tenant_id).await?.is_none() {
+        return Err(ApiError::NotFound(format!("Tenant {} no encontrado", tenant_id)));
+    }
+    
+    let site = Site {
+        id: Uuid::new_v4().to_string(),
+        tenant_id,
+        name: payload.name,
+        address: payload.address,
+        timezone,
+        opening_hours: payload.opening_hours,
+        chairs: payload.chairs,
+        contact_email: payload.contact_email,
+        status: "active".into(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    
+    repo.put_site(&site).await?;
+    
+    tracing::info!(tenant_id = %site.tenant_id, site_id = %site.id, "Site created");
+    
+    created_response(site)
+}
+
+async fn list_sites(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Site)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+    
+    let sites = repo.list_sites(&tenant_id).await?;
+    
+    success_response(serde_json::json!({"sites": sites, "count": sites.len()}))
+}
+
+async fn get_site(repo: &dyn Repository, req: &Request, tenant_id: &str, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Site)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+    
+    success_response(fetch_site(repo, &tenant_id, id).await?)
+}
+
+async fn update_site(repo: &dyn Repository, req: &Request, tenant_id: &str, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Update, Resource::Site)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+    
+    let (payload, timezone) = validate_site(req)?;
+    let current = fetch_site(repo, &tenant_id, id).await?;
+    
+    let site = Site {
+        name: payload.name,
+        address: payload.address,
+        timezone,
+        opening_hours: payload.opening_hours,
+        chairs: payload.chairs,
+        contact_email: payload.contact_email,
+        ..current
+    };
+    
+    repo.put_site(&site).await?;
+    
+    success_response(site)
+}
+
+/// Las reservas y horarios existentes siguen apuntando a la sede, así que no se
+/// borra: queda inactiva y deja de aceptar reservas y de ofrecer disponibilidad.
+async fn delete_site(repo: &dyn Repository, req: &Request, tenant_id: &str, id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Delete, Resource::Site)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+    
+    let site = Site {
+        status: "inactive".into(),
+        ..fetch_site(repo, &tenant_id, id).await?
+    };
+    
+    repo.put_site(&site).await?;
+    
+    tracing::info!(tenant_id = %site.tenant_id, site_id = %site.id, "Site deactivated");
+    
+    success_response(serde_json::json!({
+        "message": "Sede desactivada",
+        "site_id": site.id
+    }))
+}
+
+fn query_param(req: &Request, name: &str) -> Option<String> {
+    req.query_string_parameters_ref()
+        .and_then(|params| params.first(name))
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Tenant y email del paciente cuyas preferencias se consultan: el `email` de
+/// la query o, para un paciente, el suyo (y solo el suyo).
+fn preferences_target(claims: &JwtClaims, req: &Request, tenant_id: &str, scope: Scope) -> Result<(String, String), ApiError> {
+    let tenant_id = resolve_tenant(claims, Some(tenant_id), scope)?;
+    let own = claims.email.as_deref();
+    let email = match (query_param(req, "email"), scope) {
+        (Some(email), Scope::Own) if own.is_some_and(|own| own.eq_ignore_ascii_case(&email)) => email,
+        (Some(_), Scope::Own) => return Err(ApiError::Forbidden("Solo puedes gestionar tus propias preferencias".into())),
+        (Some(email), _) => email,
+        (None, _) => own.map(str::to_string).ok_or_else(|| ApiError::Validation("email es obligatorio".into()))?,
+    };
+    Ok((tenant_id, email))
+}
+
+async fn fetch_preferences(repo: &dyn Repository, tenant_id: &str, email: &str) -> Result<ContactPreferences, StoreError> {
+    Ok(repo.get_preferences(tenant_id, email).await?.unwrap_or_else(|| ContactPreferences::new(tenant_id, email)))
+}
+
+/// Preferencias de comunicación de un paciente. Sin registro, las de por
+/// defecto: transaccionales sí, marketing no.
+async fn get_preferences(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Contact)?;
+    let (tenant_id, email) = preferences_target(&claims, req, tenant_id, scope)?;
+
+    success_response(fetch_preferences(repo, &tenant_id, &email).await?)
+}
+
+/// Reemplaza las altas y bajas explícitas del paciente.
+async fn update_preferences(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Update, Resource::Contact)?;
+    let (tenant_id, email) = preferences_target(&claims, req, tenant_id, scope)?;
+
+    let payload = req.payload::<PreferencesRequest>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+    let preferences = ContactPreferences {
+        preferences: payload.preferences,
+        locale: payload.locale,
+        updated_at: chrono::Utc::now().to_rfc3339(),
+        ..ContactPreferences::new(&tenant_id, &email)
+    };
+    preferences.validate()
+        .map_err(|e| ApiError::Validation(format!("preferences inválido: {}", e)))?;
+    repo.put_preferences(&preferences).await?;
+
+    tracing::info!(tenant_id = %tenant_id, "Contact preferences updated");
+
+    success_response(preferences)
+}
+
+async fn list_suppressions(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Contact)?;
+    if scope < Scope::Tenant {
+        return Err(ApiError::Forbidden("Sin permiso para ver la lista de supresión".into()));
+    }
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+
+    let suppressions = repo.list_suppressions(&tenant_id).await?;
+    success_response(serde_json::json!({"suppressions": suppressions, "count": suppressions.len()}))
+}
+
+/// Vuelve a habilitar una dirección (p. ej. el paciente arregló su buzón).
+async fn delete_suppression(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Delete, Resource::Contact)?;
+    let tenant_id = resolve_tenant(&claims, Some(tenant_id), scope)?;
+
+    let channel = query_param(req, "channel").as_deref().map_or(Some(Channel::Email), Channel::parse)
+        .ok_or_else(|| ApiError::Validation("channel debe ser email, sms o whatsapp".into()))?;
+    let address = query_param(req, "address")
+        .ok_or_else(|| ApiError::Validation("address es obligatorio".into()))?;
+    match repo.delete_suppression(&tenant_id, channel, &address).await {
+        Ok(()) => {}
+        Err(StoreError::ConditionFailed) => return Err(ApiError::NotFound("La dirección no está en la lista de supresión".into())),
+        Err(e) => return Err(e.into()),
+    }
+
+    tracing::info!(tenant_id = %tenant_id, channel = channel.as_str(), "Address removed from suppression list");
+
+    success_response(serde_json::json!({"message": "Dirección habilitada", "channel": channel, "address": address}))
+}
+
+/// Las plantillas son de los administradores de la clínica.
+async fn template_tenant(req: &Request, tenant_id: &str) -> Result<String, ApiError> {
+    let claims = parse_jwt_claims(req).await?;
+    let scope = authorize(&claims, Action::Update, Resource::Tenant)?;
+    resolve_tenant(&claims, Some(tenant_id), scope)
+}
+
+fn template_path(channel: &str, locale: &str) -> Result<(Channel, Locale), ApiError> {
+    let channel = Channel::parse(channel)
+        .ok_or_else(|| ApiError::Validation("channel debe ser email, sms o whatsapp".into()))?;
+    let locale = Locale::parse(locale)
+        .ok_or_else(|| ApiError::Validation("locale debe ser es, en o pt".into()))?;
+    Ok((channel, locale))
+}
+
+/// Plantillas propias de la clínica; el resto de combinaciones usa las incluidas.
+async fn list_templates(repo: &dyn Repository, req: &Request, tenant_id: &str) -> Result<Response<Body>, ApiError> {
+    let tenant_id = template_tenant(req, tenant_id).await?;
+
+    let templates = repo.list_template_overrides(&tenant_id).await?;
+    success_response(serde_json::json!({"templates": templates, "count": templates.len()}))
+}
+
+/// Crea o reemplaza la plantilla de un canal, nombre e idioma. Se renderiza
+/// con un contexto de ejemplo como al enviarla: una variable que no existe o
+/// un parcial que no está se rechazan con 400.
+async fn put_template(
+    repo: &dyn Repository,
+    req: &Request,
+    tenant_id: &str,
+    channel: &str,
+    name: &str,
+    locale: &str,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id = template_tenant(req, tenant_id).await?;
+    let (channel, locale) = template_path(channel, locale)?;
+
+    let payload = req.payload::<TemplateRequest>()?
+        .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
+    let template = TemplateOverride {
+        tenant_id,
+        channel,
+        template: name.to_string(),
+        locale,
+        subject: payload.subject,
+        body: payload.body,
+        updated_at: chrono::Utc::now().to_rfc3339(),
+    };
+    template.validate()
+        .map_err(|e| ApiError::Validation(format!("Plantilla inválida: {}", e)))?;
+    check_override(&template, &TemplateContext::sample())
+        .map_err(|e| ApiError::Validation(format!("Plantilla inválida: {}", e)))?;
+    repo.put_template_override(&template).await?;
+
+    tracing::info!(tenant_id = %template.tenant_id, channel = channel.as_str(), template = %template.template, locale = locale.as_str(), "Template saved");
+
+    success_response(template)
+}
+
+/// Vuelve a la plantilla incluida.
+async fn delete_template(
+    repo: &dyn Repository,
+    req: &Request,
+    tenant_id: &str,
+    channel: &str,
+    name: &str,
+    locale: &str,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id = template_tenant(req, tenant_id).await?;
+    let (channel, locale) = template_path(channel, locale)?;
+
+    match repo.delete_template_override(&tenant_id, channel, name, locale).await {
+        Ok(()) => {}
+        Err(StoreError::ConditionFailed) => return Err(ApiError::NotFound("La clínica no tiene esa plantilla".into())),
+        Err(e) => return Err(e.into()),
     }
+
+    tracing::info!(tenant_id = %tenant_id, channel = channel.as_str(), template = %name, locale = locale.as_str(), "Template deleted");
+
+    success_response(serde_json::json!({"message": "Plantilla eliminada", "channel": channel, "template": name, "locale": locale}))
+}
+
+fn verify_unsubscribe(unsubscribe_secret: Option<&[u8]>, req: &Request) -> Result<UnsubscribeToken, ApiError> {
+    let secret = unsubscribe_secret
+        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("UNSUBSCRIBE_SECRET no configurado")))?;
+    query_param(req, "token")
+        .and_then(|token| UnsubscribeToken::verify(&token, secret))
+        .ok_or_else(|| ApiError::Forbidden("Enlace de baja inválido".into()))
+}
+
+fn unsubscribe_response(token: UnsubscribeToken, opted_in: bool) -> Result<Response<Body>, ApiError> {
+    success_response(serde_json::json!({
+        "tenant_id": token.tenant_id,
+        "email": token.email,
+        "channel": token.channel,
+        "category": token.category,
+        "opted_in": opted_in
+    }))
+}
+
+/// Qué da de baja el enlace, sin aplicarla: los clientes de correo abren los
+/// enlaces para previsualizarlos, así que la baja es solo con `POST`.
+async fn unsubscribe_status(repo: &dyn Repository, unsubscribe_secret: Option<&[u8]>, req: &Request) -> Result<Response<Body>, ApiError> {
+    let token = verify_unsubscribe(unsubscribe_secret, req)?;
+    let preferences = fetch_preferences(repo, &token.tenant_id, &token.email).await?;
+    let opted_in = preferences.allows(token.channel, token.category);
+    unsubscribe_response(token, opted_in)
+}
+
+/// Baja del enlace firmado; también la baja en un clic de `List-Unsubscribe`
+/// (RFC 8058), que manda el body `List-Unsubscribe=One-Click`.
+async fn unsubscribe(repo: &dyn Repository, unsubscribe_secret: Option<&[u8]>, req: &Request) -> Result<Response<Body>, ApiError> {
+    let token = verify_unsubscribe(unsubscribe_secret, req)?;
+    let mut preferences = fetch_preferences(repo, &token.tenant_id, &token.email).await?;
+    preferences.set(token.channel, token.category, false);
+    preferences.updated_at = chrono::Utc::now().to_rfc3339();
+    repo.put_preferences(&preferences).await?;
+
+    tracing::info!(tenant_id = %token.tenant_id, channel = token.channel.as_str(), category = token.category.as_str(), "Patient unsubscribed");
+
+    unsubscribe_response(token, false)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     init_tracing();
-    run(service_fn(handler)).await
+    let repo = DynamoStore::from_env();
+    let unsubscribe_secret = secret_from_env();
+    run(service_fn(|req| handler(&repo, unsubscribe_secret.as_deref(), req))).await
 }
 
 #[cfg(test)]
@@ -127,6 +523,13 @@ mod tests {
     use super::*;
     use lambda_http::http::StatusCode;
     use serde_json::json;
+    use lambda_http::http::Method;
+    use lambda_http::aws_lambda_events::query_map::QueryMap;
+    use shared_lib::models::{MessageCategory, Suppression, SuppressionReason};
+    use shared_lib::repository::{ContactRepository, TenantRepository};
+    use std::collections::HashMap;
+    use shared_lib::testing::{bearer, tenant};
+    use shared_lib::MemoryStore;
 
     #[tokio::test]
     async fn test_create_tenant_validates_email() {
@@ -140,8 +543,9 @@ mod tests {
         *request.method_mut() = lambda_http::http::Method::POST;
         *request.uri_mut() = "/tenants".parse().unwrap();
         request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Owner"], "owner@example.com").parse().unwrap());
 
-        let response = handler(request).await.unwrap();
+        let response = handler(&MemoryStore::new(), None, request).await.unwrap();
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
         let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
@@ -150,15 +554,319 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_tenant_not_found_returns_404() {
-        if std::env::var("CI").is_ok() {
-            // En CI no hay DynamoDB disponible; evitamos depender de AWS en este test.
-            return;
-        }
         let mut request = Request::new(Body::Empty);
         *request.method_mut() = lambda_http::http::Method::GET;
         *request.uri_mut() = "/tenants/non-existent-id".parse().unwrap();
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Owner"], "owner@example.com").parse().unwrap());
 
-        let response = handler(request).await.unwrap();
+        let response = handler(&MemoryStore::new(), None, request).await.unwrap();
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
+
+    #[tokio::test]
+    async fn test_only_owner_can_create_tenants() {
+        let body = json!({
+            "name": "Clínica Test",
+            "contact_email": "clinica@example.com"
+        }).to_string();
+
+        let mut request = Request::new(Body::from(body));
+        *request.method_mut() = lambda_http::http::Method::POST;
+        *request.uri_mut() = "/tenants".parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Admin"], "admin@example.com").parse().unwrap());
+
+        let response = handler(&MemoryStore::new(), None, request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_get_other_tenant_is_forbidden() {
+        let mut request = Request::new(Body::Empty);
+        *request.method_mut() = lambda_http::http::Method::GET;
+        *request.uri_mut() = "/tenants/tenant-b".parse().unwrap();
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Admin"], "admin@example.com").parse().unwrap());
+
+        let response = handler(&MemoryStore::new(), None, request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_create_tenant_rejects_unknown_timezone() {
+        let body = json!({
+            "name": "Clínica Test",
+            "contact_email": "clinica@example.com",
+            "timezone": "GMT-5"
+        }).to_string();
+
+        let mut request = Request::new(Body::from(body));
+        *request.method_mut() = lambda_http::http::Method::POST;
+        *request.uri_mut() = "/tenants".parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Owner"], "owner@example.com").parse().unwrap());
+
+        let response = handler(&MemoryStore::new(), None, request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    fn site_request(method: Method, uri: &str, body: Option<serde_json::Value>, auth: String) -> Request {
+        let mut request = Request::new(body.map(|b| Body::from(b.to_string())).unwrap_or(Body::Empty));
+        *request.method_mut() = method;
+        *request.uri_mut() = uri.parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", auth.parse().unwrap());
+        request
+    }
+
+    async fn store_with_tenant() -> MemoryStore {
+        let store = MemoryStore::new();
+        store.put_tenant(&tenant("tenant-a")).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_site_lifecycle() {
+        let store = store_with_tenant().await;
+        let admin = || bearer("tenant-a", &["Admin"], "admin@example.com");
+
+        let created = handler(&store, None, site_request(Method::POST, "/tenants/tenant-a/sites", Some(json!({
+            "name": "Sede Norte",
+            "address": "Calle 100 #15-20",
+            "timezone": "America/Bogota",
+            "chairs": 3,
+            "opening_hours": {"days": [{"weekday": "mon", "start": "08:00", "end": "18:00"}]}
+        })), admin())).await.unwrap();
+        assert_eq!(created.status(), StatusCode::CREATED);
+        let site: serde_json::Value = serde_json::from_slice(created.body()).unwrap();
+        let uri = format!("/tenants/tenant-a/sites/{}", site["id"].as_str().unwrap());
+
+        let updated = handler(&store, None, site_request(Method::PUT, &uri, Some(json!({
+            "name": "Sede Norte",
+            "address": "Calle 100 #15-20",
+            "chairs": 4
+        })), admin())).await.unwrap();
+        assert_eq!(updated.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(updated.body()).unwrap();
+        assert_eq!(body["chairs"], 4);
+        assert_eq!(body["timezone"], serde_json::Value::Null);
+
+        let deleted = handler(&store, None, site_request(Method::DELETE, &uri, None, admin())).await.unwrap();
+        assert_eq!(deleted.status(), StatusCode::OK);
+
+        let listed = handler(&store, None, site_request(Method::GET, "/tenants/tenant-a/sites", None, bearer("tenant-a", &["Paciente"], "ana@example.com"))).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(listed.body()).unwrap();
+        assert_eq!(body["count"], 1);
+        assert_eq!(body["sites"][0]["status"], "inactive");
+    }
+
+    #[tokio::test]
+    async fn test_site_validation_and_tenant_scope() {
+        let store = store_with_tenant().await;
+        let admin = || bearer("tenant-a", &["Admin"], "admin@example.com");
+
+        let overlapping = handler(&store, None, site_request(Method::POST, "/tenants/tenant-a/sites", Some(json!({
+            "name": "Sede Norte",
+            "address": "Calle 100 #15-20",
+            "opening_hours": {"days": [
+                {"weekday": "mon", "start": "08:00", "end": "12:00"},
+                {"weekday": "mon", "start": "11:00", "end": "18:00"}
+            ]}
+        })), admin())).await.unwrap();
+        assert_eq!(overlapping.status(), StatusCode::BAD_REQUEST);
+
+        let other_tenant = handler(&store, None, site_request(Method::POST, "/tenants/tenant-b/sites", Some(json!({
+            "name": "Sede Sur",
+            "address": "Carrera 7 #1-10"
+        })), admin())).await.unwrap();
+        assert_eq!(other_tenant.status(), StatusCode::FORBIDDEN);
+
+        let reception = handler(&store, None, site_request(Method::POST, "/tenants/tenant-a/sites", Some(json!({
+            "name": "Sede Sur",
+            "address": "Carrera 7 #1-10"
+        })), bearer("tenant-a", &["Recepción"], "front@example.com"))).await.unwrap();
+        assert_eq!(reception.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_update_reminder_settings() {
+        let store = store_with_tenant().await;
+        let admin = || bearer("tenant-a", &["Admin"], "admin@example.com");
+
+        let updated = handler(&store, None, site_request(Method::PUT, "/tenants/tenant-a/settings", Some(json!({
+            "reminders": [
+                {"hours_before": 48},
+                {"days_before": 0, "at": "07:00", "channel": "sms", "template": "booking-reminder-sms"}
+            ],
+            "channels": ["whatsapp", "email"]
+        })), admin())).await.unwrap();
+        assert_eq!(updated.status(), StatusCode::OK);
+        let tenant = store.get_tenant("tenant-a").await.unwrap().unwrap();
+        assert_eq!(tenant.settings.reminders.len(), 2);
+        assert_eq!(tenant.settings.channels, [Channel::Whatsapp, Channel::Email]);
+        assert_eq!(tenant.settings.reminders[1].offset.label(), "d0-0700");
+
+        let got = handler(&store, None, site_request(Method::GET, "/tenants/tenant-a", None, admin())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(got.body()).unwrap();
+        assert_eq!(body["settings"]["reminders"][0], json!({"hours_before": 48, "channel": "email", "template": "booking-reminder"}));
+        assert_eq!(body["settings"]["reminders"][1]["at"], "07:00");
+
+        let repeated = handler(&store, None, site_request(Method::PUT, "/tenants/tenant-a/settings", Some(json!({
+            "reminders": [{"hours_before": 24}, {"hours_before": 24, "channel": "whatsapp"}]
+        })), admin())).await.unwrap();
+        assert_eq!(repeated.status(), StatusCode::BAD_REQUEST);
+
+        let no_channels = handler(&store, None, site_request(Method::PUT, "/tenants/tenant-a/settings", Some(json!({
+            "channels": []
+        })), admin())).await.unwrap();
+        assert_eq!(no_channels.status(), StatusCode::BAD_REQUEST);
+
+        let reception = handler(&store, None, site_request(Method::PUT, "/tenants/tenant-a/settings", Some(json!({
+            "reminders": []
+        })), bearer("tenant-a", &["Recepción"], "front@example.com"))).await.unwrap();
+        assert_eq!(reception.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_branding_and_tenant_templates() {
+        let store = store_with_tenant().await;
+        let admin = || bearer("tenant-a", &["Admin"], "admin@example.com");
+
+        let settings = |branding: serde_json::Value| site_request(Method::PUT, "/tenants/tenant-a/settings", Some(json!({
+            "locale": "en",
+            "branding": branding
+        })), admin());
+        let updated = handler(&store, None, settings(json!({"primary_color": "#0f766e", "sender_name": "Clínica A", "reply_to": "hola@clinica-a.com"}))).await.unwrap();
+        assert_eq!(updated.status(), StatusCode::OK);
+        let tenant = store.get_tenant("tenant-a").await.unwrap().unwrap();
+        assert_eq!(tenant.settings.locale, Locale::En);
+        assert_eq!(tenant.settings.branding.primary_color.as_deref(), Some("#0f766e"));
+        for branding in [json!({"primary_color": "red; background: url(x)"}), json!({"logo_url": "http://example.com/logo.png"}), json!({"sender_name": "A\" <x@evil.com>"})] {
+            assert_eq!(handler(&store, None, settings(branding)).await.unwrap().status(), StatusCode::BAD_REQUEST);
+        }
+
+        let uri = "/tenants/tenant-a/templates/email/booking-confirmation/en";
+        let put = |uri: &str, body: serde_json::Value, auth: String| site_request(Method::PUT, uri, Some(body), auth);
+        let saved = handler(&store, None, put(uri, json!({"subject": "Booked at {{ clinic_name }}", "body": "<p>Hi {{ patient_name }}</p>"}), admin())).await.unwrap();
+        assert_eq!(saved.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(saved.body()).unwrap();
+        assert_eq!((body["channel"].as_str(), body["template"].as_str(), body["locale"].as_str()), (Some("email"), Some("booking-confirmation"), Some("en")));
+
+        let broken = handler(&store, None, put(uri, json!({"body": "{% if %}"}), admin())).await.unwrap();
+        assert_eq!(broken.status(), StatusCode::BAD_REQUEST);
+        // Se valida como al enviarla: variables y parciales tienen que existir
+        for body in [json!({"body": "Hi {{ patient_nmae }}"}), json!({"subject": "{% if hours_befor %}soon{% endif %}", "body": "y"}), json!({"body": "{% include \"email/partials/missing.html\" %}"})] {
+            assert_eq!(handler(&store, None, put(uri, body, admin())).await.unwrap().status(), StatusCode::BAD_REQUEST);
+        }
+        let with_partial = handler(&store, None, put("/tenants/tenant-a/templates/email/booking-reminder/en", json!({"body": "{% include \"email/partials/footer.html\" %}"}), admin())).await.unwrap();
+        assert_eq!(with_partial.status(), StatusCode::OK);
+        let sms_subject = handler(&store, None, put("/tenants/tenant-a/templates/sms/booking-confirmation/en", json!({"subject": "x", "body": "y"}), admin())).await.unwrap();
+        assert_eq!(sms_subject.status(), StatusCode::BAD_REQUEST);
+        let unknown_locale = handler(&store, None, put("/tenants/tenant-a/templates/email/booking-confirmation/fr", json!({"body": "y"}), admin())).await.unwrap();
+        assert_eq!(unknown_locale.status(), StatusCode::BAD_REQUEST);
+        let reception = handler(&store, None, put(uri, json!({"body": "y"}), bearer("tenant-a", &["Recepción"], "front@example.com"))).await.unwrap();
+        assert_eq!(reception.status(), StatusCode::FORBIDDEN);
+
+        let listed = handler(&store, None, site_request(Method::GET, "/tenants/tenant-a/templates", None, admin())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(listed.body()).unwrap();
+        assert_eq!(body["count"], 2);
+        assert_eq!(body["templates"][0]["subject"], "Booked at {{ clinic_name }}");
+
+        assert_eq!(handler(&store, None, site_request(Method::DELETE, uri, None, admin())).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(handler(&store, None, site_request(Method::DELETE, uri, None, admin())).await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_patient_manages_own_preferences() {
+        let store = store_with_tenant().await;
+        let patient = || bearer("tenant-a", &["Paciente"], "ana@example.com");
+
+        let defaults = handler(&store, None, site_request(Method::GET, "/tenants/tenant-a/preferences", None, patient())).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(defaults.body()).unwrap();
+        assert_eq!(body["email"], "ana@example.com");
+        assert_eq!(body["preferences"], json!([]));
+
+        let updated = handler(&store, None, site_request(Method::PUT, "/tenants/tenant-a/preferences", Some(json!({
+            "preferences": [
+                {"channel": "sms", "category": "transactional", "opted_in": false},
+                {"channel": "email", "category": "marketing", "opted_in": true}
+            ],
+            "locale": "pt"
+        })), patient())).await.unwrap();
+        assert_eq!(updated.status(), StatusCode::OK);
+        let stored = store.get_preferences("tenant-a", "ana@example.com").await.unwrap().unwrap();
+        assert_eq!(stored.locale, Some(Locale::Pt));
+        assert!(!stored.allows(Channel::Sms, MessageCategory::Transactional));
+        assert!(stored.allows(Channel::Email, MessageCategory::Marketing));
+
+        let other = handler(&store, None, site_request(Method::GET, "/tenants/tenant-a/preferences", None, patient())
+            .with_query_string_parameters(QueryMap::from(HashMap::from([("email".to_string(), "luis@example.com".to_string())])))).await.unwrap();
+        assert_eq!(other.status(), StatusCode::FORBIDDEN);
+
+        let repeated = handler(&store, None, site_request(Method::PUT, "/tenants/tenant-a/preferences", Some(json!({
+            "preferences": [
+                {"channel": "sms", "category": "transactional", "opted_in": false},
+                {"channel": "sms", "category": "transactional", "opted_in": true}
+            ]
+        })), patient())).await.unwrap();
+        assert_eq!(repeated.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_only_admins_lift_suppressions() {
+        let store = store_with_tenant().await;
+        store.put_suppression(&Suppression {
+            tenant_id: "tenant-a".into(),
+            channel: Channel::Email,
+            address: "ana@example.com".into(),
+            reason: SuppressionReason::Bounce,
+            detail: Some("Permanent/General".into()),
+            created_at: "2025-10-01T10:00:00Z".into(),
+        }).await.unwrap();
+        let delete = |auth: String| site_request(Method::DELETE, "/tenants/tenant-a/suppressions", None, auth)
+            .with_query_string_parameters(QueryMap::from(HashMap::from([("address".to_string(), "Ana@example.com".to_string())])));
+
+        let listed = handler(&store, None, site_request(Method::GET, "/tenants/tenant-a/suppressions", None, bearer("tenant-a", &["Recepción"], "front@example.com"))).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(listed.body()).unwrap();
+        assert_eq!(body["suppressions"][0]["reason"], "bounce");
+
+        let reception = handler(&store, None, delete(bearer("tenant-a", &["Recepción"], "front@example.com"))).await.unwrap();
+        assert_eq!(reception.status(), StatusCode::FORBIDDEN);
+        let admin = handler(&store, None, delete(bearer("tenant-a", &["Admin"], "admin@example.com"))).await.unwrap();
+        assert_eq!(admin.status(), StatusCode::OK);
+        let again = handler(&store, None, delete(bearer("tenant-a", &["Admin"], "admin@example.com"))).await.unwrap();
+        assert_eq!(again.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_signed_link_unsubscribes_without_login() {
+        let store = store_with_tenant().await;
+        let token = UnsubscribeToken {
+            tenant_id: "tenant-a".into(),
+            email: "ana@example.com".into(),
+            channel: Channel::Email,
+            category: MessageCategory::Transactional,
+        }
+        .sign(b"secret");
+        let unsubscribe = |method: Method, token: &str| {
+            let mut request = Request::new(Body::from("List-Unsubscribe=One-Click"));
+            *request.method_mut() = method;
+            *request.uri_mut() = "/unsubscribe".parse().unwrap();
+            request.with_query_string_parameters(QueryMap::from(HashMap::from([("token".to_string(), token.to_string())])))
+        };
+
+        // Abrir el enlace no da de baja
+        let status = handler(&store, Some(b"secret"), unsubscribe(Method::GET, &token)).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(status.body()).unwrap();
+        assert_eq!(body["opted_in"], true);
+        assert!(store.get_preferences("tenant-a", "ana@example.com").await.unwrap().is_none());
+
+        let done = handler(&store, Some(b"secret"), unsubscribe(Method::POST, &token)).await.unwrap();
+        assert_eq!(done.status(), StatusCode::OK);
+        let stored = store.get_preferences("tenant-a", "ana@example.com").await.unwrap().unwrap();
+        assert!(!stored.allows(Channel::Email, MessageCategory::Transactional));
+        assert!(stored.allows(Channel::Sms, MessageCategory::Transactional));
+
+        let forged = handler(&store, Some(b"otro"), unsubscribe(Method::POST, &token)).await.unwrap();
+        assert_eq!(forged.status(), StatusCode::FORBIDDEN);
+    }
 }
@@ -1,14 +1,17 @@
 use lambda_http::{run, service_fn, Body, Request, Response, Error, RequestExt, RequestPayloadExt};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use validator::Validate;
-use shared_lib::{init_tracing, success_response, created_response, ApiError, get_client, table_name};
-use aws_sdk_dynamodb::types::AttributeValue;
+use shared_lib::{init_tracing, success_response, created_response, ApiError, parse_jwt_claims, authorize, resolve_tenant, Action, Resource};
+use shared_lib::models::Treatment;
+use shared_lib::{DynamoStore, Repository};
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize, Validate)]
 struct CreateTreatmentRequest {
+    /// Opcional: por defecto el tenant del token (override solo para super-admin)
+    #[serde(default)]
     #[validate(length(min = 1, max = 50))]
-    tenant_id: String,
+    tenant_id: Option<String>,
     
     #[validate(length(min = 3, max = 100))]
     name: String,
@@ -23,27 +26,16 @@ struct CreateTreatmentRequest {
     price: Option<f64>,
 }
 
-#[derive(Debug, Serialize)]
-struct Treatment {
-    id: String,
-    tenant_id: String,
-    name: String,
-    duration_minutes: i32,
-    buffer_minutes: i32,
-    price: f64,
-    created_at: String,
-}
-
-async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let result: Result<Response<Body>, ApiError> = (|| async {
+async fn handler(repo: &dyn Repository, req: Request) -> Result<Response<Body>, Error> {
+    let result: Result<Response<Body>, ApiError> = async {
         let method = req.method().as_str();
         
         match method {
-            "POST" => create_treatment(req).await,
-            "GET" => list_treatments(req).await,
+            "POST" => create_treatment(repo, req).await,
+            "GET" => list_treatments(repo, req).await,
             _ => Err(ApiError::NotFound("Método no soportado".into()))
         }
-    })().await;
+    }.await;
     
     match result {
         Ok(resp) => Ok(resp),
@@ -51,76 +43,46 @@ async fn handler(req: Request) -> Result<Response<Body>, Error> {
     }
 }
 
-async fn create_treatment(req: Request) -> Result<Response<Body>, ApiError> {
+async fn create_treatment(repo: &dyn Repository, req: Request) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(&req).await?;
+    let scope = authorize(&claims, Action::Create, Resource::Treatment)?;
+
     let payload = req.payload::<CreateTreatmentRequest>()?
         .ok_or_else(|| ApiError::Validation("Body vacío".into()))?;
     
     payload.validate()
         .map_err(|e| ApiError::Validation(format!("{:?}", e)))?;
+
+    let tenant_id = resolve_tenant(&claims, payload.tenant_id.as_deref(), scope)?;
     
     let treatment_id = Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
     
-    let client = get_client().await;
-    
-    client.put_item()
-        .table_name(table_name())
-        .item("PK", AttributeValue::S(format!("TENANT#{}", payload.tenant_id)))
-        .item("SK", AttributeValue::S(format!("TREATMENT#{}", treatment_id)))
-        .item("GSI1PK", AttributeValue::S(format!("TENANT#{}", payload.tenant_id)))
-        .item("GSI1SK", AttributeValue::S(format!("TREATMENT#{}", treatment_id)))
-        .item("id", AttributeValue::S(treatment_id.clone()))
-        .item("tenantId", AttributeValue::S(payload.tenant_id.clone()))
-        .item("name", AttributeValue::S(payload.name.clone()))
-        .item("durationMinutes", AttributeValue::N(payload.duration_minutes.to_string()))
-        .item("bufferMinutes", AttributeValue::N(payload.buffer_minutes.unwrap_or(0).to_string()))
-        .item("price", AttributeValue::N(payload.price.unwrap_or(0.0).to_string()))
-        .item("createdAt", AttributeValue::S(now.clone()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB error: {}", e)))?;
-    
     let treatment = Treatment {
         id: treatment_id,
-        tenant_id: payload.tenant_id,
+        tenant_id,
         name: payload.name,
         duration_minutes: payload.duration_minutes,
         buffer_minutes: payload.buffer_minutes.unwrap_or(0),
         price: payload.price.unwrap_or(0.0),
         created_at: now,
     };
+
+    repo.put_treatment(&treatment).await?;
     
     created_response(treatment)
 }
 
-async fn list_treatments(req: Request) -> Result<Response<Body>, ApiError> {
-    let tenant_id = req.query_string_parameters_ref()
-        .and_then(|params| params.first("tenant_id"))
-        .ok_or_else(|| ApiError::Validation("tenant_id requerido".into()))?;
-    
-    let client = get_client().await;
-    
-    let result = client.query()
-        .table_name(table_name())
-        .key_condition_expression("PK = :pk AND begins_with(SK, :sk)")
-        .expression_attribute_values(":pk", AttributeValue::S(format!("TENANT#{}", tenant_id)))
-        .expression_attribute_values(":sk", AttributeValue::S("TREATMENT#".to_string()))
-        .send()
-        .await
-        .map_err(|e| ApiError::Internal(anyhow::anyhow!("DynamoDB error: {}", e)))?;
+async fn list_treatments(repo: &dyn Repository, req: Request) -> Result<Response<Body>, ApiError> {
+    let claims = parse_jwt_claims(&req).await?;
+    let scope = authorize(&claims, Action::Read, Resource::Treatment)?;
+
+    // tenant_id por query es opcional y solo puede diferir del token para super-admin
+    let requested = req.query_string_parameters_ref()
+        .and_then(|params| params.first("tenant_id").map(str::to_string));
+    let tenant_id = resolve_tenant(&claims, requested.as_deref(), scope)?;
     
-    let treatments: Vec<Treatment> = result.items.unwrap_or_default()
-        .iter()
-        .map(|item| Treatment {
-            id: item.get("id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            tenant_id: item.get("tenantId").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            name: item.get("name").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            duration_minutes: item.get("durationMinutes").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(30),
-            buffer_minutes: item.get("bufferMinutes").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0),
-            price: item.get("price").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0.0),
-            created_at: item.get("createdAt").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-        })
-        .collect();
+    let treatments = repo.list_treatments(&tenant_id).await?;
     
     success_response(serde_json::json!({"treatments": treatments, "count": treatments.len()}))
 }
@@ -128,5 +90,47 @@ async fn list_treatments(req: Request) -> Result<Response<Body>, ApiError> {
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     init_tracing();
-    run(service_fn(handler)).await
+    let repo = DynamoStore::from_env();
+    run(service_fn(|req| handler(&repo, req))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_http::http::StatusCode;
+    use lambda_http::aws_lambda_events::query_map::QueryMap;
+    use serde_json::json;
+    use shared_lib::testing::bearer;
+    use shared_lib::MemoryStore;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_create_treatment_for_other_tenant_is_forbidden() {
+        let body = json!({
+            "tenant_id": "tenant-b",
+            "name": "Limpieza",
+            "duration_minutes": 30
+        }).to_string();
+
+        let mut request = Request::new(Body::from(body));
+        *request.method_mut() = lambda_http::http::Method::POST;
+        *request.uri_mut() = "/treatments".parse().unwrap();
+        request.headers_mut().insert("content-type", "application/json".parse().unwrap());
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Admin"], "admin@example.com").parse().unwrap());
+
+        let response = handler(&MemoryStore::new(), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_list_treatments_of_other_tenant_is_forbidden() {
+        let mut request = Request::new(Body::Empty)
+            .with_query_string_parameters(QueryMap::from(HashMap::from([("tenant_id".to_string(), "tenant-b".to_string())])));
+        *request.method_mut() = lambda_http::http::Method::GET;
+        *request.uri_mut() = "/treatments".parse().unwrap();
+        request.headers_mut().insert("authorization", bearer("tenant-a", &["Paciente"], "ana@example.com").parse().unwrap());
+
+        let response = handler(&MemoryStore::new(), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
 }
@@ -1,4 +1,6 @@
+
 #[tokio::test]
+#[allow(clippy::assertions_on_constants, clippy::nonminimal_bool)]
 async fn test_treatment_duration_validation() {
     assert!(30 >= 5 && 30 <= 480);
     assert!(45 >= 5 && 45 <= 480);
@@ -0,0 +1,60 @@
+//! Costo por invocación de obtener el cliente DynamoDB y de leer el tratamiento
+//! al crear una reserva. Corre offline: credenciales y región ficticias y
+//! `MemoryStore` como tabla.
+//!
+//!     cargo bench -p shared-lib --bench dynamodb_client
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use shared_lib::dynamodb::build_client;
+use shared_lib::models::Treatment;
+use shared_lib::repository::TreatmentRepository;
+use shared_lib::{get_client, MemoryStore};
+
+fn offline_aws_env() {
+    std::env::set_var("AWS_REGION", "us-east-1");
+    std::env::set_var("AWS_ACCESS_KEY_ID", "bench");
+    std::env::set_var("AWS_SECRET_ACCESS_KEY", "bench");
+    std::env::set_var("AWS_EC2_METADATA_DISABLED", "true");
+}
+
+fn client(c: &mut Criterion) {
+    offline_aws_env();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("dynamodb_client");
+    // Comportamiento previo: cargar config y construir el cliente en cada llamada
+    group.bench_function("build_per_call", |b| b.to_async(&rt).iter(build_client));
+    group.bench_function("shared", |b| b.to_async(&rt).iter(get_client));
+    group.finish();
+}
+
+fn treatment_reads(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let store = MemoryStore::new();
+    rt.block_on(store.put_treatment(&Treatment {
+        id: "treat-1".into(),
+        tenant_id: "tenant-a".into(),
+        name: "Limpieza".into(),
+        duration_minutes: 30,
+        buffer_minutes: 10,
+        price: 0.0,
+        created_at: "2025-01-01T00:00:00Z".into(),
+    }))
+    .unwrap();
+
+    let mut group = c.benchmark_group("create_booking_treatment");
+    // Comportamiento previo: una lectura para la duración y otra para el buffer
+    group.bench_function("two_reads", |b| {
+        b.to_async(&rt).iter(|| async {
+            let duration = store.get_treatment("tenant-a", "treat-1").await.unwrap().unwrap().duration_minutes;
+            let buffer = store.get_treatment("tenant-a", "treat-1").await.unwrap().unwrap().buffer_minutes;
+            duration + buffer
+        })
+    });
+    group.bench_function("single_read", |b| {
+        b.to_async(&rt).iter(|| async { store.get_treatment("tenant-a", "treat-1").await.unwrap().unwrap().occupied_minutes() })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, client, treatment_reads);
+criterion_main!(benches);
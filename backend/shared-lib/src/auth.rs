@@ -1,9 +1,10 @@
 use lambda_http::Request;
 use serde::Deserialize;
+use jsonwebtoken::{decode, decode_header, errors::ErrorKind, Algorithm, DecodingKey, Validation};
 use crate::error::ApiError;
-use base64::Engine as _;
+use crate::jwks::{cached_key_set, cognito_issuer_from_env, JwksSource, KeySet};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct JwtClaims {
     pub sub: Option<String>,
     pub email: Option<String>,
@@ -12,6 +13,47 @@ pub struct JwtClaims {
     pub tenant_id: Option<String>,
     #[serde(rename = "custom:tenant_id")]
     pub custom_tenant_id: Option<String>,
+    pub token_use: Option<String>,
+    pub client_id: Option<String>,
+}
+
+impl JwtClaims {
+    /// Tenant del token (`tenant_id` o `custom:tenant_id`).
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant_id.as_deref().or(self.custom_tenant_id.as_deref())
+    }
+
+    pub fn require_tenant(&self) -> Result<String, ApiError> {
+        self.tenant()
+            .map(str::to_string)
+            .ok_or_else(|| ApiError::Forbidden("Tenant no presente en token".into()))
+    }
+}
+
+/// Parámetros contra los que se valida cada token.
+#[derive(Debug, Clone)]
+pub struct VerifierConfig {
+    pub issuer: String,
+    /// Client ID de Cognito: se compara con `aud` (ID token) o `client_id` (access token).
+    pub audience: String,
+    /// `id` o `access`
+    pub token_use: String,
+}
+
+impl VerifierConfig {
+    /// Lee `JWT_ISSUER` / `JWT_AUDIENCE` / `JWT_TOKEN_USE`, con fallback a
+    /// `COGNITO_USER_POOL_ID` y `COGNITO_CLIENT_ID`.
+    pub fn from_env() -> Result<Self, ApiError> {
+        let issuer = std::env::var("JWT_ISSUER")
+            .ok()
+            .or_else(cognito_issuer_from_env)
+            .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("JWT_ISSUER no configurado")))?;
+        let audience = std::env::var("JWT_AUDIENCE")
+            .or_else(|_| std::env::var("COGNITO_CLIENT_ID"))
+            .map_err(|_| ApiError::Internal(anyhow::anyhow!("JWT_AUDIENCE no configurado")))?;
+        let token_use = std::env::var("JWT_TOKEN_USE").unwrap_or_else(|_| "id".into());
+        Ok(VerifierConfig { issuer, audience, token_use })
+    }
 }
 
 fn extract_bearer_token(req: &Request) -> Result<String, ApiError> {
@@ -30,29 +72,189 @@ fn extract_bearer_token(req: &Request) -> Result<String, ApiError> {
     }
 }
 
-pub fn parse_jwt_claims(req: &Request) -> Result<JwtClaims, ApiError> {
-    let token = extract_bearer_token(req)?;
-    // Formato JWT: header.payload.signature (base64url)
-    let mut parts = token.split('.');
-    let _header = parts.next().ok_or_else(|| ApiError::Validation("JWT malformado".into()))?;
-    let payload_b64 = parts.next().ok_or_else(|| ApiError::Validation("JWT malformado".into()))?;
+/// `kid` del header JWT; se exige RS256 antes de buscar la llave.
+fn token_kid(token: &str) -> Result<String, ApiError> {
+    let header = decode_header(token).map_err(|_| ApiError::InvalidToken("JWT malformado".into()))?;
+    if header.alg != Algorithm::RS256 {
+        return Err(ApiError::InvalidToken("Algoritmo de firma no permitido".into()));
+    }
+    header.kid.ok_or_else(|| ApiError::InvalidToken("JWT sin kid".into()))
+}
 
-    let payload_bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
-        .decode(payload_b64)
-        .map_err(|_| ApiError::Validation("Payload JWT inválido".into()))?;
+fn verify_with_key(token: &str, key: &DecodingKey, config: &VerifierConfig) -> Result<JwtClaims, ApiError> {
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_issuer(&[config.issuer.as_str()]);
+    validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+    if config.token_use == "id" {
+        validation.set_audience(&[config.audience.as_str()]);
+    } else {
+        // Los access tokens de Cognito no traen `aud`; se valida `client_id` abajo
+        validation.validate_aud = false;
+    }
 
-    let claims: JwtClaims = serde_json::from_slice(&payload_bytes)
-        .map_err(|_| ApiError::Validation("Claims JWT inválidos".into()))?;
+    let data = decode::<JwtClaims>(token, key, &validation).map_err(|e| match e.kind() {
+        ErrorKind::ExpiredSignature => ApiError::TokenExpired,
+        ErrorKind::InvalidSignature => ApiError::InvalidToken("Firma JWT inválida".into()),
+        ErrorKind::InvalidIssuer => ApiError::InvalidToken("Issuer JWT inválido".into()),
+        ErrorKind::InvalidAudience => ApiError::InvalidToken("Audience JWT inválido".into()),
+        ErrorKind::MissingRequiredClaim(claim) => ApiError::InvalidToken(format!("Falta claim {}", claim)),
+        _ => ApiError::InvalidToken("JWT inválido".into()),
+    })?;
+    let claims = data.claims;
+
+    if claims.token_use.as_deref() != Some(config.token_use.as_str()) {
+        return Err(ApiError::InvalidToken("token_use no permitido".into()));
+    }
+    if config.token_use != "id" && claims.client_id.as_deref() != Some(config.audience.as_str()) {
+        return Err(ApiError::InvalidToken("client_id JWT inválido".into()));
+    }
 
     Ok(claims)
 }
 
-pub fn require_tenant(req: &Request) -> Result<String, ApiError> {
-    let claims = parse_jwt_claims(req)?;
-    if let Some(t) = claims.tenant_id.or(claims.custom_tenant_id) {
-        return Ok(t);
+/// Verifica firma, expiración, issuer, audience y `token_use` contra un JWKS ya cargado.
+pub fn verify_token(token: &str, keys: &KeySet, config: &VerifierConfig) -> Result<JwtClaims, ApiError> {
+    let kid = token_kid(token)?;
+    let key = keys.get(&kid).ok_or_else(|| ApiError::InvalidToken("kid desconocido".into()))?;
+    verify_with_key(token, key, config)
+}
+
+pub async fn parse_jwt_claims(req: &Request) -> Result<JwtClaims, ApiError> {
+    let token = extract_bearer_token(req)?;
+    let kid = token_kid(&token)?;
+    let config = VerifierConfig::from_env()?;
+    let source = JwksSource::from_env()?;
+
+    let mut keys = cached_key_set(&source, false).await?;
+    if keys.get(&kid).is_none() {
+        // Posible rotación de llaves en Cognito: recargar una vez
+        keys = cached_key_set(&source, true).await?;
     }
-    Err(ApiError::Forbidden("Tenant no presente en token".into()))
+    verify_token(&token, &keys, &config)
+}
+
+pub async fn require_tenant(req: &Request) -> Result<String, ApiError> {
+    parse_jwt_claims(req).await?.require_tenant()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    const ISSUER: &str = "https://cognito-idp.us-east-1.amazonaws.com/us-east-1_test";
+    const CLIENT_ID: &str = "test-client";
 
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read(path).unwrap()
+    }
+
+    fn keys() -> KeySet {
+        KeySet::from_json(&fixture("jwks.json")).unwrap()
+    }
+
+    fn config() -> VerifierConfig {
+        VerifierConfig { issuer: ISSUER.into(), audience: CLIENT_ID.into(), token_use: "id".into() }
+    }
+
+    fn sign(claims: serde_json::Value, kid: &str) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.into());
+        let key = EncodingKey::from_rsa_pem(&fixture("jwt-test-key.pem")).unwrap();
+        encode(&header, &claims, &key).unwrap()
+    }
+
+    fn id_claims() -> serde_json::Value {
+        json!({
+            "sub": "user-1",
+            "email": "ana@example.com",
+            "cognito:groups": ["Admin"],
+            "custom:tenant_id": "tenant-a",
+            "token_use": "id",
+            "aud": CLIENT_ID,
+            "iss": ISSUER,
+            "exp": chrono::Utc::now().timestamp() + 600,
+        })
+    }
+
+    #[test]
+    fn test_valid_id_token_is_accepted() {
+        let token = sign(id_claims(), "test-key-1");
+        let claims = verify_token(&token, &keys(), &config()).unwrap();
+        assert_eq!(claims.custom_tenant_id.as_deref(), Some("tenant-a"));
+        assert_eq!(claims.groups.unwrap(), vec!["Admin".to_string()]);
+    }
+
+    #[test]
+    fn test_expired_token_is_rejected_as_expired() {
+        let mut claims = id_claims();
+        claims["exp"] = json!(chrono::Utc::now().timestamp() - 3600);
+        let token = sign(claims, "test-key-1");
+        assert!(matches!(verify_token(&token, &keys(), &config()), Err(ApiError::TokenExpired)));
+    }
+
+    #[test]
+    fn test_forged_payload_is_rejected() {
+        let token = sign(id_claims(), "test-key-1");
+        let parts: Vec<&str> = token.split('.').collect();
+        let mut forged = id_claims();
+        forged["custom:tenant_id"] = json!("tenant-b");
+        let payload = base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            forged.to_string(),
+        );
+        let tampered = format!("{}.{}.{}", parts[0], payload, parts[2]);
+        assert!(matches!(verify_token(&tampered, &keys(), &config()), Err(ApiError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn test_wrong_issuer_audience_and_token_use_are_rejected() {
+        for (field, value) in [("iss", "https://evil.example.com"), ("aud", "other-client"), ("token_use", "access")] {
+            let mut claims = id_claims();
+            claims[field] = json!(value);
+            let token = sign(claims, "test-key-1");
+            assert!(
+                matches!(verify_token(&token, &keys(), &config()), Err(ApiError::InvalidToken(_))),
+                "{} debería invalidar el token",
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_kid_and_unsigned_tokens_are_rejected() {
+        let token = sign(id_claims(), "rotated-key");
+        assert!(matches!(verify_token(&token, &keys(), &config()), Err(ApiError::InvalidToken(_))));
+
+        let header = base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, r#"{"alg":"none","kid":"test-key-1"}"#);
+        let payload = base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, id_claims().to_string());
+        let unsigned = format!("{}.{}.", header, payload);
+        assert!(matches!(verify_token(&unsigned, &keys(), &config()), Err(ApiError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn test_access_token_checks_client_id() {
+        let access_config = VerifierConfig { token_use: "access".into(), ..config() };
+        let mut claims = id_claims();
+        claims["token_use"] = json!("access");
+        claims.as_object_mut().unwrap().remove("aud");
+        claims["client_id"] = json!(CLIENT_ID);
+        let token = sign(claims.clone(), "test-key-1");
+        assert!(verify_token(&token, &keys(), &access_config).is_ok());
+
+        claims["client_id"] = json!("other-client");
+        let token = sign(claims, "test-key-1");
+        assert!(matches!(verify_token(&token, &keys(), &access_config), Err(ApiError::InvalidToken(_))));
+    }
+
+    #[tokio::test]
+    async fn test_key_set_loads_from_local_file() {
+        let source = JwksSource::File(format!("{}/tests/fixtures/jwks.json", env!("CARGO_MANIFEST_DIR")).into());
+        let first = cached_key_set(&source, false).await.unwrap();
+        let second = cached_key_set(&source, false).await.unwrap();
+        assert!(first.get("test-key-1").is_some());
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+    }
+}
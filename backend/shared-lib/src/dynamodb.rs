@@ -1,7 +1,19 @@
 use aws_sdk_dynamodb::Client;
 use std::env;
+use tokio::sync::OnceCell;
 
-pub async fn get_client() -> Client {
+/// Cliente compartido por todas las invocaciones del proceso: Lambda reutiliza
+/// el entorno entre invocaciones warm, así que la configuración de AWS se
+/// resuelve una sola vez (en el cold start).
+static CLIENT: OnceCell<Client> = OnceCell::const_new();
+
+pub async fn get_client() -> &'static Client {
+    CLIENT.get_or_init(build_client).await
+}
+
+/// Carga la configuración de AWS y construye un cliente nuevo. Los handlers
+/// deben usar `get_client`; se expone para benchmarks y tooling.
+pub async fn build_client() -> Client {
     let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
     Client::new(&config)
 }
@@ -14,9 +14,18 @@ pub enum ApiError {
     #[error("Conflicto: {0}")]
     Conflict(String),
 
+    #[error("No procesable: {0}")]
+    Unprocessable(String),
+
     #[error("Prohibido: {0}")]
     Forbidden(String),
 
+    #[error("Token expirado")]
+    TokenExpired,
+
+    #[error("Token inválido: {0}")]
+    InvalidToken(String),
+
     #[error("Error interno: {0}")]
     Internal(#[from] anyhow::Error),
 }
@@ -28,14 +37,22 @@ impl ApiError {
             ApiError::Validation(msg) => (StatusCode::BAD_REQUEST, msg),
             ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
             ApiError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            ApiError::Unprocessable(msg) => (StatusCode::UNPROCESSABLE_ENTITY, msg),
             ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
+            ApiError::TokenExpired => (StatusCode::UNAUTHORIZED, "Token expirado".to_string()),
+            ApiError::InvalidToken(msg) => (StatusCode::UNAUTHORIZED, msg),
             ApiError::Internal(err) => {
                 tracing::error!(error = %err, "Internal error");
                 (StatusCode::INTERNAL_SERVER_ERROR, "Error interno del servidor".to_string())
             }
         };
 
-        Response::builder()
+        let mut builder = Response::builder();
+        if status == StatusCode::UNAUTHORIZED {
+            builder = builder.header("www-authenticate", "Bearer error=\"invalid_token\"");
+        }
+
+        builder
             .status(status)
             .header("content-type", "application/json")
             .header("x-content-type-options", "nosniff")
@@ -0,0 +1,273 @@
+//! Eventos iCalendar (RFC 5545) para adjuntar a los emails de una reserva, de
+//! modo que el paciente la agregue a cualquier calendario. Un mismo `UID` por
+//! reserva y un `SEQUENCE` creciente hacen que el calendario actualice (o
+//! cancele, con `METHOD:CANCEL`) el evento que ya tiene en vez de duplicarlo.
+//!
+//! Las horas van en UTC (`...Z`), así no hace falta un `VTIMEZONE`; cada
+//! calendario las muestra en la zona del usuario.
+
+use chrono::{DateTime, Utc};
+
+const PRODID: &str = "-//Turnaki NexioQ//Reservas//ES";
+
+/// Largo máximo de una línea sin el CRLF (RFC 5545 §3.1).
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Método iTIP del calendario (RFC 5546).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Crea o actualiza el evento
+    Request,
+    /// Cancela el evento con el mismo `UID`
+    Cancel,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Request => "REQUEST",
+            Method::Cancel => "CANCEL",
+        }
+    }
+}
+
+/// Organizador o asistente, como `mailto:` con nombre opcional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Party {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEvent {
+    /// Estable por reserva; ver [`CalendarEvent::booking_uid`]
+    pub uid: String,
+    /// Revisión del evento; cada cambio de la reserva debe subirla
+    pub sequence: u32,
+    /// Momento en que se generó el evento
+    pub stamp: DateTime<Utc>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub organizer: Party,
+    pub attendee: Party,
+}
+
+impl CalendarEvent {
+    pub fn booking_uid(booking_id: &str) -> String {
+        format!("booking-{}@turnaki.nexioq.com", booking_id)
+    }
+
+    /// Objeto `VCALENDAR` completo, con líneas CRLF plegadas a 75 octetos.
+    pub fn to_ics(&self, method: Method) -> String {
+        let status = match method {
+            Method::Request => "CONFIRMED",
+            Method::Cancel => "CANCELLED",
+        };
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            format!("PRODID:{}", PRODID),
+            "CALSCALE:GREGORIAN".to_string(),
+            format!("METHOD:{}", method.as_str()),
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", escape_text(&self.uid)),
+            format!("SEQUENCE:{}", self.sequence),
+            format!("DTSTAMP:{}", format_utc(self.stamp)),
+            format!("DTSTART:{}", format_utc(self.start)),
+            format!("DTEND:{}", format_utc(self.end)),
+            format!("SUMMARY:{}", escape_text(&self.summary)),
+        ];
+        if let Some(description) = &self.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        if let Some(location) = &self.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        lines.extend([
+            format!("ORGANIZER{}:mailto:{}", common_name(&self.organizer), self.organizer.email),
+            format!(
+                "ATTENDEE{};ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=FALSE:mailto:{}",
+                common_name(&self.attendee),
+                self.attendee.email
+            ),
+            format!("STATUS:{}", status),
+            "TRANSP:OPAQUE".to_string(),
+            "END:VEVENT".to_string(),
+            "END:VCALENDAR".to_string(),
+        ]);
+        lines.iter().map(|line| fold(line)).collect()
+    }
+}
+
+fn format_utc(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Valor TEXT (§3.3.11): `\`, `;` y `,` se escapan y los saltos de línea son `\n`.
+fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parámetro `CN` entre comillas; un valor de parámetro no admite `"` (§3.1).
+fn common_name(party: &Party) -> String {
+    match &party.name {
+        Some(name) => {
+            let name: String = name.chars().filter(|c| *c != '"' && !c.is_control()).collect();
+            format!(";CN=\"{}\"", name)
+        }
+        None => String::new(),
+    }
+}
+
+/// Pliega una línea lógica en líneas de hasta 75 octetos terminadas en CRLF;
+/// las de continuación empiezan con un espacio. No corta un carácter UTF-8.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / MAX_LINE_OCTETS * 3 + 2);
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn event() -> CalendarEvent {
+        CalendarEvent {
+            uid: CalendarEvent::booking_uid("b1"),
+            sequence: 0,
+            stamp: utc("2025-09-30T12:00:00Z"),
+            start: utc("2025-10-01T15:00:00Z"),
+            end: utc("2025-10-01T15:30:00Z"),
+            summary: "Limpieza - Clínica A".into(),
+            description: Some("Profesional: Dra. Pérez".into()),
+            location: Some("Calle 100 #15-20, Bogotá".into()),
+            organizer: Party { name: Some("Clínica A".into()), email: "a@example.com".into() },
+            attendee: Party { name: Some("Ana".into()), email: "ana@example.com".into() },
+        }
+    }
+
+    /// Comprueba la sintaxis de RFC 5545 que usan estos objetos: CRLF, líneas
+    /// de hasta 75 octetos, plegado, componentes balanceados, nombres de
+    /// propiedad válidos, propiedades obligatorias y valores TEXT escapados.
+    /// Devuelve las líneas lógicas (desplegadas).
+    fn assert_rfc5545(ics: &str) -> Vec<String> {
+        assert!(ics.ends_with("\r\n"), "debe terminar en CRLF");
+        let physical: Vec<&str> = ics[..ics.len() - 2].split("\r\n").collect();
+        let mut logical: Vec<String> = vec![];
+        for line in physical {
+            assert!(!line.contains('\r') && !line.contains('\n'), "salto de línea suelto en {:?}", line);
+            assert!(line.len() <= MAX_LINE_OCTETS, "línea de {} octetos: {:?}", line.len(), line);
+            match line.strip_prefix(' ') {
+                Some(continuation) => logical.last_mut().expect("continuación sin línea previa").push_str(continuation),
+                None => logical.push(line.to_string()),
+            }
+        }
+
+        let mut stack: Vec<&str> = vec![];
+        let mut vevent_props: Vec<&str> = vec![];
+        let mut calendar_props: Vec<&str> = vec![];
+        for line in &logical {
+            let name_end = line.find([':', ';']).unwrap_or_else(|| panic!("línea sin ':' {:?}", line));
+            let name = &line[..name_end];
+            assert!(!name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-'), "nombre inválido {:?}", name);
+            let value = &line[line.find(':').unwrap() + 1..];
+            match name {
+                "BEGIN" => stack.push(value),
+                "END" => assert_eq!(stack.pop(), Some(value), "END sin su BEGIN"),
+                _ if stack.last() == Some(&"VEVENT") => vevent_props.push(name),
+                _ if stack.last() == Some(&"VCALENDAR") => calendar_props.push(name),
+                _ => panic!("propiedad fuera de un componente: {:?}", line),
+            }
+            if ["SUMMARY", "DESCRIPTION", "LOCATION"].contains(&name) {
+                let mut chars = value.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => assert!(matches!(chars.next(), Some('\\' | ';' | ',' | 'n' | 'N')), "escape inválido en {:?}", value),
+                        ';' | ',' => panic!("'{}' sin escapar en {:?}", c, value),
+                        _ => {}
+                    }
+                }
+            }
+            if ["DTSTAMP", "DTSTART", "DTEND"].contains(&name) {
+                assert!(chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ").is_ok(), "DATE-TIME UTC inválido {:?}", value);
+            }
+        }
+        assert!(stack.is_empty(), "componentes sin cerrar: {:?}", stack);
+        assert_eq!(logical.first().map(String::as_str), Some("BEGIN:VCALENDAR"));
+        for required in ["VERSION", "PRODID", "METHOD"] {
+            assert_eq!(calendar_props.iter().filter(|p| **p == required).count(), 1, "{} en VCALENDAR", required);
+        }
+        // iTIP exige además SEQUENCE, ORGANIZER y ATTENDEE en REQUEST y CANCEL
+        for required in ["UID", "DTSTAMP", "DTSTART", "DTEND", "SEQUENCE", "ORGANIZER", "ATTENDEE"] {
+            assert_eq!(vevent_props.iter().filter(|p| **p == required).count(), 1, "{} en VEVENT", required);
+        }
+        logical
+    }
+
+    #[test]
+    fn test_request_is_valid_rfc5545() {
+        let lines = assert_rfc5545(&event().to_ics(Method::Request));
+        assert!(lines.contains(&"METHOD:REQUEST".to_string()));
+        assert!(lines.contains(&"UID:booking-b1@turnaki.nexioq.com".to_string()));
+        assert!(lines.contains(&"DTSTART:20251001T150000Z".to_string()));
+        assert!(lines.contains(&"DTEND:20251001T153000Z".to_string()));
+        assert!(lines.contains(&"LOCATION:Calle 100 #15-20\\, Bogotá".to_string()));
+        assert!(lines.contains(&"ORGANIZER;CN=\"Clínica A\":mailto:a@example.com".to_string()));
+        assert!(lines.contains(&"STATUS:CONFIRMED".to_string()));
+    }
+
+    #[test]
+    fn test_cancel_targets_the_same_event_with_higher_sequence() {
+        let cancel = CalendarEvent { sequence: 2, ..event() };
+        let lines = assert_rfc5545(&cancel.to_ics(Method::Cancel));
+        assert!(lines.contains(&"METHOD:CANCEL".to_string()));
+        assert!(lines.contains(&"STATUS:CANCELLED".to_string()));
+        assert!(lines.contains(&"SEQUENCE:2".to_string()));
+        assert!(lines.contains(&format!("UID:{}", event().uid)));
+    }
+
+    #[test]
+    fn test_long_utf8_lines_are_folded_and_text_escaped() {
+        let event = CalendarEvent {
+            description: Some("Cita de ortodoncia; traer radiografías, exámenes y documento.\nLlegar 10 minutos antes. Ñandú 🦷🦷🦷 ".repeat(3)),
+            attendee: Party { name: Some("Ana \"la paciente\"".into()), email: "ana@example.com".into() },
+            ..event()
+        };
+        let ics = event.to_ics(Method::Request);
+        let lines = assert_rfc5545(&ics);
+        assert!(ics.contains("\r\n "), "la descripción larga debe plegarse");
+        let description = lines.iter().find(|l| l.starts_with("DESCRIPTION:")).unwrap();
+        assert!(description.starts_with("DESCRIPTION:Cita de ortodoncia\\; traer radiografías\\, exámenes"));
+        assert!(description.contains("\\nLlegar"));
+        assert!(description.contains("🦷🦷🦷"));
+        assert!(lines.iter().any(|l| l.starts_with("ATTENDEE;CN=\"Ana la paciente\";")));
+    }
+}
@@ -0,0 +1,173 @@
+//! Soporte del header `Idempotency-Key` para endpoints que mutan estado.
+//!
+//! La primera request con una key la reserva en DynamoDB (con TTL), ejecuta la
+//! operación y guarda la respuesta. Un reintento con la misma key y el mismo
+//! body recibe esa respuesta tal cual (con `idempotent-replayed: true`); con otro
+//! body se rechaza con `422`, y mientras la primera sigue en curso, con `409`.
+//! Las keys son por tenant y usuario, así que dos clientes no chocan entre sí.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use lambda_http::{Body, Request, Response};
+use sha2::{Digest, Sha256};
+
+use crate::auth::parse_jwt_claims;
+use crate::error::ApiError;
+use crate::repository::{Repository, StoreError, StoredResponse};
+
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+/// Cuánto tiempo se puede reintentar con la misma key
+pub const IDEMPOTENCY_TTL_HOURS: i64 = 24;
+/// Cuánto dura la reserva de una key sin respuesta guardada. Más que el
+/// timeout de las lambdas: pasado este tiempo la ejecución que la tomó murió
+/// y un reintento puede volver a tomarla.
+pub const IDEMPOTENCY_LEASE_SECONDS: i64 = 300;
+const MAX_KEY_LEN: usize = 255;
+
+/// Ejecuta `operation` respetando el `Idempotency-Key` de `req`, si lo trae.
+/// Sin header se ejecuta directamente. Los errores de `operation` también se
+/// guardan (un 409 se repite igual), salvo los 5xx, que liberan la key para que
+/// el reintento vuelva a intentarlo.
+pub async fn idempotent<F, Fut>(repo: &dyn Repository, req: &Request, operation: F) -> Result<Response<Body>, ApiError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Response<Body>, ApiError>>,
+{
+    let Some(key) = idempotency_key(req)? else {
+        return operation().await;
+    };
+    let claims = parse_jwt_claims(req).await?;
+    let principal = claims.sub.clone().or_else(|| claims.email.clone()).unwrap_or_default();
+    let scope = format!("{}#{}", claims.require_tenant()?, principal);
+    let hash = request_hash(req);
+
+    let now = chrono::Utc::now().timestamp();
+    let expires_at = now + IDEMPOTENCY_TTL_HOURS * 3600;
+    match repo.claim_idempotency_key(&scope, &key, &hash, now, now + IDEMPOTENCY_LEASE_SECONDS, expires_at).await {
+        Ok(()) => {}
+        Err(StoreError::ConditionFailed) => return replay(repo, &scope, &key, &hash).await,
+        Err(e) => return Err(e.into()),
+    }
+
+    let response = operation().await.unwrap_or_else(ApiError::into_response);
+    let stored = if response.status().is_server_error() {
+        repo.release_idempotency_key(&scope, &key).await
+    } else {
+        repo.complete_idempotency_key(&scope, &key, &to_stored(&response)).await
+    };
+    // La operación ya se aplicó: un fallo aquí solo pierde la protección del reintento
+    if let Err(e) = stored {
+        tracing::warn!(error = %e, idempotency_key = %key, "No se pudo guardar la respuesta idempotente");
+    }
+    Ok(response)
+}
+
+fn idempotency_key(req: &Request) -> Result<Option<String>, ApiError> {
+    let Some(raw) = req.headers().get(IDEMPOTENCY_HEADER) else {
+        return Ok(None);
+    };
+    let key = raw.to_str().map(str::trim).unwrap_or_default();
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(ApiError::Validation(format!("Idempotency-Key inválida (1 a {} caracteres ASCII)", MAX_KEY_LEN)));
+    }
+    Ok(Some(key.to_string()))
+}
+
+/// Huella de método, ruta y body. Un body JSON se normaliza (claves ordenadas,
+/// sin espacios) para que reserializarlo en el cliente no cuente como otro body.
+fn request_hash(req: &Request) -> String {
+    let body: &[u8] = req.body().as_ref();
+    let normalized = serde_json::from_slice::<serde_json::Value>(body)
+        .map(|value| value.to_string().into_bytes())
+        .unwrap_or_else(|_| body.to_vec());
+
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(req.uri().path().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(&normalized);
+    hex::encode(hasher.finalize())
+}
+
+async fn replay(repo: &dyn Repository, scope: &str, key: &str, hash: &str) -> Result<Response<Body>, ApiError> {
+    let record = repo.get_idempotency_record(scope, key).await?
+        .ok_or_else(|| ApiError::Conflict("La Idempotency-Key acaba de liberarse; reintentar".into()))?;
+    if record.request_hash != hash {
+        return Err(ApiError::Unprocessable("Idempotency-Key ya usada con otra request".into()));
+    }
+    let stored = record.response
+        .ok_or_else(|| ApiError::Conflict("Una request con esta Idempotency-Key sigue en curso".into()))?;
+
+    let mut builder = Response::builder().status(stored.status);
+    for (name, value) in &stored.headers {
+        builder = builder.header(name, value);
+    }
+    builder
+        .header(REPLAYED_HEADER, "true")
+        .body(stored.body.into())
+        .map_err(|e| ApiError::Internal(e.into()))
+}
+
+fn to_stored(response: &Response<Body>) -> StoredResponse {
+    let headers: BTreeMap<String, String> = response.headers().iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    StoredResponse {
+        status: response.status().as_u16(),
+        headers,
+        body: String::from_utf8_lossy(response.body().as_ref()).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{IdempotencyRepository, MemoryStore};
+
+    fn post(path: &str, body: &str) -> Request {
+        let mut request = Request::new(Body::from(body));
+        *request.method_mut() = lambda_http::http::Method::POST;
+        *request.uri_mut() = path.parse().unwrap();
+        request
+    }
+
+    #[test]
+    fn test_request_hash_ignores_json_formatting_only() {
+        let compact = request_hash(&post("/bookings", r#"{"a":1,"b":"x"}"#));
+        assert_eq!(compact, request_hash(&post("/bookings", "{ \"b\": \"x\",\n  \"a\": 1 }")));
+        assert_ne!(compact, request_hash(&post("/bookings", r#"{"a":2,"b":"x"}"#)));
+        assert_ne!(compact, request_hash(&post("/bookings/1", r#"{"a":1,"b":"x"}"#)));
+    }
+
+    #[tokio::test]
+    async fn test_expired_key_can_be_claimed_again() {
+        let store = MemoryStore::new();
+        store.claim_idempotency_key("t#u", "k1", "h1", 100, 200, 200).await.unwrap();
+        assert!(matches!(store.claim_idempotency_key("t#u", "k1", "h1", 150, 250, 250).await, Err(StoreError::ConditionFailed)));
+        // Otro usuario del mismo tenant no comparte keys
+        store.claim_idempotency_key("t#v", "k1", "h1", 150, 250, 250).await.unwrap();
+
+        store.claim_idempotency_key("t#u", "k1", "h2", 201, 301, 301).await.unwrap();
+        let record = store.get_idempotency_record("t#u", "k1").await.unwrap().unwrap();
+        assert_eq!((record.request_hash.as_str(), record.response), ("h2", None));
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_claim_can_be_retried_after_its_lease() {
+        let store = MemoryStore::new();
+        // La primera ejecución tomó la key y murió sin guardar respuesta ni liberarla
+        store.claim_idempotency_key("t#u", "k1", "h1", 100, 400, 86_500).await.unwrap();
+        assert!(matches!(store.claim_idempotency_key("t#u", "k1", "h1", 399, 699, 86_799).await, Err(StoreError::ConditionFailed)));
+        store.claim_idempotency_key("t#u", "k1", "h1", 401, 701, 86_801).await.unwrap();
+
+        // Con la respuesta guardada el lease ya no cuenta: vale hasta expiresAt
+        let response = StoredResponse { status: 201, headers: BTreeMap::new(), body: "{}".into() };
+        store.complete_idempotency_key("t#u", "k1", &response).await.unwrap();
+        assert!(matches!(store.claim_idempotency_key("t#u", "k1", "h1", 800, 1100, 87_200).await, Err(StoreError::ConditionFailed)));
+        let record = store.get_idempotency_record("t#u", "k1").await.unwrap().unwrap();
+        assert_eq!(record.response, Some(response));
+    }
+}
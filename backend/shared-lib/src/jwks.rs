@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::DecodingKey;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::error::ApiError;
+
+/// Tiempo mínimo entre recargas forzadas (kid desconocido) para evitar que
+/// tokens con `kid` aleatorios provoquen una descarga del JWKS por request.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Origen del JWKS: endpoint HTTP (Cognito) o archivo local (tests / offline).
+#[derive(Debug, Clone, PartialEq)]
+pub enum JwksSource {
+    Url(String),
+    File(PathBuf),
+}
+
+impl JwksSource {
+    /// Resuelve el origen desde el entorno, en orden de prioridad:
+    /// `JWKS_PATH`, `JWKS_URL` y finalmente el endpoint estándar de Cognito
+    /// derivado de `COGNITO_USER_POOL_ID` + `AWS_REGION`.
+    pub fn from_env() -> Result<Self, ApiError> {
+        if let Ok(path) = std::env::var("JWKS_PATH") {
+            return Ok(JwksSource::File(PathBuf::from(path)));
+        }
+        if let Ok(url) = std::env::var("JWKS_URL") {
+            return Ok(JwksSource::Url(url));
+        }
+        let issuer = cognito_issuer_from_env()
+            .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("JWKS no configurado (JWKS_URL, JWKS_PATH o COGNITO_USER_POOL_ID)")))?;
+        Ok(JwksSource::Url(format!("{}/.well-known/jwks.json", issuer)))
+    }
+}
+
+/// Issuer de Cognito (`https://cognito-idp.<region>.amazonaws.com/<pool>`) si el entorno lo permite.
+pub fn cognito_issuer_from_env() -> Option<String> {
+    let pool_id = std::env::var("COGNITO_USER_POOL_ID").ok()?;
+    let region = std::env::var("AWS_REGION")
+        .ok()
+        .or_else(|| pool_id.split_once('_').map(|(r, _)| r.to_string()))?;
+    Some(format!("https://cognito-idp.{}.amazonaws.com/{}", region, pool_id))
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkDocument {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kid: String,
+    kty: String,
+    #[serde(default)]
+    alg: Option<String>,
+    #[serde(rename = "use", default)]
+    key_use: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+/// Conjunto de llaves públicas RSA indexadas por `kid`.
+#[derive(Clone, Default)]
+pub struct KeySet {
+    keys: HashMap<String, DecodingKey>,
+}
+
+impl KeySet {
+    /// Parsea un documento JWKS. Solo se conservan llaves RSA de firma (RS256).
+    pub fn from_json(bytes: &[u8]) -> Result<Self, ApiError> {
+        let doc: JwkDocument = serde_json::from_slice(bytes)
+            .map_err(|e| ApiError::Internal(anyhow::anyhow!("JWKS inválido: {}", e)))?;
+
+        let mut keys = HashMap::new();
+        for jwk in doc.keys {
+            if jwk.kty != "RSA"
+                || jwk.alg.as_deref().is_some_and(|a| a != "RS256")
+                || jwk.key_use.as_deref().is_some_and(|u| u != "sig")
+            {
+                continue;
+            }
+            let (Some(n), Some(e)) = (jwk.n.as_deref(), jwk.e.as_deref()) else {
+                continue;
+            };
+            let key = DecodingKey::from_rsa_components(n, e)
+                .map_err(|e| ApiError::Internal(anyhow::anyhow!("JWK {} inválida: {}", jwk.kid, e)))?;
+            keys.insert(jwk.kid, key);
+        }
+
+        if keys.is_empty() {
+            return Err(ApiError::Internal(anyhow::anyhow!("JWKS sin llaves RS256 utilizables")));
+        }
+        Ok(KeySet { keys })
+    }
+
+    pub fn get(&self, kid: &str) -> Option<&DecodingKey> {
+        self.keys.get(kid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+pub async fn load_key_set(source: &JwksSource) -> Result<KeySet, ApiError> {
+    let bytes = match source {
+        JwksSource::File(path) => tokio::fs::read(path)
+            .await
+            .map_err(|e| ApiError::Internal(anyhow::anyhow!("No se pudo leer JWKS {}: {}", path.display(), e)))?,
+        JwksSource::Url(url) => reqwest::get(url)
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| ApiError::Internal(anyhow::anyhow!("No se pudo descargar JWKS: {}", e)))?
+            .bytes()
+            .await
+            .map_err(|e| ApiError::Internal(anyhow::anyhow!("No se pudo leer JWKS: {}", e)))?
+            .to_vec(),
+    };
+    KeySet::from_json(&bytes)
+}
+
+struct CachedKeySet {
+    source: JwksSource,
+    keys: Arc<KeySet>,
+    fetched_at: Instant,
+}
+
+// Cache a nivel de proceso: sobrevive entre invocaciones de la misma instancia Lambda.
+static CACHE: RwLock<Option<CachedKeySet>> = RwLock::const_new(None);
+
+fn cache_ttl() -> Duration {
+    let secs = std::env::var("JWKS_CACHE_TTL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(3600);
+    Duration::from_secs(secs)
+}
+
+/// Devuelve el JWKS cacheado, descargándolo si no existe o expiró.
+/// Con `force_refresh` (p. ej. `kid` desconocido tras rotación) se recarga,
+/// salvo que la última descarga sea más reciente que `MIN_REFRESH_INTERVAL`.
+pub async fn cached_key_set(source: &JwksSource, force_refresh: bool) -> Result<Arc<KeySet>, ApiError> {
+    {
+        let guard = CACHE.read().await;
+        if let Some(cached) = guard.as_ref().filter(|c| &c.source == source) {
+            let age = cached.fetched_at.elapsed();
+            let fresh = age < cache_ttl();
+            if fresh && (!force_refresh || age < MIN_REFRESH_INTERVAL) {
+                return Ok(cached.keys.clone());
+            }
+        }
+    }
+
+    let mut guard = CACHE.write().await;
+    // Otro task pudo haber recargado mientras esperábamos el lock de escritura
+    if let Some(cached) = guard.as_ref().filter(|c| &c.source == source) {
+        if cached.fetched_at.elapsed() < MIN_REFRESH_INTERVAL {
+            return Ok(cached.keys.clone());
+        }
+    }
+
+    let keys = Arc::new(load_key_set(source).await?);
+    tracing::info!(keys = keys.len(), "JWKS loaded");
+    *guard = Some(CachedKeySet { source: source.clone(), keys: keys.clone(), fetched_at: Instant::now() });
+    Ok(keys)
+}
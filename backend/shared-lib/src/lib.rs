@@ -2,10 +2,28 @@ pub mod error;
 pub mod response;
 pub mod tracing;
 pub mod dynamodb;
+pub mod idempotency;
+pub mod ics;
 pub mod auth;
+pub mod jwks;
+pub mod rbac;
+pub mod models;
+pub mod patients;
+pub mod reminders;
+pub mod repository;
+pub mod schedule;
+pub mod slots;
+pub mod templates;
+pub mod timezone;
+pub mod unsubscribe;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
 
 pub use error::ApiError;
 pub use response::{success_response, created_response};
 pub use tracing::init_tracing;
 pub use dynamodb::{get_client, table_name};
-pub use auth::{parse_jwt_claims, require_tenant, JwtClaims};
+pub use idempotency::idempotent;
+pub use auth::{parse_jwt_claims, require_tenant, verify_token, JwtClaims, VerifierConfig};
+pub use rbac::{authorize, require_role, resolve_tenant, Action, Resource, Role, Scope};
+pub use repository::{DynamoStore, MemoryStore, Repository, StoreError};
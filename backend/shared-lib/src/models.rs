@@ -0,0 +1,893 @@
+//! Entidades de dominio compartidas por los handlers. Se serializan en
+//! snake_case hacia la API; el mapeo a atributos DynamoDB vive en `repository`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::reminders::{default_reminder_policy, validate_reminder_policy, ReminderRule};
+use crate::schedule::{OpeningHours, WeeklySchedule};
+use crate::timezone::{Tz, DEFAULT_TIMEZONE};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tenant {
+    pub id: String,
+    pub name: String,
+    pub contact_email: String,
+    pub timezone: String,
+    pub created_at: String,
+    pub status: String,
+    #[serde(default)]
+    pub settings: TenantSettings,
+}
+
+impl Tenant {
+    /// Zona IANA de la clínica; un valor inválido heredado cae en la zona por defecto.
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(DEFAULT_TIMEZONE)
+    }
+}
+
+/// Configuración de la clínica que editan sus administradores.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TenantSettings {
+    /// Recordatorios de cada cita confirmada; una lista vacía los desactiva
+    #[serde(default = "default_reminder_policy")]
+    pub reminders: Vec<ReminderRule>,
+    /// Canales de las notificaciones de reservas. El paciente puede elegir
+    /// entre estos; si no elige, se notifica por todos
+    #[serde(default = "default_channels")]
+    pub channels: Vec<Channel>,
+    /// Idioma de las notificaciones de los pacientes que no eligieron uno
+    #[serde(default)]
+    pub locale: Locale,
+    #[serde(default)]
+    pub branding: Branding,
+}
+
+fn default_channels() -> Vec<Channel> {
+    vec![Channel::Email]
+}
+
+impl Default for TenantSettings {
+    fn default() -> Self {
+        Self {
+            reminders: default_reminder_policy(),
+            channels: default_channels(),
+            locale: Locale::default(),
+            branding: Branding::default(),
+        }
+    }
+}
+
+impl TenantSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_reminder_policy(&self.reminders)?;
+        if self.channels.is_empty() {
+            return Err("channels: al menos un canal".into());
+        }
+        if self.channels.iter().enumerate().any(|(i, c)| self.channels[..i].contains(c)) {
+            return Err("channels: canal repetido".into());
+        }
+        self.branding.validate().map_err(|e| format!("branding: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Imagen de la clínica en sus notificaciones. Lo que no define usa el de
+/// Turnaki.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Branding {
+    /// URL `https` del logo para el encabezado de los emails
+    #[serde(default)]
+    pub logo_url: Option<String>,
+    /// Color principal de los emails, `#rrggbb`
+    #[serde(default)]
+    pub primary_color: Option<String>,
+    /// Nombre del remitente; la dirección sigue siendo la verificada en SES
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    /// A dónde llegan las respuestas del paciente
+    #[serde(default)]
+    pub reply_to: Option<String>,
+}
+
+impl Branding {
+    /// Los valores terminan en el CSS y las cabeceras del email: nada que
+    /// pueda escaparse de ahí.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.logo_url {
+            if !url.starts_with("https://") || url.len() > 500 || url.chars().any(|c| c.is_whitespace() || c == '"') {
+                return Err("logo_url debe ser una URL https".into());
+            }
+        }
+        if let Some(color) = &self.primary_color {
+            let hex = color.strip_prefix('#').unwrap_or_default();
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err("primary_color debe tener formato #rrggbb".into());
+            }
+        }
+        if let Some(name) = &self.sender_name {
+            if name.trim().is_empty() || name.chars().count() > 64 || name.chars().any(|c| c.is_control() || c == '"' || c == '<' || c == '>') {
+                return Err("sender_name: hasta 64 caracteres, sin comillas ni <>".into());
+            }
+        }
+        if let Some(reply_to) = &self.reply_to {
+            let valid = reply_to.split_once('@').is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'));
+            if !valid || reply_to.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                return Err("reply_to debe ser un email".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Idioma de las notificaciones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    Es,
+    En,
+    Pt,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 3] = [Locale::Es, Locale::En, Locale::Pt];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::Es => "es",
+            Locale::En => "en",
+            Locale::Pt => "pt",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Locale> {
+        match value {
+            "es" => Some(Locale::Es),
+            "en" => Some(Locale::En),
+            "pt" => Some(Locale::Pt),
+            _ => None,
+        }
+    }
+}
+
+/// Canal por el que se envía una notificación.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Email,
+    Sms,
+    Whatsapp,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Email => "email",
+            Channel::Sms => "sms",
+            Channel::Whatsapp => "whatsapp",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Channel> {
+        match value {
+            "email" => Some(Channel::Email),
+            "sms" => Some(Channel::Sms),
+            "whatsapp" => Some(Channel::Whatsapp),
+            _ => None,
+        }
+    }
+
+    /// SMS y WhatsApp van al teléfono del paciente.
+    pub fn needs_phone(&self) -> bool {
+        !matches!(self, Channel::Email)
+    }
+}
+
+/// Canales que eligió el paciente, en su orden, sin los que la clínica no
+/// habilita ni los que necesitan un teléfono que no dio. Vacío si no eligió
+/// (o si no queda ninguno).
+pub fn preferred_channels(chosen: &[Channel], enabled: &[Channel], phone: Option<&str>) -> Vec<Channel> {
+    chosen
+        .iter()
+        .copied()
+        .filter(|c| enabled.contains(c) && (phone.is_some() || !c.needs_phone()))
+        .collect()
+}
+
+/// Teléfono en formato E.164: `+` y entre 8 y 15 dígitos.
+pub fn validate_phone(phone: &str) -> Result<(), String> {
+    let digits = phone.strip_prefix('+').ok_or("debe empezar con +")?;
+    if !(8..=15).contains(&digits.len()) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err("usar formato E.164 (+573001234567)".into());
+    }
+    Ok(())
+}
+
+/// Tipo de mensaje, para las preferencias del paciente: los transaccionales
+/// (confirmaciones, recordatorios, cambios de su reserva) se envían salvo que
+/// se dé de baja; los de marketing solo si los aceptó.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageCategory {
+    #[default]
+    Transactional,
+    Marketing,
+}
+
+impl MessageCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageCategory::Transactional => "transactional",
+            MessageCategory::Marketing => "marketing",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<MessageCategory> {
+        match value {
+            "transactional" => Some(MessageCategory::Transactional),
+            "marketing" => Some(MessageCategory::Marketing),
+            _ => None,
+        }
+    }
+
+    /// Si el paciente la recibe sin haber dicho nada.
+    pub fn default_opt_in(&self) -> bool {
+        matches!(self, MessageCategory::Transactional)
+    }
+}
+
+/// Alta o baja explícita del paciente para un canal y un tipo de mensaje.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelPreference {
+    pub channel: Channel,
+    pub category: MessageCategory,
+    pub opted_in: bool,
+}
+
+/// Preferencias de comunicación de un paciente con una clínica, por email.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContactPreferences {
+    pub tenant_id: String,
+    pub email: String,
+    /// Solo lo que el paciente eligió; el resto sigue `default_opt_in`
+    pub preferences: Vec<ChannelPreference>,
+    /// Idioma en que quiere sus notificaciones; sin él, el de la clínica
+    #[serde(default)]
+    pub locale: Option<Locale>,
+    pub updated_at: String,
+}
+
+impl ContactPreferences {
+    pub fn new(tenant_id: &str, email: &str) -> Self {
+        Self {
+            tenant_id: tenant_id.to_string(),
+            email: contact_address(Channel::Email, email),
+            preferences: Vec::new(),
+            locale: None,
+            updated_at: String::new(),
+        }
+    }
+
+    pub fn allows(&self, channel: Channel, category: MessageCategory) -> bool {
+        self.preferences
+            .iter()
+            .find(|p| p.channel == channel && p.category == category)
+            .map_or(category.default_opt_in(), |p| p.opted_in)
+    }
+
+    pub fn set(&mut self, channel: Channel, category: MessageCategory, opted_in: bool) {
+        self.preferences.retain(|p| p.channel != channel || p.category != category);
+        self.preferences.push(ChannelPreference { channel, category, opted_in });
+    }
+
+    /// Sin repetir canal y categoría.
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, preference) in self.preferences.iter().enumerate() {
+            if self.preferences[..i].iter().any(|p| p.channel == preference.channel && p.category == preference.category) {
+                return Err(format!("{} / {} repetido", preference.channel.as_str(), preference.category.as_str()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Por qué una dirección está en la lista de supresión del tenant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressionReason {
+    /// Rebote permanente: la dirección no existe o no acepta correo
+    Bounce,
+    /// El paciente marcó el mensaje como spam
+    Complaint,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Bounce => "bounce",
+            SuppressionReason::Complaint => "complaint",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<SuppressionReason> {
+        match value {
+            "bounce" => Some(SuppressionReason::Bounce),
+            "complaint" => Some(SuppressionReason::Complaint),
+            _ => None,
+        }
+    }
+}
+
+/// Dirección a la que el tenant no vuelve a enviar por ese canal hasta que
+/// alguien la quite de la lista.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suppression {
+    pub tenant_id: String,
+    pub channel: Channel,
+    pub address: String,
+    pub reason: SuppressionReason,
+    pub detail: Option<String>,
+    pub created_at: String,
+}
+
+/// Dirección normalizada para comparar: emails sin espacios y en minúsculas,
+/// teléfonos tal cual (E.164).
+pub fn contact_address(channel: Channel, address: &str) -> String {
+    match channel {
+        Channel::Email => address.trim().to_lowercase(),
+        Channel::Sms | Channel::Whatsapp => address.trim().to_string(),
+    }
+}
+
+/// Plantilla propia de una clínica para un canal, plantilla e idioma. Donde
+/// no tiene una, se usa la incluida en `send-notification`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateOverride {
+    pub tenant_id: String,
+    pub channel: Channel,
+    /// `booking-confirmation`, `booking-reminder`, ... o una propia que use la
+    /// política de recordatorios
+    pub template: String,
+    pub locale: Locale,
+    /// Asunto (solo email); sin él, el de la plantilla incluida
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// Fuente minijinja, con las mismas variables que las incluidas
+    pub body: String,
+    pub updated_at: String,
+}
+
+impl TemplateOverride {
+    /// Nombre en minúsculas, dígitos y guiones; no valida la sintaxis.
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_name = !self.template.is_empty()
+            && self.template.len() <= 64
+            && self.template.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid_name {
+            return Err("template: minúsculas, dígitos y guiones".into());
+        }
+        if self.body.trim().is_empty() || self.body.len() > 100_000 {
+            return Err("body: entre 1 y 100000 caracteres".into());
+        }
+        match (&self.subject, self.channel) {
+            (Some(_), Channel::Sms | Channel::Whatsapp) => return Err("subject: solo para email".into()),
+            (Some(subject), Channel::Email) if subject.trim().is_empty() || subject.len() > 200 => {
+                return Err("subject: entre 1 y 200 caracteres".into())
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Sede de una clínica. `site_id` de reservas, slot locks y horarios apunta aquí.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Site {
+    pub id: String,
+    pub tenant_id: String,
+    pub name: String,
+    pub address: String,
+    /// Zona IANA propia; `None` usa la del tenant
+    pub timezone: Option<String>,
+    pub opening_hours: OpeningHours,
+    /// Sillones/consultorios disponibles
+    pub chairs: u32,
+    pub contact_email: Option<String>,
+    pub status: String,
+    pub created_at: String,
+}
+
+impl Site {
+    pub fn is_active(&self) -> bool {
+        self.status == "active"
+    }
+
+    /// Zona de la sede, o la del tenant si no la sobreescribe.
+    pub fn tz(&self, tenant_tz: Tz) -> Tz {
+        self.timezone.as_deref().and_then(|name| name.parse().ok()).unwrap_or(tenant_tz)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Treatment {
+    pub id: String,
+    pub tenant_id: String,
+    pub name: String,
+    pub duration_minutes: i32,
+    pub buffer_minutes: i32,
+    pub price: f64,
+    pub created_at: String,
+}
+
+impl Treatment {
+    /// Minutos que ocupa en la agenda: duración más buffer de limpieza/preparación.
+    pub fn occupied_minutes(&self) -> i64 {
+        (self.duration_minutes + self.buffer_minutes) as i64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Professional {
+    pub id: String,
+    pub tenant_id: String,
+    pub name: String,
+    pub email: String,
+    pub specialties: Vec<String>,
+    /// Sedes asignadas; el horario solo puede usar estas sedes
+    pub site_ids: Vec<String>,
+    pub schedule: WeeklySchedule,
+    pub status: String,
+    pub created_at: String,
+}
+
+impl Professional {
+    pub fn works_at(&self, site_id: &str) -> bool {
+        self.site_ids.iter().any(|s| s == site_id)
+    }
+}
+
+/// Paciente de una clínica. Las reservas lo referencian por `patient_id`; el
+/// nombre y el contacto de cada reserva quedan como se enviaron.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Patient {
+    pub id: String,
+    pub tenant_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    /// E.164
+    #[serde(default)]
+    pub phone: Option<String>,
+    /// Documento de identidad, tal como se escribió
+    #[serde(default)]
+    pub document_id: Option<String>,
+    #[serde(default)]
+    pub birth_date: Option<chrono::NaiveDate>,
+    /// Responsable de un menor; su contacto se usa si el paciente no tiene
+    #[serde(default)]
+    pub guardian: Option<Guardian>,
+    pub created_at: String,
+    #[serde(default)]
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Guardian {
+    pub name: String,
+    /// Madre, padre, tutor, ...
+    #[serde(default)]
+    pub relationship: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub phone: Option<String>,
+}
+
+/// Datos por los que se busca un paciente (GSI2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PatientLookup {
+    Email,
+    Phone,
+    Document,
+}
+
+impl PatientLookup {
+    pub const ALL: [PatientLookup; 3] = [PatientLookup::Email, PatientLookup::Phone, PatientLookup::Document];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PatientLookup::Email => "email",
+            PatientLookup::Phone => "phone",
+            PatientLookup::Document => "document",
+        }
+    }
+
+    /// Valor normalizado para comparar: emails en minúsculas, teléfonos tal
+    /// cual y documentos en mayúsculas sin espacios, puntos ni guiones.
+    pub fn normalize(&self, value: &str) -> String {
+        match self {
+            PatientLookup::Email => contact_address(Channel::Email, value),
+            PatientLookup::Phone => contact_address(Channel::Sms, value),
+            PatientLookup::Document => value.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_uppercase).collect(),
+        }
+    }
+}
+
+fn validate_email(field: &str, email: &str) -> Result<(), String> {
+    let valid = email.split_once('@').is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'));
+    if !valid || email.len() > 254 || email.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(format!("{} debe ser un email", field));
+    }
+    Ok(())
+}
+
+fn validate_name(field: &str, name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name.chars().count() > 100 {
+        return Err(format!("{}: entre 1 y 100 caracteres", field));
+    }
+    Ok(())
+}
+
+impl Patient {
+    pub fn validate(&self, today: chrono::NaiveDate) -> Result<(), String> {
+        validate_name("name", &self.name)?;
+        if let Some(email) = &self.email {
+            validate_email("email", email)?;
+        }
+        if let Some(phone) = &self.phone {
+            validate_phone(phone).map_err(|e| format!("phone: {}", e))?;
+        }
+        if let Some(document) = &self.document_id {
+            let normalized = PatientLookup::Document.normalize(document);
+            if normalized.is_empty() || document.chars().count() > 30 {
+                return Err("document_id: hasta 30 caracteres, con al menos una letra o número".into());
+            }
+        }
+        if self.birth_date.is_some_and(|date| date > today) {
+            return Err("birth_date no puede ser futura".into());
+        }
+        if let Some(guardian) = &self.guardian {
+            validate_name("guardian.name", &guardian.name)?;
+            if let Some(email) = &guardian.email {
+                validate_email("guardian.email", email)?;
+            }
+            if let Some(phone) = &guardian.phone {
+                validate_phone(phone).map_err(|e| format!("guardian.phone: {}", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Email de contacto: el propio o el del responsable.
+    pub fn contact_email(&self) -> Option<&str> {
+        self.email.as_deref().or_else(|| self.guardian.as_ref()?.email.as_deref())
+    }
+
+    /// Teléfono de contacto: el propio o el del responsable.
+    pub fn contact_phone(&self) -> Option<&str> {
+        self.phone.as_deref().or_else(|| self.guardian.as_ref()?.phone.as_deref())
+    }
+
+    /// Valores normalizados por los que se encuentra al paciente. El email y
+    /// el teléfono son los de contacto: un menor aparece también por los de su
+    /// responsable.
+    pub fn lookup_keys(&self) -> Vec<(PatientLookup, String)> {
+        [
+            (PatientLookup::Email, self.contact_email()),
+            (PatientLookup::Phone, self.contact_phone()),
+            (PatientLookup::Document, self.document_id.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(lookup, value)| Some((lookup, lookup.normalize(value?))))
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Booking {
+    pub id: String,
+    pub tenant_id: String,
+    pub site_id: String,
+    pub professional_id: String,
+    pub treatment_id: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub patient_name: String,
+    pub patient_email: String,
+    /// E.164; necesario para SMS y WhatsApp
+    #[serde(default)]
+    pub patient_phone: Option<String>,
+    /// Canales que prefiere el paciente; vacío = los de la clínica
+    #[serde(default)]
+    pub notification_channels: Vec<Channel>,
+    /// Paciente del directorio de la clínica; las reservas anteriores al
+    /// directorio no lo tienen hasta que se vinculan
+    #[serde(default)]
+    pub patient_id: Option<String>,
+    pub status: BookingStatus,
+    pub created_at: String,
+}
+
+impl Booking {
+    /// Canales por los que se notifica la reserva: los que prefiere el paciente
+    /// o, si no eligió ninguno, los de la clínica que se le pueden enviar. Email
+    /// siempre se puede, así que si no queda ninguno se usa ese.
+    pub fn notification_channels(&self, enabled: &[Channel]) -> Vec<Channel> {
+        let phone = self.patient_phone.as_deref();
+        let mut channels = preferred_channels(&self.notification_channels, enabled, phone);
+        if channels.is_empty() {
+            channels = preferred_channels(enabled, enabled, phone);
+        }
+        if channels.is_empty() {
+            channels.push(Channel::Email);
+        }
+        channels
+    }
+}
+
+/// Estado de una reserva. Las transiciones válidas las decide la función de
+/// bookings; aquí solo se define el vocabulario compartido.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookingStatus {
+    Pending,
+    Confirmed,
+    CheckedIn,
+    InProgress,
+    Completed,
+    NoShow,
+    CancelledByPatient,
+    CancelledByClinic,
+}
+
+impl BookingStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BookingStatus::Pending => "pending",
+            BookingStatus::Confirmed => "confirmed",
+            BookingStatus::CheckedIn => "checked_in",
+            BookingStatus::InProgress => "in_progress",
+            BookingStatus::Completed => "completed",
+            BookingStatus::NoShow => "no_show",
+            BookingStatus::CancelledByPatient => "cancelled_by_patient",
+            BookingStatus::CancelledByClinic => "cancelled_by_clinic",
+        }
+    }
+
+    /// Lee el valor guardado. `cancelled` es anterior a distinguir quién cancela
+    /// y se lee como cancelación de la clínica.
+    pub fn parse(value: &str) -> Option<BookingStatus> {
+        match value {
+            "pending" => Some(BookingStatus::Pending),
+            "confirmed" => Some(BookingStatus::Confirmed),
+            "checked_in" => Some(BookingStatus::CheckedIn),
+            "in_progress" => Some(BookingStatus::InProgress),
+            "completed" => Some(BookingStatus::Completed),
+            "no_show" => Some(BookingStatus::NoShow),
+            "cancelled_by_patient" => Some(BookingStatus::CancelledByPatient),
+            "cancelled_by_clinic" | "cancelled" => Some(BookingStatus::CancelledByClinic),
+            _ => None,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, BookingStatus::CancelledByPatient | BookingStatus::CancelledByClinic)
+    }
+}
+
+/// Estado de un intento de notificación. `Pending` es que se registró y se
+/// está enviando; `Sent`, que el proveedor la aceptó; `Delivered`, `Bounced`
+/// y `Complained` llegan después con su feedback (por ahora solo el de SES).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Sent,
+    Failed,
+    /// No se envió: dirección en la lista de supresión o paciente dado de baja
+    Suppressed,
+    Delivered,
+    Bounced,
+    Complained,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Suppressed => "suppressed",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Bounced => "bounced",
+            DeliveryStatus::Complained => "complained",
+        }
+    }
+
+    /// Estados desde los que se llega a este. El estado solo avanza
+    /// (`Pending` → `Sent` → `Delivered` → `Bounced` → `Complained`), así que
+    /// un feedback repetido o atrasado no deshace uno posterior.
+    pub fn advances_from(&self) -> &'static [DeliveryStatus] {
+        use DeliveryStatus::*;
+        match self {
+            Pending => &[],
+            Sent | Failed | Suppressed => &[Pending],
+            Delivered => &[Pending, Sent],
+            // Un rebote asíncrono llega después de la entrega
+            Bounced => &[Pending, Sent, Delivered],
+            Complained => &[Pending, Sent, Delivered, Bounced],
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<DeliveryStatus> {
+        match value {
+            "pending" => Some(DeliveryStatus::Pending),
+            "sent" => Some(DeliveryStatus::Sent),
+            "failed" => Some(DeliveryStatus::Failed),
+            "suppressed" => Some(DeliveryStatus::Suppressed),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "bounced" => Some(DeliveryStatus::Bounced),
+            "complained" => Some(DeliveryStatus::Complained),
+            _ => None,
+        }
+    }
+}
+
+/// Un intento de envío de una notificación de la reserva. Cada reintento es
+/// un registro nuevo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationRecord {
+    /// `<event_id>-<canal>` si la notificación viene de un evento de la
+    /// reserva; si no, `<epoch ms>-<uuid>`
+    pub id: String,
+    pub booking_id: String,
+    pub channel: Channel,
+    /// `confirmation`, `reminder`, `cancellation` o `rescheduled`
+    pub notification_type: String,
+    pub template: String,
+    /// Email o teléfono; vacío si la notificación no tenía destinatario
+    pub recipient: String,
+    /// Id del mensaje en el proveedor (SES, Twilio, WhatsApp)
+    pub provider_message_id: Option<String>,
+    pub status: DeliveryStatus,
+    pub error: Option<String>,
+    pub attempted_at: String,
+    pub updated_at: String,
+}
+
+/// Cambio de estado registrado: quién, cuándo y por qué.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub booking_id: String,
+    pub from: BookingStatus,
+    pub to: BookingStatus,
+    /// Email (o `sub`) del usuario que hizo el cambio
+    pub actor: String,
+    pub reason: Option<String>,
+    pub at: String,
+}
+
+/// Bloqueo de un slot de agenda de un profesional: `TENANT#t#PROF#p#DATE#d` /
+/// `SLOT#HH:MM`. La sede queda como atributo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlotLock {
+    pub tenant_id: String,
+    pub site_id: String,
+    /// `YYYY-MM-DD`
+    pub date: String,
+    /// `HH:MM`
+    pub time: String,
+    pub professional_id: String,
+    /// Reserva dueña del lock, o la retención mientras `status` es `held`
+    pub booking_id: String,
+    /// `reserved` | `held`
+    pub status: String,
+    pub created_at: String,
+    /// Solo en retenciones; nunca se expone
+    #[serde(default, skip_serializing)]
+    pub hold_token: Option<String>,
+    /// Epoch en segundos en que vence la retención (atributo TTL)
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+impl SlotLock {
+    /// Una retención vencida ya no ocupa el slot aunque el TTL de DynamoDB
+    /// todavía no la haya borrado.
+    pub fn is_active(&self, now: i64) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+/// Retención temporal de un horario mientras el paciente completa sus datos.
+/// `HOLD#id` / `METADATA`; sus slot locks quedan en estado `held` con el mismo
+/// `expires_at`, y al confirmarla se convierten en los de la reserva.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlotHold {
+    pub id: String,
+    pub tenant_id: String,
+    pub site_id: String,
+    pub professional_id: String,
+    pub treatment_id: String,
+    pub start_time: String,
+    pub end_time: String,
+    #[serde(skip_serializing)]
+    pub token: String,
+    /// Epoch en segundos
+    pub expires_at: i64,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+impl SlotHold {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.expires_at > now
+    }
+}
+
+/// Qué le pasó a una reserva; lo consume `booking-events` desde el outbox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookingEventType {
+    Created,
+    StatusChanged,
+    Rescheduled,
+}
+
+impl BookingEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BookingEventType::Created => "created",
+            BookingEventType::StatusChanged => "status_changed",
+            BookingEventType::Rescheduled => "rescheduled",
+        }
+    }
+}
+
+/// Evento del outbox transaccional: se escribe en la misma transacción que el
+/// cambio de la reserva (`BOOKING#id` / `EVENT#<at>#<id>`) y el stream de la
+/// tabla lo entrega al consumidor. `booking` es el estado ya aplicado.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookingEvent {
+    pub id: String,
+    pub event_type: BookingEventType,
+    pub booking: Booking,
+    /// Solo en `status_changed`
+    #[serde(default)]
+    pub previous_status: Option<BookingStatus>,
+    /// Solo en `rescheduled`
+    #[serde(default)]
+    pub previous_start_time: Option<String>,
+    pub occurred_at: String,
+}
+
+impl BookingEvent {
+    fn new(event_type: BookingEventType, booking: &Booking, occurred_at: &str) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            event_type,
+            booking: booking.clone(),
+            previous_status: None,
+            previous_start_time: None,
+            occurred_at: occurred_at.to_string(),
+        }
+    }
+
+    pub fn created(booking: &Booking, occurred_at: &str) -> Self {
+        Self::new(BookingEventType::Created, booking, occurred_at)
+    }
+
+    pub fn status_changed(booking: &Booking, from: BookingStatus, occurred_at: &str) -> Self {
+        Self { previous_status: Some(from), ..Self::new(BookingEventType::StatusChanged, booking, occurred_at) }
+    }
+
+    pub fn rescheduled(booking: &Booking, previous_start_time: &str, occurred_at: &str) -> Self {
+        Self {
+            previous_start_time: Some(previous_start_time.to_string()),
+            ..Self::new(BookingEventType::Rescheduled, booking, occurred_at)
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! A qué paciente del directorio corresponde una reserva. Las reservas solo
+//! traen nombre y contacto en texto libre; se busca primero por documento y,
+//! sin él, por email o teléfono con el mismo nombre (un email puede ser de
+//! toda una familia). Si nada coincide se crea el paciente: las reservas nuevas
+//! lo dan de alta en su misma transacción (`new_patient`), el backfill con
+//! `match_or_create`.
+
+use std::collections::HashMap;
+
+use crate::models::{Booking, Patient, PatientLookup};
+use crate::repository::{BookingFilter, PageRequest, Repository, StoreError};
+
+/// Datos del paciente que trae una reserva.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PatientContact<'a> {
+    pub name: &'a str,
+    pub email: Option<&'a str>,
+    pub phone: Option<&'a str>,
+    pub document_id: Option<&'a str>,
+}
+
+impl<'a> PatientContact<'a> {
+    pub fn of(booking: &'a Booking) -> Self {
+        Self {
+            name: &booking.patient_name,
+            email: Some(&booking.patient_email),
+            phone: booking.patient_phone.as_deref(),
+            document_id: None,
+        }
+    }
+}
+
+/// Nombre para comparar: minúsculas, sin tildes y con un espacio entre palabras.
+fn comparable_name(name: &str) -> String {
+    let folded: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'í' | 'ì' | 'î' | 'ï' => 'i',
+            'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+            'ú' | 'ù' | 'û' | 'ü' => 'u',
+            'ñ' => 'n',
+            'ç' => 'c',
+            c => c,
+        })
+        .collect();
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Paciente del tenant que corresponde a `contact`, si hay uno.
+pub async fn find_match(repo: &dyn Repository, tenant_id: &str, contact: &PatientContact<'_>) -> Result<Option<Patient>, StoreError> {
+    if let Some(document) = contact.document_id {
+        if let Some(patient) = repo.find_patients(tenant_id, PatientLookup::Document, document).await?.into_iter().next() {
+            return Ok(Some(patient));
+        }
+    }
+    let name = comparable_name(contact.name);
+    for (lookup, value) in [(PatientLookup::Email, contact.email), (PatientLookup::Phone, contact.phone)] {
+        let Some(value) = value else { continue };
+        let candidates = repo.find_patients(tenant_id, lookup, value).await?;
+        if let Some(patient) = candidates.into_iter().find(|patient| comparable_name(&patient.name) == name) {
+            return Ok(Some(patient));
+        }
+    }
+    Ok(None)
+}
+
+/// Paciente con los datos de `contact`, todavía sin guardar.
+///
+/// Dos reservas simultáneas de un paciente nuevo sin documento pueden crear
+/// dos pacientes; se unifican editando el directorio.
+pub fn new_patient(tenant_id: &str, contact: &PatientContact<'_>, now: &str) -> Patient {
+    Patient {
+        id: uuid::Uuid::new_v4().to_string(),
+        tenant_id: tenant_id.to_string(),
+        name: contact.name.trim().to_string(),
+        email: contact.email.map(str::to_string),
+        phone: contact.phone.map(str::to_string),
+        document_id: contact.document_id.map(str::to_string),
+        birth_date: None,
+        guardian: None,
+        created_at: now.to_string(),
+        updated_at: now.to_string(),
+    }
+}
+
+/// El paciente que corresponde a `contact` o, si no hay, uno nuevo con esos
+/// datos. Devuelve además si se creó.
+pub async fn match_or_create(
+    repo: &dyn Repository,
+    tenant_id: &str,
+    contact: &PatientContact<'_>,
+    now: &str,
+) -> Result<(Patient, bool), StoreError> {
+    if let Some(patient) = find_match(repo, tenant_id, contact).await? {
+        return Ok((patient, false));
+    }
+    let patient = new_patient(tenant_id, contact, now);
+    repo.create_patient(&patient).await?;
+    Ok((patient, true))
+}
+
+/// Resultado de `backfill_bookings`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct BackfillReport {
+    /// Reservas leídas
+    pub bookings: usize,
+    /// Reservas vinculadas a un paciente (o que se vincularían en `dry_run`)
+    pub linked: usize,
+    /// Pacientes creados para ellas
+    pub patients_created: usize,
+    /// Ya tenían paciente
+    pub already_linked: usize,
+}
+
+/// Pacientes ya resueltos en una corrida del backfill, por email o teléfono
+/// normalizado y nombre. `find_match` busca por GSI2, que no es consistente:
+/// un paciente que se acaba de crear puede no aparecer todavía, y la siguiente
+/// reserva suya crearía otro.
+#[derive(Default)]
+struct Resolved(HashMap<(PatientLookup, String, String), String>);
+
+impl Resolved {
+    fn keys(contact: &PatientContact<'_>) -> Vec<(PatientLookup, String, String)> {
+        let name = comparable_name(contact.name);
+        [(PatientLookup::Email, contact.email), (PatientLookup::Phone, contact.phone)]
+            .into_iter()
+            .filter_map(|(lookup, value)| Some((lookup, lookup.normalize(value?), name.clone())))
+            .collect()
+    }
+
+    fn get(&self, contact: &PatientContact<'_>) -> Option<String> {
+        Self::keys(contact).iter().find_map(|key| self.0.get(key).cloned())
+    }
+
+    fn insert(&mut self, contact: &PatientContact<'_>, patient_id: &str) {
+        for key in Self::keys(contact) {
+            self.0.insert(key, patient_id.to_string());
+        }
+    }
+}
+
+/// Vincula al directorio las reservas del tenant anteriores a él, creando los
+/// pacientes que falten. Se puede repetir: las reservas ya vinculadas se
+/// saltan. Con `dry_run` no escribe nada.
+pub async fn backfill_bookings(repo: &dyn Repository, tenant_id: &str, dry_run: bool, now: &str) -> Result<BackfillReport, StoreError> {
+    let mut report = BackfillReport::default();
+    let mut resolved = Resolved::default();
+    let mut page = PageRequest::new(Some(100), None);
+    loop {
+        let result = repo.list_bookings(tenant_id, &BookingFilter::default(), &page).await?;
+        for booking in &result.bookings {
+            report.bookings += 1;
+            if booking.patient_id.is_some() {
+                report.already_linked += 1;
+                continue;
+            }
+            let contact = PatientContact::of(booking);
+            let (patient_id, created) = match resolved.get(&contact) {
+                Some(patient_id) => (patient_id, false),
+                None if dry_run => match find_match(repo, tenant_id, &contact).await? {
+                    Some(patient) => (patient.id, false),
+                    // Solo para reconocer sus otras reservas en esta corrida
+                    None => (format!("new-{}", booking.id), true),
+                },
+                None => {
+                    let (patient, created) = match_or_create(repo, tenant_id, &contact, now).await?;
+                    (patient.id, created)
+                }
+            };
+            resolved.insert(&contact, &patient_id);
+            if created {
+                report.patients_created += 1;
+            }
+            if dry_run {
+                report.linked += 1;
+                continue;
+            }
+            match repo.link_patient(&booking.id, &patient_id).await {
+                Ok(()) => report.linked += 1,
+                // Vinculada entre la lectura y la escritura (una reserva nueva, p. ej.)
+                Err(StoreError::ConditionFailed) => report.already_linked += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        match result.next_cursor {
+            Some(cursor) => page = PageRequest::new(Some(100), Some(cursor)),
+            None => return Ok(report),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{BookingEvent, BookingStatus, Guardian};
+    use crate::repository::{BookingRepository, Index, Item, ItemStore, Key, MemoryStore, Page, PatientRepository, Query, WriteOp};
+    use crate::testing;
+
+    fn booking(id: &str, name: &str, email: &str) -> Booking {
+        Booking {
+            id: id.into(),
+            start_time: format!("2025-10-0{}T15:00:00+00:00", id.len()),
+            patient_name: name.into(),
+            patient_email: email.into(),
+            ..testing::booking(BookingStatus::Confirmed)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bookings_match_patients_by_document_or_contact_and_name() {
+        let store = MemoryStore::new();
+        let contact = |name, email, document| PatientContact { name, email: Some(email), phone: None, document_id: document };
+
+        let (ana, created) = match_or_create(&store, "tenant-a", &contact("Ana María  Pérez", "ana@example.com", None), "now").await.unwrap();
+        assert!(created);
+        // Mismo email y nombre (sin tildes ni mayúsculas): la misma paciente
+        let (again, created) = match_or_create(&store, "tenant-a", &contact("ana maria perez", "ANA@example.com", None), "now").await.unwrap();
+        assert_eq!((again.id.as_str(), created), (ana.id.as_str(), false));
+        // Mismo email, otro nombre: su hija es otra paciente
+        let (daughter, created) = match_or_create(&store, "tenant-a", &contact("Sofía Pérez", "ana@example.com", None), "now").await.unwrap();
+        assert!(created);
+        assert_ne!(daughter.id, ana.id);
+
+        // El documento basta, aunque cambien nombre y email
+        let with_document = Patient {
+            document_id: Some("CC 1020".into()),
+            guardian: Some(Guardian { name: "Ana".into(), relationship: None, email: None, phone: None }),
+            ..daughter.clone()
+        };
+        store.update_patient(&daughter, &with_document).await.unwrap();
+        let found = find_match(&store, "tenant-a", &contact("Sofi", "otra@example.com", Some("cc1020"))).await.unwrap();
+        assert_eq!(found.map(|p| p.id), Some(daughter.id));
+        // Otro tenant no ve los pacientes de este
+        assert!(find_match(&store, "tenant-b", &contact("Ana María Pérez", "ana@example.com", None)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_links_existing_bookings_once() {
+        let store = MemoryStore::new();
+        for booking in [booking("b1", "Luis", "luis@example.com"), booking("b22", "Luis ", "Luis@example.com"), booking("b333", "Marta", "luis@example.com")] {
+            store.create_booking(&booking, None, &[], &BookingEvent::created(&booking, "now")).await.unwrap();
+        }
+
+        let dry_run = backfill_bookings(&store, "tenant-a", true, "now").await.unwrap();
+        assert_eq!((dry_run.linked, dry_run.patients_created), (3, 2));
+        assert!(store.list_patients("tenant-a", &PageRequest::default()).await.unwrap().patients.is_empty());
+
+        let report = backfill_bookings(&store, "tenant-a", false, "now").await.unwrap();
+        assert_eq!(report, BackfillReport { bookings: 3, linked: 3, patients_created: 2, already_linked: 0 });
+        let b1 = store.get_booking("b1").await.unwrap().unwrap();
+        let b22 = store.get_booking("b22").await.unwrap().unwrap();
+        let b333 = store.get_booking("b333").await.unwrap().unwrap();
+        assert_eq!(b1.patient_id, b22.patient_id);
+        assert_ne!(b1.patient_id, b333.patient_id);
+
+        let again = backfill_bookings(&store, "tenant-a", false, "now").await.unwrap();
+        assert_eq!((again.linked, again.already_linked), (0, 3));
+        assert_eq!(store.list_patients("tenant-a", &PageRequest::default()).await.unwrap().patients.len(), 2);
+    }
+
+    /// Como DynamoDB, el GSI2 todavía no ve los pacientes recién creados.
+    struct LaggingIndex(MemoryStore);
+
+    #[async_trait::async_trait]
+    impl ItemStore for LaggingIndex {
+        async fn get(&self, key: &Key) -> Result<Option<Item>, StoreError> {
+            self.0.get(key).await
+        }
+
+        async fn query(&self, query: &Query) -> Result<Page, StoreError> {
+            if query.index == Some(Index::Gsi2) {
+                return Ok(Page { items: vec![], last_key: None });
+            }
+            self.0.query(query).await
+        }
+
+        async fn write(&self, op: WriteOp) -> Result<(), StoreError> {
+            self.0.write(op).await
+        }
+
+        async fn transact(&self, ops: Vec<WriteOp>) -> Result<(), StoreError> {
+            self.0.transact(ops).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_links_two_bookings_of_a_new_patient_to_one_patient() {
+        let store = LaggingIndex(MemoryStore::new());
+        for booking in [booking("b1", "Luis", "luis@example.com"), booking("b22", "luis", "LUIS@example.com")] {
+            store.create_booking(&booking, None, &[], &BookingEvent::created(&booking, "now")).await.unwrap();
+        }
+
+        let report = backfill_bookings(&store, "tenant-a", false, "now").await.unwrap();
+        assert_eq!((report.linked, report.patients_created), (2, 1));
+        let b1 = store.get_booking("b1").await.unwrap().unwrap();
+        let b22 = store.get_booking("b22").await.unwrap().unwrap();
+        assert_eq!(b1.patient_id, b22.patient_id);
+        assert_eq!(store.0.list_patients("tenant-a", &PageRequest::default()).await.unwrap().patients.len(), 1);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::auth::JwtClaims;
+use crate::error::ApiError;
+
+/// Roles de negocio, mapeados desde `cognito:groups`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Owner,
+    Admin,
+    Dentist,
+    Reception,
+    Patient,
+}
+
+impl Role {
+    /// Acepta el nombre del grupo con o sin tildes y sin distinguir mayúsculas.
+    pub fn from_group(group: &str) -> Option<Role> {
+        match group.trim().to_lowercase().as_str() {
+            "owner" => Some(Role::Owner),
+            "admin" => Some(Role::Admin),
+            "odontólogo" | "odontologo" => Some(Role::Dentist),
+            "recepción" | "recepcion" => Some(Role::Reception),
+            "paciente" => Some(Role::Patient),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "Owner",
+            Role::Admin => "Admin",
+            Role::Dentist => "Odontólogo",
+            Role::Reception => "Recepción",
+            Role::Patient => "Paciente",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Tenant,
+    Site,
+    Treatment,
+    Professional,
+    Booking,
+    /// Preferencias de comunicación de los pacientes y lista de supresión
+    Contact,
+    /// Directorio de pacientes de la clínica
+    Patient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Read,
+    Create,
+    Update,
+    Delete,
+}
+
+/// Alcance concedido por un permiso, de menor a mayor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    /// Solo registros propios del usuario (p. ej. reservas con su email)
+    Own,
+    /// Cualquier registro del tenant del token
+    Tenant,
+    /// Cualquier tenant
+    Any,
+}
+
+use Action::*;
+
+const ALL: &[Action] = &[Read, Create, Update, Delete];
+
+/// Matriz de permisos: rol → recurso → acciones permitidas y su alcance.
+/// Lo que no aparece aquí está denegado.
+const PERMISSIONS: &[(Role, Resource, &[Action], Scope)] = &[
+    (Role::Owner, Resource::Tenant, ALL, Scope::Any),
+    (Role::Owner, Resource::Site, ALL, Scope::Any),
+    (Role::Owner, Resource::Treatment, ALL, Scope::Any),
+    (Role::Owner, Resource::Professional, ALL, Scope::Any),
+    (Role::Owner, Resource::Booking, ALL, Scope::Any),
+    (Role::Owner, Resource::Contact, ALL, Scope::Any),
+    (Role::Owner, Resource::Patient, ALL, Scope::Any),
+
+    (Role::Admin, Resource::Tenant, &[Read, Update], Scope::Tenant),
+    (Role::Admin, Resource::Site, ALL, Scope::Tenant),
+    (Role::Admin, Resource::Treatment, ALL, Scope::Tenant),
+    (Role::Admin, Resource::Professional, ALL, Scope::Tenant),
+    (Role::Admin, Resource::Booking, ALL, Scope::Tenant),
+    (Role::Admin, Resource::Contact, ALL, Scope::Tenant),
+    (Role::Admin, Resource::Patient, ALL, Scope::Tenant),
+
+    (Role::Dentist, Resource::Tenant, &[Read], Scope::Tenant),
+    (Role::Dentist, Resource::Site, &[Read], Scope::Tenant),
+    (Role::Dentist, Resource::Treatment, &[Read], Scope::Tenant),
+    (Role::Dentist, Resource::Professional, &[Read], Scope::Tenant),
+    (Role::Dentist, Resource::Booking, &[Read, Update], Scope::Tenant),
+    (Role::Dentist, Resource::Patient, &[Read], Scope::Tenant),
+
+    (Role::Reception, Resource::Tenant, &[Read], Scope::Tenant),
+    (Role::Reception, Resource::Site, &[Read], Scope::Tenant),
+    (Role::Reception, Resource::Treatment, &[Read], Scope::Tenant),
+    (Role::Reception, Resource::Professional, &[Read], Scope::Tenant),
+    (Role::Reception, Resource::Booking, ALL, Scope::Tenant),
+    (Role::Reception, Resource::Contact, &[Read, Update], Scope::Tenant),
+    (Role::Reception, Resource::Patient, &[Read, Create, Update], Scope::Tenant),
+
+    (Role::Patient, Resource::Tenant, &[Read], Scope::Tenant),
+    (Role::Patient, Resource::Site, &[Read], Scope::Tenant),
+    (Role::Patient, Resource::Treatment, &[Read], Scope::Tenant),
+    (Role::Patient, Resource::Professional, &[Read], Scope::Tenant),
+    (Role::Patient, Resource::Booking, ALL, Scope::Own),
+    (Role::Patient, Resource::Contact, &[Read, Update], Scope::Own),
+    (Role::Patient, Resource::Patient, &[Read], Scope::Own),
+];
+
+/// Mayor alcance que la matriz concede a `roles` para `action` sobre `resource`.
+pub fn allowed_scope(roles: &[Role], action: Action, resource: Resource) -> Option<Scope> {
+    PERMISSIONS
+        .iter()
+        .filter(|(role, res, actions, _)| roles.contains(role) && *res == resource && actions.contains(&action))
+        .map(|(_, _, _, scope)| *scope)
+        .max()
+}
+
+impl JwtClaims {
+    /// Roles reconocidos del token; los grupos desconocidos se ignoran.
+    pub fn roles(&self) -> Vec<Role> {
+        self.groups
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|g| Role::from_group(g))
+            .collect()
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles().contains(&role)
+    }
+}
+
+/// Exige que el usuario tenga al menos uno de `roles`.
+pub fn require_role(claims: &JwtClaims, roles: &[Role]) -> Result<Role, ApiError> {
+    claims
+        .roles()
+        .into_iter()
+        .find(|r| roles.contains(r))
+        .ok_or_else(|| ApiError::Forbidden("Rol insuficiente para esta operación".into()))
+}
+
+/// Autoriza `action` sobre `resource` según la matriz y devuelve el alcance concedido.
+/// Con `Scope::Own` el handler debe filtrar a los registros del propio usuario.
+pub fn authorize(claims: &JwtClaims, action: Action, resource: Resource) -> Result<Scope, ApiError> {
+    let roles = claims.roles();
+    match allowed_scope(&roles, action, resource) {
+        Some(scope) => Ok(scope),
+        None => {
+            tracing::warn!(
+                sub = ?claims.sub,
+                roles = ?roles.iter().map(Role::as_str).collect::<Vec<_>>(),
+                action = ?action,
+                resource = ?resource,
+                "Authorization denied"
+            );
+            Err(ApiError::Forbidden(format!("Sin permiso para {:?} sobre {:?}", action, resource)))
+        }
+    }
+}
+
+/// Tenant sobre el que opera la request. Por defecto es el del token; un
+/// `requested` distinto solo se acepta con alcance `Any` (super-admin) y queda
+/// registrado en el log de auditoría. Cualquier otro caso es `Forbidden`.
+pub fn resolve_tenant(claims: &JwtClaims, requested: Option<&str>, scope: Scope) -> Result<String, ApiError> {
+    let own = claims.tenant();
+    match (requested, own) {
+        (None, Some(own)) => Ok(own.to_string()),
+        (Some(req), Some(own)) if req == own => Ok(own.to_string()),
+        (Some(req), _) if scope == Scope::Any => {
+            tracing::warn!(
+                target: "audit",
+                event = "tenant_override",
+                sub = ?claims.sub,
+                email = ?claims.email,
+                token_tenant = ?own,
+                target_tenant = %req,
+                "Super-admin operating on another tenant"
+            );
+            Ok(req.to_string())
+        }
+        (Some(_), Some(_)) => Err(ApiError::Forbidden("tenant_id no coincide con el token".into())),
+        (_, None) => Err(ApiError::Forbidden("Tenant no presente en token".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(groups: &[&str]) -> JwtClaims {
+        JwtClaims {
+            sub: Some("user-1".into()),
+            email: Some("user@example.com".into()),
+            groups: Some(groups.iter().map(|g| g.to_string()).collect()),
+            tenant_id: None,
+            custom_tenant_id: Some("tenant-a".into()),
+            token_use: Some("id".into()),
+            client_id: None,
+        }
+    }
+
+    #[test]
+    fn test_group_names_map_with_and_without_accents() {
+        assert_eq!(Role::from_group("Odontólogo"), Some(Role::Dentist));
+        assert_eq!(Role::from_group("recepcion"), Some(Role::Reception));
+        assert_eq!(Role::from_group("Desconocido"), None);
+    }
+
+    #[test]
+    fn test_only_owner_creates_tenants() {
+        assert_eq!(authorize(&claims(&["Owner"]), Create, Resource::Tenant).unwrap(), Scope::Any);
+        for group in ["Admin", "Odontólogo", "Recepción", "Paciente"] {
+            assert!(authorize(&claims(&[group]), Create, Resource::Tenant).is_err(), "{}", group);
+        }
+    }
+
+    #[test]
+    fn test_catalog_writes_require_admin() {
+        assert!(authorize(&claims(&["Admin"]), Create, Resource::Treatment).is_ok());
+        assert_eq!(authorize(&claims(&["Admin"]), Delete, Resource::Site).unwrap(), Scope::Tenant);
+        assert!(authorize(&claims(&["Recepción"]), Update, Resource::Site).is_err());
+        assert!(authorize(&claims(&["Recepción"]), Create, Resource::Professional).is_err());
+        assert!(authorize(&claims(&["Paciente"]), Create, Resource::Treatment).is_err());
+        assert_eq!(authorize(&claims(&["Paciente"]), Read, Resource::Treatment).unwrap(), Scope::Tenant);
+    }
+
+    #[test]
+    fn test_patients_are_scoped_to_own_bookings() {
+        assert_eq!(authorize(&claims(&["Paciente"]), Read, Resource::Booking).unwrap(), Scope::Own);
+        assert_eq!(authorize(&claims(&["Paciente", "Recepción"]), Read, Resource::Booking).unwrap(), Scope::Tenant);
+        assert!(authorize(&claims(&["Odontólogo"]), Delete, Resource::Booking).is_err());
+    }
+
+    #[test]
+    fn test_contact_permissions() {
+        assert_eq!(authorize(&claims(&["Paciente"]), Update, Resource::Contact).unwrap(), Scope::Own);
+        assert_eq!(authorize(&claims(&["Recepción"]), Read, Resource::Contact).unwrap(), Scope::Tenant);
+        assert!(authorize(&claims(&["Recepción"]), Delete, Resource::Contact).is_err());
+        assert!(authorize(&claims(&["Odontólogo"]), Read, Resource::Contact).is_err());
+        assert_eq!(authorize(&claims(&["Admin"]), Delete, Resource::Contact).unwrap(), Scope::Tenant);
+    }
+
+    #[test]
+    fn test_patient_directory_permissions() {
+        assert_eq!(authorize(&claims(&["Recepción"]), Create, Resource::Patient).unwrap(), Scope::Tenant);
+        assert!(authorize(&claims(&["Recepción"]), Delete, Resource::Patient).is_err());
+        assert_eq!(authorize(&claims(&["Odontólogo"]), Read, Resource::Patient).unwrap(), Scope::Tenant);
+        assert!(authorize(&claims(&["Odontólogo"]), Update, Resource::Patient).is_err());
+        assert_eq!(authorize(&claims(&["Paciente"]), Read, Resource::Patient).unwrap(), Scope::Own);
+        assert!(authorize(&claims(&["Paciente"]), Update, Resource::Patient).is_err());
+    }
+
+    #[test]
+    fn test_tenant_defaults_to_token_and_rejects_cross_tenant() {
+        let admin = claims(&["Admin"]);
+        assert_eq!(resolve_tenant(&admin, None, Scope::Tenant).unwrap(), "tenant-a");
+        assert_eq!(resolve_tenant(&admin, Some("tenant-a"), Scope::Tenant).unwrap(), "tenant-a");
+        assert!(matches!(resolve_tenant(&admin, Some("tenant-b"), Scope::Tenant), Err(ApiError::Forbidden(_))));
+    }
+
+    #[test]
+    fn test_super_admin_override_requires_any_scope() {
+        let owner = claims(&["Owner"]);
+        assert_eq!(resolve_tenant(&owner, Some("tenant-b"), Scope::Any).unwrap(), "tenant-b");
+
+        let mut no_tenant = claims(&["Owner"]);
+        no_tenant.custom_tenant_id = None;
+        assert!(resolve_tenant(&no_tenant, None, Scope::Any).is_err());
+        assert_eq!(resolve_tenant(&no_tenant, Some("tenant-b"), Scope::Any).unwrap(), "tenant-b");
+    }
+
+    #[test]
+    fn test_no_groups_is_denied() {
+        assert!(authorize(&claims(&[]), Read, Resource::Booking).is_err());
+        assert!(require_role(&claims(&["Paciente"]), &[Role::Admin, Role::Owner]).is_err());
+        assert_eq!(require_role(&claims(&["Admin"]), &[Role::Admin, Role::Owner]).unwrap(), Role::Admin);
+    }
+}
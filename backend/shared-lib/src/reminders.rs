@@ -0,0 +1,488 @@
+//! Recordatorios de citas con EventBridge Scheduler. Cada recordatorio es un
+//! schedule `at()` de una sola vez, en hora local de la clínica, que invoca
+//! `send-notification` con un payload `type: reminder`. Cuáles se envían lo
+//! define la política del tenant (`TenantSettings::reminders`). Los schedules
+//! se llaman `booking-<id>-<offset>`, así que se pueden reemplazar o borrar por
+//! reserva, y se borran solos al dispararse.
+
+use std::collections::HashSet;
+
+use aws_sdk_scheduler::types::{ActionAfterCompletion, FlexibleTimeWindow, FlexibleTimeWindowMode, Target};
+use aws_sdk_scheduler::Client as SchedulerClient;
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::ApiError;
+use crate::models::Channel;
+use crate::schedule::hhmm;
+use crate::timezone::{local_to_utc, Tz};
+
+/// Máximo de recordatorios por cita que admite la política de un tenant.
+pub const MAX_REMINDERS: usize = 5;
+
+/// Plantilla de `send-notification` de los recordatorios por defecto.
+pub const DEFAULT_REMINDER_TEMPLATE: &str = "booking-reminder";
+
+/// Cuándo sale un recordatorio respecto de la cita.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ReminderOffset {
+    /// `hours_before` horas reales antes de la cita
+    HoursBefore { hours_before: u32 },
+    /// A la hora local `at`, `days_before` días antes (0 = el mismo día)
+    LocalTime {
+        days_before: u32,
+        #[serde(with = "hhmm")]
+        at: NaiveTime,
+    },
+}
+
+impl ReminderOffset {
+    /// Sufijo del nombre del schedule: `24h`, `d0-0800`.
+    pub fn label(&self) -> String {
+        match self {
+            ReminderOffset::HoursBefore { hours_before } => format!("{}h", hours_before),
+            ReminderOffset::LocalTime { days_before, at } => format!("d{}-{}", days_before, at.format("%H%M")),
+        }
+    }
+}
+
+/// Un recordatorio de la política del tenant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReminderRule {
+    #[serde(flatten)]
+    pub offset: ReminderOffset,
+    #[serde(default)]
+    pub channel: Channel,
+    #[serde(default = "default_template")]
+    pub template: String,
+}
+
+fn default_template() -> String {
+    DEFAULT_REMINDER_TEMPLATE.into()
+}
+
+impl ReminderRule {
+    pub fn hours_before(hours_before: u32) -> Self {
+        Self {
+            offset: ReminderOffset::HoursBefore { hours_before },
+            channel: Channel::Email,
+            template: default_template(),
+        }
+    }
+}
+
+/// Política de los tenants que no configuraron la suya: T-24h y T-2h por email.
+pub fn default_reminder_policy() -> Vec<ReminderRule> {
+    vec![ReminderRule::hours_before(24), ReminderRule::hours_before(2)]
+}
+
+/// Offsets fuera de rango o repetidos (chocarían en el nombre del schedule) y
+/// plantillas con nombres que no son de archivo.
+pub fn validate_reminder_policy(rules: &[ReminderRule]) -> Result<(), String> {
+    if rules.len() > MAX_REMINDERS {
+        return Err(format!("máximo {} recordatorios", MAX_REMINDERS));
+    }
+    let mut labels = HashSet::new();
+    for (i, rule) in rules.iter().enumerate() {
+        match rule.offset {
+            ReminderOffset::HoursBefore { hours_before } if !(1..=720).contains(&hours_before) => {
+                return Err(format!("reminders[{}]: hours_before debe estar entre 1 y 720", i));
+            }
+            ReminderOffset::LocalTime { days_before, .. } if days_before > 30 => {
+                return Err(format!("reminders[{}]: days_before debe estar entre 0 y 30", i));
+            }
+            _ => {}
+        }
+        let valid_template = !rule.template.is_empty()
+            && rule.template.len() <= 64
+            && rule.template.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid_template {
+            return Err(format!("reminders[{}]: template inválido (usar a-z, 0-9, - y _)", i));
+        }
+        if !labels.insert(rule.offset.label()) {
+            return Err(format!("reminders[{}]: offset repetido", i));
+        }
+    }
+    Ok(())
+}
+
+/// Recordatorio de una regla de la política, expresado en hora local del
+/// tenant para la expresión `at()` de EventBridge Scheduler.
+#[derive(Debug, PartialEq)]
+pub struct Reminder {
+    pub rule: ReminderRule,
+    pub at_local: NaiveDateTime,
+    pub at_utc: DateTime<Utc>,
+}
+
+impl Reminder {
+    pub fn expression(&self) -> String {
+        format!("at({})", self.at_local.format("%Y-%m-%dT%H:%M:%S"))
+    }
+
+    pub fn label(&self) -> String {
+        self.rule.offset.label()
+    }
+
+    pub fn schedule_name(&self, booking_id: &str) -> String {
+        format!("booking-{}-{}", booking_id, self.label())
+    }
+}
+
+/// Margen mínimo para programar: Scheduler rechaza un `at()` que ya pasó, y uno
+/// a segundos de la cita podría pasar antes de que llegue la request.
+pub const MIN_LEAD_SECONDS: i64 = 60;
+
+/// Un recordatorio por regla. `hours_before` cuenta tiempo real: en un cambio
+/// de horario el de 24h cae a otra hora de reloj que la cita, pero siempre 24
+/// horas antes. Los de hora local que caen en o después de la cita (el de las
+/// 08:00 del mismo día para una cita a las 07:30) no se planifican.
+pub fn plan_reminders(appointment: DateTime<Utc>, tz: Tz, rules: &[ReminderRule]) -> Vec<Reminder> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let at_utc = match rule.offset {
+                ReminderOffset::HoursBefore { hours_before } => appointment - Duration::hours(hours_before.into()),
+                ReminderOffset::LocalTime { days_before, at } => {
+                    let date = appointment.with_timezone(&tz).date_naive() - Duration::days(days_before.into());
+                    local_to_utc(tz, date.and_time(at))
+                }
+            };
+            (at_utc < appointment).then(|| Reminder {
+                rule: rule.clone(),
+                at_local: at_utc.with_timezone(&tz).naive_local(),
+                at_utc,
+            })
+        })
+        .collect()
+}
+
+/// Recordatorios programados y los omitidos porque su hora ya pasó (una cita
+/// reservada con menos de 24 horas de anticipación, p. ej.).
+#[derive(Debug, Default, PartialEq)]
+pub struct ReminderOutcome {
+    pub scheduled: Vec<Reminder>,
+    pub skipped: Vec<Reminder>,
+}
+
+impl ReminderOutcome {
+    /// Separa los recordatorios que todavía se pueden programar a `now`.
+    pub fn split(reminders: Vec<Reminder>, now: DateTime<Utc>) -> Self {
+        let (scheduled, skipped) = reminders
+            .into_iter()
+            .partition(|r| r.at_utc > now + Duration::seconds(MIN_LEAD_SECONDS));
+        Self { scheduled, skipped }
+    }
+}
+
+/// Cita para la que se programan recordatorios. `rules` es la política del
+/// tenant; `details` son campos extra del payload de `send-notification`
+/// (profesional, tratamiento, dirección...).
+#[derive(Debug, Clone)]
+pub struct ReminderRequest {
+    pub booking_id: String,
+    pub patient_email: String,
+    pub patient_name: String,
+    pub patient_phone: Option<String>,
+    /// Canales que eligió el paciente entre los de la clínica
+    /// (`models::preferred_channels`); vacío = los de cada regla
+    pub preferred_channels: Vec<Channel>,
+    pub appointment: DateTime<Utc>,
+    pub tz: Tz,
+    pub rules: Vec<ReminderRule>,
+    pub details: serde_json::Map<String, Value>,
+}
+
+impl ReminderRequest {
+    /// Canal de la regla si el paciente lo acepta y se le puede enviar; si no,
+    /// el primero que eligió el paciente, y email como último recurso.
+    pub fn channel_for(&self, rule: &ReminderRule) -> Channel {
+        let reachable = self.patient_phone.is_some() || !rule.channel.needs_phone();
+        if reachable && (self.preferred_channels.is_empty() || self.preferred_channels.contains(&rule.channel)) {
+            return rule.channel;
+        }
+        self.preferred_channels.first().copied().unwrap_or(Channel::Email)
+    }
+
+    fn payload(&self, reminder: &Reminder) -> Value {
+        // Fecha y hora que ve el paciente: siempre las de la clínica
+        let local = self.appointment.with_timezone(&self.tz);
+        let mut payload = self.details.clone();
+        payload.extend([
+            ("type".to_string(), "reminder".into()),
+            ("booking_id".to_string(), self.booking_id.clone().into()),
+            ("patient_email".to_string(), self.patient_email.clone().into()),
+            ("patient_name".to_string(), self.patient_name.clone().into()),
+            ("channel".to_string(), self.channel_for(&reminder.rule).as_str().into()),
+            ("template".to_string(), reminder.rule.template.clone().into()),
+            ("hours_before".to_string(), (self.appointment - reminder.at_utc).num_hours().into()),
+            ("appointment_date".to_string(), local.format("%Y-%m-%d").to_string().into()),
+            ("appointment_time".to_string(), local.format("%H:%M").to_string().into()),
+            ("timezone".to_string(), self.tz.name().into()),
+        ]);
+        if let Some(phone) = &self.patient_phone {
+            payload.insert("patient_phone".to_string(), phone.clone().into());
+        }
+        Value::Object(payload)
+    }
+}
+
+/// Crea, reemplaza y borra los schedules de recordatorio de `send-notification`.
+pub struct ReminderScheduler {
+    client: SchedulerClient,
+    role_arn: String,
+    target_arn: String,
+}
+
+impl ReminderScheduler {
+    pub fn new(client: SchedulerClient, role_arn: impl Into<String>, target_arn: impl Into<String>) -> Self {
+        Self { client, role_arn: role_arn.into(), target_arn: target_arn.into() }
+    }
+
+    /// Cliente por defecto con `SCHEDULER_ROLE_ARN` y `NOTIFICATION_LAMBDA_ARN`.
+    pub async fn from_env() -> Result<Self, ApiError> {
+        let role_arn = std::env::var("SCHEDULER_ROLE_ARN")
+            .map_err(|_| ApiError::Internal(anyhow::anyhow!("SCHEDULER_ROLE_ARN no configurado")))?;
+        let target_arn = std::env::var("NOTIFICATION_LAMBDA_ARN")
+            .map_err(|_| ApiError::Internal(anyhow::anyhow!("NOTIFICATION_LAMBDA_ARN no configurado")))?;
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Ok(Self::new(SchedulerClient::new(&config), role_arn, target_arn))
+    }
+
+    /// Programa los recordatorios de `request` que aún no pasaron. Es
+    /// idempotente: un schedule que ya existe con el mismo nombre se actualiza,
+    /// así que reintentar (o repetir con otra hora) deja un solo recordatorio.
+    pub async fn schedule(&self, request: &ReminderRequest) -> Result<ReminderOutcome, ApiError> {
+        let planned = plan_reminders(request.appointment, request.tz, &request.rules);
+        let outcome = ReminderOutcome::split(planned, Utc::now());
+        for reminder in &outcome.skipped {
+            tracing::info!(booking_id = %request.booking_id, offset = %reminder.label(), "Reminder time already passed; skipped");
+        }
+        for reminder in &outcome.scheduled {
+            self.upsert(request, reminder).await?;
+        }
+        Ok(outcome)
+    }
+
+    /// Reemplaza los recordatorios de la reserva por los de la nueva hora. Borra
+    /// primero todos: un recordatorio que con la nueva hora ya pasó (o que la
+    /// política ya no incluye) no debe quedar programado con la anterior.
+    pub async fn reschedule(&self, request: &ReminderRequest) -> Result<ReminderOutcome, ApiError> {
+        self.cancel(&request.booking_id).await?;
+        self.schedule(request).await
+    }
+
+    /// Borra los recordatorios pendientes de la reserva y devuelve sus nombres.
+    /// Sin recordatorios (o ya borrados) no es un error.
+    pub async fn cancel(&self, booking_id: &str) -> Result<Vec<String>, ApiError> {
+        let prefix = format!("booking-{}-", booking_id);
+        let mut names = vec![];
+        let mut pages = self.client.list_schedules().name_prefix(&prefix).into_paginator().send();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| ApiError::Internal(anyhow::anyhow!("Scheduler error: {}", e)))?;
+            names.extend(page.schedules().iter().filter_map(|s| s.name().map(str::to_string)));
+        }
+
+        for name in &names {
+            match self.client.delete_schedule().name(name).send().await {
+                Ok(_) => tracing::info!(schedule_name = %name, "Reminder deleted"),
+                Err(e) if e.as_service_error().is_some_and(|se| se.is_resource_not_found_exception()) => {}
+                Err(e) => return Err(ApiError::Internal(anyhow::anyhow!("Scheduler error: {}", e))),
+            }
+        }
+        Ok(names)
+    }
+
+    async fn upsert(&self, request: &ReminderRequest, reminder: &Reminder) -> Result<(), ApiError> {
+        let name = reminder.schedule_name(&request.booking_id);
+        let window = FlexibleTimeWindow::builder()
+            .mode(FlexibleTimeWindowMode::Off)
+            .build()
+            .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?;
+        let target = Target::builder()
+            .arn(&self.target_arn)
+            .role_arn(&self.role_arn)
+            .input(request.payload(reminder).to_string())
+            .build()
+            .map_err(|e| ApiError::Internal(anyhow::anyhow!("Build error: {}", e)))?;
+
+        let created = self.client
+            .create_schedule()
+            .name(&name)
+            .schedule_expression(reminder.expression())
+            .schedule_expression_timezone(request.tz.name())
+            .flexible_time_window(window.clone())
+            .target(target.clone())
+            .action_after_completion(ActionAfterCompletion::Delete)
+            .send()
+            .await;
+        match created {
+            Ok(_) => {
+                tracing::info!(schedule_name = %name, "Reminder scheduled");
+                return Ok(());
+            }
+            Err(e) if e.as_service_error().is_some_and(|se| se.is_conflict_exception()) => {}
+            Err(e) => return Err(ApiError::Internal(anyhow::anyhow!("Scheduler error: {}", e))),
+        }
+
+        // Ya existía: se actualiza con la hora y el payload actuales
+        self.client
+            .update_schedule()
+            .name(&name)
+            .schedule_expression(reminder.expression())
+            .schedule_expression_timezone(request.tz.name())
+            .flexible_time_window(window)
+            .target(target)
+            .action_after_completion(ActionAfterCompletion::Delete)
+            .send()
+            .await
+            .map_err(|e| ApiError::Internal(anyhow::anyhow!("Scheduler error: {}", e)))?;
+        tracing::info!(schedule_name = %name, "Reminder updated");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_reminders_are_local_clinic_time() {
+        let tz: Tz = "America/Bogota".parse().unwrap();
+        let reminders = plan_reminders(utc("2025-10-02T15:00:00Z"), tz, &default_reminder_policy());
+        assert_eq!(reminders[0].expression(), "at(2025-10-01T10:00:00)");
+        assert_eq!(reminders[1].expression(), "at(2025-10-02T08:00:00)");
+        assert_eq!(reminders[1].schedule_name("b1"), "booking-b1-2h");
+    }
+
+    #[test]
+    fn test_reminder_across_spring_forward_keeps_24_real_hours() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        // Cita 2025-03-09 10:00 EDT; 24h antes es 2025-03-08 09:00 EST
+        let reminders = plan_reminders(utc("2025-03-09T14:00:00Z"), tz, &default_reminder_policy());
+        assert_eq!(reminders[0].expression(), "at(2025-03-08T09:00:00)");
+        assert_eq!(reminders[0].at_utc, utc("2025-03-08T14:00:00Z"));
+        assert_eq!(reminders[1].expression(), "at(2025-03-09T08:00:00)");
+    }
+
+    #[test]
+    fn test_reminder_across_fall_back_keeps_24_real_hours() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        // Cita 2025-11-02 10:00 EST; 24h antes es 2025-11-01 11:00 EDT
+        let reminders = plan_reminders(utc("2025-11-02T15:00:00Z"), tz, &default_reminder_policy());
+        assert_eq!(reminders[0].expression(), "at(2025-11-01T11:00:00)");
+    }
+
+    #[test]
+    fn test_reminders_already_due_are_skipped() {
+        let tz: Tz = "America/Bogota".parse().unwrap();
+        let appointment = utc("2025-10-02T15:00:00Z");
+        // Reservada 10 horas antes: ya no cabe el de 24h
+        let outcome = ReminderOutcome::split(plan_reminders(appointment, tz, &default_reminder_policy()), utc("2025-10-02T05:00:00Z"));
+        assert_eq!(outcome.scheduled.iter().map(|r| r.label()).collect::<Vec<_>>(), ["2h"]);
+        assert_eq!(outcome.skipped.iter().map(|r| r.label()).collect::<Vec<_>>(), ["24h"]);
+
+        // A segundos de la hora del recordatorio tampoco se programa
+        let outcome = ReminderOutcome::split(plan_reminders(appointment, tz, &default_reminder_policy()), utc("2025-10-02T12:59:30Z"));
+        assert!(outcome.scheduled.is_empty());
+        assert_eq!(outcome.skipped.len(), 2);
+    }
+
+    #[test]
+    fn test_payload_uses_clinic_local_date_and_keeps_details() {
+        let request = ReminderRequest {
+            booking_id: "b1".into(),
+            patient_email: "ana@example.com".into(),
+            patient_name: "Ana".into(),
+            patient_phone: None,
+            preferred_channels: vec![],
+            appointment: utc("2025-10-02T03:30:00Z"),
+            tz: "America/Bogota".parse().unwrap(),
+            rules: default_reminder_policy(),
+            details: serde_json::Map::from_iter([("treatment_name".to_string(), "Limpieza".into())]),
+        };
+        let reminders = plan_reminders(request.appointment, request.tz, &request.rules);
+        let payload = request.payload(&reminders[0]);
+        assert_eq!(payload["appointment_date"], "2025-10-01");
+        assert_eq!(payload["appointment_time"], "22:30");
+        assert_eq!(payload["hours_before"], 24);
+        assert_eq!(payload["channel"], "email");
+        assert_eq!(payload["template"], "booking-reminder");
+        assert_eq!(payload["treatment_name"], "Limpieza");
+        assert!(payload.get("patient_phone").is_none());
+    }
+
+    #[test]
+    fn test_reminder_channel_follows_patient_preferences() {
+        let whatsapp = ReminderRule { channel: Channel::Whatsapp, ..ReminderRule::hours_before(24) };
+        let sms = ReminderRule { channel: Channel::Sms, ..ReminderRule::hours_before(2) };
+        let mut request = ReminderRequest {
+            booking_id: "b1".into(),
+            patient_email: "ana@example.com".into(),
+            patient_name: "Ana".into(),
+            patient_phone: Some("+573001234567".into()),
+            preferred_channels: vec![],
+            appointment: utc("2025-10-02T15:00:00Z"),
+            tz: "America/Bogota".parse().unwrap(),
+            rules: vec![whatsapp.clone(), sms.clone()],
+            details: Default::default(),
+        };
+        assert_eq!(request.channel_for(&whatsapp), Channel::Whatsapp);
+
+        // El paciente solo quiere WhatsApp: el recordatorio por SMS sale por ahí
+        request.preferred_channels = vec![Channel::Whatsapp];
+        assert_eq!(request.channel_for(&sms), Channel::Whatsapp);
+        let payload = request.payload(&plan_reminders(request.appointment, request.tz, &request.rules)[1]);
+        assert_eq!(payload["channel"], "whatsapp");
+        assert_eq!(payload["patient_phone"], "+573001234567");
+
+        // Sin teléfono, email
+        request.preferred_channels = vec![];
+        request.patient_phone = None;
+        assert_eq!(request.channel_for(&sms), Channel::Email);
+    }
+
+    #[test]
+    fn test_tenant_policy_plans_one_reminder_per_offset() {
+        let tz: Tz = "America/Bogota".parse().unwrap();
+        let rules: Vec<ReminderRule> = serde_json::from_value(serde_json::json!([
+            {"hours_before": 48},
+            {"days_before": 0, "at": "07:00", "channel": "sms", "template": "same-day"},
+        ]))
+        .unwrap();
+        assert_eq!(rules[0], ReminderRule::hours_before(48));
+
+        // Cita 2025-10-02 10:00 local
+        let reminders = plan_reminders(utc("2025-10-02T15:00:00Z"), tz, &rules);
+        assert_eq!(reminders[0].schedule_name("b1"), "booking-b1-48h");
+        assert_eq!(reminders[0].expression(), "at(2025-09-30T10:00:00)");
+        assert_eq!(reminders[1].schedule_name("b1"), "booking-b1-d0-0700");
+        assert_eq!(reminders[1].expression(), "at(2025-10-02T07:00:00)");
+        assert_eq!(reminders[1].rule.channel, Channel::Sms);
+
+        // Cita a las 06:30: el de las 07:00 del mismo día ya no tiene sentido
+        let reminders = plan_reminders(utc("2025-10-02T11:30:00Z"), tz, &rules);
+        assert_eq!(reminders.iter().map(Reminder::label).collect::<Vec<_>>(), ["48h"]);
+
+        assert!(plan_reminders(utc("2025-10-02T15:00:00Z"), tz, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_policy_validation() {
+        let at = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+        let same_morning = ReminderRule { offset: ReminderOffset::LocalTime { days_before: 0, at }, ..ReminderRule::hours_before(1) };
+        assert!(validate_reminder_policy(&[ReminderRule::hours_before(48), same_morning.clone()]).is_ok());
+        assert!(validate_reminder_policy(&[]).is_ok());
+
+        assert!(validate_reminder_policy(&[ReminderRule::hours_before(0)]).is_err());
+        assert!(validate_reminder_policy(&[ReminderRule::hours_before(24), ReminderRule::hours_before(24)]).is_err());
+        assert!(validate_reminder_policy(&[ReminderRule { template: "../secret".into(), ..same_morning }]).is_err());
+        assert!(validate_reminder_policy(&vec![ReminderRule::hours_before(24); MAX_REMINDERS + 1]).is_err());
+    }
+}
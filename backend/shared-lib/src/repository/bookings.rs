@@ -0,0 +1,560 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+
+use super::cursor::{paginate, PageRequest};
+use super::outbox::event_to_item;
+use super::patients::new_patient_ops;
+use super::store::{get_list_s, get_n, get_s, n, s, Condition, Index, Item, ItemStore, Key, Query, StoreError, WriteOp};
+use crate::models::{Booking, BookingEvent, BookingStatus, Channel, Patient, SlotHold, SlotLock, StatusChange};
+
+/// Filtros de `list_bookings`; todos opcionales y combinables.
+#[derive(Debug, Clone, Default)]
+pub struct BookingFilter {
+    pub patient_email: Option<String>,
+    pub patient_id: Option<String>,
+    pub professional_id: Option<String>,
+    pub site_id: Option<String>,
+    pub status: Option<BookingStatus>,
+    /// Rango de `startTime` (RFC3339 UTC), ambos extremos inclusive
+    pub start_between: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BookingPage {
+    pub bookings: Vec<Booking>,
+    pub next_cursor: Option<String>,
+}
+
+#[async_trait]
+pub trait BookingRepository {
+    /// Crea la reserva y reclama todos sus slot locks en una sola transacción.
+    /// Si algún lock ya existe devuelve `StoreError::ConditionFailed` y no escribe nada.
+    ///
+    /// Con `new_patient` lo da de alta en el directorio en la misma
+    /// transacción: si la reserva falla, el paciente no queda.
+    ///
+    /// Todas las escrituras de reservas guardan además su `event` de outbox en
+    /// la misma transacción: el evento existe si y solo si el cambio se aplicó.
+    async fn create_booking(&self, booking: &Booking, new_patient: Option<&Patient>, locks: &[SlotLock], event: &BookingEvent) -> Result<(), StoreError>;
+
+    async fn get_booking(&self, id: &str) -> Result<Option<Booking>, StoreError>;
+
+    /// Una página de reservas del tenant. Con `professional_id` consulta GSI3
+    /// (ordenado por inicio, el rango de fechas va en la key condition); si no,
+    /// GSI1 con el resto de filtros como FilterExpression.
+    async fn list_bookings(&self, tenant_id: &str, filter: &BookingFilter, page: &PageRequest) -> Result<BookingPage, StoreError>;
+
+    /// Aplica `change` solo si la reserva sigue en `change.from` (si no,
+    /// `ConditionFailed`), registra el cambio en el historial y libera `release`
+    /// en la misma transacción.
+    async fn transition_booking(&self, change: &StatusChange, release: &[SlotLock], event: &BookingEvent) -> Result<(), StoreError>;
+
+    /// Historial de cambios de estado, del más antiguo al más reciente.
+    async fn list_status_changes(&self, booking_id: &str) -> Result<Vec<StatusChange>, StoreError>;
+
+    /// Mueve la reserva a `booking.start_time`/`end_time`: libera los locks que ya
+    /// no aplican, reclama los nuevos y conserva los compartidos, todo atómico.
+    /// Falla con `ConditionFailed` si el estado cambió desde `booking.status`.
+    async fn reschedule_booking(
+        &self,
+        booking: &Booking,
+        old_locks: &[SlotLock],
+        new_locks: &[SlotLock],
+        now: &str,
+        event: &BookingEvent,
+    ) -> Result<(), StoreError>;
+
+    /// Vincula una reserva sin paciente al directorio. No es un cambio de la
+    /// reserva: no genera evento. Falla con `ConditionFailed` si la reserva no
+    /// existe o ya tiene paciente.
+    async fn link_patient(&self, booking_id: &str, patient_id: &str) -> Result<(), StoreError>;
+
+    /// Guarda la retención y reclama sus locks `held` en una transacción. Los
+    /// locks de retenciones vencidas se pueden reclamar aunque sigan en la tabla.
+    async fn create_hold(&self, hold: &SlotHold, locks: &[SlotLock]) -> Result<(), StoreError>;
+
+    async fn get_hold(&self, id: &str) -> Result<Option<SlotHold>, StoreError>;
+
+    /// Convierte la retención en `booking` (con el mismo id): los locks pasan a
+    /// `reserved` sin vencimiento y la retención se borra. Falla con
+    /// `ConditionFailed` si el token no coincide, si venció antes de `now` o si
+    /// otra reserva ya tomó alguno de sus locks. `new_patient`, como en
+    /// `create_booking`.
+    async fn confirm_hold(
+        &self,
+        hold: &SlotHold,
+        booking: &Booking,
+        new_patient: Option<&Patient>,
+        locks: &[SlotLock],
+        now: i64,
+        event: &BookingEvent,
+    ) -> Result<(), StoreError>;
+}
+
+#[async_trait]
+pub trait SlotLockRepository {
+    /// Locks del profesional en la fecha UTC `date`, en cualquier sede.
+    async fn list_slot_locks(&self, tenant_id: &str, professional_id: &str, date: &str) -> Result<Vec<SlotLock>, StoreError>;
+
+    /// Locks de la sede en `date` con el esquema anterior, por sede
+    /// (`TENANT#t#SITE#s#DATE#d` / `SLOT#HH:MM#prof`). Solo para migrarlos.
+    async fn list_legacy_slot_locks(&self, tenant_id: &str, site_id: &str, date: &str) -> Result<Vec<SlotLock>, StoreError>;
+
+    /// Pasa una reserva al esquema actual: reclama `locks` y después borra
+    /// `legacy`. Cada lock se reclama si la unidad está libre o ya es de la
+    /// misma reserva, así que repetirlo no falla. Si otra reserva ocupa una
+    /// unidad devuelve `ConditionFailed` y la reserva sigue con sus locks viejos.
+    async fn migrate_slot_locks(&self, legacy: &[SlotLock], locks: &[SlotLock]) -> Result<(), StoreError>;
+}
+
+fn booking_key(id: &str) -> Key {
+    Key::new(format!("BOOKING#{}", id), "METADATA")
+}
+
+/// La unidad de exclusión es el profesional, no la sede: un profesional que
+/// atiende en varias sedes no puede tener dos citas a la misma hora.
+pub fn slot_lock_key(lock: &SlotLock) -> Key {
+    Key::new(
+        slot_lock_partition(&lock.tenant_id, &lock.professional_id, &lock.date),
+        format!("SLOT#{}", lock.time),
+    )
+}
+
+fn slot_lock_partition(tenant_id: &str, professional_id: &str, date: &str) -> String {
+    format!("TENANT#{}#PROF#{}#DATE#{}", tenant_id, professional_id, date)
+}
+
+fn legacy_slot_lock_key(lock: &SlotLock) -> Key {
+    Key::new(
+        format!("TENANT#{}#SITE#{}#DATE#{}", lock.tenant_id, lock.site_id, lock.date),
+        format!("SLOT#{}#{}", lock.time, lock.professional_id),
+    )
+}
+
+fn booking_to_item(b: &Booking) -> Item {
+    let mut item = booking_key(&b.id).to_item();
+    item.extend([
+        ("GSI1PK".to_string(), s(format!("TENANT#{}", b.tenant_id))),
+        ("GSI1SK".to_string(), s(format!("BOOKING#{}", b.id))),
+        ("GSI3PK".to_string(), s(format!("PROFESSIONAL#{}", b.professional_id))),
+        ("GSI3SK".to_string(), s(&b.start_time)),
+        ("id".to_string(), s(&b.id)),
+        ("tenantId".to_string(), s(&b.tenant_id)),
+        ("siteId".to_string(), s(&b.site_id)),
+        ("professionalId".to_string(), s(&b.professional_id)),
+        ("treatmentId".to_string(), s(&b.treatment_id)),
+        ("startTime".to_string(), s(&b.start_time)),
+        ("endTime".to_string(), s(&b.end_time)),
+        ("patientName".to_string(), s(&b.patient_name)),
+        ("patientEmail".to_string(), s(&b.patient_email)),
+        ("status".to_string(), s(b.status.as_str())),
+        ("createdAt".to_string(), s(&b.created_at)),
+    ]);
+    if let Some(phone) = &b.patient_phone {
+        item.insert("patientPhone".to_string(), s(phone));
+    }
+    if let Some(patient_id) = &b.patient_id {
+        item.insert("patientId".to_string(), s(patient_id));
+    }
+    if !b.notification_channels.is_empty() {
+        let channels = b.notification_channels.iter().map(|c| s(c.as_str())).collect();
+        item.insert("notificationChannels".to_string(), AttributeValue::L(channels));
+    }
+    item
+}
+
+fn booking_from_item(item: &Item) -> Option<Booking> {
+    Some(Booking {
+        id: get_s(item, "id")?,
+        tenant_id: get_s(item, "tenantId")?,
+        site_id: get_s(item, "siteId")?,
+        professional_id: get_s(item, "professionalId")?,
+        treatment_id: get_s(item, "treatmentId")?,
+        start_time: get_s(item, "startTime")?,
+        end_time: get_s(item, "endTime")?,
+        patient_name: get_s(item, "patientName")?,
+        patient_email: get_s(item, "patientEmail")?,
+        patient_phone: get_s(item, "patientPhone"),
+        notification_channels: get_list_s(item, "notificationChannels").iter().filter_map(|c| Channel::parse(c)).collect(),
+        patient_id: get_s(item, "patientId"),
+        status: BookingStatus::parse(&get_s(item, "status")?)?,
+        created_at: get_s(item, "createdAt")?,
+    })
+}
+
+/// `BOOKING#id` / `STATUS#<at>#<to>`: ordenados por fecha bajo la propia reserva.
+fn status_change_to_item(change: &StatusChange) -> Item {
+    let mut item = Key::new(
+        format!("BOOKING#{}", change.booking_id),
+        format!("STATUS#{}#{}", change.at, change.to.as_str()),
+    )
+    .to_item();
+    item.extend([
+        ("bookingId".to_string(), s(&change.booking_id)),
+        ("from".to_string(), s(change.from.as_str())),
+        ("to".to_string(), s(change.to.as_str())),
+        ("actor".to_string(), s(&change.actor)),
+        ("at".to_string(), s(&change.at)),
+    ]);
+    if let Some(reason) = &change.reason {
+        item.insert("reason".to_string(), s(reason));
+    }
+    item
+}
+
+fn status_change_from_item(item: &Item) -> Option<StatusChange> {
+    Some(StatusChange {
+        booking_id: get_s(item, "bookingId")?,
+        from: BookingStatus::parse(&get_s(item, "from")?)?,
+        to: BookingStatus::parse(&get_s(item, "to")?)?,
+        actor: get_s(item, "actor").unwrap_or_default(),
+        reason: get_s(item, "reason"),
+        at: get_s(item, "at")?,
+    })
+}
+
+fn slot_lock_to_item(lock: &SlotLock) -> Item {
+    let mut item = slot_lock_key(lock).to_item();
+    item.extend([
+        ("siteId".to_string(), s(&lock.site_id)),
+        ("bookingId".to_string(), s(&lock.booking_id)),
+        ("status".to_string(), s(&lock.status)),
+        ("createdAt".to_string(), s(&lock.created_at)),
+    ]);
+    if let Some(token) = &lock.hold_token {
+        item.insert("holdToken".to_string(), s(token));
+    }
+    if let Some(expires_at) = lock.expires_at {
+        item.insert("expiresAt".to_string(), n(expires_at));
+    }
+    item
+}
+
+/// Reconstruye el lock desde sus claves: `TENANT#t#PROF#p#DATE#d` / `SLOT#HH:MM`.
+fn slot_lock_from_item(item: &Item) -> Option<SlotLock> {
+    let pk = get_s(item, "PK")?;
+    let sk = get_s(item, "SK")?;
+    let rest = pk.strip_prefix("TENANT#")?;
+    let (tenant_id, rest) = rest.split_once("#PROF#")?;
+    let (professional_id, date) = rest.split_once("#DATE#")?;
+    let time = sk.strip_prefix("SLOT#")?;
+    Some(SlotLock {
+        tenant_id: tenant_id.to_string(),
+        site_id: get_s(item, "siteId").unwrap_or_default(),
+        date: date.to_string(),
+        time: time.to_string(),
+        professional_id: professional_id.to_string(),
+        booking_id: get_s(item, "bookingId").unwrap_or_default(),
+        status: get_s(item, "status").unwrap_or_default(),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+        hold_token: get_s(item, "holdToken"),
+        expires_at: get_n(item, "expiresAt"),
+    })
+}
+
+fn legacy_slot_lock_from_item(item: &Item) -> Option<SlotLock> {
+    let pk = get_s(item, "PK")?;
+    let sk = get_s(item, "SK")?;
+    let rest = pk.strip_prefix("TENANT#")?;
+    let (tenant_id, rest) = rest.split_once("#SITE#")?;
+    let (site_id, date) = rest.split_once("#DATE#")?;
+    let (time, professional_id) = sk.strip_prefix("SLOT#")?.split_once('#')?;
+    Some(SlotLock {
+        tenant_id: tenant_id.to_string(),
+        site_id: site_id.to_string(),
+        date: date.to_string(),
+        time: time.to_string(),
+        professional_id: professional_id.to_string(),
+        booking_id: get_s(item, "bookingId").unwrap_or_default(),
+        status: get_s(item, "status").unwrap_or_default(),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+        hold_token: get_s(item, "holdToken"),
+        expires_at: get_n(item, "expiresAt"),
+    })
+}
+
+fn hold_key(id: &str) -> Key {
+    Key::new(format!("HOLD#{}", id), "METADATA")
+}
+
+fn hold_to_item(hold: &SlotHold) -> Item {
+    let mut item = hold_key(&hold.id).to_item();
+    item.extend([
+        ("id".to_string(), s(&hold.id)),
+        ("tenantId".to_string(), s(&hold.tenant_id)),
+        ("siteId".to_string(), s(&hold.site_id)),
+        ("professionalId".to_string(), s(&hold.professional_id)),
+        ("treatmentId".to_string(), s(&hold.treatment_id)),
+        ("startTime".to_string(), s(&hold.start_time)),
+        ("endTime".to_string(), s(&hold.end_time)),
+        ("holdToken".to_string(), s(&hold.token)),
+        ("expiresAt".to_string(), n(hold.expires_at)),
+        ("createdBy".to_string(), s(&hold.created_by)),
+        ("createdAt".to_string(), s(&hold.created_at)),
+    ]);
+    item
+}
+
+fn hold_from_item(item: &Item) -> Option<SlotHold> {
+    Some(SlotHold {
+        id: get_s(item, "id")?,
+        tenant_id: get_s(item, "tenantId")?,
+        site_id: get_s(item, "siteId")?,
+        professional_id: get_s(item, "professionalId")?,
+        treatment_id: get_s(item, "treatmentId")?,
+        start_time: get_s(item, "startTime")?,
+        end_time: get_s(item, "endTime")?,
+        token: get_s(item, "holdToken")?,
+        expires_at: get_n(item, "expiresAt")?,
+        created_by: get_s(item, "createdBy").unwrap_or_default(),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+    })
+}
+
+/// Reclama la unidad si está libre o si solo la ocupa una retención vencida
+/// (el TTL de DynamoDB borra con retraso). Los locks `reserved` no vencen.
+fn claim(lock: &SlotLock) -> WriteOp {
+    let now = chrono::Utc::now().timestamp();
+    WriteOp::put_if(
+        slot_lock_to_item(lock),
+        Condition::Or(vec![Condition::item_not_exists(), Condition::Lt("expiresAt".into(), n(now))]),
+    )
+}
+
+/// Solo libera locks propios (o ya ausentes): nunca borra la unidad de otra reserva.
+fn release(lock: &SlotLock) -> WriteOp {
+    WriteOp::Delete {
+        key: slot_lock_key(lock),
+        condition: Some(Condition::Or(vec![
+            Condition::item_not_exists(),
+            Condition::eq("bookingId", s(&lock.booking_id)),
+        ])),
+    }
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> BookingRepository for S {
+    async fn create_booking(&self, booking: &Booking, new_patient: Option<&Patient>, locks: &[SlotLock], event: &BookingEvent) -> Result<(), StoreError> {
+        let mut ops: Vec<WriteOp> = locks.iter().map(claim).collect();
+        ops.extend(new_patient.map(new_patient_ops).unwrap_or_default());
+        ops.push(WriteOp::put(booking_to_item(booking)));
+        ops.push(WriteOp::put(event_to_item(event)));
+        self.transact(ops).await
+    }
+
+    async fn get_booking(&self, id: &str) -> Result<Option<Booking>, StoreError> {
+        Ok(self.get(&booking_key(id)).await?.as_ref().and_then(booking_from_item))
+    }
+
+    async fn list_bookings(&self, tenant_id: &str, filter: &BookingFilter, page: &PageRequest) -> Result<BookingPage, StoreError> {
+        let mut conditions = vec![];
+        let query = match &filter.professional_id {
+            Some(professional_id) => {
+                // GSI3 no está particionado por tenant y también indexa el profesional
+                conditions.push(Condition::eq("tenantId", s(tenant_id)));
+                conditions.push(Condition::AttributeExists("startTime".into()));
+                let query = Query::on_index(Index::Gsi3, format!("PROFESSIONAL#{}", professional_id));
+                match &filter.start_between {
+                    Some((from, to)) => query.between(from, to),
+                    None => query,
+                }
+            }
+            None => {
+                if let Some((from, to)) = &filter.start_between {
+                    conditions.push(Condition::Between("startTime".into(), s(from), s(to)));
+                }
+                Query::on_index(Index::Gsi1, format!("TENANT#{}", tenant_id)).begins_with("BOOKING#")
+            }
+        };
+        if let Some(email) = &filter.patient_email {
+            conditions.push(Condition::eq("patientEmail", s(email)));
+        }
+        if let Some(patient_id) = &filter.patient_id {
+            conditions.push(Condition::eq("patientId", s(patient_id)));
+        }
+        if let Some(site_id) = &filter.site_id {
+            conditions.push(Condition::eq("siteId", s(site_id)));
+        }
+        if let Some(status) = filter.status {
+            conditions.push(Condition::eq("status", s(status.as_str())));
+        }
+        let query = match conditions.len() {
+            0 => query,
+            1 => query.filter(conditions.remove(0)),
+            _ => query.filter(Condition::And(conditions)),
+        };
+
+        let (items, next_cursor) = paginate(self, query, page).await?;
+        Ok(BookingPage { bookings: items.iter().filter_map(booking_from_item).collect(), next_cursor })
+    }
+
+    async fn transition_booking(&self, change: &StatusChange, release_locks: &[SlotLock], event: &BookingEvent) -> Result<(), StoreError> {
+        let mut set = vec![
+            ("status".into(), s(change.to.as_str())),
+            ("statusChangedAt".into(), s(&change.at)),
+            ("statusChangedBy".into(), s(&change.actor)),
+        ];
+        let mut remove = vec![];
+        match &change.reason {
+            Some(reason) => set.push(("statusReason".into(), s(reason))),
+            None => remove.push("statusReason".into()),
+        }
+        let mut ops = vec![
+            WriteOp::Update {
+                key: booking_key(&change.booking_id),
+                set,
+                remove,
+                condition: Some(Condition::And(vec![
+                    Condition::item_exists(),
+                    Condition::eq("status", s(change.from.as_str())),
+                ])),
+            },
+            WriteOp::put(status_change_to_item(change)),
+            WriteOp::put(event_to_item(event)),
+        ];
+        ops.extend(release_locks.iter().map(release));
+        self.transact(ops).await
+    }
+
+    async fn list_status_changes(&self, booking_id: &str) -> Result<Vec<StatusChange>, StoreError> {
+        let query = Query::partition(format!("BOOKING#{}", booking_id)).begins_with("STATUS#");
+        Ok(self.query_all(&query).await?.iter().filter_map(status_change_from_item).collect())
+    }
+
+    async fn reschedule_booking(
+        &self,
+        booking: &Booking,
+        old_locks: &[SlotLock],
+        new_locks: &[SlotLock],
+        now: &str,
+        event: &BookingEvent,
+    ) -> Result<(), StoreError> {
+        // DynamoDB no admite dos operaciones sobre el mismo item en una transacción:
+        // los locks presentes en ambos conjuntos se conservan tal cual.
+        let old_keys: HashSet<Key> = old_locks.iter().map(slot_lock_key).collect();
+        let new_keys: HashSet<Key> = new_locks.iter().map(slot_lock_key).collect();
+
+        let mut ops: Vec<WriteOp> = old_locks.iter().filter(|l| !new_keys.contains(&slot_lock_key(l))).map(release).collect();
+        ops.extend(new_locks.iter().filter(|l| !old_keys.contains(&slot_lock_key(l))).map(claim));
+        ops.push(WriteOp::Update {
+            key: booking_key(&booking.id),
+            set: vec![
+                ("startTime".into(), s(&booking.start_time)),
+                ("endTime".into(), s(&booking.end_time)),
+                ("GSI3SK".into(), s(&booking.start_time)),
+                ("updatedAt".into(), s(now)),
+            ],
+            remove: vec![],
+            condition: Some(Condition::And(vec![
+                Condition::item_exists(),
+                Condition::eq("status", s(booking.status.as_str())),
+            ])),
+        });
+        ops.push(WriteOp::put(event_to_item(event)));
+        self.transact(ops).await
+    }
+
+    async fn link_patient(&self, booking_id: &str, patient_id: &str) -> Result<(), StoreError> {
+        self.write(WriteOp::Update {
+            key: booking_key(booking_id),
+            set: vec![("patientId".into(), s(patient_id))],
+            remove: vec![],
+            condition: Some(Condition::And(vec![Condition::item_exists(), Condition::AttributeNotExists("patientId".into())])),
+        })
+        .await
+    }
+
+    async fn create_hold(&self, hold: &SlotHold, locks: &[SlotLock]) -> Result<(), StoreError> {
+        let mut ops: Vec<WriteOp> = locks.iter().map(claim).collect();
+        ops.push(WriteOp::put_if(hold_to_item(hold), Condition::item_not_exists()));
+        self.transact(ops).await
+    }
+
+    async fn get_hold(&self, id: &str) -> Result<Option<SlotHold>, StoreError> {
+        Ok(self.get(&hold_key(id)).await?.as_ref().and_then(hold_from_item))
+    }
+
+    async fn confirm_hold(
+        &self,
+        hold: &SlotHold,
+        booking: &Booking,
+        new_patient: Option<&Patient>,
+        locks: &[SlotLock],
+        now: i64,
+        event: &BookingEvent,
+    ) -> Result<(), StoreError> {
+        let mut ops: Vec<WriteOp> = locks
+            .iter()
+            .map(|lock| WriteOp::Update {
+                key: slot_lock_key(lock),
+                set: vec![("status".into(), s("reserved")), ("bookingId".into(), s(&booking.id))],
+                remove: vec!["holdToken".into(), "expiresAt".into()],
+                condition: Some(Condition::And(vec![
+                    Condition::eq("bookingId", s(&hold.id)),
+                    Condition::eq("holdToken", s(&hold.token)),
+                ])),
+            })
+            .collect();
+        ops.push(WriteOp::Delete {
+            key: hold_key(&hold.id),
+            condition: Some(Condition::And(vec![
+                Condition::eq("holdToken", s(&hold.token)),
+                Condition::Gt("expiresAt".into(), n(now)),
+            ])),
+        });
+        ops.extend(new_patient.map(new_patient_ops).unwrap_or_default());
+        ops.push(WriteOp::put_if(booking_to_item(booking), Condition::item_not_exists()));
+        ops.push(WriteOp::put(event_to_item(event)));
+        self.transact(ops).await
+    }
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> SlotLockRepository for S {
+    async fn list_slot_locks(&self, tenant_id: &str, professional_id: &str, date: &str) -> Result<Vec<SlotLock>, StoreError> {
+        let query = Query::partition(slot_lock_partition(tenant_id, professional_id, date)).begins_with("SLOT#");
+        Ok(self.query_all(&query).await?.iter().filter_map(slot_lock_from_item).collect())
+    }
+
+    async fn list_legacy_slot_locks(&self, tenant_id: &str, site_id: &str, date: &str) -> Result<Vec<SlotLock>, StoreError> {
+        let query = Query::partition(format!("TENANT#{}#SITE#{}#DATE#{}", tenant_id, site_id, date)).begins_with("SLOT#");
+        Ok(self.query_all(&query).await?.iter().filter_map(legacy_slot_lock_from_item).collect())
+    }
+
+    async fn migrate_slot_locks(&self, legacy: &[SlotLock], locks: &[SlotLock]) -> Result<(), StoreError> {
+        let now = chrono::Utc::now().timestamp();
+        let claims: Vec<WriteOp> = locks
+            .iter()
+            .map(|lock| {
+                WriteOp::put_if(
+                    slot_lock_to_item(lock),
+                    Condition::Or(vec![
+                        Condition::item_not_exists(),
+                        Condition::eq("bookingId", s(&lock.booking_id)),
+                        Condition::Lt("expiresAt".into(), n(now)),
+                    ]),
+                )
+            })
+            .collect();
+        if !claims.is_empty() {
+            self.transact(claims).await?;
+        }
+        // Aparte: locks nuevos y viejos juntos pueden pasar de 100 items
+        let deletes: Vec<WriteOp> = legacy
+            .iter()
+            .map(|lock| WriteOp::Delete {
+                key: legacy_slot_lock_key(lock),
+                condition: Some(Condition::Or(vec![
+                    Condition::item_not_exists(),
+                    Condition::eq("bookingId", s(&lock.booking_id)),
+                ])),
+            })
+            .collect();
+        if deletes.is_empty() {
+            return Ok(());
+        }
+        self.transact(deletes).await
+    }
+}
@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+
+use super::store::{get_list_s, get_n, get_s, n, s, Item, ItemStore, Key, Query, StoreError, WriteOp};
+use crate::models::{Professional, Site, Tenant, Treatment};
+use crate::schedule::WeeklySchedule;
+
+#[async_trait]
+pub trait TenantRepository {
+    async fn put_tenant(&self, tenant: &Tenant) -> Result<(), StoreError>;
+    async fn get_tenant(&self, id: &str) -> Result<Option<Tenant>, StoreError>;
+}
+
+#[async_trait]
+pub trait SiteRepository {
+    async fn put_site(&self, site: &Site) -> Result<(), StoreError>;
+    async fn get_site(&self, tenant_id: &str, id: &str) -> Result<Option<Site>, StoreError>;
+    async fn list_sites(&self, tenant_id: &str) -> Result<Vec<Site>, StoreError>;
+}
+
+#[async_trait]
+pub trait TreatmentRepository {
+    async fn put_treatment(&self, treatment: &Treatment) -> Result<(), StoreError>;
+    async fn get_treatment(&self, tenant_id: &str, id: &str) -> Result<Option<Treatment>, StoreError>;
+    async fn list_treatments(&self, tenant_id: &str) -> Result<Vec<Treatment>, StoreError>;
+}
+
+#[async_trait]
+pub trait ProfessionalRepository {
+    async fn put_professional(&self, professional: &Professional) -> Result<(), StoreError>;
+    async fn get_professional(&self, tenant_id: &str, id: &str) -> Result<Option<Professional>, StoreError>;
+    async fn list_professionals(&self, tenant_id: &str) -> Result<Vec<Professional>, StoreError>;
+}
+
+fn tenant_key(id: &str) -> Key {
+    Key::new(format!("TENANT#{}", id), "METADATA")
+}
+
+fn tenant_to_item(t: &Tenant) -> Item {
+    let mut item = tenant_key(&t.id).to_item();
+    item.extend([
+        ("GSI1PK".to_string(), s("TENANT")),
+        ("GSI1SK".to_string(), s(format!("TENANT#{}", t.id))),
+        ("id".to_string(), s(&t.id)),
+        ("name".to_string(), s(&t.name)),
+        ("contactEmail".to_string(), s(&t.contact_email)),
+        ("timezone".to_string(), s(&t.timezone)),
+        ("createdAt".to_string(), s(&t.created_at)),
+        ("status".to_string(), s(&t.status)),
+        ("settings".to_string(), s(serde_json::to_string(&t.settings).unwrap_or_default())),
+    ]);
+    item
+}
+
+fn tenant_from_item(item: &Item) -> Tenant {
+    Tenant {
+        id: get_s(item, "id").unwrap_or_default(),
+        name: get_s(item, "name").unwrap_or_default(),
+        contact_email: get_s(item, "contactEmail").unwrap_or_default(),
+        timezone: get_s(item, "timezone").unwrap_or_else(|| "UTC".into()),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+        status: get_s(item, "status").unwrap_or_default(),
+        settings: get_s(item, "settings").and_then(|raw| serde_json::from_str(&raw).ok()).unwrap_or_default(),
+    }
+}
+
+fn site_key(tenant_id: &str, id: &str) -> Key {
+    Key::new(format!("TENANT#{}", tenant_id), format!("SITE#{}", id))
+}
+
+fn site_to_item(site: &Site) -> Item {
+    let mut item = site_key(&site.tenant_id, &site.id).to_item();
+    item.extend([
+        ("GSI1PK".to_string(), s(format!("TENANT#{}", site.tenant_id))),
+        ("GSI1SK".to_string(), s(format!("SITE#{}", site.id))),
+        ("id".to_string(), s(&site.id)),
+        ("tenantId".to_string(), s(&site.tenant_id)),
+        ("name".to_string(), s(&site.name)),
+        ("address".to_string(), s(&site.address)),
+        ("openingHours".to_string(), s(serde_json::to_string(&site.opening_hours).unwrap_or_default())),
+        ("chairs".to_string(), n(site.chairs)),
+        ("status".to_string(), s(&site.status)),
+        ("createdAt".to_string(), s(&site.created_at)),
+    ]);
+    if let Some(tz) = &site.timezone {
+        item.insert("timezone".to_string(), s(tz));
+    }
+    if let Some(email) = &site.contact_email {
+        item.insert("contactEmail".to_string(), s(email));
+    }
+    item
+}
+
+fn site_from_item(item: &Item) -> Site {
+    Site {
+        id: get_s(item, "id").unwrap_or_default(),
+        tenant_id: get_s(item, "tenantId").unwrap_or_default(),
+        name: get_s(item, "name").unwrap_or_default(),
+        address: get_s(item, "address").unwrap_or_default(),
+        timezone: get_s(item, "timezone"),
+        opening_hours: get_s(item, "openingHours").and_then(|raw| serde_json::from_str(&raw).ok()).unwrap_or_default(),
+        chairs: get_n(item, "chairs").unwrap_or(1),
+        contact_email: get_s(item, "contactEmail"),
+        status: get_s(item, "status").unwrap_or_default(),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+    }
+}
+
+fn treatment_key(tenant_id: &str, id: &str) -> Key {
+    Key::new(format!("TENANT#{}", tenant_id), format!("TREATMENT#{}", id))
+}
+
+fn treatment_to_item(t: &Treatment) -> Item {
+    let mut item = treatment_key(&t.tenant_id, &t.id).to_item();
+    item.extend([
+        ("GSI1PK".to_string(), s(format!("TENANT#{}", t.tenant_id))),
+        ("GSI1SK".to_string(), s(format!("TREATMENT#{}", t.id))),
+        ("id".to_string(), s(&t.id)),
+        ("tenantId".to_string(), s(&t.tenant_id)),
+        ("name".to_string(), s(&t.name)),
+        ("durationMinutes".to_string(), n(t.duration_minutes)),
+        ("bufferMinutes".to_string(), n(t.buffer_minutes)),
+        ("price".to_string(), n(t.price)),
+        ("createdAt".to_string(), s(&t.created_at)),
+    ]);
+    item
+}
+
+fn treatment_from_item(item: &Item) -> Treatment {
+    Treatment {
+        id: get_s(item, "id").unwrap_or_default(),
+        tenant_id: get_s(item, "tenantId").unwrap_or_default(),
+        name: get_s(item, "name").unwrap_or_default(),
+        duration_minutes: get_n(item, "durationMinutes").unwrap_or(30),
+        buffer_minutes: get_n(item, "bufferMinutes").unwrap_or(0),
+        price: get_n(item, "price").unwrap_or(0.0),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+    }
+}
+
+fn professional_key(tenant_id: &str, id: &str) -> Key {
+    Key::new(format!("TENANT#{}", tenant_id), format!("PROFESSIONAL#{}", id))
+}
+
+fn professional_to_item(p: &Professional) -> Item {
+    let mut item = professional_key(&p.tenant_id, &p.id).to_item();
+    item.extend([
+        ("GSI1PK".to_string(), s(format!("TENANT#{}", p.tenant_id))),
+        ("GSI1SK".to_string(), s(format!("PROFESSIONAL#{}", p.id))),
+        ("GSI3PK".to_string(), s(format!("PROFESSIONAL#{}", p.id))),
+        ("GSI3SK".to_string(), s("METADATA")),
+        ("id".to_string(), s(&p.id)),
+        ("tenantId".to_string(), s(&p.tenant_id)),
+        ("name".to_string(), s(&p.name)),
+        ("email".to_string(), s(&p.email)),
+        ("specialties".to_string(), AttributeValue::L(p.specialties.iter().map(s).collect())),
+        ("siteIds".to_string(), AttributeValue::L(p.site_ids.iter().map(s).collect())),
+        ("schedule".to_string(), s(serde_json::to_string(&p.schedule).unwrap_or_default())),
+        ("status".to_string(), s(&p.status)),
+        ("createdAt".to_string(), s(&p.created_at)),
+    ]);
+    item
+}
+
+fn professional_from_item(item: &Item) -> Professional {
+    // Registros antiguos guardaban "{}" u otro JSON libre: se leen como horario vacío
+    let schedule: WeeklySchedule = get_s(item, "schedule").and_then(|raw| serde_json::from_str(&raw).ok()).unwrap_or_default();
+    // Sin `siteIds` (anteriores a las sedes) se asume asignado a las sedes de su horario
+    let site_ids = match item.contains_key("siteIds") {
+        true => get_list_s(item, "siteIds"),
+        false => schedule.site_ids().into_iter().map(str::to_string).collect(),
+    };
+    Professional {
+        id: get_s(item, "id").unwrap_or_default(),
+        tenant_id: get_s(item, "tenantId").unwrap_or_default(),
+        name: get_s(item, "name").unwrap_or_default(),
+        email: get_s(item, "email").unwrap_or_default(),
+        specialties: get_list_s(item, "specialties"),
+        site_ids,
+        schedule,
+        status: get_s(item, "status").unwrap_or_default(),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+    }
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> TenantRepository for S {
+    async fn put_tenant(&self, tenant: &Tenant) -> Result<(), StoreError> {
+        self.write(WriteOp::put(tenant_to_item(tenant))).await
+    }
+
+    async fn get_tenant(&self, id: &str) -> Result<Option<Tenant>, StoreError> {
+        Ok(self.get(&tenant_key(id)).await?.as_ref().map(tenant_from_item))
+    }
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> SiteRepository for S {
+    async fn put_site(&self, site: &Site) -> Result<(), StoreError> {
+        self.write(WriteOp::put(site_to_item(site))).await
+    }
+
+    async fn get_site(&self, tenant_id: &str, id: &str) -> Result<Option<Site>, StoreError> {
+        Ok(self.get(&site_key(tenant_id, id)).await?.as_ref().map(site_from_item))
+    }
+
+    async fn list_sites(&self, tenant_id: &str) -> Result<Vec<Site>, StoreError> {
+        let query = Query::partition(format!("TENANT#{}", tenant_id)).begins_with("SITE#");
+        Ok(self.query_all(&query).await?.iter().map(site_from_item).collect())
+    }
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> TreatmentRepository for S {
+    async fn put_treatment(&self, treatment: &Treatment) -> Result<(), StoreError> {
+        self.write(WriteOp::put(treatment_to_item(treatment))).await
+    }
+
+    async fn get_treatment(&self, tenant_id: &str, id: &str) -> Result<Option<Treatment>, StoreError> {
+        Ok(self.get(&treatment_key(tenant_id, id)).await?.as_ref().map(treatment_from_item))
+    }
+
+    async fn list_treatments(&self, tenant_id: &str) -> Result<Vec<Treatment>, StoreError> {
+        let query = Query::partition(format!("TENANT#{}", tenant_id)).begins_with("TREATMENT#");
+        Ok(self.query_all(&query).await?.iter().map(treatment_from_item).collect())
+    }
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> ProfessionalRepository for S {
+    async fn put_professional(&self, professional: &Professional) -> Result<(), StoreError> {
+        self.write(WriteOp::put(professional_to_item(professional))).await
+    }
+
+    async fn get_professional(&self, tenant_id: &str, id: &str) -> Result<Option<Professional>, StoreError> {
+        Ok(self.get(&professional_key(tenant_id, id)).await?.as_ref().map(professional_from_item))
+    }
+
+    async fn list_professionals(&self, tenant_id: &str) -> Result<Vec<Professional>, StoreError> {
+        let query = Query::partition(format!("TENANT#{}", tenant_id)).begins_with("PROFESSIONAL#");
+        Ok(self.query_all(&query).await?.iter().map(professional_from_item).collect())
+    }
+}
+
@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+
+use super::store::{get_s, s, Condition, Item, ItemStore, Key, Query, StoreError, WriteOp};
+use crate::models::{contact_address, Channel, ContactPreferences, Locale, Suppression, SuppressionReason};
+
+#[async_trait]
+pub trait ContactRepository {
+    async fn get_preferences(&self, tenant_id: &str, email: &str) -> Result<Option<ContactPreferences>, StoreError>;
+    async fn put_preferences(&self, preferences: &ContactPreferences) -> Result<(), StoreError>;
+
+    async fn get_suppression(&self, tenant_id: &str, channel: Channel, address: &str) -> Result<Option<Suppression>, StoreError>;
+    async fn list_suppressions(&self, tenant_id: &str) -> Result<Vec<Suppression>, StoreError>;
+    /// Agrega la dirección a la lista; si ya estaba, queda el último motivo.
+    async fn put_suppression(&self, suppression: &Suppression) -> Result<(), StoreError>;
+    /// Falla con `ConditionFailed` si la dirección no estaba en la lista.
+    async fn delete_suppression(&self, tenant_id: &str, channel: Channel, address: &str) -> Result<(), StoreError>;
+}
+
+/// `TENANT#id` / `PREFERENCES#<email>`
+fn preferences_key(tenant_id: &str, email: &str) -> Key {
+    Key::new(format!("TENANT#{}", tenant_id), format!("PREFERENCES#{}", contact_address(Channel::Email, email)))
+}
+
+/// `TENANT#id` / `SUPPRESSION#<canal>#<dirección>`
+fn suppression_key(tenant_id: &str, channel: Channel, address: &str) -> Key {
+    Key::new(
+        format!("TENANT#{}", tenant_id),
+        format!("SUPPRESSION#{}#{}", channel.as_str(), contact_address(channel, address)),
+    )
+}
+
+fn preferences_to_item(preferences: &ContactPreferences) -> Item {
+    let mut item = preferences_key(&preferences.tenant_id, &preferences.email).to_item();
+    item.extend([
+        ("tenantId".to_string(), s(&preferences.tenant_id)),
+        ("email".to_string(), s(contact_address(Channel::Email, &preferences.email))),
+        ("preferences".to_string(), s(serde_json::to_string(&preferences.preferences).unwrap_or_default())),
+        ("updatedAt".to_string(), s(&preferences.updated_at)),
+    ]);
+    if let Some(locale) = preferences.locale {
+        item.insert("locale".to_string(), s(locale.as_str()));
+    }
+    item
+}
+
+fn preferences_from_item(item: &Item) -> Option<ContactPreferences> {
+    Some(ContactPreferences {
+        tenant_id: get_s(item, "tenantId")?,
+        email: get_s(item, "email")?,
+        preferences: serde_json::from_str(&get_s(item, "preferences")?).ok()?,
+        locale: get_s(item, "locale").as_deref().and_then(Locale::parse),
+        updated_at: get_s(item, "updatedAt").unwrap_or_default(),
+    })
+}
+
+fn suppression_to_item(suppression: &Suppression) -> Item {
+    let mut item = suppression_key(&suppression.tenant_id, suppression.channel, &suppression.address).to_item();
+    item.extend([
+        ("tenantId".to_string(), s(&suppression.tenant_id)),
+        ("channel".to_string(), s(suppression.channel.as_str())),
+        ("address".to_string(), s(contact_address(suppression.channel, &suppression.address))),
+        ("reason".to_string(), s(suppression.reason.as_str())),
+        ("createdAt".to_string(), s(&suppression.created_at)),
+    ]);
+    if let Some(detail) = &suppression.detail {
+        item.insert("detail".to_string(), s(detail));
+    }
+    item
+}
+
+fn suppression_from_item(item: &Item) -> Option<Suppression> {
+    Some(Suppression {
+        tenant_id: get_s(item, "tenantId")?,
+        channel: Channel::parse(&get_s(item, "channel")?)?,
+        address: get_s(item, "address")?,
+        reason: SuppressionReason::parse(&get_s(item, "reason")?)?,
+        detail: get_s(item, "detail"),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+    })
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> ContactRepository for S {
+    async fn get_preferences(&self, tenant_id: &str, email: &str) -> Result<Option<ContactPreferences>, StoreError> {
+        Ok(self.get(&preferences_key(tenant_id, email)).await?.as_ref().and_then(preferences_from_item))
+    }
+
+    async fn put_preferences(&self, preferences: &ContactPreferences) -> Result<(), StoreError> {
+        self.write(WriteOp::put(preferences_to_item(preferences))).await
+    }
+
+    async fn get_suppression(&self, tenant_id: &str, channel: Channel, address: &str) -> Result<Option<Suppression>, StoreError> {
+        Ok(self.get(&suppression_key(tenant_id, channel, address)).await?.as_ref().and_then(suppression_from_item))
+    }
+
+    async fn list_suppressions(&self, tenant_id: &str) -> Result<Vec<Suppression>, StoreError> {
+        let query = Query::partition(format!("TENANT#{}", tenant_id)).begins_with("SUPPRESSION#");
+        Ok(self.query_all(&query).await?.iter().filter_map(suppression_from_item).collect())
+    }
+
+    async fn put_suppression(&self, suppression: &Suppression) -> Result<(), StoreError> {
+        self.write(WriteOp::put(suppression_to_item(suppression))).await
+    }
+
+    async fn delete_suppression(&self, tenant_id: &str, channel: Channel, address: &str) -> Result<(), StoreError> {
+        self.write(WriteOp::Delete {
+            key: suppression_key(tenant_id, channel, address),
+            condition: Some(Condition::item_exists()),
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MessageCategory;
+    use crate::repository::MemoryStore;
+
+    #[tokio::test]
+    async fn test_preferences_and_suppressions_are_per_tenant_and_case_insensitive() {
+        let store = MemoryStore::new();
+        let mut preferences = ContactPreferences::new("tenant-a", "Ana@Example.com");
+        preferences.set(Channel::Sms, MessageCategory::Transactional, false);
+        preferences.locale = Some(Locale::Pt);
+        store.put_preferences(&preferences).await.unwrap();
+
+        let stored = store.get_preferences("tenant-a", "ana@example.com ").await.unwrap().unwrap();
+        assert_eq!(stored.locale, Some(Locale::Pt));
+        assert!(!stored.allows(Channel::Sms, MessageCategory::Transactional));
+        assert!(stored.allows(Channel::Email, MessageCategory::Transactional));
+        assert!(!stored.allows(Channel::Email, MessageCategory::Marketing));
+        assert!(store.get_preferences("tenant-b", "ana@example.com").await.unwrap().is_none());
+
+        store.put_suppression(&Suppression {
+            tenant_id: "tenant-a".into(),
+            channel: Channel::Email,
+            address: "Ana@Example.com".into(),
+            reason: SuppressionReason::Bounce,
+            detail: Some("Permanent/NoEmail".into()),
+            created_at: "2025-10-01T10:00:00Z".into(),
+        }).await.unwrap();
+        assert!(store.get_suppression("tenant-a", Channel::Email, "ana@example.com").await.unwrap().is_some());
+        assert!(store.get_suppression("tenant-b", Channel::Email, "ana@example.com").await.unwrap().is_none());
+        assert_eq!(store.list_suppressions("tenant-a").await.unwrap().len(), 1);
+
+        store.delete_suppression("tenant-a", Channel::Email, "ANA@example.com").await.unwrap();
+        assert!(matches!(
+            store.delete_suppression("tenant-a", Channel::Email, "ana@example.com").await,
+            Err(StoreError::ConditionFailed)
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+//! Paginación por cursor. El cursor es el `LastEvaluatedKey` serializado como
+//! JSON y codificado en base64url: opaco para el cliente, que solo lo reenvía.
+
+use std::collections::HashMap;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use super::store::{get_s, s, Item, ItemStore, Query, StoreError};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Tamaño de página y cursor recibidos del cliente.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRequest {
+    pub limit: usize,
+    pub cursor: Option<String>,
+}
+
+impl PageRequest {
+    /// `limit` se acota a `1..=MAX_PAGE_SIZE` (por defecto `DEFAULT_PAGE_SIZE`).
+    pub fn new(limit: Option<usize>, cursor: Option<String>) -> Self {
+        PageRequest {
+            limit: limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+            cursor: cursor.filter(|c| !c.is_empty()),
+        }
+    }
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        PageRequest::new(None, None)
+    }
+}
+
+/// Las claves de la tabla y de los índices son siempre strings.
+pub fn encode_cursor(key: &Item) -> String {
+    let plain: HashMap<&str, String> = key.keys().filter_map(|k| Some((k.as_str(), get_s(key, k)?))).collect();
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&plain).unwrap_or_default())
+}
+
+/// Decodifica el cursor y comprueba que apunta a la misma partición que `query`,
+/// para que no sirva para leer fuera de ella.
+pub fn decode_cursor(token: &str, query: &Query) -> Result<Item, StoreError> {
+    let plain: HashMap<String, String> = URL_SAFE_NO_PAD
+        .decode(token)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or(StoreError::InvalidCursor)?;
+    let pk_attr = query.index.map(|i| i.key_attrs().0).unwrap_or("PK");
+    if plain.get(pk_attr) != Some(&query.pk) {
+        return Err(StoreError::InvalidCursor);
+    }
+    Ok(plain.into_iter().map(|(k, v)| (k, s(v))).collect())
+}
+
+/// Una página de `query` según `page`; devuelve los items y el cursor siguiente.
+pub async fn paginate<S: ItemStore + ?Sized>(store: &S, query: Query, page: &PageRequest) -> Result<(Vec<Item>, Option<String>), StoreError> {
+    let mut query = query;
+    query.limit.get_or_insert(page.limit as i32);
+    if let Some(cursor) = &page.cursor {
+        query.start_key = Some(decode_cursor(cursor, &query)?);
+    }
+    let result = store.query_limited(&query, page.limit).await?;
+    Ok((result.items, result.last_key.as_ref().map(encode_cursor)))
+}
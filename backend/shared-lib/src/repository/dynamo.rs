@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::error::ProvideErrorMetadata;
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::types::{AttributeValue, ConditionCheck, Delete, Put, TransactWriteItem, Update};
+use aws_sdk_dynamodb::Client;
+
+use super::store::{Condition, Item, ItemStore, Key, Page, Query, SortKey, StoreError, WriteOp};
+use crate::dynamodb::{get_client, table_name};
+
+/// Backend real sobre la tabla `TABLE_NAME`.
+pub struct DynamoStore {
+    table: String,
+}
+
+impl DynamoStore {
+    pub fn new(table: impl Into<String>) -> Self {
+        DynamoStore { table: table.into() }
+    }
+
+    pub fn from_env() -> Self {
+        DynamoStore::new(table_name())
+    }
+
+    async fn client(&self) -> &'static Client {
+        get_client().await
+    }
+}
+
+/// Traduce `Condition` a expresiones con placeholders (`#a0`, `:v0`), de modo
+/// que palabras reservadas como `status` o `name` no requieran tratamiento especial.
+#[derive(Default)]
+struct Expr {
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl Expr {
+    fn name(&mut self, attr: &str) -> String {
+        if let Some((placeholder, _)) = self.names.iter().find(|(_, a)| a.as_str() == attr) {
+            return placeholder.clone();
+        }
+        let placeholder = format!("#a{}", self.names.len());
+        self.names.insert(placeholder.clone(), attr.to_string());
+        placeholder
+    }
+
+    fn value(&mut self, value: &AttributeValue) -> String {
+        let placeholder = format!(":v{}", self.values.len());
+        self.values.insert(placeholder.clone(), value.clone());
+        placeholder
+    }
+
+    fn condition(&mut self, condition: &Condition) -> String {
+        let mut binary = |attr: &str, op: &str, value: &AttributeValue| {
+            let n = self.name(attr);
+            let v = self.value(value);
+            format!("{} {} {}", n, op, v)
+        };
+        match condition {
+            Condition::AttributeExists(a) => format!("attribute_exists({})", self.name(a)),
+            Condition::AttributeNotExists(a) => format!("attribute_not_exists({})", self.name(a)),
+            Condition::Eq(a, v) => binary(a, "=", v),
+            Condition::Ne(a, v) => binary(a, "<>", v),
+            Condition::Lt(a, v) => binary(a, "<", v),
+            Condition::Gt(a, v) => binary(a, ">", v),
+            Condition::Between(a, lo, hi) => {
+                let n = self.name(a);
+                let lo = self.value(lo);
+                let hi = self.value(hi);
+                format!("{} BETWEEN {} AND {}", n, lo, hi)
+            }
+            Condition::In(a, vs) => {
+                let n = self.name(a);
+                let placeholders: Vec<String> = vs.iter().map(|v| self.value(v)).collect();
+                format!("{} IN ({})", n, placeholders.join(", "))
+            }
+            Condition::And(cs) => self.join(cs, " AND "),
+            Condition::Or(cs) => self.join(cs, " OR "),
+        }
+    }
+
+    fn join(&mut self, conditions: &[Condition], sep: &str) -> String {
+        let parts: Vec<String> = conditions.iter().map(|c| format!("({})", self.condition(c))).collect();
+        parts.join(sep)
+    }
+
+    fn update(&mut self, set: &[(String, AttributeValue)], remove: &[String]) -> String {
+        let mut expr = String::new();
+        if !set.is_empty() {
+            let parts: Vec<String> = set
+                .iter()
+                .map(|(a, v)| {
+                    let n = self.name(a);
+                    format!("{} = {}", n, self.value(v))
+                })
+                .collect();
+            expr.push_str(&format!("SET {}", parts.join(", ")));
+        }
+        if !remove.is_empty() {
+            let parts: Vec<String> = remove.iter().map(|a| self.name(a)).collect();
+            if !expr.is_empty() {
+                expr.push(' ');
+            }
+            expr.push_str(&format!("REMOVE {}", parts.join(", ")));
+        }
+        expr
+    }
+
+    fn names(&self) -> Option<HashMap<String, String>> {
+        (!self.names.is_empty()).then(|| self.names.clone())
+    }
+
+    fn values(&self) -> Option<HashMap<String, AttributeValue>> {
+        (!self.values.is_empty()).then(|| self.values.clone())
+    }
+}
+
+fn backend<E: std::fmt::Display>(context: &str) -> impl FnOnce(E) -> StoreError + '_ {
+    move |e| StoreError::Backend(anyhow::anyhow!("DynamoDB {} error: {}", context, e))
+}
+
+fn map_write_error<E: ProvideErrorMetadata + std::fmt::Display>(err: E) -> StoreError {
+    if err.code() == Some("ConditionalCheckFailedException") {
+        StoreError::ConditionFailed
+    } else {
+        StoreError::Backend(anyhow::anyhow!("DynamoDB write error: {}", err))
+    }
+}
+
+impl DynamoStore {
+    fn transact_item(&self, op: WriteOp) -> Result<TransactWriteItem, StoreError> {
+        op.validate()?;
+        let build_err = |e: aws_sdk_dynamodb::error::BuildError| StoreError::Backend(anyhow::anyhow!("Build error: {}", e));
+        let mut expr = Expr::default();
+        let item = match op {
+            WriteOp::Put { item, condition } => {
+                let cond = condition.map(|c| expr.condition(&c));
+                let put = Put::builder()
+                    .table_name(&self.table)
+                    .set_item(Some(item))
+                    .set_condition_expression(cond)
+                    .set_expression_attribute_names(expr.names())
+                    .set_expression_attribute_values(expr.values())
+                    .build()
+                    .map_err(build_err)?;
+                TransactWriteItem::builder().put(put).build()
+            }
+            WriteOp::Update { key, set, remove, condition } => {
+                let update_expr = expr.update(&set, &remove);
+                let cond = condition.map(|c| expr.condition(&c));
+                let update = Update::builder()
+                    .table_name(&self.table)
+                    .set_key(Some(key.to_item()))
+                    .update_expression(update_expr)
+                    .set_condition_expression(cond)
+                    .set_expression_attribute_names(expr.names())
+                    .set_expression_attribute_values(expr.values())
+                    .build()
+                    .map_err(build_err)?;
+                TransactWriteItem::builder().update(update).build()
+            }
+            WriteOp::Delete { key, condition } => {
+                let cond = condition.map(|c| expr.condition(&c));
+                let delete = Delete::builder()
+                    .table_name(&self.table)
+                    .set_key(Some(key.to_item()))
+                    .set_condition_expression(cond)
+                    .set_expression_attribute_names(expr.names())
+                    .set_expression_attribute_values(expr.values())
+                    .build()
+                    .map_err(build_err)?;
+                TransactWriteItem::builder().delete(delete).build()
+            }
+            WriteOp::ConditionCheck { key, condition } => {
+                let cond = expr.condition(&condition);
+                let check = ConditionCheck::builder()
+                    .table_name(&self.table)
+                    .set_key(Some(key.to_item()))
+                    .condition_expression(cond)
+                    .set_expression_attribute_names(expr.names())
+                    .set_expression_attribute_values(expr.values())
+                    .build()
+                    .map_err(build_err)?;
+                TransactWriteItem::builder().condition_check(check).build()
+            }
+        };
+        Ok(item)
+    }
+}
+
+#[async_trait]
+impl ItemStore for DynamoStore {
+    async fn get(&self, key: &Key) -> Result<Option<Item>, StoreError> {
+        let result = self
+            .client()
+            .await
+            .get_item()
+            .table_name(&self.table)
+            .set_key(Some(key.to_item()))
+            .send()
+            .await
+            .map_err(backend("get"))?;
+        Ok(result.item)
+    }
+
+    async fn query(&self, query: &Query) -> Result<Page, StoreError> {
+        let (pk_attr, sk_attr) = match query.index {
+            Some(index) => index.key_attrs(),
+            None => ("PK", "SK"),
+        };
+        let mut expr = Expr::default();
+        let pk_name = expr.name(pk_attr);
+        let pk_value = expr.value(&AttributeValue::S(query.pk.clone()));
+        let mut key_condition = format!("{} = {}", pk_name, pk_value);
+        match &query.sk {
+            Some(SortKey::BeginsWith(prefix)) => {
+                let sk_name = expr.name(sk_attr);
+                let v = expr.value(&AttributeValue::S(prefix.clone()));
+                key_condition.push_str(&format!(" AND begins_with({}, {})", sk_name, v));
+            }
+            Some(SortKey::Between(from, to)) => {
+                let sk_name = expr.name(sk_attr);
+                let a = expr.value(&AttributeValue::S(from.clone()));
+                let b = expr.value(&AttributeValue::S(to.clone()));
+                key_condition.push_str(&format!(" AND {} BETWEEN {} AND {}", sk_name, a, b));
+            }
+            None => {}
+        }
+        let filter = query.filter.as_ref().map(|c| expr.condition(c));
+
+        let result = self
+            .client()
+            .await
+            .query()
+            .table_name(&self.table)
+            .set_index_name(query.index.map(|i| i.name().to_string()))
+            .key_condition_expression(key_condition)
+            .set_filter_expression(filter)
+            .set_expression_attribute_names(expr.names())
+            .set_expression_attribute_values(expr.values())
+            .set_limit(query.limit)
+            .set_exclusive_start_key(query.start_key.clone())
+            .scan_index_forward(!query.descending)
+            .send()
+            .await
+            .map_err(backend("query"))?;
+
+        Ok(Page { items: result.items.unwrap_or_default(), last_key: result.last_evaluated_key })
+    }
+
+    async fn write(&self, op: WriteOp) -> Result<(), StoreError> {
+        op.validate()?;
+        let client = self.client().await;
+        let mut expr = Expr::default();
+        match op {
+            WriteOp::Put { item, condition } => {
+                let cond = condition.map(|c| expr.condition(&c));
+                client
+                    .put_item()
+                    .table_name(&self.table)
+                    .set_item(Some(item))
+                    .set_condition_expression(cond)
+                    .set_expression_attribute_names(expr.names())
+                    .set_expression_attribute_values(expr.values())
+                    .send()
+                    .await
+                    .map_err(map_write_error)?;
+            }
+            WriteOp::Update { key, set, remove, condition } => {
+                let update_expr = expr.update(&set, &remove);
+                let cond = condition.map(|c| expr.condition(&c));
+                client
+                    .update_item()
+                    .table_name(&self.table)
+                    .set_key(Some(key.to_item()))
+                    .update_expression(update_expr)
+                    .set_condition_expression(cond)
+                    .set_expression_attribute_names(expr.names())
+                    .set_expression_attribute_values(expr.values())
+                    .send()
+                    .await
+                    .map_err(map_write_error)?;
+            }
+            WriteOp::Delete { key, condition } => {
+                let cond = condition.map(|c| expr.condition(&c));
+                client
+                    .delete_item()
+                    .table_name(&self.table)
+                    .set_key(Some(key.to_item()))
+                    .set_condition_expression(cond)
+                    .set_expression_attribute_names(expr.names())
+                    .set_expression_attribute_values(expr.values())
+                    .send()
+                    .await
+                    .map_err(map_write_error)?;
+            }
+            check @ WriteOp::ConditionCheck { .. } => self.transact(vec![check]).await?,
+        }
+        Ok(())
+    }
+
+    async fn transact(&self, ops: Vec<WriteOp>) -> Result<(), StoreError> {
+        let items = ops.into_iter().map(|op| self.transact_item(op)).collect::<Result<Vec<_>, _>>()?;
+        let result = self
+            .client()
+            .await
+            .transact_write_items()
+            .set_transact_items(Some(items))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                let condition_failed = matches!(
+                    err.as_service_error(),
+                    Some(TransactWriteItemsError::TransactionCanceledException(ex))
+                        if ex.cancellation_reasons().iter().any(|r| r.code() == Some("ConditionalCheckFailed"))
+                );
+                if condition_failed {
+                    Err(StoreError::ConditionFailed)
+                } else {
+                    Err(StoreError::Backend(anyhow::anyhow!("DynamoDB transaction error: {}", err)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_expression_uses_placeholders() {
+        let mut expr = Expr::default();
+        let cond = Condition::And(vec![
+            Condition::item_exists(),
+            Condition::ne("status", AttributeValue::S("cancelled".into())),
+        ]);
+        assert_eq!(expr.condition(&cond), "(attribute_exists(#a0)) AND (#a1 <> :v0)");
+        assert_eq!(expr.names.get("#a1").map(String::as_str), Some("status"));
+    }
+
+    #[test]
+    fn test_update_expression_reuses_names() {
+        let mut expr = Expr::default();
+        let update = expr.update(
+            &[("status".into(), AttributeValue::S("x".into())), ("updatedAt".into(), AttributeValue::S("t".into()))],
+            &["holdToken".into()],
+        );
+        assert_eq!(update, "SET #a0 = :v0, #a1 = :v1 REMOVE #a2");
+        let cond = expr.condition(&Condition::eq("status", AttributeValue::S("y".into())));
+        assert_eq!(cond, "#a0 = :v2");
+    }
+
+    #[test]
+    fn test_empty_update_is_rejected() {
+        let store = DynamoStore::new("table");
+        let empty = WriteOp::Update { key: Key::new("A", "1"), set: vec![], remove: vec![], condition: None };
+        assert!(matches!(store.transact_item(empty), Err(StoreError::Backend(_))));
+    }
+}
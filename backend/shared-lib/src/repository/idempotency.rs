@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+
+use super::store::{get_n, get_s, n, s, Condition, Item, ItemStore, Key, StoreError, WriteOp};
+
+/// Respuesta guardada para reproducirla ante un reintento.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdempotencyRecord {
+    pub key: String,
+    pub request_hash: String,
+    /// `None` mientras la primera request sigue en curso
+    pub response: Option<StoredResponse>,
+    /// Epoch en segundos; atributo TTL de la tabla
+    pub expires_at: i64,
+}
+
+#[async_trait]
+pub trait IdempotencyRepository {
+    /// Reserva `key` dentro de `scope` (tenant + usuario) hasta `lease_until`.
+    /// Falla con `ConditionFailed` si ya hay un registro vigente: el TTL de
+    /// DynamoDB puede tardar en borrar los vencidos, así que esos se
+    /// sobreescriben, igual que una reserva sin respuesta cuyo lease venció
+    /// (la ejecución murió antes de guardarla o de liberar la key).
+    async fn claim_idempotency_key(
+        &self,
+        scope: &str,
+        key: &str,
+        request_hash: &str,
+        now: i64,
+        lease_until: i64,
+        expires_at: i64,
+    ) -> Result<(), StoreError>;
+
+    async fn get_idempotency_record(&self, scope: &str, key: &str) -> Result<Option<IdempotencyRecord>, StoreError>;
+
+    async fn complete_idempotency_key(&self, scope: &str, key: &str, response: &StoredResponse) -> Result<(), StoreError>;
+
+    /// Libera la key para que un reintento vuelva a ejecutar la operación.
+    async fn release_idempotency_key(&self, scope: &str, key: &str) -> Result<(), StoreError>;
+}
+
+fn idempotency_key(scope: &str, key: &str) -> Key {
+    Key::new(format!("IDEMPOTENCY#{}", scope), format!("KEY#{}", key))
+}
+
+fn record_from_item(item: &Item) -> IdempotencyRecord {
+    let response = get_n::<u16>(item, "statusCode").map(|status| StoredResponse {
+        status,
+        headers: get_s(item, "headers")
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default(),
+        body: get_s(item, "body").unwrap_or_default(),
+    });
+    IdempotencyRecord {
+        key: get_s(item, "idempotencyKey").unwrap_or_default(),
+        request_hash: get_s(item, "requestHash").unwrap_or_default(),
+        response,
+        expires_at: get_n(item, "expiresAt").unwrap_or_default(),
+    }
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> IdempotencyRepository for S {
+    async fn claim_idempotency_key(
+        &self,
+        scope: &str,
+        key: &str,
+        request_hash: &str,
+        now: i64,
+        lease_until: i64,
+        expires_at: i64,
+    ) -> Result<(), StoreError> {
+        let mut item = idempotency_key(scope, key).to_item();
+        item.extend([
+            ("idempotencyKey".to_string(), s(key)),
+            ("requestHash".to_string(), s(request_hash)),
+            ("leaseUntil".to_string(), n(lease_until)),
+            ("expiresAt".to_string(), n(expires_at)),
+        ]);
+        let condition = Condition::Or(vec![
+            Condition::item_not_exists(),
+            Condition::Lt("expiresAt".into(), n(now)),
+            Condition::And(vec![
+                Condition::AttributeNotExists("statusCode".into()),
+                Condition::Lt("leaseUntil".into(), n(now)),
+            ]),
+        ]);
+        self.write(WriteOp::put_if(item, condition)).await
+    }
+
+    async fn get_idempotency_record(&self, scope: &str, key: &str) -> Result<Option<IdempotencyRecord>, StoreError> {
+        Ok(self.get(&idempotency_key(scope, key)).await?.as_ref().map(record_from_item))
+    }
+
+    async fn complete_idempotency_key(&self, scope: &str, key: &str, response: &StoredResponse) -> Result<(), StoreError> {
+        self.write(WriteOp::Update {
+            key: idempotency_key(scope, key),
+            set: vec![
+                ("statusCode".into(), n(response.status)),
+                ("headers".into(), s(serde_json::to_string(&response.headers).unwrap_or_default())),
+                ("body".into(), s(&response.body)),
+            ],
+            remove: vec!["leaseUntil".into()],
+            condition: Some(Condition::item_exists()),
+        })
+        .await
+    }
+
+    async fn release_idempotency_key(&self, scope: &str, key: &str) -> Result<(), StoreError> {
+        self.write(WriteOp::delete(idempotency_key(scope, key))).await
+    }
+}
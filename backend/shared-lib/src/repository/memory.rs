@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::store::{get_s, Item, ItemStore, Key, Page, Query, SortKey, StoreError, WriteOp};
+
+/// Límite de operaciones por TransactWriteItems en DynamoDB.
+const MAX_TRANSACT_ITEMS: usize = 100;
+
+/// Backend en memoria para tests y ejecución local. Reproduce la semántica
+/// relevante de DynamoDB: condiciones por item, transacciones todo-o-nada,
+/// rechazo de dos operaciones sobre el mismo item en una transacción,
+/// `Limit` aplicado antes del filtro y paginación por `LastEvaluatedKey`.
+#[derive(Default)]
+pub struct MemoryStore {
+    items: Mutex<BTreeMap<Key, Item>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserta items sin condiciones (datos semilla de tests).
+    pub fn seed(&self, items: impl IntoIterator<Item = Item>) {
+        let mut guard = self.items.lock().unwrap();
+        for item in items {
+            let key = Key::of(&item).expect("item semilla sin PK/SK");
+            guard.insert(key, item);
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<Item> {
+        self.items.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn apply(items: &mut BTreeMap<Key, Item>, op: WriteOp) {
+    match op {
+        WriteOp::Put { item, .. } => {
+            if let Some(key) = Key::of(&item) {
+                items.insert(key, item);
+            }
+        }
+        WriteOp::Update { key, set, remove, .. } => {
+            let entry = items.entry(key.clone()).or_insert_with(|| key.to_item());
+            for (attr, value) in set {
+                entry.insert(attr, value);
+            }
+            for attr in remove {
+                entry.remove(&attr);
+            }
+        }
+        WriteOp::Delete { key, .. } => {
+            items.remove(&key);
+        }
+        WriteOp::ConditionCheck { .. } => {}
+    }
+}
+
+fn sort_key_matches(sk: Option<&SortKey>, value: &str) -> bool {
+    match sk {
+        None => true,
+        Some(SortKey::BeginsWith(prefix)) => value.starts_with(prefix.as_str()),
+        Some(SortKey::Between(from, to)) => value >= from.as_str() && value <= to.as_str(),
+    }
+}
+
+#[async_trait]
+impl ItemStore for MemoryStore {
+    async fn get(&self, key: &Key) -> Result<Option<Item>, StoreError> {
+        Ok(self.items.lock().unwrap().get(key).cloned())
+    }
+
+    async fn query(&self, query: &Query) -> Result<Page, StoreError> {
+        let guard = self.items.lock().unwrap();
+        let (pk_attr, sk_attr) = query.index.map(|i| i.key_attrs()).unwrap_or(("PK", "SK"));
+
+        // (sort key, clave de tabla, item) de la partición pedida, ordenados por sort key
+        let mut matches: Vec<(String, Key, &Item)> = guard
+            .iter()
+            .filter(|(_, item)| get_s(item, pk_attr).as_deref() == Some(query.pk.as_str()))
+            .filter_map(|(key, item)| get_s(item, sk_attr).map(|sk| (sk, key.clone(), item)))
+            .filter(|(sk, _, _)| sort_key_matches(query.sk.as_ref(), sk))
+            .collect();
+        matches.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        if query.descending {
+            matches.reverse();
+        }
+
+        if let Some(start) = &query.start_key {
+            let start_key = Key::of(start).ok_or_else(|| StoreError::Backend(anyhow::anyhow!("ExclusiveStartKey inválido")))?;
+            let start_sk = get_s(start, sk_attr).ok_or_else(|| StoreError::Backend(anyhow::anyhow!("ExclusiveStartKey inválido")))?;
+            // Como DynamoDB, se sigue después de la posición de la clave aunque
+            // el item ya no exista (un hold o lock que venció entre páginas)
+            let start = (&start_sk, &start_key);
+            matches.retain(|(sk, key, _)| if query.descending { (sk, key) < start } else { (sk, key) > start });
+        }
+
+        let limit = query.limit.map(|l| l.max(0) as usize).unwrap_or(usize::MAX);
+        let has_more = matches.len() > limit;
+        matches.truncate(limit);
+
+        let last_key = if has_more {
+            matches.last().map(|(_, key, item)| {
+                let mut last = key.to_item();
+                for attr in [pk_attr, sk_attr] {
+                    if let Some(v) = item.get(attr) {
+                        last.insert(attr.to_string(), v.clone());
+                    }
+                }
+                last
+            })
+        } else {
+            None
+        };
+
+        let items = matches
+            .into_iter()
+            .filter(|(_, _, item)| query.filter.as_ref().is_none_or(|f| f.evaluate(Some(item))))
+            .map(|(_, _, item)| item.clone())
+            .collect();
+
+        Ok(Page { items, last_key })
+    }
+
+    async fn write(&self, op: WriteOp) -> Result<(), StoreError> {
+        self.transact(vec![op]).await
+    }
+
+    async fn transact(&self, ops: Vec<WriteOp>) -> Result<(), StoreError> {
+        if ops.len() > MAX_TRANSACT_ITEMS {
+            return Err(StoreError::Backend(anyhow::anyhow!("Transacción excede {} items", MAX_TRANSACT_ITEMS)));
+        }
+
+        let mut guard = self.items.lock().unwrap();
+
+        let mut seen = HashSet::new();
+        for op in &ops {
+            op.validate()?;
+            let key = op.key().ok_or_else(|| StoreError::Backend(anyhow::anyhow!("Operación sin PK/SK")))?;
+            if !seen.insert(key) {
+                return Err(StoreError::Backend(anyhow::anyhow!(
+                    "Transaction request cannot include multiple operations on one item"
+                )));
+            }
+        }
+
+        // Todas las condiciones se evalúan contra el estado previo; si una falla no se aplica nada
+        for op in &ops {
+            if let (Some(condition), Some(key)) = (op.condition(), op.key()) {
+                if !condition.evaluate(guard.get(&key)) {
+                    return Err(StoreError::ConditionFailed);
+                }
+            }
+        }
+
+        for op in ops {
+            apply(&mut guard, op);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::store::{n, s, Condition, Index};
+    use std::collections::HashMap;
+
+    fn item(pk: &str, sk: &str) -> Item {
+        HashMap::from([("PK".to_string(), s(pk)), ("SK".to_string(), s(sk))])
+    }
+
+    #[tokio::test]
+    async fn test_put_if_not_exists_rejects_duplicates() {
+        let store = MemoryStore::new();
+        store.write(WriteOp::put_if(item("A", "1"), Condition::item_not_exists())).await.unwrap();
+        let second = store.write(WriteOp::put_if(item("A", "1"), Condition::item_not_exists())).await;
+        assert!(matches!(second, Err(StoreError::ConditionFailed)));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_is_all_or_nothing() {
+        let store = MemoryStore::new();
+        store.seed([item("SLOT", "10:15")]);
+
+        let result = store
+            .transact(vec![
+                WriteOp::put_if(item("SLOT", "10:00"), Condition::item_not_exists()),
+                WriteOp::put_if(item("SLOT", "10:15"), Condition::item_not_exists()),
+                WriteOp::put(item("BOOKING", "METADATA")),
+            ])
+            .await;
+
+        assert!(matches!(result, Err(StoreError::ConditionFailed)));
+        assert_eq!(store.len(), 1, "ninguna escritura debe aplicarse");
+    }
+
+    #[tokio::test]
+    async fn test_transaction_rejects_two_ops_on_same_item() {
+        let store = MemoryStore::new();
+        let result = store
+            .transact(vec![WriteOp::put(item("A", "1")), WriteOp::delete(Key::new("A", "1"))])
+            .await;
+        assert!(matches!(result, Err(StoreError::Backend(_))));
+    }
+
+    #[tokio::test]
+    async fn test_empty_update_is_rejected() {
+        let store = MemoryStore::new();
+        let empty = WriteOp::Update { key: Key::new("A", "1"), set: vec![], remove: vec![], condition: None };
+        assert!(matches!(store.write(empty).await, Err(StoreError::Backend(_))));
+        assert!(store.is_empty(), "no debe crear el item");
+    }
+
+    #[tokio::test]
+    async fn test_update_condition_on_attribute() {
+        let store = MemoryStore::new();
+        let mut booking = item("BOOKING#1", "METADATA");
+        booking.insert("status".into(), s("confirmed"));
+        store.seed([booking]);
+
+        let cancel = || WriteOp::Update {
+            key: Key::new("BOOKING#1", "METADATA"),
+            set: vec![("status".into(), s("cancelled"))],
+            remove: vec![],
+            condition: Some(Condition::ne("status", s("cancelled"))),
+        };
+        store.write(cancel()).await.unwrap();
+        assert!(matches!(store.write(cancel()).await, Err(StoreError::ConditionFailed)));
+    }
+
+    #[tokio::test]
+    async fn test_query_paginates_and_filters_after_limit() {
+        let store = MemoryStore::new();
+        store.seed((0..5).map(|i| {
+            let mut it = item(&format!("BOOKING#{}", i), "METADATA");
+            it.insert("GSI1PK".into(), s("TENANT#a"));
+            it.insert("GSI1SK".into(), s(format!("BOOKING#{}", i)));
+            it.insert("n".into(), n(i));
+            it
+        }));
+
+        let query = Query { limit: Some(2), ..Query::on_index(Index::Gsi1, "TENANT#a") };
+        let first = store.query(&query).await.unwrap();
+        assert_eq!(first.items.len(), 2);
+        assert!(first.last_key.is_some());
+
+        let all = store.query_all(&query).await.unwrap();
+        assert_eq!(all.len(), 5);
+
+        let filtered = store
+            .query(&Query { limit: Some(2), ..Query::on_index(Index::Gsi1, "TENANT#a").filter(Condition::Gt("n".into(), n(0))) })
+            .await
+            .unwrap();
+        assert_eq!(filtered.items.len(), 1, "el filtro se aplica después del limit");
+    }
+
+    #[tokio::test]
+    async fn test_query_limited_fills_page_and_resumes_after_last_item() {
+        let store = MemoryStore::new();
+        store.seed((0..6).map(|i| {
+            let mut it = item(&format!("BOOKING#{}", i), "METADATA");
+            it.insert("GSI1PK".into(), s("TENANT#a"));
+            it.insert("GSI1SK".into(), s(format!("BOOKING#{}", i)));
+            it.insert("n".into(), n(i % 2));
+            it
+        }));
+
+        let query = Query { limit: Some(2), ..Query::on_index(Index::Gsi1, "TENANT#a").filter(Condition::eq("n", n(1))) };
+        let first = store.query_limited(&query, 2).await.unwrap();
+        assert_eq!(first.items.len(), 2, "recorre páginas hasta llenar el límite");
+
+        let rest = store.query_limited(&Query { start_key: first.last_key, ..query }, 2).await.unwrap();
+        assert_eq!(rest.items.len(), 1);
+        assert_eq!(get_s(&rest.items[0], "PK").as_deref(), Some("BOOKING#5"));
+    }
+
+    #[tokio::test]
+    async fn test_query_resumes_after_start_key_deleted_between_pages() {
+        for (descending, expected) in [(false, ["SLOT#2", "SLOT#3", "SLOT#4"]), (true, ["SLOT#2", "SLOT#1", "SLOT#0"])] {
+            let store = MemoryStore::new();
+            store.seed((0..5).map(|i| item("LOCKS", &format!("SLOT#{}", i))));
+
+            let query = Query { limit: Some(2), descending, ..Query::partition("LOCKS") };
+            let cursor = store.query(&query).await.unwrap().last_key.unwrap();
+            // El último item de la página vence antes de pedir la siguiente
+            store.write(WriteOp::delete(Key::of(&cursor).unwrap())).await.unwrap();
+
+            let rest = store.query_all(&Query { start_key: Some(cursor), ..query }).await.unwrap();
+            let sks: Vec<String> = rest.iter().filter_map(|it| get_s(it, "SK")).collect();
+            assert_eq!(sks, expected);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Acceso a datos de la tabla principal.
+//!
+//! `ItemStore` abstrae las operaciones de DynamoDB (get, query, escrituras
+//! condicionales y TransactWriteItems) con dos backends: `DynamoStore` y
+//! `MemoryStore`. Los repositorios de dominio se implementan una sola vez sobre
+//! `ItemStore`, de modo que los handlers se prueban offline con `MemoryStore`.
+
+mod bookings;
+mod catalog;
+mod contacts;
+pub mod cursor;
+pub mod dynamo;
+mod idempotency;
+pub mod memory;
+mod notifications;
+mod outbox;
+mod patients;
+mod store;
+mod templates;
+
+pub use bookings::{slot_lock_key, BookingFilter, BookingPage, BookingRepository, SlotLockRepository};
+pub use cursor::PageRequest;
+pub use contacts::ContactRepository;
+pub use catalog::{ProfessionalRepository, SiteRepository, TenantRepository, TreatmentRepository};
+pub use dynamo::DynamoStore;
+pub use idempotency::{IdempotencyRecord, IdempotencyRepository, StoredResponse};
+pub use memory::MemoryStore;
+pub use notifications::{event_notification_id, notification_id, NotificationRepository};
+pub use outbox::{OutboxRepository, OUTBOX_RETENTION_DAYS};
+pub use patients::{PatientPage, PatientRepository};
+pub use store::{get_list_s, get_n, get_s, n, s, Condition, Index, Item, ItemStore, Key, Page, Query, SortKey, StoreError, WriteOp};
+pub use templates::TemplateRepository;
+
+/// Todos los repositorios de dominio; lo implementa cualquier `ItemStore`.
+pub trait Repository:
+    TenantRepository + SiteRepository + TreatmentRepository + ProfessionalRepository + BookingRepository + SlotLockRepository
+    + IdempotencyRepository + OutboxRepository + NotificationRepository + ContactRepository + TemplateRepository + PatientRepository + Send + Sync
+{
+}
+
+impl<T> Repository for T where
+    T: TenantRepository + SiteRepository + TreatmentRepository + ProfessionalRepository + BookingRepository + SlotLockRepository
+    + IdempotencyRepository + OutboxRepository + NotificationRepository + ContactRepository + TemplateRepository + PatientRepository + Send + Sync
+{
+}
@@ -0,0 +1,264 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::store::{get_s, s, Condition, Item, ItemStore, Key, Query, StoreError, WriteOp};
+use crate::models::{Channel, DeliveryStatus, NotificationRecord};
+
+/// Id de un intento de envío que no viene de un evento (un recordatorio, p.
+/// ej.): `<epoch ms>-<uuid>`.
+pub fn notification_id(at: DateTime<Utc>) -> String {
+    format!("{:013}-{}", at.timestamp_millis(), uuid::Uuid::new_v4().simple())
+}
+
+/// Id del envío de un evento de la reserva por un canal: cada entrega repetida
+/// del mismo mensaje da el mismo id. Con `-` y no `#`: SES no acepta `#` en
+/// los tags, por donde vuelve el feedback con este id.
+pub fn event_notification_id(event_id: &str, channel: Channel) -> String {
+    format!("{}-{}", event_id, channel.as_str())
+}
+
+#[async_trait]
+pub trait NotificationRepository {
+    async fn put_notification(&self, record: &NotificationRecord) -> Result<(), StoreError>;
+
+    /// Registra el intento antes de llamar al proveedor. Falla con
+    /// `ConditionFailed` si ya hay uno con el mismo id que no falló: ese
+    /// mensaje ya se envió (o se está enviando) y no hay que repetirlo.
+    async fn claim_notification(&self, record: &NotificationRecord) -> Result<(), StoreError>;
+
+    /// Intentos de envío de una reserva, del más antiguo al más reciente (por
+    /// `attemptedAt`).
+    async fn list_notifications(&self, booking_id: &str) -> Result<Vec<NotificationRecord>, StoreError>;
+
+    /// Guarda el resultado del envío sobre el registro `pending` de
+    /// `claim_notification`. Si el feedback del proveedor llegó antes y ya
+    /// cambió el estado, solo completa la plantilla y el id del mensaje.
+    async fn complete_notification(&self, record: &NotificationRecord) -> Result<(), StoreError>;
+
+    /// Actualiza el estado con el feedback del proveedor, solo si lo hace
+    /// avanzar (`DeliveryStatus::advances_from`). Falla con `ConditionFailed`
+    /// si el intento no existe o ya está en ese estado o uno posterior.
+    async fn update_notification_status(
+        &self,
+        booking_id: &str,
+        id: &str,
+        status: DeliveryStatus,
+        error: Option<&str>,
+        at: &str,
+    ) -> Result<(), StoreError>;
+}
+
+/// `BOOKING#id` / `NOTIFICATION#<id>`: junto a la reserva y su historial.
+fn notification_key(booking_id: &str, id: &str) -> Key {
+    Key::new(format!("BOOKING#{}", booking_id), format!("NOTIFICATION#{}", id))
+}
+
+fn notification_to_item(record: &NotificationRecord) -> Item {
+    let mut item = notification_key(&record.booking_id, &record.id).to_item();
+    item.extend([
+        ("notificationId".to_string(), s(&record.id)),
+        ("bookingId".to_string(), s(&record.booking_id)),
+        ("channel".to_string(), s(record.channel.as_str())),
+        ("notificationType".to_string(), s(&record.notification_type)),
+        ("template".to_string(), s(&record.template)),
+        ("recipient".to_string(), s(&record.recipient)),
+        ("status".to_string(), s(record.status.as_str())),
+        ("attemptedAt".to_string(), s(&record.attempted_at)),
+        ("updatedAt".to_string(), s(&record.updated_at)),
+    ]);
+    if let Some(id) = &record.provider_message_id {
+        item.insert("providerMessageId".to_string(), s(id));
+    }
+    if let Some(error) = &record.error {
+        item.insert("error".to_string(), s(error));
+    }
+    item
+}
+
+fn notification_from_item(item: &Item) -> Option<NotificationRecord> {
+    Some(NotificationRecord {
+        id: get_s(item, "notificationId")?,
+        booking_id: get_s(item, "bookingId")?,
+        channel: Channel::parse(&get_s(item, "channel")?)?,
+        notification_type: get_s(item, "notificationType").unwrap_or_default(),
+        template: get_s(item, "template").unwrap_or_default(),
+        recipient: get_s(item, "recipient").unwrap_or_default(),
+        provider_message_id: get_s(item, "providerMessageId"),
+        status: DeliveryStatus::parse(&get_s(item, "status")?)?,
+        error: get_s(item, "error"),
+        attempted_at: get_s(item, "attemptedAt")?,
+        updated_at: get_s(item, "updatedAt")?,
+    })
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> NotificationRepository for S {
+    async fn put_notification(&self, record: &NotificationRecord) -> Result<(), StoreError> {
+        self.write(WriteOp::put(notification_to_item(record))).await
+    }
+
+    async fn claim_notification(&self, record: &NotificationRecord) -> Result<(), StoreError> {
+        let condition = Condition::Or(vec![
+            Condition::item_not_exists(),
+            Condition::eq("status", s(DeliveryStatus::Failed.as_str())),
+        ]);
+        self.write(WriteOp::put_if(notification_to_item(record), condition)).await
+    }
+
+    async fn complete_notification(&self, record: &NotificationRecord) -> Result<(), StoreError> {
+        let key = notification_key(&record.booking_id, &record.id);
+        let mut details = vec![("template".to_string(), s(&record.template))];
+        if let Some(id) = &record.provider_message_id {
+            details.push(("providerMessageId".to_string(), s(id)));
+        }
+        let mut set = details.clone();
+        set.extend([("status".to_string(), s(record.status.as_str())), ("updatedAt".to_string(), s(&record.updated_at))]);
+        let mut remove = vec![];
+        match &record.error {
+            Some(error) => set.push(("error".to_string(), s(error))),
+            None => remove.push("error".to_string()),
+        }
+        let result = self
+            .write(WriteOp::Update {
+                key: key.clone(),
+                set,
+                remove,
+                condition: Some(advances(record.status)),
+            })
+            .await;
+        match result {
+            Err(StoreError::ConditionFailed) => {
+                self.write(WriteOp::Update { key, set: details, remove: vec![], condition: Some(Condition::item_exists()) })
+                    .await
+            }
+            other => other,
+        }
+    }
+
+    async fn list_notifications(&self, booking_id: &str) -> Result<Vec<NotificationRecord>, StoreError> {
+        let query = Query::partition(format!("BOOKING#{}", booking_id)).begins_with("NOTIFICATION#");
+        let mut records: Vec<NotificationRecord> = self.query_all(&query).await?.iter().filter_map(notification_from_item).collect();
+        records.sort_by(|a, b| a.attempted_at.cmp(&b.attempted_at));
+        Ok(records)
+    }
+
+    async fn update_notification_status(
+        &self,
+        booking_id: &str,
+        id: &str,
+        status: DeliveryStatus,
+        error: Option<&str>,
+        at: &str,
+    ) -> Result<(), StoreError> {
+        let mut set = vec![("status".to_string(), s(status.as_str())), ("updatedAt".to_string(), s(at))];
+        let mut remove = Vec::new();
+        match error {
+            Some(error) => set.push(("error".to_string(), s(error))),
+            None => remove.push("error".to_string()),
+        }
+        self.write(WriteOp::Update {
+            key: notification_key(booking_id, id),
+            set,
+            remove,
+            condition: Some(advances(status)),
+        })
+        .await
+    }
+}
+
+/// El intento existe y está en un estado anterior a `status`.
+fn advances(status: DeliveryStatus) -> Condition {
+    Condition::Or(status.advances_from().iter().map(|from| Condition::eq("status", s(from.as_str()))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::MemoryStore;
+
+    fn record(id: &str) -> NotificationRecord {
+        NotificationRecord {
+            id: id.into(),
+            booking_id: "b1".into(),
+            channel: Channel::Email,
+            notification_type: "confirmation".into(),
+            template: "booking-confirmation".into(),
+            recipient: "ana@example.com".into(),
+            provider_message_id: Some("ses-1".into()),
+            status: DeliveryStatus::Sent,
+            error: None,
+            attempted_at: "2025-10-01T10:00:00Z".into(),
+            updated_at: "2025-10-01T10:00:00Z".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notifications_are_listed_in_order_and_take_feedback() {
+        let store = MemoryStore::new();
+        let first = notification_id("2025-10-01T10:00:00Z".parse().unwrap());
+        let second = notification_id("2025-10-01T10:05:00Z".parse().unwrap());
+        store.put_notification(&record(&second)).await.unwrap();
+        store.put_notification(&record(&first)).await.unwrap();
+
+        let listed = store.list_notifications("b1").await.unwrap();
+        assert_eq!(listed.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec![first.as_str(), second.as_str()]);
+        assert_eq!(listed[0], record(&first));
+
+        store
+            .update_notification_status("b1", &first, DeliveryStatus::Bounced, Some("Permanent/General"), "2025-10-01T10:01:00Z")
+            .await
+            .unwrap();
+        let updated = &store.list_notifications("b1").await.unwrap()[0];
+        assert_eq!(updated.status, DeliveryStatus::Bounced);
+        assert_eq!(updated.error.as_deref(), Some("Permanent/General"));
+        assert_eq!(updated.provider_message_id.as_deref(), Some("ses-1"));
+
+        assert!(matches!(
+            store.update_notification_status("b1", "missing", DeliveryStatus::Delivered, None, "2025-10-01T10:02:00Z").await,
+            Err(StoreError::ConditionFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_claim_skips_notifications_already_sent() {
+        let store = MemoryStore::new();
+        let id = event_notification_id("e1", Channel::Email);
+        let pending = NotificationRecord { status: DeliveryStatus::Pending, provider_message_id: None, ..record(&id) };
+        store.claim_notification(&pending).await.unwrap();
+        assert!(matches!(store.claim_notification(&pending).await, Err(StoreError::ConditionFailed)));
+
+        // Un intento que falló se puede volver a tomar
+        let failed = NotificationRecord { status: DeliveryStatus::Failed, error: Some("Throttling".into()), ..pending.clone() };
+        store.put_notification(&failed).await.unwrap();
+        store.claim_notification(&pending).await.unwrap();
+        assert_eq!(store.list_notifications("b1").await.unwrap(), [pending]);
+    }
+
+    #[tokio::test]
+    async fn test_status_only_moves_forward() {
+        let store = MemoryStore::new();
+        let id = event_notification_id("e1", Channel::Email);
+        let pending = NotificationRecord { status: DeliveryStatus::Pending, provider_message_id: None, ..record(&id) };
+        store.claim_notification(&pending).await.unwrap();
+        let current = || async { store.list_notifications("b1").await.unwrap().remove(0) };
+
+        // El feedback llega antes de que se guarde el resultado del envío
+        store.update_notification_status("b1", &id, DeliveryStatus::Delivered, None, "t1").await.unwrap();
+        store.complete_notification(&record(&id)).await.unwrap();
+        let delivered = current().await;
+        assert_eq!((delivered.status, delivered.provider_message_id.as_deref()), (DeliveryStatus::Delivered, Some("ses-1")));
+
+        store.update_notification_status("b1", &id, DeliveryStatus::Complained, Some("abuse"), "t2").await.unwrap();
+        // SNS vuelve a entregar la entrega
+        assert!(matches!(
+            store.update_notification_status("b1", &id, DeliveryStatus::Delivered, None, "t3").await,
+            Err(StoreError::ConditionFailed)
+        ));
+        assert!(matches!(
+            store.update_notification_status("b1", &id, DeliveryStatus::Bounced, None, "t3").await,
+            Err(StoreError::ConditionFailed)
+        ));
+        let complained = current().await;
+        assert_eq!((complained.status, complained.error.as_deref(), complained.updated_at.as_str()), (DeliveryStatus::Complained, Some("abuse"), "t2"));
+    }
+}
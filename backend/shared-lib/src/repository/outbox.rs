@@ -0,0 +1,126 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+
+use super::store::{get_s, n, s, Condition, Item, ItemStore, Key, Query, StoreError, WriteOp};
+use crate::models::BookingEvent;
+
+/// Días que se conservan los eventos del outbox y sus marcas de procesado. El
+/// stream solo retiene 24 horas, así que cualquier reintento cae dentro.
+pub const OUTBOX_RETENTION_DAYS: i64 = 30;
+
+#[async_trait]
+pub trait OutboxRepository {
+    /// Eventos del outbox de una reserva, del más antiguo al más reciente.
+    async fn list_booking_events(&self, booking_id: &str) -> Result<Vec<BookingEvent>, StoreError>;
+
+    /// Toma el procesamiento de `event_id` hasta `lease_until`. Falla con
+    /// `ConditionFailed` si ya se procesó o si otra ejecución lo tiene tomado;
+    /// un lease vencido (ejecución que murió a medias) se puede volver a tomar.
+    async fn claim_event(&self, event_id: &str, now: i64, lease_until: i64) -> Result<(), StoreError>;
+
+    /// Marca el evento como procesado: las entregas repetidas se descartan.
+    async fn complete_event(&self, event_id: &str) -> Result<(), StoreError>;
+
+    /// Libera el evento tras un fallo para que el reintento lo procese.
+    async fn release_event(&self, event_id: &str) -> Result<(), StoreError>;
+
+    async fn is_event_processed(&self, event_id: &str) -> Result<bool, StoreError>;
+}
+
+/// `BOOKING#id` / `EVENT#<at>#<id>`: junto a la reserva y ordenado por fecha.
+pub(super) fn event_to_item(event: &BookingEvent) -> Item {
+    let mut item = Key::new(
+        format!("BOOKING#{}", event.booking.id),
+        format!("EVENT#{}#{}", event.occurred_at, event.id),
+    )
+    .to_item();
+    item.extend([
+        ("eventId".to_string(), s(&event.id)),
+        ("eventType".to_string(), s(event.event_type.as_str())),
+        ("bookingId".to_string(), s(&event.booking.id)),
+        ("tenantId".to_string(), s(&event.booking.tenant_id)),
+        ("occurredAt".to_string(), s(&event.occurred_at)),
+        ("payload".to_string(), s(serde_json::to_string(event).unwrap_or_default())),
+    ]);
+    if let Ok(at) = DateTime::parse_from_rfc3339(&event.occurred_at) {
+        item.insert("expiresAt".to_string(), n(at.timestamp() + OUTBOX_RETENTION_DAYS * 86400));
+    }
+    item
+}
+
+fn event_from_item(item: &Item) -> Option<BookingEvent> {
+    serde_json::from_str(&get_s(item, "payload")?).ok()
+}
+
+fn processed_key(event_id: &str) -> Key {
+    Key::new(format!("OUTBOX#{}", event_id), "PROCESSED")
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> OutboxRepository for S {
+    async fn list_booking_events(&self, booking_id: &str) -> Result<Vec<BookingEvent>, StoreError> {
+        let query = Query::partition(format!("BOOKING#{}", booking_id)).begins_with("EVENT#");
+        Ok(self.query_all(&query).await?.iter().filter_map(event_from_item).collect())
+    }
+
+    async fn claim_event(&self, event_id: &str, now: i64, lease_until: i64) -> Result<(), StoreError> {
+        let mut item = processed_key(event_id).to_item();
+        item.extend([
+            ("eventId".to_string(), s(event_id)),
+            ("status".to_string(), s("processing")),
+            ("leaseUntil".to_string(), n(lease_until)),
+            ("expiresAt".to_string(), n(now + OUTBOX_RETENTION_DAYS * 86400)),
+        ]);
+        let condition = Condition::Or(vec![
+            Condition::item_not_exists(),
+            Condition::And(vec![
+                Condition::eq("status", s("processing")),
+                Condition::Lt("leaseUntil".into(), n(now)),
+            ]),
+        ]);
+        self.write(WriteOp::put_if(item, condition)).await
+    }
+
+    async fn complete_event(&self, event_id: &str) -> Result<(), StoreError> {
+        self.write(WriteOp::Update {
+            key: processed_key(event_id),
+            set: vec![("status".into(), s("done"))],
+            remove: vec!["leaseUntil".into()],
+            condition: Some(Condition::item_exists()),
+        })
+        .await
+    }
+
+    async fn release_event(&self, event_id: &str) -> Result<(), StoreError> {
+        self.write(WriteOp::delete(processed_key(event_id))).await
+    }
+
+    async fn is_event_processed(&self, event_id: &str) -> Result<bool, StoreError> {
+        Ok(self.get(&processed_key(event_id)).await?.is_some_and(|item| get_s(&item, "status").as_deref() == Some("done")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::MemoryStore;
+
+    #[tokio::test]
+    async fn test_event_is_processed_once_and_stale_leases_are_reclaimed() {
+        let store = MemoryStore::new();
+        store.claim_event("ev-1", 100, 160).await.unwrap();
+        // Entrega duplicada mientras la primera sigue en curso
+        assert!(matches!(store.claim_event("ev-1", 120, 180).await, Err(StoreError::ConditionFailed)));
+        // La primera ejecución murió: el lease vence y otra lo retoma
+        store.claim_event("ev-1", 161, 221).await.unwrap();
+        assert!(!store.is_event_processed("ev-1").await.unwrap());
+
+        store.complete_event("ev-1").await.unwrap();
+        assert!(store.is_event_processed("ev-1").await.unwrap());
+        assert!(matches!(store.claim_event("ev-1", 10_000, 10_060).await, Err(StoreError::ConditionFailed)));
+
+        store.claim_event("ev-2", 100, 160).await.unwrap();
+        store.release_event("ev-2").await.unwrap();
+        store.claim_event("ev-2", 101, 161).await.unwrap();
+    }
+}
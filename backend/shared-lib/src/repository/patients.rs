@@ -0,0 +1,294 @@
+use async_trait::async_trait;
+
+use super::cursor::{paginate, PageRequest};
+use super::store::{get_s, s, Condition, Index, Item, ItemStore, Key, Query, StoreError, WriteOp};
+use crate::models::{Patient, PatientLookup};
+
+#[derive(Debug, Clone, Default)]
+pub struct PatientPage {
+    pub patients: Vec<Patient>,
+    pub next_cursor: Option<String>,
+}
+
+#[async_trait]
+pub trait PatientRepository {
+    /// Falla con `ConditionFailed` si ya existe un paciente con ese id o con
+    /// ese documento.
+    async fn create_patient(&self, patient: &Patient) -> Result<(), StoreError>;
+    async fn get_patient(&self, tenant_id: &str, id: &str) -> Result<Option<Patient>, StoreError>;
+    /// Una página del directorio, en el orden de la tabla (por id).
+    async fn list_patients(&self, tenant_id: &str, page: &PageRequest) -> Result<PatientPage, StoreError>;
+    /// Pacientes del tenant con ese email, teléfono o documento (GSI2).
+    async fn find_patients(&self, tenant_id: &str, lookup: PatientLookup, value: &str) -> Result<Vec<Patient>, StoreError>;
+    /// Reemplaza `previous` por `patient` y actualiza sus búsquedas en la misma
+    /// transacción. Falla con `ConditionFailed` si el paciente no existe, si
+    /// cambió desde que se leyó `previous` (su `updatedAt` es otro) o si el
+    /// documento nuevo ya es de otro.
+    async fn update_patient(&self, previous: &Patient, patient: &Patient) -> Result<(), StoreError>;
+    /// Falla con `ConditionFailed` si el paciente no existe o cambió desde que
+    /// se leyó.
+    async fn delete_patient(&self, patient: &Patient) -> Result<(), StoreError>;
+}
+
+/// `TENANT#id` / `PATIENT#id`
+fn patient_key(tenant_id: &str, id: &str) -> Key {
+    Key::new(format!("TENANT#{}", tenant_id), format!("PATIENT#{}", id))
+}
+
+/// Una búsqueda del paciente: `TENANT#t#PATIENT#id` / `LOOKUP#<tipo>`, con
+/// `GSI2PK = TENANT#t#PATIENT_<TIPO>#<valor>`. Un mismo email o teléfono puede
+/// ser de varios pacientes (los hijos de un mismo responsable, p. ej.).
+fn lookup_key(tenant_id: &str, id: &str, lookup: PatientLookup) -> Key {
+    Key::new(format!("TENANT#{}#PATIENT#{}", tenant_id, id), format!("LOOKUP#{}", lookup.as_str()))
+}
+
+fn lookup_partition(tenant_id: &str, lookup: PatientLookup, value: &str) -> String {
+    format!("TENANT#{}#PATIENT_{}#{}", tenant_id, lookup.as_str().to_uppercase(), value)
+}
+
+/// Reserva del documento para un solo paciente de la clínica:
+/// `TENANT#t#PATIENT_DOCUMENT#<normalizado>` / `PATIENT_DOCUMENT`. La búsqueda
+/// por GSI2 no sirve para esto porque el índice no es consistente.
+fn document_key(tenant_id: &str, document: &str) -> Key {
+    Key::new(
+        format!("TENANT#{}#PATIENT_DOCUMENT#{}", tenant_id, PatientLookup::Document.normalize(document)),
+        "PATIENT_DOCUMENT",
+    )
+}
+
+/// El documento del paciente, si tiene uno que no queda vacío al normalizarlo.
+fn document_of(patient: &Patient) -> Option<&str> {
+    patient.document_id.as_deref()
+        .filter(|document| !PatientLookup::Document.normalize(document).is_empty())
+}
+
+/// El paciente existe y sigue como se leyó.
+fn unchanged(patient: &Patient) -> Condition {
+    Condition::eq("updatedAt", s(&patient.updated_at))
+}
+
+/// Se puede escribir si el documento está libre o ya es de este paciente (los
+/// creados antes de la reserva no la tienen).
+fn claim_document(patient: &Patient, document: &str) -> WriteOp {
+    let mut item = document_key(&patient.tenant_id, document).to_item();
+    item.extend([
+        ("tenantId".to_string(), s(&patient.tenant_id)),
+        ("patientId".to_string(), s(&patient.id)),
+    ]);
+    WriteOp::put_if(item, Condition::Or(vec![Condition::item_not_exists(), Condition::eq("patientId", s(&patient.id))]))
+}
+
+fn lookup_to_item(patient: &Patient, lookup: PatientLookup, value: &str) -> Item {
+    let mut item = lookup_key(&patient.tenant_id, &patient.id, lookup).to_item();
+    item.extend([
+        ("GSI2PK".to_string(), s(lookup_partition(&patient.tenant_id, lookup, value))),
+        ("GSI2SK".to_string(), s(format!("PATIENT#{}", patient.id))),
+        ("tenantId".to_string(), s(&patient.tenant_id)),
+        ("patientId".to_string(), s(&patient.id)),
+    ]);
+    item
+}
+
+fn patient_to_item(patient: &Patient) -> Item {
+    let mut item = patient_key(&patient.tenant_id, &patient.id).to_item();
+    item.extend([
+        ("id".to_string(), s(&patient.id)),
+        ("tenantId".to_string(), s(&patient.tenant_id)),
+        ("name".to_string(), s(&patient.name)),
+        ("createdAt".to_string(), s(&patient.created_at)),
+        ("updatedAt".to_string(), s(&patient.updated_at)),
+    ]);
+    let optional = [
+        ("email", patient.email.clone()),
+        ("phone", patient.phone.clone()),
+        ("documentId", patient.document_id.clone()),
+        ("birthDate", patient.birth_date.map(|date| date.to_string())),
+        ("guardian", patient.guardian.as_ref().and_then(|guardian| serde_json::to_string(guardian).ok())),
+    ];
+    item.extend(optional.into_iter().filter_map(|(name, value)| Some((name.to_string(), s(value?)))));
+    item
+}
+
+fn patient_from_item(item: &Item) -> Option<Patient> {
+    Some(Patient {
+        id: get_s(item, "id")?,
+        tenant_id: get_s(item, "tenantId")?,
+        name: get_s(item, "name")?,
+        email: get_s(item, "email"),
+        phone: get_s(item, "phone"),
+        document_id: get_s(item, "documentId"),
+        birth_date: get_s(item, "birthDate").and_then(|raw| raw.parse().ok()),
+        guardian: get_s(item, "guardian").and_then(|raw| serde_json::from_str(&raw).ok()),
+        created_at: get_s(item, "createdAt").unwrap_or_default(),
+        updated_at: get_s(item, "updatedAt").unwrap_or_default(),
+    })
+}
+
+/// Alta del paciente con sus búsquedas y su documento (hasta cinco items),
+/// para transacciones que lo crean junto con otra cosa (una reserva, p. ej.).
+pub(super) fn new_patient_ops(patient: &Patient) -> Vec<WriteOp> {
+    let mut ops = vec![WriteOp::put_if(patient_to_item(patient), Condition::item_not_exists())];
+    ops.extend(patient.lookup_keys().iter().map(|(lookup, value)| WriteOp::put(lookup_to_item(patient, *lookup, value))));
+    ops.extend(document_of(patient).map(|document| claim_document(patient, document)));
+    ops
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> PatientRepository for S {
+    async fn create_patient(&self, patient: &Patient) -> Result<(), StoreError> {
+        self.transact(new_patient_ops(patient)).await
+    }
+
+    async fn get_patient(&self, tenant_id: &str, id: &str) -> Result<Option<Patient>, StoreError> {
+        Ok(self.get(&patient_key(tenant_id, id)).await?.as_ref().and_then(patient_from_item))
+    }
+
+    async fn list_patients(&self, tenant_id: &str, page: &PageRequest) -> Result<PatientPage, StoreError> {
+        let query = Query::partition(format!("TENANT#{}", tenant_id)).begins_with("PATIENT#");
+        let (items, next_cursor) = paginate(self, query, page).await?;
+        Ok(PatientPage { patients: items.iter().filter_map(patient_from_item).collect(), next_cursor })
+    }
+
+    async fn find_patients(&self, tenant_id: &str, lookup: PatientLookup, value: &str) -> Result<Vec<Patient>, StoreError> {
+        let query = Query::on_index(Index::Gsi2, lookup_partition(tenant_id, lookup, &lookup.normalize(value)));
+        let mut patients = Vec::new();
+        for item in self.query_all(&query).await? {
+            // Una búsqueda sin paciente es de uno borrado a mitad de la consulta
+            if let Some(patient) = self.get_patient(tenant_id, &get_s(&item, "patientId").unwrap_or_default()).await? {
+                patients.push(patient);
+            }
+        }
+        Ok(patients)
+    }
+
+    async fn update_patient(&self, previous: &Patient, patient: &Patient) -> Result<(), StoreError> {
+        let current = patient.lookup_keys();
+        // DynamoDB no admite dos operaciones sobre el mismo item en una
+        // transacción: las búsquedas que siguen se reescriben, no se borran
+        let mut ops: Vec<WriteOp> = previous.lookup_keys().into_iter()
+            .filter(|(lookup, _)| !current.iter().any(|(kept, _)| kept == lookup))
+            .map(|(lookup, _)| WriteOp::delete(lookup_key(&previous.tenant_id, &previous.id, lookup)))
+            .collect();
+        // Sin esta condición, dos ediciones simultáneas dejarían búsquedas de la
+        // que pierde apuntando al paciente
+        ops.push(WriteOp::put_if(patient_to_item(patient), unchanged(previous)));
+        ops.extend(current.iter().map(|(lookup, value)| WriteOp::put(lookup_to_item(patient, *lookup, value))));
+        // El documento que deja de tener se libera para otro paciente
+        let before = document_of(previous).map(|document| document_key(&previous.tenant_id, document));
+        let after = document_of(patient).map(|document| document_key(&patient.tenant_id, document));
+        if let Some(before) = before.filter(|before| after.as_ref() != Some(before)) {
+            ops.push(WriteOp::delete(before));
+        }
+        ops.extend(document_of(patient).map(|document| claim_document(patient, document)));
+        self.transact(ops).await
+    }
+
+    async fn delete_patient(&self, patient: &Patient) -> Result<(), StoreError> {
+        let mut ops = vec![WriteOp::Delete {
+            key: patient_key(&patient.tenant_id, &patient.id),
+            condition: Some(unchanged(patient)),
+        }];
+        ops.extend(PatientLookup::ALL.iter().map(|lookup| WriteOp::delete(lookup_key(&patient.tenant_id, &patient.id, *lookup))));
+        ops.extend(document_of(patient).map(|document| WriteOp::delete(document_key(&patient.tenant_id, document))));
+        self.transact(ops).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Guardian;
+    use crate::repository::MemoryStore;
+
+    fn patient(id: &str, name: &str) -> Patient {
+        Patient {
+            id: id.into(),
+            tenant_id: "tenant-a".into(),
+            name: name.into(),
+            email: None,
+            phone: None,
+            document_id: None,
+            birth_date: None,
+            guardian: None,
+            created_at: "2025-10-01T10:00:00Z".into(),
+            updated_at: "2025-10-01T10:00:00Z".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_patients_are_found_by_email_phone_and_document() {
+        let store = MemoryStore::new();
+        let ana = Patient {
+            email: Some("Ana@Example.com".into()),
+            phone: Some("+573001234567".into()),
+            document_id: Some("1.020.304-5".into()),
+            birth_date: Some("1990-05-01".parse().unwrap()),
+            ..patient("p1", "Ana")
+        };
+        // Menor sin contacto propio: se lo encuentra por el de su madre
+        let sofia = Patient {
+            guardian: Some(Guardian {
+                name: "Ana".into(),
+                relationship: Some("madre".into()),
+                email: Some("ana@example.com".into()),
+                phone: None,
+            }),
+            ..patient("p2", "Sofía")
+        };
+        store.create_patient(&ana).await.unwrap();
+        store.create_patient(&sofia).await.unwrap();
+        assert!(matches!(store.create_patient(&ana).await, Err(StoreError::ConditionFailed)));
+        // El documento es de un solo paciente aunque se escriba distinto
+        let twin = Patient { document_id: Some("1020304-5".into()), ..patient("p3", "Otra") };
+        assert!(matches!(store.create_patient(&twin).await, Err(StoreError::ConditionFailed)));
+        assert!(store.get_patient("tenant-a", "p3").await.unwrap().is_none());
+
+        assert_eq!(store.get_patient("tenant-a", "p1").await.unwrap(), Some(ana.clone()));
+        assert_eq!(store.list_patients("tenant-a", &PageRequest::default()).await.unwrap().patients.len(), 2);
+        let first = store.list_patients("tenant-a", &PageRequest::new(Some(1), None)).await.unwrap();
+        assert_eq!(first.patients, vec![ana.clone()]);
+        let second = store.list_patients("tenant-a", &PageRequest::new(Some(1), first.next_cursor)).await.unwrap();
+        assert_eq!(second.patients, vec![sofia.clone()]);
+        assert_eq!(store.find_patients("tenant-a", PatientLookup::Email, " ANA@example.com").await.unwrap().len(), 2);
+        assert_eq!(store.find_patients("tenant-a", PatientLookup::Phone, "+573001234567").await.unwrap(), vec![ana.clone()]);
+        assert_eq!(store.find_patients("tenant-a", PatientLookup::Document, "10203045").await.unwrap(), vec![ana.clone()]);
+        assert!(store.find_patients("tenant-b", PatientLookup::Document, "10203045").await.unwrap().is_empty());
+
+        let updated = Patient { phone: None, document_id: Some("99".into()), ..ana.clone() };
+        store.update_patient(&ana, &updated).await.unwrap();
+        assert!(store.find_patients("tenant-a", PatientLookup::Phone, "+573001234567").await.unwrap().is_empty());
+        assert!(store.find_patients("tenant-a", PatientLookup::Document, "10203045").await.unwrap().is_empty());
+        assert_eq!(store.find_patients("tenant-a", PatientLookup::Document, "99").await.unwrap(), vec![updated.clone()]);
+        // El documento anterior queda libre y el nuevo no se puede tomar
+        let sofia_with_document = Patient { document_id: Some("99".into()), ..sofia.clone() };
+        assert!(matches!(store.update_patient(&sofia, &sofia_with_document).await, Err(StoreError::ConditionFailed)));
+        store.create_patient(&twin).await.unwrap();
+        store.delete_patient(&twin).await.unwrap();
+
+        store.delete_patient(&updated).await.unwrap();
+        assert!(store.get_patient("tenant-a", "p1").await.unwrap().is_none());
+        store.update_patient(&sofia, &sofia_with_document).await.unwrap();
+        store.update_patient(&sofia_with_document, &sofia).await.unwrap();
+        assert_eq!(store.find_patients("tenant-a", PatientLookup::Email, "ana@example.com").await.unwrap(), vec![sofia]);
+        assert!(matches!(store.delete_patient(&updated).await, Err(StoreError::ConditionFailed)));
+        assert!(matches!(store.update_patient(&updated, &updated).await, Err(StoreError::ConditionFailed)));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_edits_do_not_overwrite_each_other() {
+        let store = MemoryStore::new();
+        let ana = Patient { email: Some("ana@example.com".into()), ..patient("p1", "Ana") };
+        store.create_patient(&ana).await.unwrap();
+
+        // Dos recepcionistas leen la misma versión; la primera guarda
+        let first = Patient { email: Some("ana.perez@example.com".into()), updated_at: "2025-10-02T09:00:00Z".into(), ..ana.clone() };
+        store.update_patient(&ana, &first).await.unwrap();
+        let second = Patient { phone: Some("+573001234567".into()), updated_at: "2025-10-02T09:00:01Z".into(), ..ana.clone() };
+        assert!(matches!(store.update_patient(&ana, &second).await, Err(StoreError::ConditionFailed)));
+        assert!(store.find_patients("tenant-a", PatientLookup::Phone, "+573001234567").await.unwrap().is_empty());
+        assert!(matches!(store.delete_patient(&ana).await, Err(StoreError::ConditionFailed)));
+
+        assert_eq!(store.get_patient("tenant-a", "p1").await.unwrap(), Some(first.clone()));
+        store.delete_patient(&first).await.unwrap();
+    }
+}
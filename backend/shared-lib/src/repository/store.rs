@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+
+use crate::error::ApiError;
+
+/// Item de la tabla principal tal como lo almacena DynamoDB.
+pub type Item = HashMap<String, AttributeValue>;
+
+/// Clave primaria de la tabla (single-table design: PK + SK).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key {
+    pub pk: String,
+    pub sk: String,
+}
+
+impl Key {
+    pub fn new(pk: impl Into<String>, sk: impl Into<String>) -> Self {
+        Key { pk: pk.into(), sk: sk.into() }
+    }
+
+    pub fn of(item: &Item) -> Option<Key> {
+        Some(Key::new(get_s(item, "PK")?, get_s(item, "SK")?))
+    }
+
+    pub fn to_item(&self) -> Item {
+        HashMap::from([
+            ("PK".to_string(), AttributeValue::S(self.pk.clone())),
+            ("SK".to_string(), AttributeValue::S(self.sk.clone())),
+        ])
+    }
+}
+
+/// Subconjunto de condition/filter expressions de DynamoDB que usan los handlers.
+/// Cada backend la traduce (DynamoDB) o la evalúa (memoria) con la misma semántica.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    AttributeExists(String),
+    AttributeNotExists(String),
+    Eq(String, AttributeValue),
+    Ne(String, AttributeValue),
+    Lt(String, AttributeValue),
+    Gt(String, AttributeValue),
+    /// `attr BETWEEN lo AND hi`, ambos inclusive
+    Between(String, AttributeValue, AttributeValue),
+    In(String, Vec<AttributeValue>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
+impl Condition {
+    /// `attribute_not_exists(PK)`: el item no debe existir.
+    pub fn item_not_exists() -> Self {
+        Condition::AttributeNotExists("PK".into())
+    }
+
+    /// `attribute_exists(PK)`: el item debe existir.
+    pub fn item_exists() -> Self {
+        Condition::AttributeExists("PK".into())
+    }
+
+    pub fn eq(attr: &str, value: AttributeValue) -> Self {
+        Condition::Eq(attr.into(), value)
+    }
+
+    pub fn ne(attr: &str, value: AttributeValue) -> Self {
+        Condition::Ne(attr.into(), value)
+    }
+
+    /// Evalúa la condición contra el estado actual del item (`None` = no existe).
+    pub fn evaluate(&self, item: Option<&Item>) -> bool {
+        let attr = |name: &str| item.and_then(|i| i.get(name));
+        match self {
+            Condition::AttributeExists(a) => attr(a).is_some(),
+            Condition::AttributeNotExists(a) => attr(a).is_none(),
+            Condition::Eq(a, v) => attr(a).is_some_and(|x| values_equal(x, v)),
+            Condition::Ne(a, v) => !attr(a).is_some_and(|x| values_equal(x, v)),
+            Condition::Lt(a, v) => attr(a).and_then(|x| compare(x, v)).is_some_and(|o| o.is_lt()),
+            Condition::Gt(a, v) => attr(a).and_then(|x| compare(x, v)).is_some_and(|o| o.is_gt()),
+            Condition::Between(a, lo, hi) => attr(a).is_some_and(|x| {
+                compare(x, lo).is_some_and(|o| o.is_ge()) && compare(x, hi).is_some_and(|o| o.is_le())
+            }),
+            Condition::In(a, vs) => attr(a).is_some_and(|x| vs.iter().any(|v| values_equal(x, v))),
+            Condition::And(cs) => cs.iter().all(|c| c.evaluate(item)),
+            Condition::Or(cs) => cs.iter().any(|c| c.evaluate(item)),
+        }
+    }
+}
+
+fn values_equal(a: &AttributeValue, b: &AttributeValue) -> bool {
+    match (a, b) {
+        (AttributeValue::N(x), AttributeValue::N(y)) => match (x.parse::<f64>(), y.parse::<f64>()) {
+            (Ok(x), Ok(y)) => x == y,
+            _ => x == y,
+        },
+        _ => a == b,
+    }
+}
+
+fn compare(a: &AttributeValue, b: &AttributeValue) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (AttributeValue::S(x), AttributeValue::S(y)) => Some(x.cmp(y)),
+        (AttributeValue::N(x), AttributeValue::N(y)) => x.parse::<f64>().ok()?.partial_cmp(&y.parse::<f64>().ok()?),
+        _ => None,
+    }
+}
+
+/// Operación de escritura individual o dentro de una transacción.
+#[derive(Debug, Clone)]
+pub enum WriteOp {
+    Put { item: Item, condition: Option<Condition> },
+    /// `SET` de atributos y `REMOVE` de otros. Igual que en DynamoDB, crea el
+    /// item si no existe (usar `Condition::item_exists()` para evitarlo).
+    Update { key: Key, set: Vec<(String, AttributeValue)>, remove: Vec<String>, condition: Option<Condition> },
+    Delete { key: Key, condition: Option<Condition> },
+    ConditionCheck { key: Key, condition: Condition },
+}
+
+impl WriteOp {
+    pub fn put(item: Item) -> Self {
+        WriteOp::Put { item, condition: None }
+    }
+
+    pub fn put_if(item: Item, condition: Condition) -> Self {
+        WriteOp::Put { item, condition: Some(condition) }
+    }
+
+    pub fn delete(key: Key) -> Self {
+        WriteOp::Delete { key, condition: None }
+    }
+
+    pub fn key(&self) -> Option<Key> {
+        match self {
+            WriteOp::Put { item, .. } => Key::of(item),
+            WriteOp::Update { key, .. } | WriteOp::Delete { key, .. } | WriteOp::ConditionCheck { key, .. } => Some(key.clone()),
+        }
+    }
+
+    /// Rechaza un `Update` sin `SET` ni `REMOVE`: DynamoDB no acepta una
+    /// expresión vacía, y en memoria solo crearía el item.
+    pub fn validate(&self) -> Result<(), StoreError> {
+        match self {
+            WriteOp::Update { set, remove, .. } if set.is_empty() && remove.is_empty() => {
+                Err(StoreError::Backend(anyhow::anyhow!("Update sin atributos que cambiar")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn condition(&self) -> Option<&Condition> {
+        match self {
+            WriteOp::Put { condition, .. } | WriteOp::Update { condition, .. } | WriteOp::Delete { condition, .. } => condition.as_ref(),
+            WriteOp::ConditionCheck { condition, .. } => Some(condition),
+        }
+    }
+}
+
+/// Índices secundarios de la tabla principal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Index {
+    Gsi1,
+    Gsi2,
+    Gsi3,
+}
+
+impl Index {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Index::Gsi1 => "GSI1",
+            Index::Gsi2 => "GSI2",
+            Index::Gsi3 => "GSI3",
+        }
+    }
+
+    pub fn key_attrs(&self) -> (&'static str, &'static str) {
+        match self {
+            Index::Gsi1 => ("GSI1PK", "GSI1SK"),
+            Index::Gsi2 => ("GSI2PK", "GSI2SK"),
+            Index::Gsi3 => ("GSI3PK", "GSI3SK"),
+        }
+    }
+}
+
+/// Condición sobre la sort key de una query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortKey {
+    BeginsWith(String),
+    Between(String, String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub index: Option<Index>,
+    pub pk: String,
+    pub sk: Option<SortKey>,
+    /// Se aplica después de `limit`, como el FilterExpression de DynamoDB.
+    pub filter: Option<Condition>,
+    pub limit: Option<i32>,
+    pub start_key: Option<Item>,
+    pub descending: bool,
+}
+
+impl Query {
+    pub fn partition(pk: impl Into<String>) -> Self {
+        Query { pk: pk.into(), ..Default::default() }
+    }
+
+    pub fn on_index(index: Index, pk: impl Into<String>) -> Self {
+        Query { index: Some(index), pk: pk.into(), ..Default::default() }
+    }
+
+    pub fn begins_with(mut self, prefix: impl Into<String>) -> Self {
+        self.sk = Some(SortKey::BeginsWith(prefix.into()));
+        self
+    }
+
+    pub fn between(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.sk = Some(SortKey::Between(from.into(), to.into()));
+        self
+    }
+
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.filter = Some(condition);
+        self
+    }
+
+    /// `ExclusiveStartKey` para continuar justo después de `item`: la clave de
+    /// la tabla más la del índice consultado, como el `LastEvaluatedKey`.
+    pub fn start_key_after(&self, item: &Item) -> Item {
+        let mut attrs = vec!["PK", "SK"];
+        if let Some(index) = self.index {
+            let (pk, sk) = index.key_attrs();
+            attrs.extend([pk, sk]);
+        }
+        attrs.into_iter()
+            .filter_map(|a| item.get(a).map(|v| (a.to_string(), v.clone())))
+            .collect()
+    }
+}
+
+/// Página de resultados; `last_key` es el `LastEvaluatedKey` si hay más.
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    pub items: Vec<Item>,
+    pub last_key: Option<Item>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    /// Falló una condición (o se canceló la transacción por una condición)
+    #[error("Condición de escritura no cumplida")]
+    ConditionFailed,
+
+    /// Cursor de paginación corrupto o de otra consulta
+    #[error("Cursor de paginación inválido")]
+    InvalidCursor,
+
+    #[error(transparent)]
+    Backend(#[from] anyhow::Error),
+}
+
+impl StoreError {
+    /// Traduce `ConditionFailed` a un 409 con mensaje propio del caso de uso.
+    pub fn conflict(self, message: &str) -> ApiError {
+        match self {
+            StoreError::ConditionFailed => ApiError::Conflict(message.into()),
+            other => other.into(),
+        }
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::ConditionFailed => ApiError::Conflict("Conflicto de escritura concurrente".into()),
+            StoreError::InvalidCursor => ApiError::Validation("cursor inválido".into()),
+            StoreError::Backend(e) => ApiError::Internal(e),
+        }
+    }
+}
+
+/// Operaciones de bajo nivel sobre la tabla principal. Los repositorios de
+/// dominio están implementados sobre este trait, así que cualquier backend
+/// (DynamoDB o memoria) los obtiene automáticamente.
+#[async_trait]
+pub trait ItemStore: Send + Sync {
+    async fn get(&self, key: &Key) -> Result<Option<Item>, StoreError>;
+
+    /// Una página de resultados (DynamoDB corta en 1 MB o en `limit`).
+    async fn query(&self, query: &Query) -> Result<Page, StoreError>;
+
+    async fn write(&self, op: WriteOp) -> Result<(), StoreError>;
+
+    /// Todas las operaciones se aplican o ninguna (TransactWriteItems).
+    async fn transact(&self, ops: Vec<WriteOp>) -> Result<(), StoreError>;
+
+    /// Hasta `limit` items que pasan el filtro, recorriendo páginas si el filtro
+    /// descarta items. `last_key` continúa después del último item devuelto, así
+    /// que no se pierden los que quedaban en la página cortada.
+    async fn query_limited(&self, query: &Query, limit: usize) -> Result<Page, StoreError> {
+        let mut query = query.clone();
+        let mut items = Vec::new();
+        loop {
+            let page = self.query(&query).await?;
+            for item in page.items {
+                if items.len() == limit {
+                    let last_key = items.last().map(|last| query.start_key_after(last));
+                    return Ok(Page { items, last_key });
+                }
+                items.push(item);
+            }
+            match page.last_key {
+                Some(last) if items.len() < limit => query.start_key = Some(last),
+                last_key => return Ok(Page { items, last_key }),
+            }
+        }
+    }
+
+    /// Recorre todas las páginas de la query.
+    async fn query_all(&self, query: &Query) -> Result<Vec<Item>, StoreError> {
+        let mut query = query.clone();
+        let mut items = Vec::new();
+        loop {
+            let page = self.query(&query).await?;
+            items.extend(page.items);
+            match page.last_key {
+                Some(last) => query.start_key = Some(last),
+                None => return Ok(items),
+            }
+        }
+    }
+}
+
+// Helpers de lectura de atributos
+
+pub fn get_s(item: &Item, name: &str) -> Option<String> {
+    item.get(name).and_then(|v| v.as_s().ok()).cloned()
+}
+
+pub fn get_n<T: std::str::FromStr>(item: &Item, name: &str) -> Option<T> {
+    item.get(name).and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok())
+}
+
+pub fn get_list_s(item: &Item, name: &str) -> Vec<String> {
+    item.get(name)
+        .and_then(|v| v.as_l().ok())
+        .map(|list| list.iter().filter_map(|v| v.as_s().ok().cloned()).collect())
+        .unwrap_or_default()
+}
+
+pub fn s(value: impl Into<String>) -> AttributeValue {
+    AttributeValue::S(value.into())
+}
+
+pub fn n(value: impl ToString) -> AttributeValue {
+    AttributeValue::N(value.to_string())
+}
@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+
+use super::store::{get_s, s, Condition, Item, ItemStore, Key, Query, StoreError, WriteOp};
+use crate::models::{Channel, Locale, TemplateOverride};
+
+#[async_trait]
+pub trait TemplateRepository {
+    async fn get_template_override(
+        &self,
+        tenant_id: &str,
+        channel: Channel,
+        template: &str,
+        locale: Locale,
+    ) -> Result<Option<TemplateOverride>, StoreError>;
+    async fn list_template_overrides(&self, tenant_id: &str) -> Result<Vec<TemplateOverride>, StoreError>;
+    async fn put_template_override(&self, template: &TemplateOverride) -> Result<(), StoreError>;
+    /// Falla con `ConditionFailed` si el tenant no tenía esa plantilla.
+    async fn delete_template_override(&self, tenant_id: &str, channel: Channel, template: &str, locale: Locale) -> Result<(), StoreError>;
+}
+
+/// `TENANT#id` / `TEMPLATE#<canal>#<plantilla>#<idioma>`
+fn template_key(tenant_id: &str, channel: Channel, template: &str, locale: Locale) -> Key {
+    Key::new(
+        format!("TENANT#{}", tenant_id),
+        format!("TEMPLATE#{}#{}#{}", channel.as_str(), template, locale.as_str()),
+    )
+}
+
+fn template_to_item(template: &TemplateOverride) -> Item {
+    let mut item = template_key(&template.tenant_id, template.channel, &template.template, template.locale).to_item();
+    item.extend([
+        ("tenantId".to_string(), s(&template.tenant_id)),
+        ("channel".to_string(), s(template.channel.as_str())),
+        ("template".to_string(), s(&template.template)),
+        ("locale".to_string(), s(template.locale.as_str())),
+        ("body".to_string(), s(&template.body)),
+        ("updatedAt".to_string(), s(&template.updated_at)),
+    ]);
+    if let Some(subject) = &template.subject {
+        item.insert("subject".to_string(), s(subject));
+    }
+    item
+}
+
+fn template_from_item(item: &Item) -> Option<TemplateOverride> {
+    Some(TemplateOverride {
+        tenant_id: get_s(item, "tenantId")?,
+        channel: Channel::parse(&get_s(item, "channel")?)?,
+        template: get_s(item, "template")?,
+        locale: Locale::parse(&get_s(item, "locale")?)?,
+        subject: get_s(item, "subject"),
+        body: get_s(item, "body")?,
+        updated_at: get_s(item, "updatedAt").unwrap_or_default(),
+    })
+}
+
+#[async_trait]
+impl<S: ItemStore + ?Sized> TemplateRepository for S {
+    async fn get_template_override(
+        &self,
+        tenant_id: &str,
+        channel: Channel,
+        template: &str,
+        locale: Locale,
+    ) -> Result<Option<TemplateOverride>, StoreError> {
+        Ok(self.get(&template_key(tenant_id, channel, template, locale)).await?.as_ref().and_then(template_from_item))
+    }
+
+    async fn list_template_overrides(&self, tenant_id: &str) -> Result<Vec<TemplateOverride>, StoreError> {
+        let query = Query::partition(format!("TENANT#{}", tenant_id)).begins_with("TEMPLATE#");
+        Ok(self.query_all(&query).await?.iter().filter_map(template_from_item).collect())
+    }
+
+    async fn put_template_override(&self, template: &TemplateOverride) -> Result<(), StoreError> {
+        self.write(WriteOp::put(template_to_item(template))).await
+    }
+
+    async fn delete_template_override(&self, tenant_id: &str, channel: Channel, template: &str, locale: Locale) -> Result<(), StoreError> {
+        self.write(WriteOp::Delete {
+            key: template_key(tenant_id, channel, template, locale),
+            condition: Some(Condition::item_exists()),
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::MemoryStore;
+
+    #[tokio::test]
+    async fn test_overrides_are_per_tenant_channel_and_locale() {
+        let store = MemoryStore::new();
+        let template = TemplateOverride {
+            tenant_id: "tenant-a".into(),
+            channel: Channel::Email,
+            template: "booking-confirmation".into(),
+            locale: Locale::En,
+            subject: Some("Booked at {{ clinic_name }}".into()),
+            body: "<p>Hi {{ patient_name }}</p>".into(),
+            updated_at: "2025-10-01T10:00:00Z".into(),
+        };
+        store.put_template_override(&template).await.unwrap();
+
+        let stored = store.get_template_override("tenant-a", Channel::Email, "booking-confirmation", Locale::En).await.unwrap();
+        assert_eq!(stored, Some(template));
+        assert!(store.get_template_override("tenant-a", Channel::Email, "booking-confirmation", Locale::Es).await.unwrap().is_none());
+        assert!(store.get_template_override("tenant-a", Channel::Sms, "booking-confirmation", Locale::En).await.unwrap().is_none());
+        assert!(store.get_template_override("tenant-b", Channel::Email, "booking-confirmation", Locale::En).await.unwrap().is_none());
+        assert_eq!(store.list_template_overrides("tenant-a").await.unwrap().len(), 1);
+
+        store.delete_template_override("tenant-a", Channel::Email, "booking-confirmation", Locale::En).await.unwrap();
+        assert!(matches!(
+            store.delete_template_override("tenant-a", Channel::Email, "booking-confirmation", Locale::En).await,
+            Err(StoreError::ConditionFailed)
+        ));
+    }
+}
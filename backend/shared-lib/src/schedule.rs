@@ -0,0 +1,256 @@
+//! Horario semanal tipado de un profesional: franjas de atención por día de la
+//! semana y sede, con descansos. Se guarda como JSON en el atributo `schedule`.
+//!
+//! ```json
+//! {"days": [{"weekday": "mon", "site_id": "site-1", "start": "09:00", "end": "17:00",
+//!            "breaks": [{"start": "13:00", "end": "14:00"}]}]}
+//! ```
+//!
+//! Las sedes tienen además un horario de apertura (`OpeningHours`), con la misma
+//! forma pero sin sede ni descansos; la agenda ofrecida es la intersección de ambos.
+
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TimeRange {
+    #[serde(with = "hhmm")]
+    pub start: NaiveTime,
+    #[serde(with = "hhmm")]
+    pub end: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        TimeRange { start, end }
+    }
+
+    fn overlaps(&self, other: &TimeRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    fn contains(&self, other: &TimeRange) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    fn intersection(&self, other: &TimeRange) -> Option<TimeRange> {
+        let range = TimeRange::new(self.start.max(other.start), self.end.min(other.end));
+        (range.start < range.end).then_some(range)
+    }
+}
+
+/// Partes de `ranges` que caen dentro de alguna franja de `within`.
+pub fn intersect(ranges: &[TimeRange], within: &[TimeRange]) -> Vec<TimeRange> {
+    let mut out: Vec<TimeRange> = ranges
+        .iter()
+        .flat_map(|r| within.iter().filter_map(move |w| r.intersection(w)))
+        .collect();
+    out.sort();
+    out
+}
+
+/// Franja de atención de un día de la semana en una sede.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaySchedule {
+    /// `mon`..`sun` (también acepta el nombre completo en inglés)
+    pub weekday: Weekday,
+    pub site_id: String,
+    #[serde(flatten)]
+    pub hours: TimeRange,
+    #[serde(default)]
+    pub breaks: Vec<TimeRange>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WeeklySchedule {
+    #[serde(default)]
+    pub days: Vec<DaySchedule>,
+}
+
+impl WeeklySchedule {
+    /// Franjas inválidas, descansos fuera de horario o solapados, y un mismo día
+    /// con franjas que se solapan (aunque sean en sedes distintas).
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, day) in self.days.iter().enumerate() {
+            if day.site_id.trim().is_empty() {
+                return Err(format!("days[{}]: site_id requerido", i));
+            }
+            if day.hours.start >= day.hours.end {
+                return Err(format!("days[{}]: start debe ser anterior a end", i));
+            }
+            for (j, brk) in day.breaks.iter().enumerate() {
+                if brk.start >= brk.end || !day.hours.contains(brk) {
+                    return Err(format!("days[{}].breaks[{}]: fuera del horario de atención", i, j));
+                }
+                if day.breaks[..j].iter().any(|other| other.overlaps(brk)) {
+                    return Err(format!("days[{}].breaks[{}]: se solapa con otro descanso", i, j));
+                }
+            }
+            if self.days[..i].iter().any(|other| other.weekday == day.weekday && other.hours.overlaps(&day.hours)) {
+                return Err(format!("days[{}]: se solapa con otra franja del mismo día", i));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sedes en las que atiende según el horario.
+    pub fn site_ids(&self) -> Vec<&str> {
+        let mut sites: Vec<&str> = self.days.iter().map(|d| d.site_id.as_str()).collect();
+        sites.sort_unstable();
+        sites.dedup();
+        sites
+    }
+
+    /// Intervalos de atención en `site_id` para `date`, ya descontados los descansos.
+    pub fn working_ranges(&self, site_id: &str, date: NaiveDate) -> Vec<TimeRange> {
+        let mut ranges = vec![];
+        for day in self.days.iter().filter(|d| d.weekday == date.weekday() && d.site_id == site_id) {
+            let mut breaks = day.breaks.clone();
+            breaks.sort();
+            let mut cursor = day.hours.start;
+            for brk in breaks {
+                if brk.start > cursor {
+                    ranges.push(TimeRange::new(cursor, brk.start));
+                }
+                cursor = cursor.max(brk.end);
+            }
+            if cursor < day.hours.end {
+                ranges.push(TimeRange::new(cursor, day.hours.end));
+            }
+        }
+        ranges.sort();
+        ranges
+    }
+}
+
+/// Franja de apertura de una sede en un día de la semana.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpeningDay {
+    pub weekday: Weekday,
+    #[serde(flatten)]
+    pub hours: TimeRange,
+}
+
+/// Horario de apertura de una sede. Vacío significa que no restringe la agenda
+/// de los profesionales.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpeningHours {
+    #[serde(default)]
+    pub days: Vec<OpeningDay>,
+}
+
+impl OpeningHours {
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, day) in self.days.iter().enumerate() {
+            if day.hours.start >= day.hours.end {
+                return Err(format!("days[{}]: start debe ser anterior a end", i));
+            }
+            if self.days[..i].iter().any(|other| other.weekday == day.weekday && other.hours.overlaps(&day.hours)) {
+                return Err(format!("days[{}]: se solapa con otra franja del mismo día", i));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.days.is_empty()
+    }
+
+    /// Franjas abiertas en `date`.
+    pub fn ranges(&self, date: NaiveDate) -> Vec<TimeRange> {
+        let mut ranges: Vec<TimeRange> = self.days.iter().filter(|d| d.weekday == date.weekday()).map(|d| d.hours).collect();
+        ranges.sort();
+        ranges
+    }
+}
+
+pub(crate) mod hhmm {
+    use chrono::NaiveTime;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&time.format("%H:%M").to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&raw, "%H:%M").map_err(|_| de::Error::custom(format!("hora inválida '{}' (usar HH:MM)", raw)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn hm(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    fn schedule(value: serde_json::Value) -> WeeklySchedule {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_working_ranges_subtract_breaks() {
+        let s = schedule(json!({"days": [
+            {"weekday": "wed", "site_id": "s1", "start": "09:00", "end": "17:00",
+             "breaks": [{"start": "13:00", "end": "14:00"}]},
+            {"weekday": "wed", "site_id": "s2", "start": "18:00", "end": "20:00"}
+        ]}));
+        assert!(s.validate().is_ok());
+
+        // 2025-10-01 es miércoles
+        let date = NaiveDate::from_ymd_opt(2025, 10, 1).unwrap();
+        assert_eq!(
+            s.working_ranges("s1", date),
+            vec![TimeRange::new(hm("09:00"), hm("13:00")), TimeRange::new(hm("14:00"), hm("17:00"))]
+        );
+        assert!(s.working_ranges("s1", date.succ_opt().unwrap()).is_empty());
+        assert_eq!(s.site_ids(), ["s1", "s2"]);
+    }
+
+    #[test]
+    fn test_validate_rejects_inconsistent_schedules() {
+        let inverted = schedule(json!({"days": [{"weekday": "mon", "site_id": "s1", "start": "17:00", "end": "09:00"}]}));
+        assert!(inverted.validate().is_err());
+
+        let break_outside = schedule(json!({"days": [{"weekday": "mon", "site_id": "s1", "start": "09:00", "end": "12:00",
+            "breaks": [{"start": "11:30", "end": "12:30"}]}]}));
+        assert!(break_outside.validate().is_err());
+
+        let double_booked = schedule(json!({"days": [
+            {"weekday": "mon", "site_id": "s1", "start": "09:00", "end": "13:00"},
+            {"weekday": "mon", "site_id": "s2", "start": "12:00", "end": "15:00"}
+        ]}));
+        assert!(double_booked.validate().is_err());
+    }
+
+    #[test]
+    fn test_opening_hours_clip_working_ranges() {
+        let s = schedule(json!({"days": [
+            {"weekday": "wed", "site_id": "s1", "start": "07:00", "end": "19:00",
+             "breaks": [{"start": "13:00", "end": "14:00"}]}
+        ]}));
+        let open: OpeningHours = serde_json::from_value(json!({"days": [
+            {"weekday": "wed", "start": "08:00", "end": "12:00"},
+            {"weekday": "wed", "start": "15:00", "end": "18:00"}
+        ]})).unwrap();
+        assert!(open.validate().is_ok());
+
+        let date = NaiveDate::from_ymd_opt(2025, 10, 1).unwrap();
+        assert_eq!(
+            intersect(&s.working_ranges("s1", date), &open.ranges(date)),
+            vec![TimeRange::new(hm("08:00"), hm("12:00")), TimeRange::new(hm("15:00"), hm("18:00"))]
+        );
+        assert!(open.ranges(date.succ_opt().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_legacy_empty_schedule_and_format() {
+        assert_eq!(serde_json::from_str::<WeeklySchedule>("{}").unwrap(), WeeklySchedule::default());
+        assert!(serde_json::from_value::<WeeklySchedule>(json!({"days": [
+            {"weekday": "mon", "site_id": "s1", "start": "9am", "end": "17:00"}
+        ]})).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Unidades de agenda. Una reserva ocupa cada unidad de `granularity_minutes()`
+//! que toca su intervalo `[start, end)` (duración + buffer); cada unidad es un
+//! slot lock del profesional (`SLOT#HH:MM`), así que dos citas se solapan si y solo si
+//! comparten al menos un lock y la transacción de la segunda falla.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::error::ApiError;
+use crate::models::{Booking, Professional, Site, SlotHold, SlotLock};
+use crate::repository::{BookingFilter, PageRequest, Repository, StoreError};
+use crate::schedule::intersect;
+use crate::timezone::{local_to_utc, Tz};
+
+pub const DEFAULT_GRANULARITY_MINUTES: i64 = 15;
+pub const DEFAULT_HOLD_MINUTES: i64 = 10;
+
+/// TransactWriteItems admite 100 items. Además de las unidades van, como
+/// máximo, ocho más: booking (o historial), evento de outbox, retención y el
+/// paciente nuevo con sus búsquedas y su documento.
+pub const MAX_UNITS_PER_BOOKING: usize = 92;
+
+/// `SLOT_GRANULARITY_MINUTES` (por defecto 15). Debe dividir el día exacto para
+/// que las unidades queden alineadas entre reservas.
+pub fn granularity_minutes() -> i64 {
+    std::env::var("SLOT_GRANULARITY_MINUTES")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|g| *g > 0 && 1440 % g == 0)
+        .unwrap_or(DEFAULT_GRANULARITY_MINUTES)
+}
+
+/// `SLOT_HOLD_MINUTES` (por defecto 10): cuánto dura una retención sin confirmar.
+pub fn hold_minutes() -> i64 {
+    std::env::var("SLOT_HOLD_MINUTES")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|m| *m > 0)
+        .unwrap_or(DEFAULT_HOLD_MINUTES)
+}
+
+/// Inicio de cada unidad que toca `[start, end)`, empezando por la unidad que contiene `start`.
+pub fn units(start: DateTime<Utc>, end: DateTime<Utc>, granularity: i64) -> Vec<DateTime<Utc>> {
+    let step = granularity * 60;
+    let first = start.timestamp() - start.timestamp().rem_euclid(step);
+    let mut unit = DateTime::<Utc>::from_timestamp(first, 0).unwrap_or(start);
+    let mut out = vec![];
+    while unit < end {
+        out.push(unit);
+        unit += Duration::seconds(step);
+    }
+    out
+}
+
+/// Franjas `[inicio, fin)` en UTC en que el profesional atiende en la sede el
+/// día local `date`: su horario sin descansos, recortado al de apertura de la
+/// sede. Se recorren en tiempo real, así que en un cambio de horario la franja
+/// dura lo que marca el reloj de pared.
+pub fn open_shifts(professional: &Professional, site: &Site, date: NaiveDate, tz: Tz) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut ranges = professional.schedule.working_ranges(&site.id, date);
+    if !site.opening_hours.is_empty() {
+        ranges = intersect(&ranges, &site.opening_hours.ranges(date));
+    }
+    ranges
+        .iter()
+        .map(|range| (local_to_utc(tz, date.and_time(range.start)), local_to_utc(tz, date.and_time(range.end))))
+        .collect()
+}
+
+/// Si la cita `[start, end)` cabe entera en una franja de atención del día
+/// local en que empieza: lo mismo que ofrece la disponibilidad.
+pub fn within_shifts(professional: &Professional, site: &Site, tz: Tz, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+    let date = start.with_timezone(&tz).date_naive();
+    open_shifts(professional, site, date, tz)
+        .iter()
+        .any(|(from, to)| *from <= start && end <= *to)
+}
+
+/// Locks que debe reclamar (o liberar) la reserva según su `start_time`/`end_time`.
+pub fn booking_locks(booking: &Booking, now: &str) -> Result<Vec<SlotLock>, ApiError> {
+    let template = SlotLock {
+        tenant_id: booking.tenant_id.clone(),
+        site_id: booking.site_id.clone(),
+        date: String::new(),
+        time: String::new(),
+        professional_id: booking.professional_id.clone(),
+        booking_id: booking.id.clone(),
+        status: "reserved".into(),
+        created_at: now.to_string(),
+        hold_token: None,
+        expires_at: None,
+    };
+    unit_locks(&template, &booking.start_time, &booking.end_time)
+}
+
+/// Locks `held` de una retención: los mismos que tendrá la reserva al confirmarla.
+pub fn hold_locks(hold: &SlotHold) -> Result<Vec<SlotLock>, ApiError> {
+    let template = SlotLock {
+        tenant_id: hold.tenant_id.clone(),
+        site_id: hold.site_id.clone(),
+        date: String::new(),
+        time: String::new(),
+        professional_id: hold.professional_id.clone(),
+        booking_id: hold.id.clone(),
+        status: "held".into(),
+        created_at: hold.created_at.clone(),
+        hold_token: Some(hold.token.clone()),
+        expires_at: Some(hold.expires_at),
+    };
+    unit_locks(&template, &hold.start_time, &hold.end_time)
+}
+
+/// Un lock por unidad de `[start, end)`, copiando el resto de campos de `template`.
+fn unit_locks(template: &SlotLock, start: &str, end: &str) -> Result<Vec<SlotLock>, ApiError> {
+    let units = units(parse(start)?, parse(end)?, granularity_minutes());
+    if units.len() > MAX_UNITS_PER_BOOKING {
+        return Err(ApiError::Validation("La cita excede la duración máxima reservable".into()));
+    }
+    Ok(units
+        .into_iter()
+        .map(|unit| SlotLock {
+            date: unit.format("%Y-%m-%d").to_string(),
+            time: unit.format("%H:%M").to_string(),
+            ..template.clone()
+        })
+        .collect())
+}
+
+fn parse(value: &str) -> Result<DateTime<Utc>, ApiError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| ApiError::Validation(format!("Fecha inválida: {}", value)))
+}
+
+/// Resultado de `migrate_slot_locks`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct LockMigrationReport {
+    /// Reservas futuras no canceladas leídas
+    pub bookings: usize,
+    /// Pasadas al esquema por profesional (o que se pasarían en `dry_run`)
+    pub migrated: usize,
+    /// Sin locks del esquema por sede: ya migradas o creadas después
+    pub already_migrated: usize,
+    /// Reservas con alguna unidad ocupada por otra reserva del profesional
+    /// (en otra sede). Conservan sus locks viejos hasta resolverlas a mano.
+    pub conflicts: Vec<String>,
+}
+
+/// Pasa los locks de las reservas futuras no canceladas del tenant del
+/// esquema por sede (`TENANT#t#SITE#s#DATE#d` / `SLOT#HH:MM#prof`) al de por
+/// profesional y borra los viejos. Se puede repetir: las reservas sin locks
+/// viejos se saltan. Con `dry_run` no escribe nada (ni detecta conflictos).
+///
+/// Cada reserva queda con un lock por unidad (`booking_locks`), también las
+/// anteriores a las unidades, que solo tenían el lock de su hora de inicio:
+/// así cancelarlas o reprogramarlas libera exactamente lo que tienen.
+pub async fn migrate_slot_locks(repo: &dyn Repository, tenant_id: &str, dry_run: bool, now: &str) -> Result<LockMigrationReport, ApiError> {
+    let mut report = LockMigrationReport::default();
+    let filter = BookingFilter { start_between: Some((now.to_string(), "9999-12-31T23:59:59Z".to_string())), ..Default::default() };
+    let mut page = PageRequest::new(Some(100), None);
+    loop {
+        let result = repo.list_bookings(tenant_id, &filter, &page).await?;
+        for booking in result.bookings.iter().filter(|b| !b.status.is_cancelled()) {
+            report.bookings += 1;
+            let locks = booking_locks(booking, now)?;
+            let mut dates: Vec<&str> = locks.iter().map(|lock| lock.date.as_str()).collect();
+            dates.dedup();
+            let mut legacy = vec![];
+            for date in dates {
+                let found = repo.list_legacy_slot_locks(tenant_id, &booking.site_id, date).await?;
+                legacy.extend(found.into_iter().filter(|l| l.booking_id == booking.id && l.professional_id == booking.professional_id));
+            }
+            if legacy.is_empty() {
+                report.already_migrated += 1;
+                continue;
+            }
+            if dry_run {
+                report.migrated += 1;
+                continue;
+            }
+            match repo.migrate_slot_locks(&legacy, &locks).await {
+                Ok(()) => report.migrated += 1,
+                Err(StoreError::ConditionFailed) => report.conflicts.push(booking.id.clone()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match result.next_cursor {
+            Some(cursor) => page = PageRequest::new(Some(100), Some(cursor)),
+            None => return Ok(report),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{BookingEvent, BookingStatus, StatusChange};
+    use crate::repository::{s, BookingRepository, Item, Key, MemoryStore, SlotLockRepository};
+    use crate::testing;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn booking(start: &str, end: &str) -> Booking {
+        Booking { start_time: start.into(), end_time: end.into(), ..testing::booking(BookingStatus::Confirmed) }
+    }
+
+    #[test]
+    fn test_units_cover_whole_interval() {
+        let units = units(at("2025-10-01T10:00:00Z"), at("2025-10-01T11:00:00Z"), 15);
+        assert_eq!(units.len(), 4);
+        assert_eq!(units[3], at("2025-10-01T10:45:00Z"));
+    }
+
+    #[test]
+    fn test_unaligned_interval_claims_partial_units() {
+        let units = units(at("2025-10-01T10:10:00Z"), at("2025-10-01T10:35:00Z"), 15);
+        assert_eq!(units, vec![at("2025-10-01T10:00:00Z"), at("2025-10-01T10:15:00Z"), at("2025-10-01T10:30:00Z")]);
+    }
+
+    #[test]
+    fn test_booking_locks_span_midnight_and_offsets() {
+        let locks = booking_locks(&booking("2025-10-01T18:30:00-05:00", "2025-10-01T19:30:00-05:00"), "now").unwrap();
+        let keys: Vec<_> = locks.iter().map(|l| format!("{} {}", l.date, l.time)).collect();
+        assert_eq!(keys, ["2025-10-01 23:30", "2025-10-01 23:45", "2025-10-02 00:00", "2025-10-02 00:15"]);
+    }
+
+    #[test]
+    fn test_overlapping_bookings_share_a_unit() {
+        let a = booking_locks(&booking("2025-10-01T10:00:00Z", "2025-10-01T11:00:00Z"), "now").unwrap();
+        let b = booking_locks(&booking("2025-10-01T10:15:00Z", "2025-10-01T10:45:00Z"), "now").unwrap();
+        assert!(b.iter().all(|lock| a.iter().any(|l| l.time == lock.time)));
+
+        let c = booking_locks(&booking("2025-10-01T11:00:00Z", "2025-10-01T11:30:00Z"), "now").unwrap();
+        assert!(c.iter().all(|lock| a.iter().all(|l| l.time != lock.time)));
+    }
+
+    /// Lock con la clave por sede, como los escribía la versión anterior.
+    fn legacy_lock(site_id: &str, time: &str, booking_id: &str) -> Item {
+        let mut item = Key::new(format!("TENANT#tenant-a#SITE#{}#DATE#2025-10-01", site_id), format!("SLOT#{}#prof-1", time)).to_item();
+        item.extend([
+            ("bookingId".to_string(), s(booking_id)),
+            ("status".to_string(), s("reserved")),
+            ("createdAt".to_string(), s("2025-09-01T00:00:00Z")),
+        ]);
+        item
+    }
+
+    #[tokio::test]
+    async fn test_migrate_slot_locks_rekeys_legacy_locks_by_professional() {
+        let store = MemoryStore::new();
+        let now = "2025-09-15T00:00:00+00:00";
+        let first = booking("2025-10-01T15:00:00Z", "2025-10-01T15:30:00Z");
+        // El mismo profesional a la misma hora en otra sede: el esquema por sede lo permitía
+        let second = Booking { id: "b2".into(), site_id: "site-2".into(), ..booking("2025-10-01T15:15:00Z", "2025-10-01T15:30:00Z") };
+        for b in [&first, &second] {
+            store.create_booking(b, None, &[], &BookingEvent::created(b, now)).await.unwrap();
+        }
+        store.seed([legacy_lock("site-1", "15:00", "b1"), legacy_lock("site-1", "15:15", "b1"), legacy_lock("site-2", "15:15", "b2")]);
+
+        let before = store.len();
+        let dry_run = migrate_slot_locks(&store, "tenant-a", true, now).await.unwrap();
+        assert_eq!((dry_run.bookings, dry_run.migrated), (2, 2));
+        assert_eq!(store.len(), before);
+
+        let report = migrate_slot_locks(&store, "tenant-a", false, now).await.unwrap();
+        assert_eq!(report.migrated, 1);
+        assert_eq!(report.conflicts, ["b2"]);
+        let locks = store.list_slot_locks("tenant-a", "prof-1", "2025-10-01").await.unwrap();
+        let keys: Vec<_> = locks.iter().map(|l| (l.time.as_str(), l.booking_id.as_str(), l.site_id.as_str())).collect();
+        assert_eq!(keys, [("15:00", "b1", "site-1"), ("15:15", "b1", "site-1")]);
+        assert!(store.list_legacy_slot_locks("tenant-a", "site-1", "2025-10-01").await.unwrap().is_empty());
+        // La reserva en conflicto conserva su lock viejo
+        assert_eq!(store.list_legacy_slot_locks("tenant-a", "site-2", "2025-10-01").await.unwrap().len(), 1);
+
+        let again = migrate_slot_locks(&store, "tenant-a", false, now).await.unwrap();
+        assert_eq!((again.already_migrated, again.migrated), (1, 0));
+        assert_eq!(again.conflicts, ["b2"]);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_slot_locks_gives_legacy_single_lock_bookings_every_unit() {
+        let store = MemoryStore::new();
+        let now = "2025-09-15T00:00:00+00:00";
+        // Reserva anterior a las unidades: un solo lock, el de la hora de inicio
+        let legacy = booking("2025-10-01T15:00:00Z", "2025-10-01T15:40:00Z");
+        store.create_booking(&legacy, None, &[], &BookingEvent::created(&legacy, now)).await.unwrap();
+        store.seed([legacy_lock("site-1", "15:00", "b1")]);
+
+        let report = migrate_slot_locks(&store, "tenant-a", false, now).await.unwrap();
+        assert_eq!(report.migrated, 1);
+        let times: Vec<_> = store.list_slot_locks("tenant-a", "prof-1", "2025-10-01").await.unwrap().into_iter().map(|l| l.time).collect();
+        assert_eq!(times, ["15:00", "15:15", "15:30"]);
+
+        // Cancelarla libera todas sus unidades
+        let release = booking_locks(&legacy, now).unwrap();
+        let change = StatusChange {
+            booking_id: "b1".into(),
+            from: BookingStatus::Confirmed,
+            to: BookingStatus::CancelledByClinic,
+            actor: "admin@example.com".into(),
+            reason: None,
+            at: now.into(),
+        };
+        let cancelled = Booking { status: BookingStatus::CancelledByClinic, ..legacy.clone() };
+        let event = BookingEvent::status_changed(&cancelled, BookingStatus::Confirmed, now);
+        store.transition_booking(&change, &release, &event).await.unwrap();
+        assert!(store.list_slot_locks("tenant-a", "prof-1", "2025-10-01").await.unwrap().is_empty());
+    }
+}
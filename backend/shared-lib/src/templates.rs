@@ -0,0 +1,197 @@
+//! Lo común de las plantillas de notificación: el contexto que reciben, el
+//! entorno de minijinja con los parciales de cada idioma y el render de las
+//! plantillas propias de las clínicas (`TemplateOverride`). Lo usan
+//! send-notification al enviar y tenants al guardar una plantilla, para que
+//! las dos validen igual.
+//!
+//! Una variable desconocida es un error de render (incluso dentro de un `if`),
+//! igual que un include a un parcial que no existe. Un `{% include
+//! "email/partials/..." %}` carga el parcial del mismo idioma que la plantilla
+//! que lo incluye.
+
+use std::borrow::Cow;
+
+use minijinja::{Environment, UndefinedBehavior};
+use serde::Serialize;
+
+use crate::models::{Channel, Locale, TemplateOverride};
+
+macro_rules! bundled {
+    ($($path:literal),* $(,)?) => {
+        &[$(($path, include_str!(concat!("../templates/", $path)))),*]
+    };
+}
+
+const PARTIALS: &[(&str, &str)] = bundled![
+    "es/email/partials/header.html",
+    "es/email/partials/footer.html",
+    "es/email/partials/details.html",
+    "en/email/partials/header.html",
+    "en/email/partials/footer.html",
+    "en/email/partials/details.html",
+    "pt/email/partials/header.html",
+    "pt/email/partials/footer.html",
+    "pt/email/partials/details.html",
+];
+
+/// Variables disponibles en las plantillas.
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateContext {
+    pub patient_name: String,
+    pub booking_id: String,
+    pub appointment_date: String,
+    pub appointment_time: String,
+    pub previous_appointment_date: Option<String>,
+    pub previous_appointment_time: Option<String>,
+    pub professional_name: Option<String>,
+    pub treatment_name: Option<String>,
+    pub clinic_address: Option<String>,
+    pub clinic_email: String,
+    pub clinic_name: String,
+    pub hours_before: Option<u32>,
+    pub app_url: String,
+    pub manage_booking_url: String,
+    pub booking_url: String,
+    /// Página de baja firmada para este paciente, canal y tipo de mensaje
+    pub unsubscribe_url: Option<String>,
+    /// Marca de la clínica: logo del encabezado, color de los botones y, si
+    /// recibe respuestas, a dónde
+    pub logo_url: Option<String>,
+    pub primary_color: String,
+    pub reply_to: Option<String>,
+}
+
+/// Color de Turnaki, para las clínicas que no definen el suyo.
+pub const DEFAULT_PRIMARY_COLOR: &str = "#0ea5e9";
+
+impl TemplateContext {
+    /// Contexto con todos los campos, para validar las plantillas.
+    pub fn sample() -> Self {
+        let some = |value: &str| Some(value.to_string());
+        Self {
+            patient_name: "Ana".into(),
+            booking_id: "b1".into(),
+            appointment_date: "2025-10-01".into(),
+            appointment_time: "10:00".into(),
+            previous_appointment_date: some("2025-09-30"),
+            previous_appointment_time: some("09:00"),
+            professional_name: some("Dra. Pérez"),
+            treatment_name: some("Limpieza"),
+            clinic_address: some("Calle 100"),
+            clinic_email: "clinica@example.com".into(),
+            clinic_name: "Clínica A".into(),
+            hours_before: Some(24),
+            app_url: "https://example.com".into(),
+            manage_booking_url: "https://example.com/my-appointments".into(),
+            booking_url: "https://example.com/booking".into(),
+            unsubscribe_url: some("https://example.com/unsubscribe?token=t"),
+            logo_url: some("https://example.com/logo.png"),
+            primary_color: "#123456".into(),
+            reply_to: some("recepcion@example.com"),
+        }
+    }
+
+    /// El mismo sin ningún opcional.
+    pub fn minimal() -> Self {
+        Self {
+            previous_appointment_date: None,
+            previous_appointment_time: None,
+            professional_name: None,
+            treatment_name: None,
+            clinic_address: None,
+            hours_before: None,
+            unsubscribe_url: None,
+            logo_url: None,
+            reply_to: None,
+            ..Self::sample()
+        }
+    }
+}
+
+/// Entorno con los parciales de todos los idiomas. Los includes sin idioma
+/// se resuelven con el de la plantilla que incluye.
+pub fn environment<'source>() -> Result<Environment<'source>, minijinja::Error> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_path_join_callback(|name, parent| {
+        let has_locale = |path: &str| path.split_once('/').is_some_and(|(locale, _)| Locale::parse(locale).is_some());
+        match parent.split_once('/') {
+            Some((locale, _)) if Locale::parse(locale).is_some() && !has_locale(name) => Cow::Owned(format!("{}/{}", locale, name)),
+            _ => Cow::Borrowed(name),
+        }
+    });
+    for (name, source) in PARTIALS {
+        env.add_template(name, source)?;
+    }
+    Ok(env)
+}
+
+/// Nombre de la plantilla de un canal; la extensión `.html` es la que activa
+/// el autoescape.
+pub fn file_name(locale: Locale, channel: Channel, name: &str) -> String {
+    match channel {
+        Channel::Email => format!("{}/email/{}.html", locale.as_str(), name),
+        Channel::Sms | Channel::Whatsapp => format!("{}/{}/{}.txt", locale.as_str(), channel.as_str(), name),
+    }
+}
+
+/// Nombre del asunto de un email.
+pub fn subject_name(locale: Locale, name: &str) -> String {
+    format!("{}/subject/{}.txt", locale.as_str(), name)
+}
+
+/// Asunto (si trae) y cuerpo de una plantilla de la clínica. Se compila en
+/// cada uso: no está en ningún entorno cargado de antemano.
+pub fn render_override(custom: &TemplateOverride, context: &TemplateContext) -> Result<(Option<String>, String), minijinja::Error> {
+    let env = environment()?;
+    let name = file_name(custom.locale, custom.channel, &custom.template);
+    let body = env.template_from_named_str(&name, &custom.body)?.render(context)?;
+    let subject = match &custom.subject {
+        Some(subject) => {
+            let name = subject_name(custom.locale, &custom.template);
+            Some(env.template_from_named_str(&name, subject)?.render(context)?)
+        }
+        None => None,
+    };
+    Ok((subject, body))
+}
+
+/// Error que haría enviar la incluida en lugar de la plantilla de la clínica.
+pub fn check_override(custom: &TemplateOverride, context: &TemplateContext) -> Result<(), minijinja::Error> {
+    render_override(custom, context).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(subject: Option<&str>, body: &str) -> TemplateOverride {
+        TemplateOverride {
+            tenant_id: "tenant-a".into(),
+            channel: Channel::Email,
+            template: "booking-confirmation".into(),
+            locale: Locale::Pt,
+            subject: subject.map(String::from),
+            body: body.into(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_check_override_uses_partials_and_strict_variables() {
+        let context = TemplateContext::sample();
+        let with_partial = custom(Some("{{ clinic_name }}"), "<p>{{ patient_name }}</p>{% include \"email/partials/footer.html\" %}");
+        assert!(check_override(&with_partial, &context).is_ok());
+        let (_, body) = render_override(&with_partial, &context).unwrap();
+        // El parcial es el del idioma de la plantilla
+        assert!(body.starts_with("<p>Ana</p>"));
+        assert!(body.contains("Precisa de ajuda? Fale conosco"));
+
+        let typo = check_override(&custom(None, "Olá {{ patient_nmae }}"), &context);
+        assert_eq!(typo.unwrap_err().kind(), minijinja::ErrorKind::UndefinedError);
+        let in_subject = check_override(&custom(Some("{% if hours_befor %}x{% endif %}"), "y"), &context);
+        assert_eq!(in_subject.unwrap_err().kind(), minijinja::ErrorKind::UndefinedError);
+        let missing_partial = check_override(&custom(None, "{% include \"email/partials/missing.html\" %}"), &context);
+        assert_eq!(missing_partial.unwrap_err().kind(), minijinja::ErrorKind::TemplateNotFound);
+    }
+}
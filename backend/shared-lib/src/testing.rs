@@ -0,0 +1,93 @@
+//! Utilidades para tests de handlers (feature `test-utils`): firman tokens con
+//! la llave de `tests/fixtures` y apuntan el verificador al JWKS local, y
+//! construyen las entidades que casi todos los tests necesitan; cada test
+//! cambia solo los campos que le importan (`Booking { id, ..booking(status) }`).
+
+use std::sync::Once;
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::json;
+
+use crate::models::{Booking, BookingStatus, Tenant};
+
+pub const TEST_ISSUER: &str = "https://cognito-idp.us-east-1.amazonaws.com/us-east-1_test";
+pub const TEST_CLIENT_ID: &str = "test-client";
+
+static INIT: Once = Once::new();
+
+fn fixture_path(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Configura el entorno del verificador JWT para usar el JWKS de fixtures.
+pub fn init_test_auth() {
+    INIT.call_once(|| {
+        std::env::set_var("JWKS_PATH", fixture_path("jwks.json"));
+        std::env::set_var("JWT_ISSUER", TEST_ISSUER);
+        std::env::set_var("JWT_AUDIENCE", TEST_CLIENT_ID);
+        std::env::set_var("JWT_TOKEN_USE", "id");
+    });
+}
+
+/// Firma un conjunto arbitrario de claims con la llave de test.
+pub fn sign_claims(claims: &serde_json::Value) -> String {
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("test-key-1".into());
+    let pem = std::fs::read(fixture_path("jwt-test-key.pem")).expect("fixture jwt-test-key.pem");
+    let key = EncodingKey::from_rsa_pem(&pem).expect("llave RSA de test");
+    encode(&header, claims, &key).expect("firma JWT de test")
+}
+
+/// ID token válido por 10 minutos para `tenant_id` con los grupos indicados.
+pub fn test_token(tenant_id: &str, groups: &[&str], email: &str) -> String {
+    init_test_auth();
+    sign_claims(&json!({
+        "sub": format!("sub-{}", email),
+        "email": email,
+        "cognito:groups": groups,
+        "custom:tenant_id": tenant_id,
+        "token_use": "id",
+        "aud": TEST_CLIENT_ID,
+        "iss": TEST_ISSUER,
+        "exp": chrono::Utc::now().timestamp() + 600,
+    }))
+}
+
+/// Valor listo para el header `Authorization`.
+pub fn bearer(tenant_id: &str, groups: &[&str], email: &str) -> String {
+    format!("Bearer {}", test_token(tenant_id, groups, email))
+}
+
+/// Clínica activa en America/Bogota con la configuración por defecto.
+pub fn tenant(id: &str) -> Tenant {
+    Tenant {
+        id: id.into(),
+        name: "Clínica A".into(),
+        contact_email: "a@example.com".into(),
+        timezone: "America/Bogota".into(),
+        created_at: String::new(),
+        status: "active".into(),
+        settings: Default::default(),
+    }
+}
+
+/// Reserva `b1` de Ana en tenant-a (site-1, prof-1, treat-1), el 2025-10-01 de
+/// 15:00 a 15:40 UTC, sin paciente del directorio ni preferencias de canal.
+pub fn booking(status: BookingStatus) -> Booking {
+    Booking {
+        id: "b1".into(),
+        tenant_id: "tenant-a".into(),
+        site_id: "site-1".into(),
+        professional_id: "prof-1".into(),
+        treatment_id: "treat-1".into(),
+        start_time: "2025-10-01T15:00:00+00:00".into(),
+        end_time: "2025-10-01T15:40:00+00:00".into(),
+        patient_name: "Ana".into(),
+        patient_email: "ana@example.com".into(),
+        patient_phone: None,
+        notification_channels: vec![],
+        patient_id: None,
+        status,
+        created_at: String::new(),
+    }
+}
@@ -0,0 +1,100 @@
+//! Zona horaria de la clínica. Lo que ve o configura la clínica (horarios,
+//! slots ofrecidos, recordatorios) es hora local del tenant en su zona IANA;
+//! lo persistido (`startTime`, `endTime`, claves de slot lock) es UTC.
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+pub use chrono_tz::Tz;
+
+use crate::error::ApiError;
+
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::America::Bogota;
+
+pub fn parse_timezone(name: &str) -> Result<Tz, ApiError> {
+    name.parse::<Tz>()
+        .map_err(|_| ApiError::Validation(format!("Zona horaria inválida: {} (usar IANA, p. ej. America/Bogota)", name)))
+}
+
+/// Instante de una hora local. En el salto de DST (hora inexistente) avanza lo
+/// que dura el salto, como lo haría un reloj de pared; en la hora repetida toma
+/// la primera ocurrencia.
+pub fn local_to_utc(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(dt) => dt.with_timezone(&Utc),
+        LocalResult::Ambiguous(first, _) => first.with_timezone(&Utc),
+        LocalResult::None => {
+            // Offset vigente antes del salto: 02:30 EST(-5) → 07:30Z → 03:30 EDT
+            let before = tz.from_utc_datetime(&(local - Duration::days(1))).offset().fix();
+            (local - Duration::seconds(before.local_minus_utc() as i64)).and_utc()
+        }
+    }
+}
+
+/// Fecha-hora de la API. Con offset (`Z`, `-05:00`) es un instante absoluto;
+/// sin offset (`2025-10-01T10:00[:00]`) se interpreta como hora local del tenant.
+pub fn parse_instant(raw: &str, tz: Tz) -> Result<DateTime<Utc>, ApiError> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(raw, fmt).ok())
+        .map(|local| local_to_utc(tz, local))
+        .ok_or_else(|| ApiError::Validation("start_time inválido (usar ISO8601)".into()))
+}
+
+/// Intervalo UTC `[inicio, fin)` que cubre el día local `date`. Dura 23 o 25
+/// horas en los días de cambio de horario.
+pub fn local_day_bounds(tz: Tz, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let next = date.succ_opt().unwrap_or(date);
+    (
+        local_to_utc(tz, date.and_hms_opt(0, 0, 0).unwrap_or_default()),
+        local_to_utc(tz, next.and_hms_opt(0, 0, 0).unwrap_or_default()),
+    )
+}
+
+/// Fecha local actual en la zona del tenant.
+pub fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M").unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_naive_times_use_tenant_zone() {
+        assert_eq!(parse_instant("2025-10-01T10:00", DEFAULT_TIMEZONE).unwrap(), utc("2025-10-01T15:00:00Z"));
+        // Con offset explícito se respeta el instante enviado
+        assert_eq!(parse_instant("2025-10-01T10:00:00+02:00", DEFAULT_TIMEZONE).unwrap(), utc("2025-10-01T08:00:00Z"));
+        assert!(parse_timezone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_spring_forward_gap_moves_ahead() {
+        let ny = parse_timezone("America/New_York").unwrap();
+        // 2025-03-09 02:00 EST → 03:00 EDT
+        assert_eq!(local_to_utc(ny, naive("2025-03-09T02:30")), utc("2025-03-09T07:30:00Z"));
+        assert_eq!(local_to_utc(ny, naive("2025-03-09T03:30")), utc("2025-03-09T07:30:00Z"));
+
+        let (start, end) = local_day_bounds(ny, NaiveDate::from_ymd_opt(2025, 3, 9).unwrap());
+        assert_eq!(end - start, Duration::hours(23));
+    }
+
+    #[test]
+    fn test_fall_back_takes_first_occurrence() {
+        let ny = parse_timezone("America/New_York").unwrap();
+        // 2025-11-02 02:00 EDT → 01:00 EST: 01:30 ocurre dos veces
+        assert_eq!(local_to_utc(ny, naive("2025-11-02T01:30")), utc("2025-11-02T05:30:00Z"));
+
+        let (start, end) = local_day_bounds(ny, NaiveDate::from_ymd_opt(2025, 11, 2).unwrap());
+        assert_eq!(end - start, Duration::hours(25));
+    }
+}
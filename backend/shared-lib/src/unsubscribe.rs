@@ -0,0 +1,74 @@
+//! Enlaces de baja firmados. El token lleva el tenant, el email del paciente,
+//! el canal y el tipo de mensaje, firmados con HMAC-SHA256
+//! (`UNSUBSCRIBE_SECRET`): quien lo tiene puede darse de baja de eso sin
+//! iniciar sesión, y nadie puede fabricar uno para otro paciente. No vence:
+//! un enlace de un email viejo tiene que seguir funcionando.
+//!
+//! Formato: `base64url(json)` `.` `base64url(hmac)`.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::models::{Channel, MessageCategory};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnsubscribeToken {
+    pub tenant_id: String,
+    pub email: String,
+    pub channel: Channel,
+    pub category: MessageCategory,
+}
+
+/// `UNSUBSCRIBE_SECRET`; sin él no se generan enlaces ni se aceptan bajas.
+pub fn secret_from_env() -> Option<Vec<u8>> {
+    std::env::var("UNSUBSCRIBE_SECRET").ok().filter(|s| !s.is_empty()).map(String::into_bytes)
+}
+
+fn mac(secret: &[u8], payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC acepta claves de cualquier largo");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+impl UnsubscribeToken {
+    pub fn sign(&self, secret: &[u8]) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default());
+        let signature = URL_SAFE_NO_PAD.encode(mac(secret, &payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    /// `None` si el token está mal formado o la firma no corresponde.
+    pub fn verify(token: &str, secret: &[u8]) -> Option<UnsubscribeToken> {
+        let (payload, signature) = token.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        mac(secret, payload).verify_slice(&signature).ok()?;
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_round_trip_and_tampering() {
+        let token = UnsubscribeToken {
+            tenant_id: "tenant-a".into(),
+            email: "ana@example.com".into(),
+            channel: Channel::Email,
+            category: MessageCategory::Transactional,
+        };
+        let signed = token.sign(b"secret");
+        assert_eq!(UnsubscribeToken::verify(&signed, b"secret"), Some(token.clone()));
+        assert_eq!(UnsubscribeToken::verify(&signed, b"otro"), None);
+
+        // Otro paciente con la firma del primero
+        let other = UnsubscribeToken { email: "luis@example.com".into(), ..token }.sign(b"secret");
+        let forged = format!("{}.{}", other.split_once('.').unwrap().0, signed.split_once('.').unwrap().1);
+        assert_eq!(UnsubscribeToken::verify(&forged, b"secret"), None);
+        assert_eq!(UnsubscribeToken::verify("sin-punto", b"secret"), None);
+    }
+}
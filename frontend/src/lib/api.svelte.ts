@@ -96,7 +96,13 @@ class ApiClient {
     start_time: string;
     patient_name: string;
     patient_email: string;
-  }) => this.fetch('/bookings', { method: 'POST', body: JSON.stringify(data) });
+  }, idempotencyKey?: string) =>
+    this.fetch('/bookings', {
+      method: 'POST',
+      body: JSON.stringify(data),
+      // Un reintento con la misma key devuelve la reserva ya creada
+      headers: idempotencyKey ? { 'Idempotency-Key': idempotencyKey } : undefined
+    });
 
   listBookings = (tenantId: string) =>
     this.fetch<ListResult<BookingDto>>(`/bookings?tenant_id=${tenantId}`);
@@ -111,7 +117,7 @@ class ApiClient {
     });
 
   // Availability
-  getAvailability = (data: { site_id: string; professional_id?: string; date?: string }) =>
+  getAvailability = (data: { site_id: string; treatment_id: string; professional_id?: string; date?: string }) =>
     this.fetch('/booking/availability', { method: 'POST', body: JSON.stringify(data) });
 
   // Generic methods
@@ -107,6 +107,26 @@ resource "aws_lambda_permission" "professionals" {
   source_arn    = "${module.api_gateway.api_execution_arn}/*/*"
 }
 
+# Send-notification (vista previa y envío de prueba de plantillas). El
+# permiso de invocación lo da el módulo de la lambda (api_gateway_arn)
+resource "aws_apigatewayv2_integration" "send_notification" {
+  api_id           = module.api_gateway.api_id
+  integration_type = "AWS_PROXY"
+  integration_uri  = module.lambda_send_notification.function_invoke_arn
+  integration_method = "POST"
+  payload_format_version = "2.0"
+}
+
+# Patients (rutas protegidas). El permiso de invocación lo da el módulo de la
+# lambda (api_gateway_arn)
+resource "aws_apigatewayv2_integration" "patients" {
+  api_id           = module.api_gateway.api_id
+  integration_type = "AWS_PROXY"
+  integration_uri  = module.lambda_patients.function_invoke_arn
+  integration_method = "POST"
+  payload_format_version = "2.0"
+}
+
 # =====================================
 # RUTAS HTTP → LAMBDAS
 # =====================================
@@ -139,6 +159,15 @@ resource "aws_apigatewayv2_route" "post_bookings" {
   authorizer_id      = module.api_gateway.authorizer_id
 }
 
+resource "aws_apigatewayv2_route" "get_booking" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /bookings/{id}"
+  target    = "integrations/${aws_apigatewayv2_integration.bookings.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
 resource "aws_apigatewayv2_route" "put_bookings" {
   api_id    = module.api_gateway.api_id
   route_key = "PUT /bookings/{id}"
@@ -157,6 +186,53 @@ resource "aws_apigatewayv2_route" "delete_bookings" {
   authorizer_id      = module.api_gateway.authorizer_id
 }
 
+# Retenciones temporales de horario durante el checkout
+resource "aws_apigatewayv2_route" "post_booking_holds" {
+  api_id    = module.api_gateway.api_id
+  route_key = "POST /bookings/holds"
+  target    = "integrations/${aws_apigatewayv2_integration.bookings.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "post_booking_hold_confirm" {
+  api_id    = module.api_gateway.api_id
+  route_key = "POST /bookings/holds/{id}/confirm"
+  target    = "integrations/${aws_apigatewayv2_integration.bookings.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+# Transiciones de estado: confirm, check-in, start, complete, no-show, cancel
+resource "aws_apigatewayv2_route" "post_booking_transition" {
+  api_id    = module.api_gateway.api_id
+  route_key = "POST /bookings/{id}/{action}"
+  target    = "integrations/${aws_apigatewayv2_integration.bookings.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_booking_history" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /bookings/{id}/history"
+  target    = "integrations/${aws_apigatewayv2_integration.bookings.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_booking_notifications" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /bookings/{id}/notifications"
+  target    = "integrations/${aws_apigatewayv2_integration.bookings.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
 # Availability endpoint (protegido)
 resource "aws_apigatewayv2_route" "post_availability" {
   api_id    = module.api_gateway.api_id
@@ -195,6 +271,158 @@ resource "aws_apigatewayv2_route" "get_tenant_by_id" {
   authorizer_id      = module.api_gateway.authorizer_id
 }
 
+resource "aws_apigatewayv2_route" "put_tenant_settings" {
+  api_id    = module.api_gateway.api_id
+  route_key = "PUT /tenants/{id}/settings"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+# Sites endpoints (protegidos, bajo el tenant)
+resource "aws_apigatewayv2_route" "get_sites" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /tenants/{id}/sites"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "post_sites" {
+  api_id    = module.api_gateway.api_id
+  route_key = "POST /tenants/{id}/sites"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_site_by_id" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /tenants/{id}/sites/{site_id}"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "put_site" {
+  api_id    = module.api_gateway.api_id
+  route_key = "PUT /tenants/{id}/sites/{site_id}"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "delete_site" {
+  api_id    = module.api_gateway.api_id
+  route_key = "DELETE /tenants/{id}/sites/{site_id}"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_tenant_preferences" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /tenants/{id}/preferences"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "put_tenant_preferences" {
+  api_id    = module.api_gateway.api_id
+  route_key = "PUT /tenants/{id}/preferences"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_tenant_suppressions" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /tenants/{id}/suppressions"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "delete_tenant_suppression" {
+  api_id    = module.api_gateway.api_id
+  route_key = "DELETE /tenants/{id}/suppressions"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_tenant_templates" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /tenants/{id}/templates"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "put_tenant_template" {
+  api_id    = module.api_gateway.api_id
+  route_key = "PUT /tenants/{id}/templates/{channel}/{template}/{locale}"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "delete_tenant_template" {
+  api_id    = module.api_gateway.api_id
+  route_key = "DELETE /tenants/{id}/templates/{channel}/{template}/{locale}"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "post_notification_preview" {
+  api_id    = module.api_gateway.api_id
+  route_key = "POST /tenants/{id}/notifications/preview"
+  target    = "integrations/${aws_apigatewayv2_integration.send_notification.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "post_notification_test" {
+  api_id    = module.api_gateway.api_id
+  route_key = "POST /tenants/{id}/notifications/test"
+  target    = "integrations/${aws_apigatewayv2_integration.send_notification.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_unsubscribe" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /unsubscribe"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  # Sin autenticación (endpoint público): el token firmado autoriza la baja
+}
+
+resource "aws_apigatewayv2_route" "post_unsubscribe" {
+  api_id    = module.api_gateway.api_id
+  route_key = "POST /unsubscribe"
+  target    = "integrations/${aws_apigatewayv2_integration.tenants.id}"
+  
+  # Sin autenticación (endpoint público): el token firmado autoriza la baja
+}
+
 # Treatments endpoints (protegidos)
 resource "aws_apigatewayv2_route" "get_treatments" {
   api_id    = module.api_gateway.api_id
@@ -232,3 +460,58 @@ resource "aws_apigatewayv2_route" "post_professionals" {
   authorization_type = "JWT"
   authorizer_id      = module.api_gateway.authorizer_id
 }
+
+resource "aws_apigatewayv2_route" "put_professional_sites" {
+  api_id    = module.api_gateway.api_id
+  route_key = "PUT /professionals/{id}/sites"
+  target    = "integrations/${aws_apigatewayv2_integration.professionals.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+# Directorio de pacientes (protegido, bajo el tenant)
+resource "aws_apigatewayv2_route" "post_patients" {
+  api_id    = module.api_gateway.api_id
+  route_key = "POST /tenants/{id}/patients"
+  target    = "integrations/${aws_apigatewayv2_integration.patients.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_patients" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /tenants/{id}/patients"
+  target    = "integrations/${aws_apigatewayv2_integration.patients.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "get_patient" {
+  api_id    = module.api_gateway.api_id
+  route_key = "GET /tenants/{id}/patients/{patient_id}"
+  target    = "integrations/${aws_apigatewayv2_integration.patients.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "put_patient" {
+  api_id    = module.api_gateway.api_id
+  route_key = "PUT /tenants/{id}/patients/{patient_id}"
+  target    = "integrations/${aws_apigatewayv2_integration.patients.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
+
+resource "aws_apigatewayv2_route" "delete_patient" {
+  api_id    = module.api_gateway.api_id
+  route_key = "DELETE /tenants/{id}/patients/{patient_id}"
+  target    = "integrations/${aws_apigatewayv2_integration.patients.id}"
+  
+  authorization_type = "JWT"
+  authorizer_id      = module.api_gateway.authorizer_id
+}
@@ -0,0 +1,185 @@
+# Outbox de reservas → notificaciones
+# Cada transacción de reservas escribe un evento BOOKING#id / EVENT#...; el stream
+# de la tabla lo entrega a booking-events, que encola la notificación en SQS para
+# send-notification y programa los recordatorios en EventBridge Scheduler.
+
+# =====================================
+# COLA DE NOTIFICACIONES
+# =====================================
+
+resource "aws_sqs_queue" "notifications_dlq" {
+  name                      = "${var.project_name}-${var.environment}-notifications-dlq"
+  message_retention_seconds = 1209600
+
+  tags = var.tags
+}
+
+resource "aws_sqs_queue" "notifications" {
+  name                       = "${var.project_name}-${var.environment}-notifications"
+  visibility_timeout_seconds = 180 # 6x el timeout de send-notification
+  message_retention_seconds  = 345600
+
+  redrive_policy = jsonencode({
+    deadLetterTargetArn = aws_sqs_queue.notifications_dlq.arn
+    maxReceiveCount     = 5
+  })
+
+  tags = var.tags
+}
+
+resource "aws_lambda_event_source_mapping" "notifications_queue" {
+  event_source_arn = aws_sqs_queue.notifications.arn
+  function_name    = module.lambda_send_notification.function_arn
+  batch_size       = 10
+
+  # Solo se reintentan los mensajes con error transitorio; los permanentes los
+  # mueve la función a la DLQ
+  function_response_types = ["ReportBatchItemFailures"]
+}
+
+# Firma de los enlaces de baja: send-notification los genera, tenants los valida
+resource "random_password" "unsubscribe_secret" {
+  length  = 48
+  special = false
+}
+
+# Feedback de SES: actualiza el estado de entrega de cada email enviado
+resource "aws_sns_topic_subscription" "ses_feedback" {
+  topic_arn = module.ses.feedback_topic_arn
+  protocol  = "lambda"
+  endpoint  = module.lambda_send_notification.function_arn
+}
+
+resource "aws_lambda_permission" "ses_feedback" {
+  statement_id  = "AllowSESFeedbackInvoke"
+  action        = "lambda:InvokeFunction"
+  function_name = module.lambda_send_notification.function_name
+  principal     = "sns.amazonaws.com"
+  source_arn    = module.ses.feedback_topic_arn
+}
+
+resource "aws_iam_role_policy" "send_notification_queue" {
+  role = module.iam_send_notification.role_name
+
+  policy = jsonencode({
+    Version = "2012-10-17"
+    Statement = [
+      {
+        Effect   = "Allow"
+        Action   = ["sqs:ReceiveMessage", "sqs:DeleteMessage", "sqs:GetQueueAttributes"]
+        Resource = [aws_sqs_queue.notifications.arn]
+      },
+      {
+        Effect   = "Allow"
+        Action   = ["sqs:SendMessage"]
+        Resource = [aws_sqs_queue.notifications_dlq.arn]
+      }
+    ]
+  })
+}
+
+# =====================================
+# CONSUMIDOR DEL STREAM
+# =====================================
+
+module "iam_booking_events" {
+  source = "../../modules/iam"
+
+  project_name       = var.project_name
+  environment        = var.environment
+  function_name      = "booking-events"
+  dynamodb_table_arn = module.dynamodb.table_arn
+  enable_ses_access  = false
+
+  tags = var.tags
+}
+
+resource "aws_iam_role_policy" "booking_events" {
+  role = module.iam_booking_events.role_name
+
+  policy = jsonencode({
+    Version = "2012-10-17"
+    Statement = [
+      {
+        Effect = "Allow"
+        Action = [
+          "dynamodb:DescribeStream",
+          "dynamodb:GetRecords",
+          "dynamodb:GetShardIterator",
+          "dynamodb:ListStreams"
+        ]
+        Resource = [module.dynamodb.stream_arn]
+      },
+      {
+        Effect   = "Allow"
+        Action   = ["sqs:SendMessage"]
+        Resource = [aws_sqs_queue.notifications.arn]
+      },
+      {
+        Effect = "Allow"
+        Action = [
+          "scheduler:CreateSchedule",
+          "scheduler:UpdateSchedule",
+          "scheduler:DeleteSchedule",
+          "scheduler:ListSchedules"
+        ]
+        Resource = ["*"]
+      },
+      {
+        Effect   = "Allow"
+        Action   = ["iam:PassRole"]
+        Resource = [aws_iam_role.eventbridge_scheduler.arn]
+      }
+    ]
+  })
+}
+
+module "lambda_booking_events" {
+  source = "../../modules/lambda"
+
+  project_name        = var.project_name
+  environment         = var.environment
+  function_name       = "booking-events"
+  iam_role_arn        = module.iam_booking_events.role_arn
+  lambda_zip_path     = "${path.module}/../../../backend/target/lambda/booking-events/bootstrap.zip"
+  dynamodb_table_name = module.dynamodb.table_name
+  timeout             = 60
+  memory_size         = 512
+  log_level           = "info"
+  log_retention_days  = 7
+
+  environment_variables = {
+    NOTIFICATIONS_QUEUE_URL = aws_sqs_queue.notifications.url
+    SCHEDULER_ROLE_ARN      = aws_iam_role.eventbridge_scheduler.arn
+    NOTIFICATION_LAMBDA_ARN = module.lambda_send_notification.function_arn
+  }
+
+  tags = var.tags
+}
+
+resource "aws_lambda_event_source_mapping" "booking_events_stream" {
+  event_source_arn       = module.dynamodb.stream_arn
+  function_name          = module.lambda_booking_events.function_arn
+  starting_position      = "LATEST"
+  batch_size             = 25
+  maximum_retry_attempts = 10
+
+  # La función reporta el primer registro fallido; se reintenta desde ahí
+  function_response_types = ["ReportBatchItemFailures"]
+
+  # Solo las inserciones del outbox, no el resto de escrituras de la tabla
+  filter_criteria {
+    filter {
+      pattern = jsonencode({
+        eventName = ["INSERT"]
+        dynamodb = {
+          NewImage = {
+            SK = { S = [{ prefix = "EVENT#" }] }
+          }
+        }
+      })
+    }
+  }
+
+  depends_on = [aws_iam_role_policy.booking_events]
+}
@@ -65,6 +65,11 @@ output "lambda_professionals_arn" {
   value       = module.lambda_professionals.function_arn
 }
 
+output "lambda_patients_arn" {
+  description = "ARN de Lambda Patients"
+  value       = module.lambda_patients.function_arn
+}
+
 output "lambda_tenants_arn" {
   description = "ARN de Lambda Tenants"
   value       = module.lambda_tenants.function_arn
@@ -9,6 +9,10 @@ terraform {
       source  = "hashicorp/aws"
       version = "~> 5.0"
     }
+    random = {
+      source  = "hashicorp/random"
+      version = "~> 3.6"
+    }
   }
 }
 
@@ -114,6 +118,18 @@ module "iam_professionals" {
   tags = var.tags
 }
 
+module "iam_patients" {
+  source = "../../modules/iam"
+
+  project_name       = var.project_name
+  environment        = var.environment
+  function_name      = "patients"
+  dynamodb_table_arn = module.dynamodb.table_arn
+  enable_ses_access  = false
+
+  tags = var.tags
+}
+
 module "iam_tenants" {
   source = "../../modules/iam"
 
@@ -198,6 +214,7 @@ module "lambda_bookings" {
 
   environment_variables = {
     COGNITO_USER_POOL_ID = module.cognito.user_pool_id
+    COGNITO_CLIENT_ID    = module.cognito.client_id
   }
 
   tags = var.tags
@@ -218,6 +235,11 @@ module "lambda_availability" {
   log_retention_days  = 14
   api_gateway_arn     = module.api_gateway.api_execution_arn
 
+  environment_variables = {
+    COGNITO_USER_POOL_ID = module.cognito.user_pool_id
+    COGNITO_CLIENT_ID    = module.cognito.client_id
+  }
+
   tags = var.tags
 }
 
@@ -236,6 +258,34 @@ module "lambda_professionals" {
   log_retention_days  = 14
   api_gateway_arn     = module.api_gateway.api_execution_arn
 
+  environment_variables = {
+    COGNITO_USER_POOL_ID = module.cognito.user_pool_id
+    COGNITO_CLIENT_ID    = module.cognito.client_id
+  }
+
+  tags = var.tags
+}
+
+module "lambda_patients" {
+  source = "../../modules/lambda"
+
+  project_name        = var.project_name
+  environment         = var.environment
+  function_name       = "patients"
+  iam_role_arn        = module.iam_patients.role_arn
+  lambda_zip_path     = "${path.module}/../../../backend/target/lambda/patients/bootstrap.zip"
+  dynamodb_table_name = module.dynamodb.table_name
+  timeout             = 30
+  memory_size         = 512
+  log_level           = "debug"
+  log_retention_days  = 14
+  api_gateway_arn     = module.api_gateway.api_execution_arn
+
+  environment_variables = {
+    COGNITO_USER_POOL_ID = module.cognito.user_pool_id
+    COGNITO_CLIENT_ID    = module.cognito.client_id
+  }
+
   tags = var.tags
 }
 
@@ -254,6 +304,12 @@ module "lambda_tenants" {
   log_retention_days  = 14
   api_gateway_arn     = module.api_gateway.api_execution_arn
 
+  environment_variables = {
+    COGNITO_USER_POOL_ID = module.cognito.user_pool_id
+    COGNITO_CLIENT_ID    = module.cognito.client_id
+    UNSUBSCRIBE_SECRET   = random_password.unsubscribe_secret.result
+  }
+
   tags = var.tags
 }
 
@@ -272,6 +328,11 @@ module "lambda_treatments" {
   log_retention_days  = 14
   api_gateway_arn     = module.api_gateway.api_execution_arn
 
+  environment_variables = {
+    COGNITO_USER_POOL_ID = module.cognito.user_pool_id
+    COGNITO_CLIENT_ID    = module.cognito.client_id
+  }
+
   tags = var.tags
 }
 
@@ -292,6 +353,9 @@ module "lambda_send_notification" {
 
   environment_variables = {
     SES_CONFIGURATION_SET   = module.ses.configuration_set_name
+    NOTIFICATIONS_DLQ_URL   = aws_sqs_queue.notifications_dlq.url
+    API_URL                 = module.api_gateway.api_endpoint
+    UNSUBSCRIBE_SECRET      = random_password.unsubscribe_secret.result
     SCHEDULER_ROLE_ARN      = aws_iam_role.eventbridge_scheduler.arn
     NOTIFICATION_LAMBDA_ARN = module.lambda_send_notification.function_arn
   }
@@ -330,6 +394,32 @@ resource "aws_iam_role_policy" "eventbridge_scheduler_invoke" {
   })
 }
 
+# Crear, reemplazar y borrar los schedules booking-<id>-<n>h
+resource "aws_iam_role_policy" "schedule_reminder_scheduler" {
+  role = module.iam_schedule_reminder.role_name
+
+  policy = jsonencode({
+    Version = "2012-10-17"
+    Statement = [
+      {
+        Effect = "Allow"
+        Action = [
+          "scheduler:CreateSchedule",
+          "scheduler:UpdateSchedule",
+          "scheduler:DeleteSchedule",
+          "scheduler:ListSchedules"
+        ]
+        Resource = ["*"]
+      },
+      {
+        Effect   = "Allow"
+        Action   = ["iam:PassRole"]
+        Resource = [aws_iam_role.eventbridge_scheduler.arn]
+      }
+    ]
+  })
+}
+
 module "lambda_schedule_reminder" {
   source = "../../modules/lambda"
 
@@ -6,7 +6,7 @@ resource "aws_apigatewayv2_api" "main" {
   protocol_type = "HTTP"
 
   cors_configuration {
-    allow_headers = ["authorization", "content-type", "x-amz-date", "x-api-key"]
+    allow_headers = ["authorization", "content-type", "idempotency-key", "x-amz-date", "x-api-key"]
     allow_methods = ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
     allow_origins = var.cors_allowed_origins
     max_age       = 3600
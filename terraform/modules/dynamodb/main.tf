@@ -7,6 +7,10 @@ resource "aws_dynamodb_table" "main" {
   hash_key     = "PK"
   range_key    = "SK"
 
+  # Stream para el outbox de reservas (lo consume booking-events)
+  stream_enabled   = true
+  stream_view_type = "NEW_IMAGE"
+
   attribute {
     name = "PK"
     type = "S"
@@ -51,6 +55,12 @@ resource "aws_dynamodb_table" "main" {
     projection_type = "ALL"
   }
 
+  # Registros efímeros (Idempotency-Key, retenciones de slots) se borran solos al vencer
+  ttl {
+    attribute_name = "expiresAt"
+    enabled        = true
+  }
+
   point_in_time_recovery {
     enabled = var.enable_point_in_time_recovery
   }
@@ -15,3 +15,7 @@ output "table_id" {
   value       = aws_dynamodb_table.main.id
 }
 
+output "stream_arn" {
+  description = "ARN del stream de la tabla (outbox de reservas)"
+  value       = aws_dynamodb_table.main.stream_arn
+}
@@ -34,3 +34,45 @@ resource "aws_ses_event_destination" "cloudwatch" {
   }
 }
 
+
+# Feedback por email (entregas, rebotes y quejas) para el registro de entregas
+# de cada reserva; lo consume send-notification
+resource "aws_sns_topic" "feedback" {
+  name = "${var.project_name}-${var.environment}-ses-feedback"
+
+  tags = var.tags
+}
+
+data "aws_caller_identity" "current" {}
+
+resource "aws_sns_topic_policy" "feedback" {
+  arn = aws_sns_topic.feedback.arn
+
+  policy = jsonencode({
+    Version = "2012-10-17"
+    Statement = [
+      {
+        Effect    = "Allow"
+        Principal = { Service = "ses.amazonaws.com" }
+        Action    = "SNS:Publish"
+        Resource  = aws_sns_topic.feedback.arn
+        Condition = {
+          StringEquals = { "AWS:SourceAccount" = data.aws_caller_identity.current.account_id }
+        }
+      }
+    ]
+  })
+}
+
+resource "aws_ses_event_destination" "sns" {
+  name                   = "sns-feedback"
+  configuration_set_name = aws_ses_configuration_set.main.name
+  enabled                = true
+  matching_types         = ["bounce", "complaint", "delivery"]
+
+  sns_destination {
+    topic_arn = aws_sns_topic.feedback.arn
+  }
+
+  depends_on = [aws_sns_topic_policy.feedback]
+}
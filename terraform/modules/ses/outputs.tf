@@ -20,3 +20,8 @@ output "configuration_set_name" {
   value       = aws_ses_configuration_set.main.name
 }
 
+
+output "feedback_topic_arn" {
+  description = "ARN del topic SNS con entregas, rebotes y quejas"
+  value       = aws_sns_topic.feedback.arn
+}